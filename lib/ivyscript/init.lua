@@ -23,24 +23,46 @@ local ffi = require "ffi"
 ffi.cdef([[
     typedef struct translator translator_t;
 
+    uint32_t translator_abi_version();
+
     translator_t *translator_new_ocl(const char *);
     translator_t *translator_new_ispc(const char *);
-    char *translator_generate(translator_t *, const char *);
+    translator_t *translator_clone(translator_t *);
+    int32_t translator_generate(translator_t *, const char *, char **);
+    int32_t translator_add_kernel(translator_t *, const char *);
+    int32_t translator_generate_program(translator_t *, char **, char **);
     void translator_free(translator_t *);
+    void translator_string_free(char *);
+
+    int32_t translator_clear_inputs(translator_t *);
 
-    void translator_clear_inputs(translator_t *);
+    int32_t translator_add_buffer_srgb(translator_t *, uint64_t, uint64_t, uint64_t);
+    int32_t translator_add_buffer_lrgb(translator_t *, uint64_t, uint64_t, uint64_t);
+    int32_t translator_add_buffer_xyz(translator_t *, uint64_t, uint64_t, uint64_t);
+    int32_t translator_add_buffer_lab(translator_t *, uint64_t, uint64_t, uint64_t);
+    int32_t translator_add_buffer_lch(translator_t *, uint64_t, uint64_t, uint64_t);
+    int32_t translator_add_buffer_y(translator_t *, uint64_t, uint64_t, uint64_t);
+    int32_t translator_add_buffer_l(translator_t *, uint64_t, uint64_t, uint64_t);
+    int32_t translator_add_bool(translator_t *);
+    int32_t translator_add_int(translator_t *);
+    int32_t translator_add_float(translator_t *);
+    int32_t translator_add_vec(translator_t *);
+    int32_t translator_add_mat3(translator_t *);
+    int32_t translator_add_int_const(translator_t *, int32_t);
+    int32_t translator_add_float_const(translator_t *, float);
+    int32_t translator_add_int_array(translator_t *, uint64_t);
+    int32_t translator_add_float_array(translator_t *, uint64_t);
+    int32_t translator_add_function(translator_t *, const char *);
 
-    uint64_t translator_add_buffer_srgb(translator_t *, uint64_t, uint64_t, uint64_t);
-    uint64_t translator_add_buffer_lrgb(translator_t *, uint64_t, uint64_t, uint64_t);
-    uint64_t translator_add_buffer_xyz(translator_t *, uint64_t, uint64_t, uint64_t);
-    uint64_t translator_add_buffer_lab(translator_t *, uint64_t, uint64_t, uint64_t);
-    uint64_t translator_add_buffer_lch(translator_t *, uint64_t, uint64_t, uint64_t);
-    uint64_t translator_add_buffer_y(translator_t *, uint64_t, uint64_t, uint64_t);
-    uint64_t translator_add_buffer_l(translator_t *, uint64_t, uint64_t, uint64_t);
-    uint64_t translator_add_int(translator_t *);
-    uint64_t translator_add_float(translator_t *);
+    int32_t translator_get_id(translator_t *, const char *, char **);
+    int32_t translator_get_traversal(translator_t *, const char *, int32_t *);
+    int32_t translator_set_cache_dir(translator_t *, const char *);
+    int32_t translator_set_double(translator_t *, bool);
+    int32_t translator_add_builtin(translator_t *, const char *, const char *, const char *,
+      const char *, const char *, const char *, const char *);
 
-    char *translator_get_id(translator_t *, const char *);
+    int32_t translator_lint(translator_t *, char **);
+    int32_t translator_set_lint(translator_t *, const char *, bool);
 ]])
 
 local lib
@@ -50,6 +72,31 @@ elseif ffi.os=="Linux" then
 	lib = ffi.load "lib/ivyscript/target/release/libivyscript.so"
 end
 
+local ABI_VERSION = 1
+assert(lib.translator_abi_version()==ABI_VERSION, "IvyScript library version mismatch, rebuild lib/ivyscript")
+
+local status = {
+  [-1] = "null pointer argument",
+  [-2] = "invalid UTF-8 string",
+  [-3] = "invalid argument",
+  [-4] = "translation error",
+  [-5] = "internal error",
+}
+
+local function check(code)
+  assert(code==0, status[code])
+end
+
+-- call a function returning an owned string, and release the string
+local out = ffi.new("char *[1]")
+local function getString(f, ...)
+  out[0] = nil
+  local code = f(...)
+  local s = out[0]~=nil and ffi.string(out[0]) or nil
+  lib.translator_string_free(out[0])
+  return code, s
+end
+
 local targetList = {
   OCL = lib.translator_new_ocl,
   ISPC = lib.translator_new_ispc,
@@ -59,6 +106,7 @@ function ivy.new(source, target)
   target = target or "OCL"
 
   local t = targetList[target](source)
+  assert(t~=nil, "IvyScript unable to read source")
   ffi.gc(t, lib.translator_free)
 
   local o = {
@@ -69,8 +117,39 @@ function ivy.new(source, target)
   return o
 end
 
+-- new translator sharing the parsed source and generated kernels, for use in another thread
+function ivy:clone()
+  local t = lib.translator_clone(self.t)
+  assert(t~=nil, "IvyScript unable to clone translator")
+  ffi.gc(t, lib.translator_free)
+
+  local o = {
+    t = t
+  }
+
+  setmetatable(o, ivy.meta)
+  return o
+end
+
+-- translators by target and source, parsed once per thread
+local shared = {}
+
+-- clone of the translator for the source in this thread, processes loading the same source
+-- share the parsed program and generated kernels while keeping their own inputs and settings
+function ivy.shared(source, target)
+  target = target or "OCL"
+
+  shared[target] = shared[target] or {}
+  local t = shared[target][source]
+  if not t then
+    t = ivy.new(source, target)
+    shared[target][source] = t
+  end
+  return t:clone()
+end
+
 function ivy:clear()
-  lib.translator_clear_inputs(self.t)
+  check(lib.translator_clear_inputs(self.t))
 end
 
 local cs = {
@@ -84,23 +163,127 @@ local cs = {
 }
 
 function ivy:addBuffer(buf)
-  return cs[buf.cs](self.t, buf.x, buf.y, buf.z)
+  check(cs[buf.cs](self.t, buf.x, buf.y, buf.z))
+end
+
+function ivy:addBool()
+  check(lib.translator_add_bool(self.t))
 end
 
 function ivy:addInt()
-  return lib.translator_add_int(self.t)
+  check(lib.translator_add_int(self.t))
 end
 
 function ivy:addFloat()
-  return lib.translator_add_float(self.t)
+  check(lib.translator_add_float(self.t))
 end
 
+function ivy:addVec()
+  check(lib.translator_add_vec(self.t))
+end
+
+function ivy:addMat3()
+  check(lib.translator_add_mat3(self.t))
+end
+
+-- constant values are baked into the generated kernel, the kernel argument is unused
+function ivy:addIntConst(value)
+  check(lib.translator_add_int_const(self.t, value))
+end
+
+function ivy:addFloatConst(value)
+  check(lib.translator_add_float_const(self.t, value))
+end
+
+function ivy:addIntArray(len)
+  check(lib.translator_add_int_array(self.t, len))
+end
+
+function ivy:addFloatArray(len)
+  check(lib.translator_add_float_array(self.t, len))
+end
+
+-- function of the source called through a 'fn' argument, the kernel argument is removed
+function ivy:addFunction(name)
+  check(lib.translator_add_function(self.t, name))
+end
+
+-- returns the generated source, or an empty string and the error message
 function ivy:generate(kernel)
-  return ffi.string(lib.translator_generate(self.t, kernel))
+  local code, s = getString(lib.translator_generate, self.t, kernel, out)
+  if code==0 then
+    return s
+  else
+    return "", s or status[code]
+  end
+end
+
+-- add a kernel with the current inputs to the next program
+function ivy:addKernel(kernel)
+  check(lib.translator_add_kernel(self.t, kernel))
+end
+
+-- returns a single source with all added kernels and the list of their ids in the source,
+-- or an empty string and the error message
+local manifest = ffi.new("char *[1]")
+function ivy:generateProgram()
+  out[0] = nil
+  manifest[0] = nil
+  local code = lib.translator_generate_program(self.t, out, manifest)
+  local s = out[0]~=nil and ffi.string(out[0]) or nil
+  local m = manifest[0]~=nil and ffi.string(manifest[0]) or ""
+  lib.translator_string_free(out[0])
+  lib.translator_string_free(manifest[0])
+
+  if code~=0 then
+    return "", s or status[code]
+  end
+  local ids = {}
+  for name, id in m:gmatch("(%S+) (%S+)\n") do
+    table.insert(ids, id)
+  end
+  return s, ids
+end
+
+-- cache generated kernels in a directory between runs, nil disables the cache directory
+function ivy:setCacheDir(dir)
+  check(lib.translator_set_cache_dir(self.t, dir or ""))
+end
+
+-- emit double precision arithmetic in kernels of this translator, buffers remain single precision
+function ivy:setDouble(enabled)
+  check(lib.translator_set_double(self.t, enabled))
+end
+
+-- register a native function, e.g. ivy:addBuiltin("gain", "float, float", "float", "gain_cl", "gain_ispc", clSource, ispcSource)
+function ivy:addBuiltin(name, args, ret, ocl, ispc, oclSource, ispcSource)
+  check(lib.translator_add_builtin(self.t, name, args or "", ret or "void", ocl, ispc or ocl, oclSource, ispcSource))
 end
 
 function ivy:id(kernel)
-  return ffi.string(lib.translator_get_id(self.t, kernel))
+  local code, s = getString(lib.translator_get_id, self.t, kernel, out)
+  check(code)
+  return s
+end
+
+-- tile size declared by the kernel, -1 spans the whole dimension, nil leaves the traversal to the host
+local traversal = ffi.new("int32_t[2]")
+function ivy:traversal(kernel)
+  check(lib.translator_get_traversal(self.t, kernel, traversal))
+  if traversal[0]==0 then
+    return nil
+  end
+  return traversal[0], traversal[1]
+end
+
+function ivy:lint()
+  local code, s = getString(lib.translator_lint, self.t, out)
+  check(code)
+  return s
+end
+
+function ivy:setLint(code, enabled)
+  check(lib.translator_set_lint(self.t, code, enabled))
 end
 
 return ivy
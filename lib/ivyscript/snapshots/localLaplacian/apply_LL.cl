@@ -0,0 +1,53 @@
+#define E (216.0f/24389.0f)
+#define K (24389.0f/27.0f)
+
+inline float _lab(float v) {
+  if (v>E) {
+    return cbrt(v);
+  } else {
+    return (K*v + 16.0f)/116.0f;
+  }
+}
+
+inline float _Y_L(float i) {
+	return 1.16f*_lab(i) - 0.16f;
+}
+
+inline float YtoL(float i) { return _Y_L(i); }
+
+#undef E
+#undef K
+
+
+
+
+kernel void apply_LL (
+	global float *G, global int *___str_G,
+	global float *T, global int *___str_T,
+	global float *O, global int *___str_O,
+	int l,
+	int lvl
+) {
+// current lvl, total # of levels
+const int x = get_global_id(0);
+const int y = get_global_id(1);
+int z = 0;
+if (true) {
+z = 1;
+}
+float g = YtoL(G[(clamp((int)(x), 0, (___str_G[0] - 1))*(___str_G[3]) + clamp((int)(y), 0, (___str_G[1] - 1))*(___str_G[4]) + clamp((int)(z), 0, (___str_G[2] - 1))*(___str_G[5]))]);
+float t = T[(clamp((int)(x), 0, (___str_T[0] - 1))*(___str_T[3]) + clamp((int)(y), 0, (___str_T[1] - 1))*(___str_T[4]) + clamp((int)(0), 0, (___str_T[2] - 1))*(___str_T[5]))];
+float o = O[(clamp((int)(x), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(0), 0, (___str_O[2] - 1))*(___str_O[5]))];
+float v = g*lvl;
+int vl = (int)(floor(v));
+int vh = vl + 1;
+float vf = vh - v;
+vh = min(vh, lvl);
+if (l==vl) {
+o = o + vf*t;
+}
+if (l==vh) {
+o = o + (1.0000000f - vf)*t;
+}
+if (x>=0 && x<___str_O[0] && y>=0 && y<___str_O[1])  O[(clamp((int)(x), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(0), 0, (___str_O[2] - 1))*(___str_O[5]))] = o;
+}
\ No newline at end of file
@@ -0,0 +1,13 @@
+
+
+
+kernel void post_LL (
+	global float *I, global int *___str_I,
+	global float *O, global int *___str_O
+) {
+const int x = get_global_id(0);
+const int y = get_global_id(1);
+float3 i = (float3)( I[(clamp((int)(x), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(0), 0, (___str_I[2] - 1))*(___str_I[5]))], I[(clamp((int)(x), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(1), 0, (___str_I[2] - 1))*(___str_I[5]))], I[(clamp((int)(x), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(2), 0, (___str_I[2] - 1))*(___str_I[5]))] );
+float o_y = O[(clamp((int)(x), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(1), 0, (___str_O[2] - 1))*(___str_O[5]))];
+if (x>=0 && x<___str_O[0] && y>=0 && y<___str_O[1])  { float3 __v = (i*o_y/i.y); O[(clamp((int)(x), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(0), 0, (___str_O[2] - 1))*(___str_O[5]))] = __v.x; O[(clamp((int)(x), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(1), 0, (___str_O[2] - 1))*(___str_O[5]))] = __v.y; O[(clamp((int)(x), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(2), 0, (___str_O[2] - 1))*(___str_O[5]))] = __v.z; }
+}
\ No newline at end of file
@@ -0,0 +1,69 @@
+#define E (216.0f/24389.0f)
+#define K (24389.0f/27.0f)
+
+inline float _lab(float v) {
+  if (v>E) {
+    return cbrt(v);
+  } else {
+    return (K*v + 16.0f)/116.0f;
+  }
+}
+
+inline float _xyz(float V) {
+  if (pown(V, 3)>E) {
+    return pown(V, 3);
+  } else {
+    return (116.0f*V - 16.0f)/K;
+  }
+}
+
+inline float _Y_L(float i) {
+	return 1.16f*_lab(i) - 0.16f;
+}
+
+inline float _L_Y(float i) {
+	return _xyz((i + 0.16f)/1.16f);
+}
+
+inline float YtoL(float i) { return _Y_L(i); }
+
+inline float LtoY(float i) { return _L_Y(i); }
+
+inline float range(float p, float w, float x) {
+  x = (x - (p - w)) / (2 * w + 0.000001f);
+  x = clamp(x, 0.0f, 1.0f);
+  return 2.0f * pown(x, 3) - 3.0f * pown(x, 2) + 1.0f;
+}
+
+#undef E
+#undef K
+
+
+
+
+kernel void transform (
+	global float *I, global int *___str_I,
+	global float *D, global int *___str_D,
+	global float *R, global int *___str_R,
+	global float *O, global int *___str_O,
+	float m
+) {
+// midpoint
+const int x = get_global_id(0);
+const int y = get_global_id(1);
+int iz = 0;
+if (true) {
+iz = 1;
+}
+int oz = 0;
+if (true) {
+oz = 1;
+}
+float i = clamp(YtoL(I[(clamp((int)(x), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(iz), 0, (___str_I[2] - 1))*(___str_I[5]))]), 0.0000000f, 1.0000000f);
+// works only in range [0, 1]
+float d = D[(clamp((int)(x), 0, (___str_D[0] - 1))*(___str_D[3]) + clamp((int)(y), 0, (___str_D[1] - 1))*(___str_D[4]) + clamp((int)(0), 0, (___str_D[2] - 1))*(___str_D[5]))] + 1.0000000f;
+float r = R[(clamp((int)(x), 0, (___str_R[0] - 1))*(___str_R[3]) + clamp((int)(y), 0, (___str_R[1] - 1))*(___str_R[4]) + clamp((int)(0), 0, (___str_R[2] - 1))*(___str_R[5]))];
+float o = clamp(fabs(i - m)/r*0.5000000f, 0.0000000f, 1.0000000f);
+float f = 2.0000000f*pown(o, 3) - 3.0000000f*pown(o, 2) + 1.0000000f;
+if (x>=0 && x<___str_O[0] && y>=0 && y<___str_O[1] && oz>=0 && oz<___str_O[2])  O[(clamp((int)(x), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(oz), 0, (___str_O[2] - 1))*(___str_O[5]))] = LtoY(f*(i - m)*d + (1.0000000f - f)*(i - m) + m);
+}
\ No newline at end of file
@@ -0,0 +1,10 @@
+
+
+
+kernel void zero_LL (
+	global float *L, global int *___str_L
+) {
+const int x = get_global_id(0);
+const int y = get_global_id(1);
+if (x>=0 && x<___str_L[0] && y>=0 && y<___str_L[1])  L[(clamp((int)(x), 0, (___str_L[0] - 1))*(___str_L[3]) + clamp((int)(y), 0, (___str_L[1] - 1))*(___str_L[4]) + clamp((int)(0), 0, (___str_L[2] - 1))*(___str_L[5]))] = 0.0000000f;
+}
\ No newline at end of file
@@ -0,0 +1,12 @@
+
+
+
+kernel void ivy_copy (
+	global float *I, global int *___str_I,
+	global float *O, global int *___str_O
+) {
+const int x = get_global_id(0);
+const int y = get_global_id(1);
+const int z = get_global_id(2);
+if (x>=0 && x<___str_O[0] && y>=0 && y<___str_O[1] && z>=0 && z<___str_O[2])  O[(clamp((int)(x), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(z), 0, (___str_O[2] - 1))*(___str_O[5]))] = I[(clamp((int)(x), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(z), 0, (___str_I[2] - 1))*(___str_I[5]))];
+}
\ No newline at end of file
@@ -0,0 +1,17 @@
+
+
+
+kernel void ivy_LT (
+	global float *A, global int *___str_A,
+	global float *B, global int *___str_B,
+	global float *O, global int *___str_O
+) {
+const int x = get_global_id(0);
+const int y = get_global_id(1);
+const int z = get_global_id(2);
+if (A[(clamp((int)(x), 0, (___str_A[0] - 1))*(___str_A[3]) + clamp((int)(y), 0, (___str_A[1] - 1))*(___str_A[4]) + clamp((int)(z), 0, (___str_A[2] - 1))*(___str_A[5]))]<B[(clamp((int)(x), 0, (___str_B[0] - 1))*(___str_B[3]) + clamp((int)(y), 0, (___str_B[1] - 1))*(___str_B[4]) + clamp((int)(z), 0, (___str_B[2] - 1))*(___str_B[5]))]) {
+if (x>=0 && x<___str_O[0] && y>=0 && y<___str_O[1] && z>=0 && z<___str_O[2])  O[(clamp((int)(x), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(z), 0, (___str_O[2] - 1))*(___str_O[5]))] = 1.0000000f;
+} else {
+if (x>=0 && x<___str_O[0] && y>=0 && y<___str_O[1] && z>=0 && z<___str_O[2])  O[(clamp((int)(x), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(z), 0, (___str_O[2] - 1))*(___str_O[5]))] = 0.0000000f;
+}
+}
\ No newline at end of file
@@ -0,0 +1,35 @@
+constant int SX = 7;
+constant int SY = 7;
+constant float eps = 0.0000010f;
+
+
+
+kernel void accumulate (
+	global float *I, global int *___str_I,
+	global float *T1, global int *___str_T1,
+	global float *T3, global int *___str_T3,
+	global float *T4, global int *___str_T4,
+	global float *W, global int *___str_W,
+	global float *p1, global int *___str_p1,
+	global float *p2, global int *___str_p2,
+	int ox,
+	int oy
+) {
+const int x = get_global_id(0);
+const int y = get_global_id(1);
+float3 sigma = pown((1.0000000f - (float3)(p2[(clamp((int)(x), 0, (___str_p2[0] - 1))*(___str_p2[3]) + clamp((int)(y), 0, (___str_p2[1] - 1))*(___str_p2[4]) + clamp((int)(0), 0, (___str_p2[2] - 1))*(___str_p2[5]))], p1[(clamp((int)(x), 0, (___str_p1[0] - 1))*(___str_p1[3]) + clamp((int)(y), 0, (___str_p1[1] - 1))*(___str_p1[4]) + clamp((int)(0), 0, (___str_p1[2] - 1))*(___str_p1[5]))], p2[(clamp((int)(x), 0, (___str_p2[0] - 1))*(___str_p2[3]) + clamp((int)(y), 0, (___str_p2[1] - 1))*(___str_p2[4]) + clamp((int)(0), 0, (___str_p2[2] - 1))*(___str_p2[5]))])), 5);
+float3 pf = exp((-T1[(clamp((int)(x), 0, (___str_T1[0] - 1))*(___str_T1[3]) + clamp((int)(y), 0, (___str_T1[1] - 1))*(___str_T1[4]) + clamp((int)(0), 0, (___str_T1[2] - 1))*(___str_T1[5]))])*sigma);
+float3 nf = exp((-T1[(clamp((int)(x - ox), 0, (___str_T1[0] - 1))*(___str_T1[3]) + clamp((int)(y - oy), 0, (___str_T1[1] - 1))*(___str_T1[4]) + clamp((int)(0), 0, (___str_T1[2] - 1))*(___str_T1[5]))])*sigma);
+float3 pi = (float3)( I[(clamp((int)(x + ox), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y + oy), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(0), 0, (___str_I[2] - 1))*(___str_I[5]))], I[(clamp((int)(x + ox), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y + oy), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(1), 0, (___str_I[2] - 1))*(___str_I[5]))], I[(clamp((int)(x + ox), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y + oy), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(2), 0, (___str_I[2] - 1))*(___str_I[5]))] );
+pi.x = pi.x/max(pi.y, eps);
+pi.z = pi.z/max(pi.y, eps);
+float3 ni = (float3)( I[(clamp((int)(x - ox), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y - oy), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(0), 0, (___str_I[2] - 1))*(___str_I[5]))], I[(clamp((int)(x - ox), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y - oy), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(1), 0, (___str_I[2] - 1))*(___str_I[5]))], I[(clamp((int)(x - ox), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y - oy), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(2), 0, (___str_I[2] - 1))*(___str_I[5]))] );
+ni.x = ni.x/max(ni.y, eps);
+ni.z = ni.z/max(ni.y, eps);
+float3 o = (float3)( T3[(clamp((int)(x), 0, (___str_T3[0] - 1))*(___str_T3[3]) + clamp((int)(y), 0, (___str_T3[1] - 1))*(___str_T3[4]) + clamp((int)(0), 0, (___str_T3[2] - 1))*(___str_T3[5]))], T3[(clamp((int)(x), 0, (___str_T3[0] - 1))*(___str_T3[3]) + clamp((int)(y), 0, (___str_T3[1] - 1))*(___str_T3[4]) + clamp((int)(1), 0, (___str_T3[2] - 1))*(___str_T3[5]))], T3[(clamp((int)(x), 0, (___str_T3[0] - 1))*(___str_T3[3]) + clamp((int)(y), 0, (___str_T3[1] - 1))*(___str_T3[4]) + clamp((int)(2), 0, (___str_T3[2] - 1))*(___str_T3[5]))] ) + pi*pf + ni*nf;
+float3 f = (float3)( T4[(clamp((int)(x), 0, (___str_T4[0] - 1))*(___str_T4[3]) + clamp((int)(y), 0, (___str_T4[1] - 1))*(___str_T4[4]) + clamp((int)(0), 0, (___str_T4[2] - 1))*(___str_T4[5]))], T4[(clamp((int)(x), 0, (___str_T4[0] - 1))*(___str_T4[3]) + clamp((int)(y), 0, (___str_T4[1] - 1))*(___str_T4[4]) + clamp((int)(1), 0, (___str_T4[2] - 1))*(___str_T4[5]))], T4[(clamp((int)(x), 0, (___str_T4[0] - 1))*(___str_T4[3]) + clamp((int)(y), 0, (___str_T4[1] - 1))*(___str_T4[4]) + clamp((int)(2), 0, (___str_T4[2] - 1))*(___str_T4[5]))] ) + pf + nf;
+float3 w = (float3)( W[(clamp((int)(x), 0, (___str_W[0] - 1))*(___str_W[3]) + clamp((int)(y), 0, (___str_W[1] - 1))*(___str_W[4]) + clamp((int)(0), 0, (___str_W[2] - 1))*(___str_W[5]))], W[(clamp((int)(x), 0, (___str_W[0] - 1))*(___str_W[3]) + clamp((int)(y), 0, (___str_W[1] - 1))*(___str_W[4]) + clamp((int)(1), 0, (___str_W[2] - 1))*(___str_W[5]))], W[(clamp((int)(x), 0, (___str_W[0] - 1))*(___str_W[3]) + clamp((int)(y), 0, (___str_W[1] - 1))*(___str_W[4]) + clamp((int)(2), 0, (___str_W[2] - 1))*(___str_W[5]))] );
+if (x>=0 && x<___str_W[0] && y>=0 && y<___str_W[1])  { float3 __v = max(w, max(pf, nf)); W[(clamp((int)(x), 0, (___str_W[0] - 1))*(___str_W[3]) + clamp((int)(y), 0, (___str_W[1] - 1))*(___str_W[4]) + clamp((int)(0), 0, (___str_W[2] - 1))*(___str_W[5]))] = __v.x; W[(clamp((int)(x), 0, (___str_W[0] - 1))*(___str_W[3]) + clamp((int)(y), 0, (___str_W[1] - 1))*(___str_W[4]) + clamp((int)(1), 0, (___str_W[2] - 1))*(___str_W[5]))] = __v.y; W[(clamp((int)(x), 0, (___str_W[0] - 1))*(___str_W[3]) + clamp((int)(y), 0, (___str_W[1] - 1))*(___str_W[4]) + clamp((int)(2), 0, (___str_W[2] - 1))*(___str_W[5]))] = __v.z; }
+if (x>=0 && x<___str_T3[0] && y>=0 && y<___str_T3[1])  { float3 __v = o; T3[(clamp((int)(x), 0, (___str_T3[0] - 1))*(___str_T3[3]) + clamp((int)(y), 0, (___str_T3[1] - 1))*(___str_T3[4]) + clamp((int)(0), 0, (___str_T3[2] - 1))*(___str_T3[5]))] = __v.x; T3[(clamp((int)(x), 0, (___str_T3[0] - 1))*(___str_T3[3]) + clamp((int)(y), 0, (___str_T3[1] - 1))*(___str_T3[4]) + clamp((int)(1), 0, (___str_T3[2] - 1))*(___str_T3[5]))] = __v.y; T3[(clamp((int)(x), 0, (___str_T3[0] - 1))*(___str_T3[3]) + clamp((int)(y), 0, (___str_T3[1] - 1))*(___str_T3[4]) + clamp((int)(2), 0, (___str_T3[2] - 1))*(___str_T3[5]))] = __v.z; }
+if (x>=0 && x<___str_T4[0] && y>=0 && y<___str_T4[1])  { float3 __v = f; T4[(clamp((int)(x), 0, (___str_T4[0] - 1))*(___str_T4[3]) + clamp((int)(y), 0, (___str_T4[1] - 1))*(___str_T4[4]) + clamp((int)(0), 0, (___str_T4[2] - 1))*(___str_T4[5]))] = __v.x; T4[(clamp((int)(x), 0, (___str_T4[0] - 1))*(___str_T4[3]) + clamp((int)(y), 0, (___str_T4[1] - 1))*(___str_T4[4]) + clamp((int)(1), 0, (___str_T4[2] - 1))*(___str_T4[5]))] = __v.y; T4[(clamp((int)(x), 0, (___str_T4[0] - 1))*(___str_T4[3]) + clamp((int)(y), 0, (___str_T4[1] - 1))*(___str_T4[4]) + clamp((int)(2), 0, (___str_T4[2] - 1))*(___str_T4[5]))] = __v.z; }
+}
\ No newline at end of file
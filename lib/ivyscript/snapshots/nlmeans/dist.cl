@@ -0,0 +1,28 @@
+constant int SX = 7;
+constant int SY = 7;
+constant float eps = 0.0000010f;
+
+
+
+kernel void dist (
+	global float *I, global int *___str_I,
+	global float *T1, global int *___str_T1,
+	global float *p1, global int *___str_p1,
+	global float *p2, global int *___str_p2,
+	global float *p5, global int *___str_p5,
+	int ox,
+	int oy
+) {
+const int x = get_global_id(0);
+const int y = get_global_id(1);
+float3 i1 = (float3)( I[(clamp((int)(x), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(0), 0, (___str_I[2] - 1))*(___str_I[5]))], I[(clamp((int)(x), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(1), 0, (___str_I[2] - 1))*(___str_I[5]))], I[(clamp((int)(x), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(2), 0, (___str_I[2] - 1))*(___str_I[5]))] );
+float3 i2 = (float3)( I[(clamp((int)(x + ox), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y + oy), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(0), 0, (___str_I[2] - 1))*(___str_I[5]))], I[(clamp((int)(x + ox), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y + oy), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(1), 0, (___str_I[2] - 1))*(___str_I[5]))], I[(clamp((int)(x + ox), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y + oy), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(2), 0, (___str_I[2] - 1))*(___str_I[5]))] );
+// photon well depth for proper scaling of the poisson noise
+float depth = 25000.0000000f;
+// anscombe transform from poisson noise to unit standard deviation noise
+i1 = 2.0000000f*sqrt(i1*depth + p5[clamp((int)(0), 0, (___str_p5[2] - 1))]*pown(200.0000000f, 2));
+i2 = 2.0000000f*sqrt(i2*depth + p5[clamp((int)(0), 0, (___str_p5[2] - 1))]*pown(200.0000000f, 2));
+float3 o = pown(fabs(i1 - i2), 2);
+if (x>=0 && x<___str_T1[0] && y>=0 && y<___str_T1[1])  T1[(clamp((int)(x), 0, (___str_T1[0] - 1))*(___str_T1[3]) + clamp((int)(y), 0, (___str_T1[1] - 1))*(___str_T1[4]) + clamp((int)(0), 0, (___str_T1[2] - 1))*(___str_T1[5]))] = (o.x + o.y + o.z);
+// r/g/b weight?
+}
\ No newline at end of file
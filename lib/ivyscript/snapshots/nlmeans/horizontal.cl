@@ -0,0 +1,19 @@
+constant int SX = 7;
+constant int SY = 7;
+constant float eps = 0.0000010f;
+
+
+
+kernel void horizontal (
+	global float *T1, global int *___str_T1,
+	global float *T2, global int *___str_T2,
+	global float *k, global int *___str_k
+) {
+const int x = get_global_id(0);
+const int y = get_global_id(1);
+float sum = 0.0000000f;
+for (int i = (-SX); i<=SX; i += 1) {
+sum = sum + T1[(clamp((int)(x + i), 0, (___str_T1[0] - 1))*(___str_T1[3]) + clamp((int)(y), 0, (___str_T1[1] - 1))*(___str_T1[4]) + clamp((int)(0), 0, (___str_T1[2] - 1))*(___str_T1[5]))]*k[clamp((int)(i + 7), 0, (___str_k[0] * ___str_k[1] * ___str_k[2] - 1))];
+}
+if (x>=0 && x<___str_T2[0] && y>=0 && y<___str_T2[1])  T2[(clamp((int)(x), 0, (___str_T2[0] - 1))*(___str_T2[3]) + clamp((int)(y), 0, (___str_T2[1] - 1))*(___str_T2[4]) + clamp((int)(0), 0, (___str_T2[2] - 1))*(___str_T2[5]))] = sum;
+}
\ No newline at end of file
@@ -0,0 +1,17 @@
+constant int SX = 7;
+constant int SY = 7;
+constant float eps = 0.0000010f;
+
+
+
+kernel void init (
+	global float *T3, global int *___str_T3,
+	global float *T4, global int *___str_T4,
+	global float *W, global int *___str_W
+) {
+const int x = get_global_id(0);
+const int y = get_global_id(1);
+if (x>=0 && x<___str_T3[0] && y>=0 && y<___str_T3[1])  { float3 __v = (float3)(0.0000000f); T3[(clamp((int)(x), 0, (___str_T3[0] - 1))*(___str_T3[3]) + clamp((int)(y), 0, (___str_T3[1] - 1))*(___str_T3[4]) + clamp((int)(0), 0, (___str_T3[2] - 1))*(___str_T3[5]))] = __v.x; T3[(clamp((int)(x), 0, (___str_T3[0] - 1))*(___str_T3[3]) + clamp((int)(y), 0, (___str_T3[1] - 1))*(___str_T3[4]) + clamp((int)(1), 0, (___str_T3[2] - 1))*(___str_T3[5]))] = __v.y; T3[(clamp((int)(x), 0, (___str_T3[0] - 1))*(___str_T3[3]) + clamp((int)(y), 0, (___str_T3[1] - 1))*(___str_T3[4]) + clamp((int)(2), 0, (___str_T3[2] - 1))*(___str_T3[5]))] = __v.z; }
+if (x>=0 && x<___str_T4[0] && y>=0 && y<___str_T4[1])  { float3 __v = (float3)(0.0000000f); T4[(clamp((int)(x), 0, (___str_T4[0] - 1))*(___str_T4[3]) + clamp((int)(y), 0, (___str_T4[1] - 1))*(___str_T4[4]) + clamp((int)(0), 0, (___str_T4[2] - 1))*(___str_T4[5]))] = __v.x; T4[(clamp((int)(x), 0, (___str_T4[0] - 1))*(___str_T4[3]) + clamp((int)(y), 0, (___str_T4[1] - 1))*(___str_T4[4]) + clamp((int)(1), 0, (___str_T4[2] - 1))*(___str_T4[5]))] = __v.y; T4[(clamp((int)(x), 0, (___str_T4[0] - 1))*(___str_T4[3]) + clamp((int)(y), 0, (___str_T4[1] - 1))*(___str_T4[4]) + clamp((int)(2), 0, (___str_T4[2] - 1))*(___str_T4[5]))] = __v.z; }
+if (x>=0 && x<___str_W[0] && y>=0 && y<___str_W[1])  { float3 __v = (float3)(eps); W[(clamp((int)(x), 0, (___str_W[0] - 1))*(___str_W[3]) + clamp((int)(y), 0, (___str_W[1] - 1))*(___str_W[4]) + clamp((int)(0), 0, (___str_W[2] - 1))*(___str_W[5]))] = __v.x; W[(clamp((int)(x), 0, (___str_W[0] - 1))*(___str_W[3]) + clamp((int)(y), 0, (___str_W[1] - 1))*(___str_W[4]) + clamp((int)(1), 0, (___str_W[2] - 1))*(___str_W[5]))] = __v.y; W[(clamp((int)(x), 0, (___str_W[0] - 1))*(___str_W[3]) + clamp((int)(y), 0, (___str_W[1] - 1))*(___str_W[4]) + clamp((int)(2), 0, (___str_W[2] - 1))*(___str_W[5]))] = __v.z; }
+}
\ No newline at end of file
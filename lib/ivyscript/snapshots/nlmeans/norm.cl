@@ -0,0 +1,28 @@
+constant int SX = 7;
+constant int SY = 7;
+constant float eps = 0.0000010f;
+
+
+
+kernel void norm (
+	global float *I, global int *___str_I,
+	global float *T3, global int *___str_T3,
+	global float *T4, global int *___str_T4,
+	global float *W, global int *___str_W,
+	global float *O, global int *___str_O,
+	global float *p3, global int *___str_p3
+) {
+const int x = get_global_id(0);
+const int y = get_global_id(1);
+float3 i = (float3)( I[(clamp((int)(x), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(0), 0, (___str_I[2] - 1))*(___str_I[5]))], I[(clamp((int)(x), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(1), 0, (___str_I[2] - 1))*(___str_I[5]))], I[(clamp((int)(x), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(2), 0, (___str_I[2] - 1))*(___str_I[5]))] );
+float3 j = i;
+i.x = i.x/max(i.y, eps);
+i.z = i.z/max(i.y, eps);
+float3 w = (float3)( W[(clamp((int)(x), 0, (___str_W[0] - 1))*(___str_W[3]) + clamp((int)(y), 0, (___str_W[1] - 1))*(___str_W[4]) + clamp((int)(0), 0, (___str_W[2] - 1))*(___str_W[5]))], W[(clamp((int)(x), 0, (___str_W[0] - 1))*(___str_W[3]) + clamp((int)(y), 0, (___str_W[1] - 1))*(___str_W[4]) + clamp((int)(1), 0, (___str_W[2] - 1))*(___str_W[5]))], W[(clamp((int)(x), 0, (___str_W[0] - 1))*(___str_W[3]) + clamp((int)(y), 0, (___str_W[1] - 1))*(___str_W[4]) + clamp((int)(2), 0, (___str_W[2] - 1))*(___str_W[5]))] );
+float3 o = (w*i + (float3)( T3[(clamp((int)(x), 0, (___str_T3[0] - 1))*(___str_T3[3]) + clamp((int)(y), 0, (___str_T3[1] - 1))*(___str_T3[4]) + clamp((int)(0), 0, (___str_T3[2] - 1))*(___str_T3[5]))], T3[(clamp((int)(x), 0, (___str_T3[0] - 1))*(___str_T3[3]) + clamp((int)(y), 0, (___str_T3[1] - 1))*(___str_T3[4]) + clamp((int)(1), 0, (___str_T3[2] - 1))*(___str_T3[5]))], T3[(clamp((int)(x), 0, (___str_T3[0] - 1))*(___str_T3[3]) + clamp((int)(y), 0, (___str_T3[1] - 1))*(___str_T3[4]) + clamp((int)(2), 0, (___str_T3[2] - 1))*(___str_T3[5]))] ))/(w + (float3)( T4[(clamp((int)(x), 0, (___str_T4[0] - 1))*(___str_T4[3]) + clamp((int)(y), 0, (___str_T4[1] - 1))*(___str_T4[4]) + clamp((int)(0), 0, (___str_T4[2] - 1))*(___str_T4[5]))], T4[(clamp((int)(x), 0, (___str_T4[0] - 1))*(___str_T4[3]) + clamp((int)(y), 0, (___str_T4[1] - 1))*(___str_T4[4]) + clamp((int)(1), 0, (___str_T4[2] - 1))*(___str_T4[5]))], T4[(clamp((int)(x), 0, (___str_T4[0] - 1))*(___str_T4[3]) + clamp((int)(y), 0, (___str_T4[1] - 1))*(___str_T4[4]) + clamp((int)(2), 0, (___str_T4[2] - 1))*(___str_T4[5]))] ));
+float f = p3[(clamp((int)(x), 0, (___str_p3[0] - 1))*(___str_p3[3]) + clamp((int)(y), 0, (___str_p3[1] - 1))*(___str_p3[4]) + clamp((int)(0), 0, (___str_p3[2] - 1))*(___str_p3[5]))];
+o.x = o.x*o.y;
+o.z = o.z*o.y;
+o = j*(1 - f) + o*f;
+if (x>=0 && x<___str_O[0] && y>=0 && y<___str_O[1])  { float3 __v = o; O[(clamp((int)(x), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(0), 0, (___str_O[2] - 1))*(___str_O[5]))] = __v.x; O[(clamp((int)(x), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(1), 0, (___str_O[2] - 1))*(___str_O[5]))] = __v.y; O[(clamp((int)(x), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(2), 0, (___str_O[2] - 1))*(___str_O[5]))] = __v.z; }
+}
\ No newline at end of file
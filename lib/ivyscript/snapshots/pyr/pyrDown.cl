@@ -0,0 +1,33 @@
+constant float k [5] = {0.0625000f, 0.2500000f, 0.3750000f, 0.2500000f, 0.0625000f};
+constant float kk [5][5] = {{0.0039062f, 0.0156250f, 0.0234375f, 0.0156250f, 0.0039062f}, {0.0156250f, 0.0625000f, 0.0937500f, 0.0625000f, 0.0156250f}, {0.0234375f, 0.0937500f, 0.1406250f, 0.0937500f, 0.0234375f}, {0.0156250f, 0.0625000f, 0.0937500f, 0.0625000f, 0.0156250f}, {0.0039062f, 0.0156250f, 0.0234375f, 0.0156250f, 0.0039062f}};
+
+
+
+kernel void pyrDown (
+	global float *I, global int *___str_I,
+	global float *G, global int *___str_G
+) {
+const int x = get_global_id(0);
+const int y = get_global_id(1);
+const int z = get_global_id(2);
+float h [5][5];
+for (int i = 0; i<=4; i += 1) {
+for (int j = 0; j<=4; j += 1) {
+h[i][j] = I[(clamp((int)(x*2 + i - 2), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y*2 + j - 2), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(z), 0, (___str_I[2] - 1))*(___str_I[5]))];
+}
+}
+float v [5];
+for (int i = 0; i<=4; i += 1) {
+v[i] = 0;
+}
+for (int i = 0; i<=4; i += 1) {
+for (int j = 0; j<=4; j += 1) {
+v[i] = v[i] + h[i][j]*k[j];
+}
+}
+float g = 0.0000000f;
+for (int i = 0; i<=4; i += 1) {
+g = g + v[i]*k[i];
+}
+if (x>=0 && x<___str_G[0] && y>=0 && y<___str_G[1] && z>=0 && z<___str_G[2])  G[(clamp((int)(x), 0, (___str_G[0] - 1))*(___str_G[3]) + clamp((int)(y), 0, (___str_G[1] - 1))*(___str_G[4]) + clamp((int)(z), 0, (___str_G[2] - 1))*(___str_G[5]))] = g;
+}
\ No newline at end of file
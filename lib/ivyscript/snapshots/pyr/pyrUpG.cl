@@ -0,0 +1,25 @@
+constant float k [5] = {0.0625000f, 0.2500000f, 0.3750000f, 0.2500000f, 0.0625000f};
+constant float kk [5][5] = {{0.0039062f, 0.0156250f, 0.0234375f, 0.0156250f, 0.0039062f}, {0.0156250f, 0.0625000f, 0.0937500f, 0.0625000f, 0.0156250f}, {0.0234375f, 0.0937500f, 0.1406250f, 0.0937500f, 0.0234375f}, {0.0156250f, 0.0625000f, 0.0937500f, 0.0625000f, 0.0156250f}, {0.0039062f, 0.0156250f, 0.0234375f, 0.0156250f, 0.0039062f}};
+
+
+
+kernel void pyrUpG (
+	global float *L, global int *___str_L,
+	global float *G, global int *___str_G,
+	global float *O, global int *___str_O,
+	global float *f, global int *___str_f
+) {
+const int x = get_global_id(0);
+const int y = get_global_id(1);
+const int z = get_global_id(2);
+float g [3][3];
+for (int i = 0; i<=2; i += 1) {
+for (int j = 0; j<=2; j += 1) {
+g[i][j] = G[(clamp((int)(x + i - 1), 0, (___str_G[0] - 1))*(___str_G[3]) + clamp((int)(y + j - 1), 0, (___str_G[1] - 1))*(___str_G[4]) + clamp((int)(z), 0, (___str_G[2] - 1))*(___str_G[5]))];
+}
+}
+if (x*2>=0 && x*2<___str_O[0] && y*2>=0 && y*2<___str_O[1] && z>=0 && z<___str_O[2])  O[(clamp((int)(x*2), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y*2), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(z), 0, (___str_O[2] - 1))*(___str_O[5]))] = (g[0][0]*kk[0][0] + g[0][1]*kk[0][2] + g[0][2]*kk[0][4] + g[1][0]*kk[2][0] + g[1][1]*kk[2][2] + g[1][2]*kk[2][4] + g[2][0]*kk[4][0] + g[2][1]*kk[4][2] + g[2][2]*kk[4][4])*4.0000000f - L[(clamp((int)(x*2), 0, (___str_L[0] - 1))*(___str_L[3]) + clamp((int)(y*2), 0, (___str_L[1] - 1))*(___str_L[4]) + clamp((int)(z), 0, (___str_L[2] - 1))*(___str_L[5]))]*f[(clamp((int)(x*2), 0, (___str_f[0] - 1))*(___str_f[3]) + clamp((int)(y*2), 0, (___str_f[1] - 1))*(___str_f[4]) + clamp((int)(z), 0, (___str_f[2] - 1))*(___str_f[5]))];
+if (x*2 + 1>=0 && x*2 + 1<___str_O[0] && y*2 + 1>=0 && y*2 + 1<___str_O[1] && z>=0 && z<___str_O[2])  O[(clamp((int)(x*2 + 1), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y*2 + 1), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(z), 0, (___str_O[2] - 1))*(___str_O[5]))] = (g[1][1]*kk[1][1] + g[1][2]*kk[1][3] + g[2][1]*kk[3][1] + g[2][2]*kk[3][3])*4.0000000f - L[(clamp((int)(x*2 + 1), 0, (___str_L[0] - 1))*(___str_L[3]) + clamp((int)(y*2 + 1), 0, (___str_L[1] - 1))*(___str_L[4]) + clamp((int)(z), 0, (___str_L[2] - 1))*(___str_L[5]))]*f[(clamp((int)(x*2 + 1), 0, (___str_f[0] - 1))*(___str_f[3]) + clamp((int)(y*2 + 1), 0, (___str_f[1] - 1))*(___str_f[4]) + clamp((int)(z), 0, (___str_f[2] - 1))*(___str_f[5]))];
+if (x*2 + 1>=0 && x*2 + 1<___str_O[0] && y*2>=0 && y*2<___str_O[1] && z>=0 && z<___str_O[2])  O[(clamp((int)(x*2 + 1), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y*2), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(z), 0, (___str_O[2] - 1))*(___str_O[5]))] = (g[1][0]*kk[1][0] + g[1][1]*kk[1][2] + g[1][2]*kk[1][4] + g[2][0]*kk[3][0] + g[2][1]*kk[3][2] + g[2][2]*kk[3][4])*4.0000000f - L[(clamp((int)(x*2 + 1), 0, (___str_L[0] - 1))*(___str_L[3]) + clamp((int)(y*2), 0, (___str_L[1] - 1))*(___str_L[4]) + clamp((int)(z), 0, (___str_L[2] - 1))*(___str_L[5]))]*f[(clamp((int)(x*2 + 1), 0, (___str_f[0] - 1))*(___str_f[3]) + clamp((int)(y*2), 0, (___str_f[1] - 1))*(___str_f[4]) + clamp((int)(z), 0, (___str_f[2] - 1))*(___str_f[5]))];
+if (x*2>=0 && x*2<___str_O[0] && y*2 + 1>=0 && y*2 + 1<___str_O[1] && z>=0 && z<___str_O[2])  O[(clamp((int)(x*2), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y*2 + 1), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(z), 0, (___str_O[2] - 1))*(___str_O[5]))] = (g[0][1]*kk[0][1] + g[0][2]*kk[0][3] + g[1][1]*kk[2][1] + g[1][2]*kk[2][3] + g[2][1]*kk[4][1] + g[2][2]*kk[4][3])*4.0000000f - L[(clamp((int)(x*2), 0, (___str_L[0] - 1))*(___str_L[3]) + clamp((int)(y*2 + 1), 0, (___str_L[1] - 1))*(___str_L[4]) + clamp((int)(z), 0, (___str_L[2] - 1))*(___str_L[5]))]*f[(clamp((int)(x*2), 0, (___str_f[0] - 1))*(___str_f[3]) + clamp((int)(y*2 + 1), 0, (___str_f[1] - 1))*(___str_f[4]) + clamp((int)(z), 0, (___str_f[2] - 1))*(___str_f[5]))];
+}
\ No newline at end of file
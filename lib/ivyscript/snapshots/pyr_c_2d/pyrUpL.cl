@@ -0,0 +1,87 @@
+#define E (216.0f/24389.0f)
+#define K (24389.0f/27.0f)
+
+inline float _lab(float v) {
+  if (v>E) {
+    return cbrt(v);
+  } else {
+    return (K*v + 16.0f)/116.0f;
+  }
+}
+
+inline float _Y_L(float i) {
+	return 1.16f*_lab(i) - 0.16f;
+}
+
+inline float YtoL(float i) { return _Y_L(i); }
+
+#undef E
+#undef K
+
+constant float k [5] = {0.0625000f, 0.2500000f, 0.3750000f, 0.2500000f, 0.0625000f};
+constant float kk [5][5] = {{0.0039062f, 0.0156250f, 0.0234375f, 0.0156250f, 0.0039062f}, {0.0156250f, 0.0625000f, 0.0937500f, 0.0625000f, 0.0156250f}, {0.0234375f, 0.0937500f, 0.1406250f, 0.0937500f, 0.0234375f}, {0.0156250f, 0.0625000f, 0.0937500f, 0.0625000f, 0.0156250f}, {0.0039062f, 0.0156250f, 0.0234375f, 0.0156250f, 0.0039062f}};
+
+float ___1_FA2_3_3_0_0___up00 (
+	float g[3][3]
+);
+
+float ___1_FA2_3_3_0_0___up11 (
+	float g[3][3]
+);
+
+float ___1_FA2_3_3_0_0___up10 (
+	float g[3][3]
+);
+
+float ___1_FA2_3_3_0_0___up01 (
+	float g[3][3]
+);
+
+
+float ___1_FA2_3_3_0_0___up00 (
+	float g[3][3]
+) {
+return (g[0][0]*kk[0][0] + g[0][1]*kk[0][2] + g[0][2]*kk[0][4] + g[1][0]*kk[2][0] + g[1][1]*kk[2][2] + g[1][2]*kk[2][4] + g[2][0]*kk[4][0] + g[2][1]*kk[4][2] + g[2][2]*kk[4][4])*4.0000000f;
+}
+
+float ___1_FA2_3_3_0_0___up11 (
+	float g[3][3]
+) {
+return (g[1][1]*kk[1][1] + g[1][2]*kk[1][3] + g[2][1]*kk[3][1] + g[2][2]*kk[3][3])*4.0000000f;
+}
+
+float ___1_FA2_3_3_0_0___up10 (
+	float g[3][3]
+) {
+return (g[1][0]*kk[1][0] + g[1][1]*kk[1][2] + g[1][2]*kk[1][4] + g[2][0]*kk[3][0] + g[2][1]*kk[3][2] + g[2][2]*kk[3][4])*4.0000000f;
+}
+
+float ___1_FA2_3_3_0_0___up01 (
+	float g[3][3]
+) {
+return (g[0][1]*kk[0][1] + g[0][2]*kk[0][3] + g[1][1]*kk[2][1] + g[1][2]*kk[2][3] + g[2][1]*kk[4][1] + g[2][2]*kk[4][3])*4.0000000f;
+}
+
+
+kernel void pyrUpL (
+	global float *I, global int *___str_I,
+	global float *G, global int *___str_G,
+	global float *L, global int *___str_L
+) {
+const int x = get_global_id(0);
+const int y = get_global_id(1);
+int z = 0;
+if (true) {
+z = 1;
+}
+float g [3][3];
+for (int i = 0; i<=2; i += 1) {
+for (int j = 0; j<=2; j += 1) {
+g[i][j] = G[(clamp((int)(x + i - 1), 0, (___str_G[0] - 1))*(___str_G[3]) + clamp((int)(y + j - 1), 0, (___str_G[1] - 1))*(___str_G[4]) + clamp((int)(0), 0, (___str_G[2] - 1))*(___str_G[5]))];
+}
+}
+if (x*2>=0 && x*2<___str_L[0] && y*2>=0 && y*2<___str_L[1])  L[(clamp((int)(x*2), 0, (___str_L[0] - 1))*(___str_L[3]) + clamp((int)(y*2), 0, (___str_L[1] - 1))*(___str_L[4]) + clamp((int)(0), 0, (___str_L[2] - 1))*(___str_L[5]))] = YtoL(___1_FA2_3_3_0_0___up00(g)) - YtoL(I[(clamp((int)(x*2), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y*2), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(z), 0, (___str_I[2] - 1))*(___str_I[5]))]);
+if (x*2 + 1>=0 && x*2 + 1<___str_L[0] && y*2 + 1>=0 && y*2 + 1<___str_L[1])  L[(clamp((int)(x*2 + 1), 0, (___str_L[0] - 1))*(___str_L[3]) + clamp((int)(y*2 + 1), 0, (___str_L[1] - 1))*(___str_L[4]) + clamp((int)(0), 0, (___str_L[2] - 1))*(___str_L[5]))] = YtoL(___1_FA2_3_3_0_0___up11(g)) - YtoL(I[(clamp((int)(x*2 + 1), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y*2 + 1), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(z), 0, (___str_I[2] - 1))*(___str_I[5]))]);
+if (x*2 + 1>=0 && x*2 + 1<___str_L[0] && y*2>=0 && y*2<___str_L[1])  L[(clamp((int)(x*2 + 1), 0, (___str_L[0] - 1))*(___str_L[3]) + clamp((int)(y*2), 0, (___str_L[1] - 1))*(___str_L[4]) + clamp((int)(0), 0, (___str_L[2] - 1))*(___str_L[5]))] = YtoL(___1_FA2_3_3_0_0___up10(g)) - YtoL(I[(clamp((int)(x*2 + 1), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y*2), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(z), 0, (___str_I[2] - 1))*(___str_I[5]))]);
+if (x*2>=0 && x*2<___str_L[0] && y*2 + 1>=0 && y*2 + 1<___str_L[1])  L[(clamp((int)(x*2), 0, (___str_L[0] - 1))*(___str_L[3]) + clamp((int)(y*2 + 1), 0, (___str_L[1] - 1))*(___str_L[4]) + clamp((int)(0), 0, (___str_L[2] - 1))*(___str_L[5]))] = YtoL(___1_FA2_3_3_0_0___up01(g)) - YtoL(I[(clamp((int)(x*2), 0, (___str_I[0] - 1))*(___str_I[3]) + clamp((int)(y*2 + 1), 0, (___str_I[1] - 1))*(___str_I[4]) + clamp((int)(z), 0, (___str_I[2] - 1))*(___str_I[5]))]);
+}
\ No newline at end of file
@@ -0,0 +1,118 @@
+#define wp_x 0.95047f
+#define wp_y 1.0f
+#define wp_z 1.08883f
+#define E (216.0f/24389.0f)
+#define K (24389.0f/27.0f)
+
+inline float _lab(float v) {
+  if (v>E) {
+    return cbrt(v);
+  } else {
+    return (K*v + 16.0f)/116.0f;
+  }
+}
+
+inline float _xyz(float V) {
+  if (pown(V, 3)>E) {
+    return pown(V, 3);
+  } else {
+    return (116.0f*V - 16.0f)/K;
+  }
+}
+
+inline float3 _XYZ_LAB(float3 i) {
+	float3 o;
+	i.x = _lab(i.x/wp_x);
+	i.y = _lab(i.y/wp_y);
+	i.z = _lab(i.z/wp_z);
+	o.x = 1.16f*i.y - 0.16f;
+	o.y = 5.0f*(i.x - i.y);
+	o.z = 2.0f*(i.y - i.z);
+	return o;
+}
+
+inline float3 _LAB_XYZ(float3 i) {
+	float3 o;
+	o.y = (i.x + 0.16f)/1.16f;
+	o.x = i.y*0.2f + o.y;
+	o.z = o.y - i.z*0.5f;
+	o.x = wp_x*_xyz(o.x);
+	o.y = wp_y*_xyz(o.y);
+	o.z = wp_z*_xyz(o.z);
+	return o;
+}
+
+inline float3 XYZtoLAB(float3 i) { return _XYZ_LAB(i); }
+
+inline float3 LABtoXYZ(float3 i) { return _LAB_XYZ(i); }
+
+#undef wp_x
+#undef wp_y
+#undef wp_z
+#undef E
+#undef K
+
+constant float k [5] = {0.0625000f, 0.2500000f, 0.3750000f, 0.2500000f, 0.0625000f};
+constant float kk [5][5] = {{0.0039062f, 0.0156250f, 0.0234375f, 0.0156250f, 0.0039062f}, {0.0156250f, 0.0625000f, 0.0937500f, 0.0625000f, 0.0156250f}, {0.0234375f, 0.0937500f, 0.1406250f, 0.0937500f, 0.0234375f}, {0.0156250f, 0.0625000f, 0.0937500f, 0.0625000f, 0.0156250f}, {0.0039062f, 0.0156250f, 0.0234375f, 0.0156250f, 0.0039062f}};
+
+float3 ___1_VA2_3_3_0_0___up00 (
+	float3 g[3][3]
+);
+
+float3 ___1_VA2_3_3_0_0___up11 (
+	float3 g[3][3]
+);
+
+float3 ___1_VA2_3_3_0_0___up10 (
+	float3 g[3][3]
+);
+
+float3 ___1_VA2_3_3_0_0___up01 (
+	float3 g[3][3]
+);
+
+
+float3 ___1_VA2_3_3_0_0___up00 (
+	float3 g[3][3]
+) {
+return (g[0][0]*kk[0][0] + g[0][1]*kk[0][2] + g[0][2]*kk[0][4] + g[1][0]*kk[2][0] + g[1][1]*kk[2][2] + g[1][2]*kk[2][4] + g[2][0]*kk[4][0] + g[2][1]*kk[4][2] + g[2][2]*kk[4][4])*4.0000000f;
+}
+
+float3 ___1_VA2_3_3_0_0___up11 (
+	float3 g[3][3]
+) {
+return (g[1][1]*kk[1][1] + g[1][2]*kk[1][3] + g[2][1]*kk[3][1] + g[2][2]*kk[3][3])*4.0000000f;
+}
+
+float3 ___1_VA2_3_3_0_0___up10 (
+	float3 g[3][3]
+) {
+return (g[1][0]*kk[1][0] + g[1][1]*kk[1][2] + g[1][2]*kk[1][4] + g[2][0]*kk[3][0] + g[2][1]*kk[3][2] + g[2][2]*kk[3][4])*4.0000000f;
+}
+
+float3 ___1_VA2_3_3_0_0___up01 (
+	float3 g[3][3]
+) {
+return (g[0][1]*kk[0][1] + g[0][2]*kk[0][3] + g[1][1]*kk[2][1] + g[1][2]*kk[2][3] + g[2][1]*kk[4][1] + g[2][2]*kk[4][3])*4.0000000f;
+}
+
+
+kernel void pyrUpG (
+	global float *L, global int *___str_L,
+	global float *G, global int *___str_G,
+	global float *O, global int *___str_O,
+	global float *f, global int *___str_f
+) {
+const int x = get_global_id(0);
+const int y = get_global_id(1);
+float3 g [3][3];
+for (int i = 0; i<=2; i += 1) {
+for (int j = 0; j<=2; j += 1) {
+g[i][j] = (float3)( G[(clamp((int)(x + i - 1), 0, (___str_G[0] - 1))*(___str_G[3]) + clamp((int)(y + j - 1), 0, (___str_G[1] - 1))*(___str_G[4]) + clamp((int)(0), 0, (___str_G[2] - 1))*(___str_G[5]))], G[(clamp((int)(x + i - 1), 0, (___str_G[0] - 1))*(___str_G[3]) + clamp((int)(y + j - 1), 0, (___str_G[1] - 1))*(___str_G[4]) + clamp((int)(1), 0, (___str_G[2] - 1))*(___str_G[5]))], G[(clamp((int)(x + i - 1), 0, (___str_G[0] - 1))*(___str_G[3]) + clamp((int)(y + j - 1), 0, (___str_G[1] - 1))*(___str_G[4]) + clamp((int)(2), 0, (___str_G[2] - 1))*(___str_G[5]))] );
+}
+}
+if (x*2>=0 && x*2<___str_O[0] && y*2>=0 && y*2<___str_O[1])  { float3 __v = LABtoXYZ(XYZtoLAB(___1_VA2_3_3_0_0___up00(g)) - (float3)( L[(clamp((int)(x*2), 0, (___str_L[0] - 1))*(___str_L[3]) + clamp((int)(y*2), 0, (___str_L[1] - 1))*(___str_L[4]) + clamp((int)(0), 0, (___str_L[2] - 1))*(___str_L[5]))], L[(clamp((int)(x*2), 0, (___str_L[0] - 1))*(___str_L[3]) + clamp((int)(y*2), 0, (___str_L[1] - 1))*(___str_L[4]) + clamp((int)(1), 0, (___str_L[2] - 1))*(___str_L[5]))], L[(clamp((int)(x*2), 0, (___str_L[0] - 1))*(___str_L[3]) + clamp((int)(y*2), 0, (___str_L[1] - 1))*(___str_L[4]) + clamp((int)(2), 0, (___str_L[2] - 1))*(___str_L[5]))] )*f[(clamp((int)(x*2), 0, (___str_f[0] - 1))*(___str_f[3]) + clamp((int)(y*2), 0, (___str_f[1] - 1))*(___str_f[4]) + clamp((int)(0), 0, (___str_f[2] - 1))*(___str_f[5]))]); O[(clamp((int)(x*2), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y*2), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(0), 0, (___str_O[2] - 1))*(___str_O[5]))] = __v.x; O[(clamp((int)(x*2), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y*2), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(1), 0, (___str_O[2] - 1))*(___str_O[5]))] = __v.y; O[(clamp((int)(x*2), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y*2), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(2), 0, (___str_O[2] - 1))*(___str_O[5]))] = __v.z; }
+if (x*2 + 1>=0 && x*2 + 1<___str_O[0] && y*2 + 1>=0 && y*2 + 1<___str_O[1])  { float3 __v = LABtoXYZ(XYZtoLAB(___1_VA2_3_3_0_0___up11(g)) - (float3)( L[(clamp((int)(x*2 + 1), 0, (___str_L[0] - 1))*(___str_L[3]) + clamp((int)(y*2 + 1), 0, (___str_L[1] - 1))*(___str_L[4]) + clamp((int)(0), 0, (___str_L[2] - 1))*(___str_L[5]))], L[(clamp((int)(x*2 + 1), 0, (___str_L[0] - 1))*(___str_L[3]) + clamp((int)(y*2 + 1), 0, (___str_L[1] - 1))*(___str_L[4]) + clamp((int)(1), 0, (___str_L[2] - 1))*(___str_L[5]))], L[(clamp((int)(x*2 + 1), 0, (___str_L[0] - 1))*(___str_L[3]) + clamp((int)(y*2 + 1), 0, (___str_L[1] - 1))*(___str_L[4]) + clamp((int)(2), 0, (___str_L[2] - 1))*(___str_L[5]))] )*f[(clamp((int)(x*2 + 1), 0, (___str_f[0] - 1))*(___str_f[3]) + clamp((int)(y*2 + 1), 0, (___str_f[1] - 1))*(___str_f[4]) + clamp((int)(0), 0, (___str_f[2] - 1))*(___str_f[5]))]); O[(clamp((int)(x*2 + 1), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y*2 + 1), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(0), 0, (___str_O[2] - 1))*(___str_O[5]))] = __v.x; O[(clamp((int)(x*2 + 1), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y*2 + 1), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(1), 0, (___str_O[2] - 1))*(___str_O[5]))] = __v.y; O[(clamp((int)(x*2 + 1), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y*2 + 1), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(2), 0, (___str_O[2] - 1))*(___str_O[5]))] = __v.z; }
+if (x*2 + 1>=0 && x*2 + 1<___str_O[0] && y*2>=0 && y*2<___str_O[1])  { float3 __v = LABtoXYZ(XYZtoLAB(___1_VA2_3_3_0_0___up10(g)) - (float3)( L[(clamp((int)(x*2 + 1), 0, (___str_L[0] - 1))*(___str_L[3]) + clamp((int)(y*2), 0, (___str_L[1] - 1))*(___str_L[4]) + clamp((int)(0), 0, (___str_L[2] - 1))*(___str_L[5]))], L[(clamp((int)(x*2 + 1), 0, (___str_L[0] - 1))*(___str_L[3]) + clamp((int)(y*2), 0, (___str_L[1] - 1))*(___str_L[4]) + clamp((int)(1), 0, (___str_L[2] - 1))*(___str_L[5]))], L[(clamp((int)(x*2 + 1), 0, (___str_L[0] - 1))*(___str_L[3]) + clamp((int)(y*2), 0, (___str_L[1] - 1))*(___str_L[4]) + clamp((int)(2), 0, (___str_L[2] - 1))*(___str_L[5]))] )*f[(clamp((int)(x*2 + 1), 0, (___str_f[0] - 1))*(___str_f[3]) + clamp((int)(y*2), 0, (___str_f[1] - 1))*(___str_f[4]) + clamp((int)(0), 0, (___str_f[2] - 1))*(___str_f[5]))]); O[(clamp((int)(x*2 + 1), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y*2), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(0), 0, (___str_O[2] - 1))*(___str_O[5]))] = __v.x; O[(clamp((int)(x*2 + 1), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y*2), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(1), 0, (___str_O[2] - 1))*(___str_O[5]))] = __v.y; O[(clamp((int)(x*2 + 1), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y*2), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(2), 0, (___str_O[2] - 1))*(___str_O[5]))] = __v.z; }
+if (x*2>=0 && x*2<___str_O[0] && y*2 + 1>=0 && y*2 + 1<___str_O[1])  { float3 __v = LABtoXYZ(XYZtoLAB(___1_VA2_3_3_0_0___up01(g)) - (float3)( L[(clamp((int)(x*2), 0, (___str_L[0] - 1))*(___str_L[3]) + clamp((int)(y*2 + 1), 0, (___str_L[1] - 1))*(___str_L[4]) + clamp((int)(0), 0, (___str_L[2] - 1))*(___str_L[5]))], L[(clamp((int)(x*2), 0, (___str_L[0] - 1))*(___str_L[3]) + clamp((int)(y*2 + 1), 0, (___str_L[1] - 1))*(___str_L[4]) + clamp((int)(1), 0, (___str_L[2] - 1))*(___str_L[5]))], L[(clamp((int)(x*2), 0, (___str_L[0] - 1))*(___str_L[3]) + clamp((int)(y*2 + 1), 0, (___str_L[1] - 1))*(___str_L[4]) + clamp((int)(2), 0, (___str_L[2] - 1))*(___str_L[5]))] )*f[(clamp((int)(x*2), 0, (___str_f[0] - 1))*(___str_f[3]) + clamp((int)(y*2 + 1), 0, (___str_f[1] - 1))*(___str_f[4]) + clamp((int)(0), 0, (___str_f[2] - 1))*(___str_f[5]))]); O[(clamp((int)(x*2), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y*2 + 1), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(0), 0, (___str_O[2] - 1))*(___str_O[5]))] = __v.x; O[(clamp((int)(x*2), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y*2 + 1), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(1), 0, (___str_O[2] - 1))*(___str_O[5]))] = __v.y; O[(clamp((int)(x*2), 0, (___str_O[0] - 1))*(___str_O[3]) + clamp((int)(y*2 + 1), 0, (___str_O[1] - 1))*(___str_O[4]) + clamp((int)(2), 0, (___str_O[2] - 1))*(___str_O[5]))] = __v.z; }
+}
\ No newline at end of file
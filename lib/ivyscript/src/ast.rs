@@ -36,9 +36,10 @@ pub enum Index {
     Array2D(Expr, Expr),
     Array3D(Expr, Expr, Expr),
     Array4D(Expr, Expr, Expr, Expr),
+    Field(String), // struct field
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub enum Prop {
     Int,
     Idx,
@@ -75,11 +76,83 @@ impl std::fmt::Display for ColorSpace {
     }
 }
 
-#[derive(Debug)]
+// optional type annotation of variables, arguments and return values
+#[derive(Debug, Eq, PartialEq, Clone)]
+pub enum Annotation {
+    Bool,
+    Int,
+    Float,
+    Vec,
+    Mat3,
+    Buffer {
+        z: Option<u64>,
+        cs: Option<ColorSpace>,
+        input: bool, // buffer is only read from
+    },
+    Function, // name of a function, resolved when specializing
+    Struct(String),
+}
+
+impl std::fmt::Display for Annotation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Annotation::Buffer { input: true, .. } = self {
+            write!(f, "const ")?;
+        }
+        match self {
+            Annotation::Bool => write!(f, "bool"),
+            Annotation::Int => write!(f, "int"),
+            Annotation::Float => write!(f, "float"),
+            Annotation::Vec => write!(f, "vec"),
+            Annotation::Mat3 => write!(f, "mat3"),
+            Annotation::Function => write!(f, "fn"),
+            Annotation::Struct(id) => write!(f, "{}", id),
+            Annotation::Buffer {
+                z: None, cs: None, ..
+            } => write!(f, "buffer"),
+            Annotation::Buffer {
+                z: Some(z),
+                cs: None,
+                ..
+            } => write!(f, "buffer[{}]", z),
+            Annotation::Buffer {
+                z: None,
+                cs: Some(cs),
+                ..
+            } => write!(f, "buffer[{}]", cs),
+            Annotation::Buffer {
+                z: Some(z),
+                cs: Some(cs),
+                ..
+            } => write!(f, "buffer[{}, {}]", z, cs),
+        }
+    }
+}
+
+// work items in a tile, workgroup reductions in atomic.cl hold as many values in local memory
+pub const MAX_TILE: u32 = 256;
+
+// iteration order of a kernel over its global range: '@tile(32, 8)', '@rows'
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+pub enum Traversal {
+    Tile(u32, u32), // tasks and work groups of a fixed size in x and y
+    Rows,           // tasks spanning single rows
+}
+
+impl Traversal {
+    // tile size in x and y, -1 spans the whole dimension
+    pub fn size(&self) -> (i32, i32) {
+        match *self {
+            Traversal::Tile(x, y) => (x as i32, y as i32),
+            Traversal::Rows => (-1, 1),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
 pub enum Literal {
     Bool(bool),
     Int(i32),
-    Float(f32),
+    Float(f64),
     //Array(Vec<f32>),
 }
 
@@ -96,13 +169,13 @@ pub struct BinaryExpr {
     pub right: Expr,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub enum UnaryOp {
     Not,
     Neg,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub enum BinaryOp {
     And,
     Or,
@@ -136,46 +209,55 @@ pub enum AssignOp {
 #[derive(Debug)]
 pub struct Cond {
     pub cond: Expr,
-    pub body: Vec<Stmt>,
+    pub body: Vec<(Stmt, usize)>,
 }
 
 #[derive(Debug)]
 pub enum Stmt {
-    Var(String, Expr),
-    Const(String, Expr),
+    Var(String, Option<Annotation>, Expr),
+    Vars(Vec<String>, Expr), // var a, b = f(...), receiving multiple return values
+    Const(String, Option<Annotation>, Expr),
     Assign(Expr, Expr),
     AssignOp(Expr, AssignOp, Expr),
 
     Call(String, Vec<Expr>),
-    Return(Option<Expr>),
+    Return(Vec<Expr>), // no values for a bare return
     Continue,
     Break,
 
     IfElse {
         cond_list: Vec<Cond>,
-        else_body: Vec<Stmt>,
+        else_body: Vec<(Stmt, usize)>,
     },
     For {
         var: String,
         from: Expr,
         to: Expr,
         step: Option<Expr>,
-        body: Vec<Stmt>,
+        body: Vec<(Stmt, usize)>,
     },
     While {
         cond: Expr,
-        body: Vec<Stmt>,
+        body: Vec<(Stmt, usize)>,
     },
 
     Kernel {
         id: String,
         args: Vec<String>,
-        body: Vec<Stmt>,
+        types: Vec<Option<Annotation>>, // argument annotations
+        traversal: Option<Traversal>,
+        body: Vec<(Stmt, usize)>,
     },
     Function {
         id: String,
         args: Vec<String>,
-        body: Vec<Stmt>,
+        types: Vec<Option<Annotation>>, // argument annotations
+        ret: Option<Annotation>,
+        body: Vec<(Stmt, usize)>,
+    },
+    Struct {
+        id: String,
+        fields: Vec<(String, Annotation)>,
     },
 
     Comment(String),
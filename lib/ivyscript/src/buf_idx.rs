@@ -25,18 +25,15 @@ impl VarType {
     pub fn idx_1d(&self, id: &str, ix: &str) -> String {
         if let VarType::Buffer { x1y1: false, .. } = self {
             format!(
-                "clamp((int)({ix}), 0, {cx})",
+                "clamp((int)({ix}), 0, (___str_{id}[0] * ___str_{id}[1] * ___str_{id}[2] - 1))",
                 ix = ix,
-                cx = format!(
-                    "(___str_{}[0] * ___str_{}[1] * ___str_{}[2] - 1)",
-                    id, id, id
-                )
+                id = id,
             )
         } else if let VarType::Buffer { x1y1: true, .. } = self {
             format!(
-                "clamp((int)({ix}), 0, {cx})",
+                "clamp((int)({ix}), 0, (___str_{id}[2] - 1))",
                 ix = ix,
-                cx = format!("(___str_{}[2] - 1)", id)
+                id = id,
             )
         } else {
             "// ERROR!!!\n".into()
@@ -59,22 +56,17 @@ impl VarType {
     pub fn idx_3d(&self, id: &str, ix: &str, iy: &str, iz: &str) -> String {
         if let VarType::Buffer { x1y1: false, .. } = self {
             format!(
-            "(clamp((int)({ix}), 0, {cx})*{sx} + clamp((int)({iy}), 0, {cy})*{sy} + clamp((int)({iz}), 0, {cz})*{sz})",
+            "(clamp((int)({ix}), 0, (___str_{id}[0] - 1))*(___str_{id}[3]) + clamp((int)({iy}), 0, (___str_{id}[1] - 1))*(___str_{id}[4]) + clamp((int)({iz}), 0, (___str_{id}[2] - 1))*(___str_{id}[5]))",
             ix = ix,
             iy = iy,
             iz = iz,
-            cx = format!("(___str_{}[0] - 1)", id),
-            cy = format!("(___str_{}[1] - 1)", id),
-            cz = format!("(___str_{}[2] - 1)", id),
-            sx = format!("(___str_{}[3])", id),
-            sy = format!("(___str_{}[4])", id),
-            sz = format!("(___str_{}[5])", id),
+            id = id,
             )
         } else if let VarType::Buffer { x1y1: true, .. } = self {
             format!(
-                "(clamp((int)({iz}), 0, {cz}))",
+                "(clamp((int)({iz}), 0, (___str_{id}[2] - 1)))",
                 iz = iz,
-                cz = format!("(___str_{}[2] - 1)", id),
+                id = id,
             )
         } else {
             "// ERROR!!!\n".into()
@@ -0,0 +1,105 @@
+/*
+  Copyright (C) 2011-2021 G. Bajlekov
+
+    Ivy is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Ivy is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+// builtin functions registered by the host, implemented natively for each target
+
+use crate::inference::VarType;
+
+// argument and return types of registered builtins
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ValueType {
+    Bool,
+    Int,
+    Float,
+    Vec,
+    Void, // only as return type
+}
+
+impl ValueType {
+    // parse type names as used in annotations: bool, int, float, vec, void
+    pub fn parse(s: &str) -> Option<ValueType> {
+        match s.trim() {
+            "bool" => Some(ValueType::Bool),
+            "int" => Some(ValueType::Int),
+            "float" => Some(ValueType::Float),
+            "vec" => Some(ValueType::Vec),
+            "void" => Some(ValueType::Void),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn var_type(&self) -> VarType {
+        match self {
+            ValueType::Bool => VarType::Bool,
+            ValueType::Int => VarType::Int,
+            ValueType::Float => VarType::Float,
+            ValueType::Vec => VarType::Vec,
+            ValueType::Void => VarType::Void,
+        }
+    }
+}
+
+// a builtin is called by name from scripts, and emitted with the spelling of the target,
+// an optional definition is included in kernels that call the builtin
+#[derive(Debug, Clone, PartialEq)]
+pub struct Builtin {
+    pub name: String,
+    pub args: Vec<ValueType>,
+    pub ret: ValueType,
+    pub ocl: String,
+    pub ispc: String,
+    pub ocl_source: Option<String>,
+    pub ispc_source: Option<String>,
+}
+
+impl Builtin {
+    pub(crate) fn check(&self) -> Result<(), String> {
+        let mut chars = self.name.chars();
+        if !chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+            || !chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            return Err(format!("Invalid builtin name '{}'", self.name));
+        }
+        if self.args.contains(&ValueType::Void) {
+            return Err(format!(
+                "Builtin '{}' can not have an argument of type 'void'",
+                self.name
+            ));
+        }
+        if self.ocl.is_empty() || self.ispc.is_empty() {
+            return Err(format!(
+                "Builtin '{}' needs a name for each target",
+                self.name
+            ));
+        }
+        Ok(())
+    }
+
+    // argument types are matched exactly, ints are promoted to floats
+    pub(crate) fn accepts(&self, input: &[VarType]) -> bool {
+        self.args.len() == input.len()
+            && self
+                .args
+                .iter()
+                .zip(input)
+                .all(|(a, t)| match (a.var_type(), *t) {
+                    (a, t) if a == t => true,
+                    (VarType::Float, VarType::Int) => true,
+                    _ => false,
+                })
+    }
+}
@@ -0,0 +1,67 @@
+/*
+  Copyright (C) 2011-2021 G. Bajlekov
+
+    Ivy is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Ivy is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, RwLock};
+
+const SHARDS: usize = 16;
+
+// generated kernels by id, split in independently locked shards
+// no lock is held while a kernel is generated, concurrent requests for the same id may both generate it
+pub struct Cache {
+    shards: Vec<RwLock<HashMap<String, Arc<str>>>>,
+}
+
+impl Cache {
+    pub fn new() -> Cache {
+        Cache {
+            shards: (0..SHARDS).map(|_| RwLock::new(HashMap::new())).collect(),
+        }
+    }
+
+    fn shard(&self, id: &str) -> &RwLock<HashMap<String, Arc<str>>> {
+        let mut hasher = DefaultHasher::new();
+        id.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % SHARDS]
+    }
+
+    fn get(&self, id: &str) -> Option<Arc<str>> {
+        // a panic while holding the lock leaves the map itself intact
+        let shard = self.shard(id).read().unwrap_or_else(|e| e.into_inner());
+        shard.get(id).cloned()
+    }
+
+    fn insert(&self, id: String, source: Arc<str>) {
+        let mut shard = self.shard(&id).write().unwrap_or_else(|e| e.into_inner());
+        shard.insert(id, source);
+    }
+
+    pub fn get_or_insert_with(
+        &self,
+        id: String,
+        f: impl FnOnce() -> Result<String, String>,
+    ) -> Result<Arc<str>, String> {
+        if let Some(source) = self.get(&id) {
+            return Ok(source);
+        }
+        let source: Arc<str> = f()?.into();
+        self.insert(id, source.clone());
+        Ok(source)
+    }
+}
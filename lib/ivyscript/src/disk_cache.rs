@@ -0,0 +1,131 @@
+/*
+  Copyright (C) 2011-2021 G. Bajlekov
+
+    Ivy is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Ivy is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+// generated kernels stored in a directory, reused between runs:
+// <key>.cl or <key>.ispc holds the generated source
+// <key>.meta holds the identifying header followed by the kernel arguments
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::ir::Kernel;
+use crate::library::library;
+use crate::Target;
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// unique names for partially written files
+static TEMP: AtomicUsize = AtomicUsize::new(0);
+
+// 64 bit FNV-1a, unlike the std hashers it is stable between builds
+pub fn hash(data: &[u8], mut h: u64) -> u64 {
+    for b in data {
+        h ^= *b as u64;
+        h = h.wrapping_mul(0x100000001b3);
+    }
+    h
+}
+
+pub const HASH_INIT: u64 = 0xcbf29ce484222325;
+
+// identifies a kernel specialization of a source for a target, crate version and standard library
+pub struct Key {
+    name: String,
+    ext: &'static str,
+    header: String,
+}
+
+impl Key {
+    pub fn new(source_hash: u64, target: Target, id: &str) -> Key {
+        let ext = match target {
+            Target::Ocl => "cl",
+            Target::Ispc => "ispc",
+        };
+        let header = format!(
+            "id: {}\ntarget: {}\nversion: {}\nstd: {:016x}\nsource: {:016x}\n",
+            id,
+            ext,
+            VERSION,
+            library(target).hash(),
+            source_hash
+        );
+        Key {
+            name: format!("{:016x}", hash(header.as_bytes(), HASH_INIT)),
+            ext,
+            header,
+        }
+    }
+}
+
+pub struct DiskCache {
+    dir: PathBuf,
+}
+
+impl DiskCache {
+    pub fn new(dir: &Path) -> Result<DiskCache, String> {
+        fs::create_dir_all(dir).map_err(|e| {
+            format!(
+                "Unable to create cache directory '{}': {}",
+                dir.display(),
+                e
+            )
+        })?;
+        Ok(DiskCache { dir: dir.into() })
+    }
+
+    fn path(&self, key: &Key, ext: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", key.name, ext))
+    }
+
+    // entries with a different header are hash collisions and ignored
+    pub fn load(&self, key: &Key) -> Option<String> {
+        let meta = fs::read_to_string(self.path(key, "meta")).ok()?;
+        if !meta.starts_with(&key.header) {
+            return None;
+        }
+        fs::read_to_string(self.path(key, key.ext)).ok()
+    }
+
+    // failing to store an entry only affects the next startup, errors are ignored
+    pub fn store(&self, key: &Key, source: &str, kernel: &Kernel) {
+        let mut meta = key.header.clone();
+        meta.push_str(&format!("kernel: {}\n", kernel.id));
+        for (arg, ty) in &kernel.args {
+            meta.push_str(&format!("arg: {} {}\n", arg, ty));
+        }
+
+        // the source is in place before the metadata that makes the entry visible
+        let _ = self
+            .write(&self.path(key, key.ext), source)
+            .and_then(|_| self.write(&self.path(key, "meta"), &meta));
+    }
+
+    // write through a temporary file, readers never see partial entries
+    fn write(&self, path: &Path, data: &str) -> std::io::Result<()> {
+        let temp = path.with_extension(format!(
+            "tmp{}-{}",
+            process::id(),
+            TEMP.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&temp, data)?;
+        fs::rename(&temp, path).inspect_err(|_| {
+            let _ = fs::remove_file(&temp);
+        })
+    }
+}
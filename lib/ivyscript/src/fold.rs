@@ -0,0 +1,474 @@
+/*
+  Copyright (C) 2011-2021 G. Bajlekov
+
+    Ivy is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Ivy is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+// constant folding of kernel bodies specialized on host provided values:
+// constants with a known value are substituted, operations on literals are evaluated
+// and branches with a constant condition are removed, called functions are specialized
+// on constant arguments and folded the same way
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::ast::{BinaryOp, Literal, UnaryOp};
+use crate::function_id::kernel_id;
+use crate::inference::VarType;
+use crate::ir::{BufIdx, Expr, ExprKind, Function, Place, Stmt};
+use crate::Precision;
+
+type Instances = RefCell<HashMap<String, Rc<Function>>>;
+
+pub struct Folder<'a> {
+    scopes: Vec<HashMap<String, Option<Literal>>>, // known values of constants, None for variables
+    instances: Option<&'a Instances>, // function instances, calls are only specialized when set
+    calls: Vec<String>,               // ids of called instances, in order of first call
+}
+
+fn literal(expr: &Expr) -> Option<Literal> {
+    match &expr.kind {
+        ExprKind::Literal(l) => Some(*l),
+        ExprKind::Grouping(e) => literal(e),
+        _ => None,
+    }
+}
+
+// value of an expression of literals, known when specializing
+pub fn constant(expr: &Expr) -> Option<Literal> {
+    literal(&Folder::new().expr(expr.clone()))
+}
+
+fn float(l: Literal) -> Option<f64> {
+    match l {
+        Literal::Int(n) => Some(n as f64),
+        Literal::Float(n) => Some(n),
+        Literal::Bool(_) => None,
+    }
+}
+
+// literal expression, negative numbers are grouped to be safe next to other operators
+fn expr(value: Literal, ty: VarType) -> Expr {
+    let negative = match value {
+        Literal::Int(n) => n < 0,
+        Literal::Float(n) => n.is_sign_negative(),
+        Literal::Bool(_) => false,
+    };
+    let e = Expr {
+        kind: ExprKind::Literal(value),
+        ty,
+    };
+    if negative {
+        Expr {
+            kind: ExprKind::Grouping(Box::new(e)),
+            ty,
+        }
+    } else {
+        e
+    }
+}
+
+// whether a variable is assigned in a body, such arguments are not specialized
+fn assigned(body: &[Stmt], id: &str) -> bool {
+    body.iter().any(|stmt| match stmt {
+        Stmt::Assign(
+            Place::Expr(Expr {
+                kind: ExprKind::Var(v),
+                ..
+            }),
+            _,
+        ) => v == id,
+        Stmt::IfElse {
+            cond_list,
+            else_body,
+        } => cond_list.iter().any(|(_, b)| assigned(b, id)) || assigned(else_body, id),
+        Stmt::For { body, .. } | Stmt::While { body, .. } => assigned(body, id),
+        _ => false,
+    })
+}
+
+fn unary(op: &UnaryOp, value: Literal) -> Option<Literal> {
+    match (op, value) {
+        (UnaryOp::Not, Literal::Bool(b)) => Some(Literal::Bool(!b)),
+        (UnaryOp::Neg, Literal::Int(n)) => n.checked_neg().map(Literal::Int),
+        (UnaryOp::Neg, Literal::Float(n)) => Some(Literal::Float(-n)),
+        _ => None,
+    }
+}
+
+fn binary(op: &BinaryOp, l: Literal, r: Literal, ty: VarType) -> Option<Literal> {
+    use BinaryOp::*;
+
+    match (op, l, r) {
+        (And, Literal::Bool(a), Literal::Bool(b)) => return Some(Literal::Bool(a && b)),
+        (Or, Literal::Bool(a), Literal::Bool(b)) => return Some(Literal::Bool(a || b)),
+        (Equal, Literal::Bool(a), Literal::Bool(b)) => return Some(Literal::Bool(a == b)),
+        (NotEqual, Literal::Bool(a), Literal::Bool(b)) => return Some(Literal::Bool(a != b)),
+        _ => {}
+    }
+
+    // integer arithmetic, division always results in a float
+    if let (Literal::Int(a), Literal::Int(b), VarType::Int) = (l, r, ty) {
+        return match op {
+            Add => a.checked_add(b),
+            Sub => a.checked_sub(b),
+            Mul => a.checked_mul(b),
+            Mod => a.checked_rem(b),
+            _ => None,
+        }
+        .map(Literal::Int);
+    }
+
+    let (a, b) = (float(l)?, float(r)?);
+    match op {
+        Add if ty == VarType::Float => Some(Literal::Float(a + b)),
+        Sub if ty == VarType::Float => Some(Literal::Float(a - b)),
+        Mul if ty == VarType::Float => Some(Literal::Float(a * b)),
+        Div if ty == VarType::Float && b != 0.0 => Some(Literal::Float(a / b)),
+        Equal => Some(Literal::Bool(a == b)),
+        NotEqual => Some(Literal::Bool(a != b)),
+        Less => Some(Literal::Bool(a < b)),
+        LessEqual => Some(Literal::Bool(a <= b)),
+        Greater => Some(Literal::Bool(a > b)),
+        GreaterEqual => Some(Literal::Bool(a >= b)),
+        _ => None,
+    }
+}
+
+impl<'a> Folder<'a> {
+    pub fn new() -> Folder<'a> {
+        Folder {
+            scopes: vec![HashMap::new()],
+            instances: None,
+            calls: Vec::new(),
+        }
+    }
+
+    // fold a body and specialize the functions it calls, new instances are added to instances
+    pub fn with_instances(instances: &'a Instances) -> Folder<'a> {
+        Folder {
+            instances: Some(instances),
+            ..Folder::new()
+        }
+    }
+
+    // ids of the instances called in the folded statements, the dependencies of the body
+    pub fn calls(self) -> Vec<String> {
+        self.calls
+    }
+
+    fn get(&self, id: &str) -> Option<Literal> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|s| s.get(id))
+            .copied()
+            .flatten()
+    }
+
+    fn declare(&mut self, id: &str, value: Option<Literal>) {
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(id.to_string(), value);
+    }
+
+    fn scoped_block(&mut self, body: Vec<Stmt>) -> Vec<Stmt> {
+        self.scopes.push(HashMap::new());
+        let body = self.block(body);
+        self.scopes.pop();
+        body
+    }
+
+    pub fn block(&mut self, body: Vec<Stmt>) -> Vec<Stmt> {
+        body.into_iter().filter_map(|s| self.stmt(s)).collect()
+    }
+
+    // returns None for removed statements
+    fn stmt(&mut self, stmt: Stmt) -> Option<Stmt> {
+        Some(match stmt {
+            Stmt::Var { id, ty, init } => {
+                let init = init.map(|e| self.expr(e));
+                self.declare(&id, None);
+                Stmt::Var { id, ty, init }
+            }
+            Stmt::Const { id, ty, init } => {
+                let init = init.map(|e| self.expr(e));
+                let value = match ty {
+                    VarType::Bool | VarType::Int | VarType::Float => {
+                        init.as_ref().and_then(literal)
+                    }
+                    _ => None,
+                };
+                self.declare(&id, value);
+                Stmt::Const { id, ty, init }
+            }
+            Stmt::Assign(place, e) => Stmt::Assign(self.place(place), self.expr(e)),
+            Stmt::Call(e) => Stmt::Call(self.expr(e)),
+            Stmt::Return(e) => Stmt::Return(e.into_iter().map(|e| self.expr(e)).collect()),
+            Stmt::IfElse {
+                cond_list,
+                else_body,
+            } => return self.if_else(cond_list, else_body),
+            Stmt::For {
+                var,
+                ty,
+                from,
+                to,
+                step,
+                body,
+            } => {
+                let from = self.expr(from);
+                let to = self.expr(to);
+                let step = step.map(|e| self.expr(e));
+                self.scopes.push(HashMap::new());
+                self.declare(&var, None);
+                let body = self.block(body);
+                self.scopes.pop();
+                Stmt::For {
+                    var,
+                    ty,
+                    from,
+                    to,
+                    step,
+                    body,
+                }
+            }
+            Stmt::While { cond, body } => {
+                let cond = self.expr(cond);
+                if let Some(Literal::Bool(false)) = literal(&cond) {
+                    return None;
+                }
+                Stmt::While {
+                    cond,
+                    body: self.scoped_block(body),
+                }
+            }
+            stmt => stmt,
+        })
+    }
+
+    // a branch with a constant true condition ends the chain and becomes the else branch,
+    // a remaining single branch is kept as 'if (true)' to preserve its scope
+    fn if_else(&mut self, cond_list: Vec<(Expr, Vec<Stmt>)>, else_body: Vec<Stmt>) -> Option<Stmt> {
+        let mut branches = Vec::new();
+        let mut else_body = Some(else_body);
+        for (cond, body) in cond_list {
+            let cond = self.expr(cond);
+            match literal(&cond) {
+                Some(Literal::Bool(false)) => {}
+                Some(Literal::Bool(true)) => {
+                    else_body = Some(body);
+                    break;
+                }
+                _ => branches.push((cond, body)),
+            }
+        }
+        let else_body = else_body.unwrap_or_default();
+
+        let mut branches = branches
+            .into_iter()
+            .map(|(cond, body)| (cond, self.scoped_block(body)))
+            .collect::<Vec<_>>();
+        let else_body = self.scoped_block(else_body);
+
+        if branches.is_empty() {
+            if else_body.is_empty() {
+                return None;
+            }
+            branches.push((expr(Literal::Bool(true), VarType::Bool), else_body));
+            return Some(Stmt::IfElse {
+                cond_list: branches,
+                else_body: Vec::new(),
+            });
+        }
+        Some(Stmt::IfElse {
+            cond_list: branches,
+            else_body,
+        })
+    }
+
+    fn place(&mut self, place: Place) -> Place {
+        match place {
+            Place::Expr(e) => Place::Expr(e), // assigned variables are never constant
+            Place::Array(id, idx) => Place::Array(id, self.exprs(idx)),
+            Place::Buffer(id, ty, idx) => Place::Buffer(id, ty, self.buf_idx(idx)),
+        }
+    }
+
+    fn exprs(&mut self, exprs: Vec<Expr>) -> Vec<Expr> {
+        exprs.into_iter().map(|e| self.expr(e)).collect()
+    }
+
+    fn boxed(&mut self, e: Expr) -> Box<Expr> {
+        Box::new(self.expr(e))
+    }
+
+    fn buf_idx(&mut self, idx: BufIdx) -> BufIdx {
+        match idx {
+            BufIdx::Linear(a) => BufIdx::Linear(self.boxed(*a)),
+            BufIdx::Pixel(a, b) => BufIdx::Pixel(self.boxed(*a), self.boxed(*b)),
+            BufIdx::Element(a, b, c) => {
+                BufIdx::Element(self.boxed(*a), self.boxed(*b), self.boxed(*c))
+            }
+        }
+    }
+
+    fn expr(&mut self, e: Expr) -> Expr {
+        let ty = e.ty;
+        let kind = match e.kind {
+            ExprKind::Var(id) => match self.get(&id) {
+                Some(value) => return expr(value, ty),
+                None => ExprKind::Var(id),
+            },
+            ExprKind::Unary(op, right) => {
+                let right = self.expr(*right);
+                if let Some(value) = literal(&right).and_then(|r| unary(&op, r)) {
+                    return expr(value, ty);
+                }
+                ExprKind::Unary(op, Box::new(right))
+            }
+            ExprKind::Binary(op, left, right) => {
+                let left = self.expr(*left);
+                let right = self.expr(*right);
+                if let (Some(l), Some(r)) = (literal(&left), literal(&right)) {
+                    if let Some(value) = binary(&op, l, r, ty) {
+                        return expr(value, ty);
+                    }
+                }
+                ExprKind::Binary(op, Box::new(left), Box::new(right))
+            }
+            ExprKind::Grouping(inner) => {
+                let inner = self.expr(*inner);
+                if literal(&inner).is_some() {
+                    return inner;
+                }
+                ExprKind::Grouping(Box::new(inner))
+            }
+            ExprKind::Builtin(id, args) => ExprKind::Builtin(id, self.exprs(args)),
+            ExprKind::Call(id, args) => {
+                let args = self.exprs(args);
+                self.call(id, args)
+            }
+            ExprKind::External(b, args) => ExprKind::External(b, self.exprs(args)),
+            ExprKind::Array(elems) => ExprKind::Array(self.exprs(elems)),
+            ExprKind::Component(e, n) => ExprKind::Component(self.boxed(*e), n),
+            ExprKind::BufLoad(id, t, idx) => ExprKind::BufLoad(id, t, self.buf_idx(idx)),
+            ExprKind::BufProp(id, t, prop, idx) => {
+                ExprKind::BufProp(id, t, prop, self.buf_idx(idx))
+            }
+            ExprKind::ArrayLoad(id, idx) => ExprKind::ArrayLoad(id, self.exprs(idx)),
+            ExprKind::ArrayPtr(id, idx) => ExprKind::ArrayPtr(id, self.exprs(idx)),
+            kind => kind,
+        };
+        Expr { kind, ty }
+    }
+
+    // calls with scalar literal arguments are redirected to an instance specialized on their
+    // values, the specialized arguments are declared as constants and no longer passed
+    fn call(&mut self, id: String, args: Vec<Expr>) -> ExprKind {
+        let function = match self.instances.and_then(|i| i.borrow().get(&id).cloned()) {
+            Some(function) => function,
+            None => return ExprKind::Call(id, args),
+        };
+
+        // additional arguments receiving returned values follow the function arguments
+        let values = function
+            .args
+            .iter()
+            .zip(&args)
+            .map(|((v, t), e)| match (t, literal(e)) {
+                (VarType::Bool, Some(l @ Literal::Bool(_)))
+                | (VarType::Int, Some(l @ Literal::Int(_)))
+                | (VarType::Float, Some(l @ Literal::Float(_)))
+                    if !assigned(&function.body, v) =>
+                {
+                    Some(l)
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let (id, args) = if values.iter().any(Option::is_some) {
+            let args = args
+                .into_iter()
+                .enumerate()
+                .filter(|(n, _)| values.get(*n).copied().flatten().is_none())
+                .map(|(_, e)| e)
+                .collect();
+            (self.specialize(&function, &values), args)
+        } else {
+            (id, args)
+        };
+        if !self.calls.contains(&id) {
+            self.calls.push(id.clone());
+        }
+        ExprKind::Call(id, args)
+    }
+
+    fn specialize(&self, function: &Function, values: &[Option<Literal>]) -> String {
+        let instances = match self.instances {
+            Some(instances) => instances,
+            None => return function.id.clone(),
+        };
+
+        // values by position in the source, function arguments are not passed
+        let mut passed = values.iter();
+        let all = function
+            .input
+            .iter()
+            .map(|t| match t {
+                VarType::Function(_) => None,
+                _ => passed.next().copied().flatten(),
+            })
+            .collect::<Vec<_>>();
+        let id = kernel_id(&function.name, &function.input, &all, Precision::Single);
+        if instances.borrow().contains_key(&id) {
+            return id;
+        }
+
+        let mut args = Vec::new();
+        let mut body = Vec::new();
+        for ((v, t), value) in function.args.iter().zip(values) {
+            match value {
+                Some(value) => body.push(Stmt::Const {
+                    id: v.clone(),
+                    ty: *t,
+                    init: Some(expr(*value, *t)),
+                }),
+                None => args.push((v.clone(), *t)),
+            }
+        }
+        body.extend(function.body.iter().cloned());
+
+        let mut folder = Folder::with_instances(instances);
+        let body = folder.block(body);
+        let specialized = Function {
+            id: id.clone(),
+            name: function.name.clone(),
+            input: function.input.clone(),
+            args,
+            ret: function.ret,
+            outs: function.outs.clone(),
+            body,
+            deps: folder.calls(),
+            random: function.random,
+        };
+        instances
+            .borrow_mut()
+            .insert(id.clone(), Rc::new(specialized));
+        id
+    }
+}
@@ -16,6 +16,7 @@
 */
 
 #[derive(Debug)]
+#[allow(dead_code)] // position is only used for debugging
 pub struct Fragment {
     pub line: usize,
     pub position: usize,
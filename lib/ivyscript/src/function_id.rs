@@ -15,48 +15,32 @@
     along with this program.  If not, see <http://www.gnu.org/licenses/>.
 */
 
-use crate::ast::ColorSpace;
+use crate::ast::{ColorSpace, Literal};
 use crate::inference::VarType;
+use crate::Precision;
 
 pub fn function_id(name: &str, input: &[VarType]) -> String {
-    let mut id = format!("___{}_", input.len());
-    for v in input {
-        let s = match v {
-            VarType::Bool => "B_".into(),
-            VarType::Int => "I_".into(),
-            VarType::Float => "F_".into(),
-            VarType::Vec => "V_".into(),
-            VarType::BoolArray(n, false, x, y, z, w) => format!("BA{}_{}_{}_{}_{}_", n, x, y, z, w),
-            VarType::IntArray(n, false, x, y, z, w) => format!("IA{}_{}_{}_{}_{}_", n, x, y, z, w),
-            VarType::FloatArray(n, false, x, y, z, w) => {
-                format!("FA{}_{}_{}_{}_{}_", n, x, y, z, w)
-            }
-            VarType::VecArray(n, false, x, y, z, w) => format!("VA{}_{}_{}_{}_{}_", n, x, y, z, w),
-            VarType::BoolArray(n, true, x, y, z, w) => format!("LBA{}_{}_{}_{}_{}_", n, x, y, z, w),
-            VarType::IntArray(n, true, x, y, z, w) => format!("LIA{}_{}_{}_{}_{}_", n, x, y, z, w),
-            VarType::FloatArray(n, true, x, y, z, w) => {
-                format!("LFA{}_{}_{}_{}_{}_", n, x, y, z, w)
-            }
-            VarType::VecArray(n, true, x, y, z, w) => format!("LVA{}_{}_{}_{}_{}_", n, x, y, z, w),
-            VarType::Buffer { z, cs, x1y1 } => format!(
-                "BUF{}{}{}_",
-                z,
-                match cs {
-                    ColorSpace::Srgb => "SRGB",
-                    ColorSpace::Lrgb => "LRGB",
-                    ColorSpace::Xyz => "XYZ",
-                    ColorSpace::Lab => "LAB",
-                    ColorSpace::Lch => "LCH",
-                    ColorSpace::Y => "Y",
-                    ColorSpace::L => "L",
-                },
-                match x1y1 {
-                    true => "1",
-                    false => "",
-                }
-            ),
-            VarType::Void => "Void".into(),
-            VarType::Unknown => "Unknown".into(),
+    kernel_id(name, input, &[], Precision::Single)
+}
+
+// kernel specializations on constant inputs also encode the values, double precision kernels are prefixed
+pub fn kernel_id(
+    name: &str,
+    input: &[VarType],
+    values: &[Option<Literal>],
+    precision: Precision,
+) -> String {
+    let mut id = match precision {
+        Precision::Single => String::new(),
+        Precision::Double => "D_".into(),
+    };
+    id.push_str(&format!("___{}_", input.len()));
+    for (n, v) in input.iter().enumerate() {
+        let s = match (values.get(n).copied().flatten(), v) {
+            (Some(Literal::Bool(b)), _) => format!("BC{}_", b as u8),
+            (Some(Literal::Int(i)), _) => format!("IC{:08x}_", i as u32),
+            (Some(Literal::Float(f)), _) => format!("FC{:08x}_", (f as f32).to_bits()),
+            (None, v) => type_id(v),
         };
         id.push_str(&s);
     }
@@ -64,3 +48,47 @@ pub fn function_id(name: &str, input: &[VarType]) -> String {
     id.push_str(name);
     id
 }
+
+fn type_id(v: &VarType) -> String {
+    match v {
+        VarType::Bool => "B_".into(),
+        VarType::Int => "I_".into(),
+        VarType::Float => "F_".into(),
+        VarType::Vec => "V_".into(),
+        VarType::Mat3 => "M3_".into(),
+        VarType::BoolArray(n, false, x, y, z, w) => format!("BA{}_{}_{}_{}_{}_", n, x, y, z, w),
+        VarType::IntArray(n, false, x, y, z, w) => format!("IA{}_{}_{}_{}_{}_", n, x, y, z, w),
+        VarType::FloatArray(n, false, x, y, z, w) => {
+            format!("FA{}_{}_{}_{}_{}_", n, x, y, z, w)
+        }
+        VarType::VecArray(n, false, x, y, z, w) => format!("VA{}_{}_{}_{}_{}_", n, x, y, z, w),
+        VarType::BoolArray(n, true, x, y, z, w) => format!("LBA{}_{}_{}_{}_{}_", n, x, y, z, w),
+        VarType::IntArray(n, true, x, y, z, w) => format!("LIA{}_{}_{}_{}_{}_", n, x, y, z, w),
+        VarType::FloatArray(n, true, x, y, z, w) => {
+            format!("LFA{}_{}_{}_{}_{}_", n, x, y, z, w)
+        }
+        VarType::VecArray(n, true, x, y, z, w) => format!("LVA{}_{}_{}_{}_{}_", n, x, y, z, w),
+        VarType::StructArray(s, n, x, y, z, w) => format!("SA{}_{}_{}_{}_{}_{}_", s, n, x, y, z, w),
+        VarType::Struct(n) => format!("S{}_", n),
+        VarType::Buffer { z, cs, x1y1 } => format!(
+            "BUF{}{}{}_",
+            z,
+            match cs {
+                ColorSpace::Srgb => "SRGB",
+                ColorSpace::Lrgb => "LRGB",
+                ColorSpace::Xyz => "XYZ",
+                ColorSpace::Lab => "LAB",
+                ColorSpace::Lch => "LCH",
+                ColorSpace::Y => "Y",
+                ColorSpace::L => "L",
+            },
+            match x1y1 {
+                true => "1",
+                false => "",
+            }
+        ),
+        VarType::Function(n) => format!("FN{}_", n),
+        VarType::Void => "Void".into(),
+        VarType::Unknown => "Unknown".into(),
+    }
+}
@@ -15,26 +15,26 @@
     along with this program.  If not, see <http://www.gnu.org/licenses/>.
 */
 
-use std::cell::RefCell;
-use std::collections::{HashMap, HashSet};
-
-use crate::ast::{
-    BinaryExpr, BinaryOp, Cond, Expr, Index, Literal, Prop, Stmt, UnaryExpr, UnaryOp,
+use std::collections::HashSet;
+use std::rc::Rc;
+use std::slice;
+
+use crate::ast::{BinaryOp, Literal, Prop, Traversal, UnaryOp};
+use crate::inference::VarType;
+use crate::ir::{
+    random_stream, reduction, struct_order, BufIdx, Expr, ExprKind, Function, Kernel, Place,
+    Program, Stmt, Struct,
 };
-use crate::function_id::function_id;
-
-use crate::inference::{Inference, VarType};
-
-pub struct Generator<'a> {
-    ast: Vec<Stmt>,
-    inference: RefCell<Inference<'a>>,
-    constants: RefCell<HashMap<String, &'a Expr>>,
-    functions: RefCell<HashMap<String, &'a Stmt>>,
-    kernels: RefCell<HashMap<String, &'a Stmt>>,
-    generated_constants: RefCell<Option<String>>,
-    generated_functions: RefCell<HashMap<String, (String, String, VarType, HashSet<String>)>>, // collect specialized functions: (declaration, definition, return value, dependencies)
-    generated_kernels: RefCell<HashMap<String, String>>, // collect specialized kernels: (kernel)
-    dependencies: RefCell<Vec<HashSet<String>>>, // collects dependencies of currently parsed function in a stack
+use crate::library::library;
+use crate::uniformity::Uniformity;
+use crate::{Precision, Target};
+
+// prints the typed IR as ISPC source
+pub struct Generator {
+    uniformity: Uniformity,
+    random: HashSet<String>, // functions drawing from the random stream, passed its counter
+    structs: Rc<Vec<Struct>>,
+    precision: Precision,
 }
 
 // helper function for generating up to 4D array indices
@@ -53,256 +53,373 @@ fn idx4(dim: u8, a: u64, b: u64, c: u64, d: u64) -> Result<String, String> {
     })
 }
 
-impl<'a> Generator<'a> {
-    #[allow(clippy::ptr_arg)]
-    pub fn new(ast: Vec<Stmt>) -> Generator<'a> {
-        Generator {
-            ast,
-            inference: RefCell::new(Inference::new()),
-            constants: RefCell::new(HashMap::new()),
-            functions: RefCell::new(HashMap::new()),
-            kernels: RefCell::new(HashMap::new()),
-            generated_constants: RefCell::new(None),
-            generated_functions: RefCell::new(HashMap::new()),
-            generated_kernels: RefCell::new(HashMap::new()),
-            dependencies: RefCell::new(Vec::new()),
+// varying store into a buffer element
+fn store(id: &str, idx: String) -> String {
+    format!("{}[(varying int)({})]", id, idx)
+}
+
+fn variability(uniform: bool) -> &'static str {
+    if uniform {
+        "uniform "
+    } else {
+        ""
+    }
+}
+
+impl Generator {
+    pub fn program(program: &Program, precision: Precision) -> Result<String, String> {
+        Generator::unit(slice::from_ref(program), precision)
+    }
+
+    // kernels in a single source, constants are shared and function instances are emitted once
+    pub fn unit(programs: &[Program], precision: Precision) -> Result<String, String> {
+        let generator = Generator {
+            uniformity: Uniformity::new(programs),
+            random: programs
+                .iter()
+                .flat_map(|p| &p.functions)
+                .filter(|f| f.random)
+                .map(|f| f.id.clone())
+                .collect(),
+            structs: programs
+                .first()
+                .map(|p| p.structs.clone())
+                .unwrap_or_default(),
+            precision,
+        };
+        generator.generate(programs)
+    }
+
+    // type name of scalars, vectors, matrices and structs
+    fn scalar(&self, t: VarType) -> Result<String, String> {
+        Ok(match (t, self.precision) {
+            (VarType::Float, Precision::Double) => "double",
+            (VarType::Vec, Precision::Double) => "double<3>",
+            (VarType::Mat3, Precision::Double) => "dmat3",
+            _ => match t {
+                VarType::Bool => "bool",
+                VarType::Int => "int",
+                VarType::Float => "float",
+                VarType::Vec => "float<3>",
+                VarType::Mat3 => "mat3",
+                VarType::Void => "void",
+                VarType::Struct(n) => return Ok(self.structs[n as usize].id.clone()),
+                t => return Err(format!("Type '{}' is not a scalar or vector type", t)),
+            },
         }
+        .into())
     }
 
-    pub fn prepare(&'a self) {
-        for stmt in &self.ast {
-            match stmt {
-                Stmt::Const(id, expr) => {
-                    self.constants.borrow_mut().insert(id.clone(), &expr);
-                }
-                Stmt::Function { id, .. } => {
-                    self.functions.borrow_mut().insert(id.clone(), &stmt);
-                }
-                Stmt::Kernel { id, .. } => {
-                    self.kernels.borrow_mut().insert(id.clone(), &stmt);
-                }
-                Stmt::Comment(..) => {}
-                Stmt::Eof => {}
-                _ => panic!("Unexpected statement in file scope!"),
-            }
+    // constructor of vectors in the precision of locals
+    fn vec(&self) -> &'static str {
+        match self.precision {
+            Precision::Single => "vec",
+            Precision::Double => "_dvec",
         }
     }
 
-    fn function(&'a self, name: &str, input: &[VarType]) -> Result<String, String> {
-        let id = function_id(name, input);
+    fn literal(&self, n: f64) -> String {
+        match self.precision {
+            Precision::Single => format!("{:.7}f", n as f32),
+            Precision::Double => format!("{:e}", n).replace('e', "d"),
+        }
+    }
 
-        if self.generated_functions.borrow().contains_key(&id) {
-            return Ok(id);
+    // matrix helpers in the precision of locals
+    fn mat3(&self, name: &str) -> String {
+        match self.precision {
+            Precision::Single => name.into(),
+            Precision::Double => name.replacen("_mat3", "_dmat3", 1),
         }
+    }
 
-        // parse function
-        if let Some(Stmt::Function { args, body, .. }) = self.functions.borrow().get(name) {
-            // new function scope, keep outer scope reference to restore at the end
-            let outer_scope = self.inference.borrow().scope.current.get();
-            self.inference.borrow().scope.open();
-            self.inference.borrow().scope.set_parent(0); // no parent scope
-            self.inference.borrow().scope.placeholder("return");
-
-            // new frame on the dependency stack
-            self.dependencies.borrow_mut().push(HashSet::new());
-
-            let mut definition = "(varying int _x, varying int _y, varying int _z, \n".to_string();
-            let mut declaration;
-
-            // generate argument signatures
-            for (k, v) in args.iter().enumerate() {
-                let arg = match input[k] {
-                    VarType::Buffer { .. } => {
-                        format!(
-                            "uniform float uniform {}[], uniform int uniform ___str_{}[]",
-                            v, v
-                        )
-                    }
-                    VarType::Int => format!("int {}", v),
-                    VarType::Float => format!("float {}", v),
-                    VarType::Vec => format!("float<3> {}", v),
-                    VarType::BoolArray(n, l, a, b, c, d) => {
-                        format!(
-                            "{}bool {}{}",
-                            if l { "uniform " } else { "" },
-                            v,
-                            idx4(n, a, b, c, d)?
-                        )
-                    }
-                    VarType::IntArray(n, l, a, b, c, d) => {
-                        format!(
-                            "{}int {}{}",
-                            if l { "uniform " } else { "" },
-                            v,
-                            idx4(n, a, b, c, d)?
-                        )
-                    }
-                    VarType::FloatArray(n, l, a, b, c, d) => {
-                        format!(
-                            "{}float {}{}",
-                            if l { "uniform " } else { "" },
-                            v,
-                            idx4(n, a, b, c, d)?
-                        )
-                    }
-                    VarType::VecArray(n, l, a, b, c, d) => {
-                        format!(
-                            "{}float<3> {}{}",
-                            if l { "uniform " } else { "" },
-                            v,
-                            idx4(n, a, b, c, d)?
-                        )
-                    }
-                    t => {
-                        return Err(format!(
-                            "Argument '{}' of function '{}' has unsupported type '{}'",
-                            v, name, t
-                        ))
-                    }
-                };
+    // values of single precision storage and helpers converted to the precision of locals
+    fn widen(&self, s: String, ty: VarType) -> String {
+        match (self.precision, ty) {
+            (Precision::Double, VarType::Float) => format!("(double)({})", s),
+            (Precision::Double, VarType::Vec) => format!("_vec_double({})", s),
+            _ => s,
+        }
+    }
 
-                self.inference.borrow().scope.add(v, input[k]); // add argument to scope
+    fn narrow(&self, s: String, ty: VarType) -> String {
+        match (self.precision, ty) {
+            (Precision::Double, VarType::Vec) => format!("_vec_float({})", s),
+            _ => s,
+        }
+    }
 
-                // comma-separate arguments
-                if k < args.len() - 1 {
-                    definition.push_str(&format!("\t{},\n", arg));
-                } else {
-                    definition.push_str(&format!("\t{}\n", arg));
-                }
+    // element type name, local prefix and dimensions of arrays
+    // arrays in local memory are single precision, shared with the atomic helpers
+    fn array(&self, t: VarType) -> Result<(String, &'static str, String), String> {
+        let (ty, n, l, a, b, c, d) = match t {
+            VarType::BoolArray(n, l, a, b, c, d) => ("bool".into(), n, l, a, b, c, d),
+            VarType::IntArray(n, l, a, b, c, d) => ("int".into(), n, l, a, b, c, d),
+            VarType::FloatArray(n, true, a, b, c, d) => ("float".into(), n, true, a, b, c, d),
+            VarType::FloatArray(n, l, a, b, c, d) => {
+                (self.scalar(VarType::Float)?, n, l, a, b, c, d)
             }
-            definition.push(')');
-
-            declaration = definition.clone(); // copy function signature into declaration
-
-            // construct function body
-            definition.push_str(" {\n");
-            for v in body {
-                definition.push_str(&self.gen_stmt(v)?);
+            VarType::VecArray(n, l, a, b, c, d) => (self.scalar(VarType::Vec)?, n, l, a, b, c, d),
+            VarType::StructArray(s, n, a, b, c, d) => {
+                (self.scalar(VarType::Struct(s))?, n, false, a, b, c, d)
             }
+            t => return Err(format!("Type '{}' is not an array type", t)),
+        };
+        Ok((ty, if l { "uniform " } else { "" }, idx4(n, a, b, c, d)?))
+    }
 
-            // get function return type
-            let ret_type = self
-                .inference
-                .borrow()
-                .scope
-                .get("return")
-                .unwrap_or(VarType::Void); // use void return type if none specified
-            let ret_string = match ret_type {
-                VarType::Bool => "bool",
-                VarType::Int => "int",
-                VarType::Float => "float",
-                VarType::Vec => "float<3>",
-                VarType::Void => "void",
-                _ => return Err(format!("Unknown return type of function '{}'", name)),
-            };
-            self.inference.borrow().scope.close();
-            self.inference.borrow().scope.set_current(outer_scope);
-
-            // collect function dependencies from stack
-            let deps = self
-                .dependencies
-                .borrow_mut()
-                .pop()
-                .ok_or_else(|| "No dependency frame found!".to_string())?;
+    fn generate(&self, programs: &[Program]) -> Result<String, String> {
+        let mut externals = String::new();
+        let mut included = Vec::new();
+        for b in programs.iter().flat_map(|p| &p.externals) {
+            if included.contains(&b) {
+                continue;
+            }
+            included.push(b);
+            if let Some(source) = &b.ispc_source {
+                externals.push_str(source);
+                externals.push('\n');
+            }
+        }
 
-            // add function return type to definition
-            definition = format!("{} {} {}}}", ret_string, id, definition);
+        // constants do not depend on the kernel inputs
+        let mut constants = String::new();
+        for v in programs.iter().take(1).flat_map(|p| p.constants.iter()) {
+            if let Stmt::Const { id, ty, init } = v {
+                let uniform = self.uniformity.stmt(v);
+                constants.push_str(&format!("const {}", self.gen_var(id, *ty, init, uniform)?));
+            }
+        }
 
-            // add function return type to declaration
-            declaration = format!("{} {} {};", ret_string, id, declaration);
+        let mut declarations = String::new();
+        let mut definitions = String::new();
+        let mut emitted = HashSet::new();
+        for f in programs.iter().flat_map(|p| &p.functions) {
+            if !emitted.insert(&f.id) {
+                continue;
+            }
+            let (declaration, definition) = self.function(f)?;
+            declarations.push_str(&declaration);
+            declarations.push_str("\n\n");
+            definitions.push_str(&definition);
+            definitions.push_str("\n\n");
+        }
 
-            // register generated_functions
-            self.generated_functions
-                .borrow_mut()
-                .insert(id.clone(), (declaration, definition, ret_type, deps));
+        let kernels = programs
+            .iter()
+            .map(|p| self.kernel(&p.kernel))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let code = format!(
+            "{}{}{}\n{}\n{}\n{}",
+            externals,
+            self.structs()?,
+            constants,
+            declarations,
+            definitions,
+            kernels.join("\n")
+        );
+        Ok(format!("{}{}", library(Target::Ispc).helpers(&code), code))
+    }
 
-            Ok(id)
-        } else {
-            Err(format!("Function '{}' not found", id))
+    // struct declarations and their constructors, taking all fields in order
+    fn structs(&self) -> Result<String, String> {
+        let mut s = String::new();
+        for n in struct_order(&self.structs) {
+            let st = &self.structs[n];
+            s.push_str(&format!("struct {} {{\n", st.id));
+            for (f, ty) in &st.fields {
+                s.push_str(&format!("\t{} {};\n", self.scalar(*ty)?, f));
+            }
+            s.push_str("};\n\n");
+
+            let args = st
+                .fields
+                .iter()
+                .map(|(f, ty)| Ok(format!("{} {}", self.scalar(*ty)?, f)))
+                .collect::<Result<Vec<_>, String>>()?;
+            s.push_str(&format!(
+                "inline {} ___new_{} ({}) {{\n{} ___s;\n",
+                st.id,
+                st.id,
+                args.join(", "),
+                st.id
+            ));
+            for (f, _) in &st.fields {
+                s.push_str(&format!("___s.{} = {};\n", f, f));
+            }
+            s.push_str("return ___s;\n}\n\n");
         }
+        Ok(s)
     }
 
-    pub fn kernel(&'a self, name: &str, input: &[VarType]) -> Result<String, String> {
-        let id = function_id(name, input);
+    fn function(&self, function: &Function) -> Result<(String, String), String> {
+        // global indices and the counter of the random stream are only passed to functions using them
+        let mut passed = Vec::new();
+        if self.uniformity.indexed(&function.id) {
+            passed.push("varying int _x, varying int _y, varying int _z");
+        }
+        if self.random.contains(&function.id) {
+            passed.push("unsigned int32 * uniform _rng");
+        }
+        let sep = if passed.is_empty() || (function.args.is_empty() && function.outs.is_empty()) {
+            ""
+        } else {
+            ", "
+        };
+        let mut signature = format!("({}{}\n", passed.join(", "), sep);
+
+        // generate argument signatures
+        let mut args = Vec::new();
+        for (v, t) in &function.args {
+            let arg = match t {
+                VarType::Buffer { .. } => format!(
+                    "uniform float uniform {}[], uniform int uniform ___str_{}[]",
+                    v, v
+                ),
+                VarType::Bool
+                | VarType::Int
+                | VarType::Float
+                | VarType::Vec
+                | VarType::Mat3
+                | VarType::Struct(_) => {
+                    format!("{} {}", self.scalar(*t)?, v)
+                }
+                VarType::BoolArray(..)
+                | VarType::IntArray(..)
+                | VarType::FloatArray(..)
+                | VarType::VecArray(..)
+                | VarType::StructArray(..) => {
+                    let (ty, local, dims) = self.array(*t)?;
+                    format!("{}{} {}{}", local, ty, v, dims)
+                }
+                t => {
+                    return Err(format!(
+                        "Argument '{}' of function '{}' has unsupported type '{}'",
+                        v, function.id, t
+                    ))
+                }
+            };
 
-        if let Some(k) = self.generated_kernels.borrow().get(&id) {
-            return Ok(k.clone());
+            args.push(arg);
         }
 
-        self.inference.borrow_mut().functions = Some(&self.generated_functions); // link generated functions to inference engine
-        self.inference.borrow().scope.clear(); // clear leftover scopes
-        *self.dependencies.borrow_mut() = vec![]; // clear the dependency stack
+        // additional return values are written through out-parameters
+        for (k, t) in function.outs.iter().enumerate() {
+            args.push(format!("{} &___ret{}", self.scalar(*t)?, k + 1));
+        }
 
-        // parse constants
-        if self.generated_constants.borrow().is_none() {
-            let mut consts = String::new();
-            for (k, v) in self.constants.borrow().iter() {
-                consts.push_str(&format!("const {}", self.gen_var(k, v)?));
+        // comma-separate arguments
+        for (k, arg) in args.iter().enumerate() {
+            if k < args.len() - 1 {
+                signature.push_str(&format!("\t{},\n", arg));
+            } else {
+                signature.push_str(&format!("\t{}\n", arg));
             }
-            self.generated_constants.replace(Some(consts));
         }
+        signature.push(')');
+
+        let ret = self.scalar(function.ret)?;
+        let declaration = format!("{} {} {};", ret, function.id, signature);
+        let definition = format!(
+            "{} {} {} {{\n{}}}",
+            ret,
+            function.id,
+            signature,
+            self.gen_block(&function.body)?
+        );
+
+        Ok((declaration, definition))
+    }
 
-        if let Some(Stmt::Kernel { id, args, body }) = self.kernels.borrow().get(name) {
-            // new kernel scope with void return type
-            self.inference.borrow().scope.open();
-            self.inference.borrow().scope.add("return", VarType::Void); // explicitly expect void return type for kernels
-
-            // new frame on the dependency stack
-            self.dependencies.borrow_mut().push(HashSet::new());
-
-            // construct kernel signature
-            let mut arguments = "\n\tuniform int _dim[],\n".to_string();
-            for (k, v) in args.iter().enumerate() {
-                // construct argument signature
-                let arg = format!(
-                    "{} {}{}",
-                    match input[k] {
-                        VarType::Buffer { .. } => "uniform float",
-                        VarType::Int => "uniform int",
-                        VarType::Float => "uniform float",
-                        VarType::IntArray(1, ..) => "uniform int",
-                        VarType::FloatArray(1, ..) => "uniform float",
-                        t =>
-                            return Err(format!(
-                                "Type '{}' of argument '{}' not supported in kernel arguments",
-                                t, v
-                            )),
-                    },
-                    v,
-                    match input[k] {
-                        VarType::Buffer { .. } => format!("[], uniform int ___str_{}[]", v),
-                        VarType::Int => "".into(),
-                        VarType::Float => "".into(),
-                        VarType::IntArray(1, ..) => "[]".into(),
-                        VarType::FloatArray(1, ..) => "[]".into(),
-                        t =>
-                            return Err(format!(
-                                "Type '{}' of argument '{}' not supported in kernel arguments",
-                                t, v
-                            )),
-                    },
-                );
-
-                self.inference.borrow().scope.add(v, input[k]); // add argument to scope
-
-                // comma-separate arguments
-                if k < args.len() - 1 {
-                    arguments.push_str(&format!("\t{},\n", arg));
-                } else {
-                    arguments.push_str(&format!("\t{}\n", arg));
+    fn kernel(&self, kernel: &Kernel) -> Result<String, String> {
+        // construct kernel signature
+        let mut arguments = "\n\tuniform int _dim[],\n".to_string();
+        let mut prelude = String::new(); // conversion of arguments passed by pointer
+        for (k, (v, t)) in kernel.args.iter().enumerate() {
+            // construct argument signature
+            let arg = match t {
+                VarType::Buffer { .. } => {
+                    format!("uniform float {}[], uniform int ___str_{}[]", v, v)
+                }
+                VarType::Bool => format!("uniform bool {}", v),
+                VarType::Int => format!("uniform int {}", v),
+                VarType::Float if self.precision == Precision::Double => {
+                    prelude.push_str(&format!("uniform double {} = ___float_{};\n", v, v));
+                    format!("uniform float ___float_{}", v)
+                }
+                VarType::Float => format!("uniform float {}", v),
+                VarType::Vec => {
+                    prelude.push_str(&format!(
+                        "{} {} = {{___vec_{}[0], ___vec_{}[1], ___vec_{}[2]}};\n",
+                        self.scalar(VarType::Vec)?,
+                        v,
+                        v,
+                        v,
+                        v
+                    ));
+                    format!("uniform float ___vec_{}[3]", v)
+                }
+                VarType::Mat3 => {
+                    // rows are padded to 4 floats, matching the layout of the OpenCL arguments
+                    let m = [0, 1, 2, 4, 5, 6, 8, 9, 10].map(|k| format!("___mat3_{}[{}]", v, k));
+                    prelude.push_str(&format!(
+                        "{} {} = {{{}}};\n",
+                        self.scalar(VarType::Mat3)?,
+                        v,
+                        m.join(", ")
+                    ));
+                    format!("uniform float ___mat3_{}[12]", v)
+                }
+                VarType::IntArray(1, false, ..) => format!("uniform int {}[]", v),
+                VarType::FloatArray(1, false, ..) => format!("uniform float {}[]", v),
+                t => {
+                    return Err(format!(
+                        "Type '{}' of argument '{}' not supported in kernel arguments",
+                        t, v
+                    ))
                 }
+            };
+
+            // comma-separate arguments
+            if k < kernel.args.len() - 1 {
+                arguments.push_str(&format!("\t{},\n", arg));
+            } else {
+                arguments.push_str(&format!("\t{}\n", arg));
             }
+        }
 
-            let mut kernel = format!("task void ___task_{} ({}) {{", id, &arguments);
-            kernel.push_str(
-                "
-uniform int _xmin = _dim[0] + taskIndex0*_dim[6];
-uniform int _xmax = _dim[0] + min(((uniform int)taskIndex0 + 1)*_dim[6], _dim[3]);
-uniform int _ymin = _dim[1] + taskIndex1*_dim[7];
-uniform int _ymax = _dim[1] + min(((uniform int)taskIndex1 + 1)*_dim[7], _dim[4]);
+        // task size in x and y, passed by the host unless declared by the kernel
+        let (tile_x, tile_y) = match kernel.traversal {
+            Some(Traversal::Tile(x, y)) => (x.to_string(), y.to_string()),
+            Some(Traversal::Rows) => ("_dim[3]".to_string(), "1".to_string()),
+            None => ("_dim[6]".to_string(), "_dim[7]".to_string()),
+        };
+
+        let mut s = format!("task void ___task_{} ({}) {{", kernel.id, &arguments);
+        s.push_str(&format!(
+            "
+uniform int _xmin = _dim[0] + taskIndex0*{tx};
+uniform int _xmax = _dim[0] + min(((uniform int)taskIndex0 + 1)*{tx}, _dim[3]);
+uniform int _ymin = _dim[1] + taskIndex1*{ty};
+uniform int _ymax = _dim[1] + min(((uniform int)taskIndex1 + 1)*{ty}, _dim[4]);
 uniform int _zmin = _dim[2] + taskIndex2*_dim[8];
 uniform int _zmax = _dim[2] + min(((uniform int)taskIndex2 + 1)*_dim[8], _dim[5]);
+",
+            tx = tile_x,
+            ty = tile_y
+        ));
 
+        if kernel.traversal.is_some() {
+            // declared traversal runs along rows within each task
+            s.push_str(
+                "
+foreach (_z = _zmin ... _zmax, _y = _ymin ... _ymax, _x = _xmin ... _xmax) {
+",
+            );
+        } else {
+            s.push_str(
+                "
 // swap _x___ and _y___ if _dim[3]==0
 if (_dim[3]<16 && _dim[4]>16) {
     uniform int _tmin = _ymin;
@@ -313,8 +430,6 @@ if (_dim[3]<16 && _dim[4]>16) {
     _xmax = _tmax;
 }
 
-seed_rng(&_rng_state, programIndex);
-
 foreach (_z = _zmin ... _zmax, _1 = _ymin ... _ymax, _0 = _xmin ... _xmax) {
 
 int _x, _y;
@@ -326,568 +441,494 @@ if (_dim[3]<16 && _dim[4]>16) {
     _y = _1;   
 }\n",
             );
+        }
 
-            // construct kernel body
-            for v in body {
-                kernel.push_str(&self.gen_stmt(v)?);
-            }
-            kernel.push_str("}\n}\n");
+        s.push_str(&prelude);
+        if kernel.random {
+            s.push_str(
+                "unsigned int32 ___rng_n = 0;\nunsigned int32 * uniform _rng = &___rng_n;\n",
+            );
+        }
+        s.push_str(&self.gen_block(&kernel.body)?);
+        s.push_str("}\n}\n");
 
-            kernel.push_str(&format!("export void {} ({}) {{", id, &arguments));
+        s.push_str(&format!("export void {} ({}) {{", kernel.id, &arguments));
 
-            kernel.push_str(
-                "
-uniform int _nx = ceil((uniform float)_dim[3]/_dim[6]);
-uniform int _ny = ceil((uniform float)_dim[4]/_dim[7]);
+        s.push_str(&format!(
+            "
+uniform int _nx = ceil((uniform float)_dim[3]/{tx});
+uniform int _ny = ceil((uniform float)_dim[4]/{ty});
 uniform int _nz = ceil((uniform float)_dim[5]/_dim[8]);
 ",
-            );
-
-            kernel.push_str(&format!("launch[_nx, _ny, _nz] ___task_{}(", id));
-            kernel.push_str("\n\t_dim,\n\t");
-            for (k, v) in args.iter().enumerate() {
-                match input[k] {
-                    VarType::Buffer { .. } => kernel.push_str(&format!("{}, ___str_{}", v, v)),
-                    _ => kernel.push_str(&format!("{}", v)),
-                }
-                if k < args.len() - 1 {
-                    kernel.push_str(", ");
-                } else {
-                    kernel.push_str(");\n");
-                }
-            }
-            kernel.push_str("}");
-
-            // check whether return value is of type void
-            if self.inference.borrow().scope.get("return") != Some(VarType::Void) {
-                return Err(format!(
-                    "Expected return value of type 'Void' for kernel '{}'",
-                    name
-                ));
+            tx = tile_x,
+            ty = tile_y
+        ));
+
+        // pass kernel arguments to task
+        let mut launch = vec!["\n\t_dim".to_string()];
+        for (k, (v, t)) in kernel.args.iter().enumerate() {
+            let arg = match t {
+                VarType::Buffer { .. } => format!("{}, ___str_{}", v, v),
+                VarType::Float if self.precision == Precision::Double => format!("___float_{}", v),
+                VarType::Vec => format!("___vec_{}", v),
+                VarType::Mat3 => format!("___mat3_{}", v),
+                _ => v.clone(),
+            };
+            if k == 0 {
+                launch.push(format!("\n\t{}", arg));
+            } else {
+                launch.push(format!(" {}", arg));
             }
-            self.inference.borrow().scope.close();
-
-            // add includes, constants and function dependencies
-            let (deps_declarations, deps_definitions) = self.gen_dependencies()?; // pops dependencies frame
-            Ok(format!(
-                "#include \"std.ispc\"\n{}\n{}\n{}\n{}",
-                self.generated_constants
-                    .borrow()
-                    .as_ref()
-                    .unwrap_or(&format!("")),
-                deps_declarations,
-                deps_definitions,
-                kernel
-            ))
-        } else {
-            Err(format!("Kernel '{}' not found in source", name))
         }
-    }
+        s.push_str(&format!(
+            "launch[_nx, _ny, _nz] ___task_{}({});\n}}",
+            kernel.id,
+            launch.join(",")
+        ));
 
-    fn gen_dependencies(&self) -> Result<(String, String), String> {
-        let mut satisfied = HashSet::new(); // dependencies which are already satisfied, eventually becomes the final list of dependencies
-        let mut deps = self
-            .dependencies
-            .borrow_mut()
-            .pop()
-            .ok_or_else(|| "No dependency frame found!".to_string())?
-            .into_iter()
-            .collect::<Vec<_>>();
-
-        // get nested dependencies
-        while let Some(id) = deps.pop() {
-            let deps_nested = self
-                .generated_functions
-                .borrow()
-                .get(&id)
-                .ok_or::<String>(format!("No function dependency '{}' found", &id))?
-                .3
-                .clone();
-            satisfied.insert(id);
-
-            deps_nested.into_iter().for_each(|id| {
-                if satisfied.get(&id).is_none() {
-                    deps.push(id);
-                }
-            })
-        }
-
-        let deps = satisfied;
-
-        let mut declarations = String::new();
-        let mut definitions = String::new();
-        for id in deps.iter() {
-            let function = self.generated_functions.borrow();
-            let function = function
-                .get(id)
-                .ok_or::<String>(format!("No function dependency '{}' found", id))?;
+        Ok(s)
+    }
 
-            declarations.push_str(&function.0);
-            declarations.push_str("\n\n");
-            definitions.push_str(&function.1);
-            definitions.push_str("\n\n");
+    fn gen_block(&self, body: &[Stmt]) -> Result<String, String> {
+        let mut s = String::new();
+        for v in body {
+            s.push_str(&self.gen_stmt(v)?);
         }
-
-        Ok((declarations, definitions))
+        Ok(s)
     }
 
-    fn gen_stmt(&'a self, stmt: &Stmt) -> Result<String, String> {
+    fn gen_stmt(&self, stmt: &Stmt) -> Result<String, String> {
         let stmt = match stmt {
-            Stmt::Var(id, expr) => self.gen_var(id, expr)?,
-            Stmt::Const(id, expr) => format!("const {}", self.gen_var(id, expr)?),
-            Stmt::Assign(id, expr) => self.gen_assign(id, expr)?,
-            Stmt::Call(id, args) => {
-                let args_str = args
-                    .iter()
-                    .map(|e| self.gen_expr(e))
-                    .collect::<Result<Vec<_>, _>>()?;
-                let vars = args
-                    .iter()
-                    .map(|e| self.inference.borrow().var_type(e))
-                    .collect::<Result<Vec<_>, _>>()?;
-                if self.inference.borrow().builtin(id, args).is_ok() {
-                    format!("{};\n", self.gen_call(id, &args_str, &vars)?)
-                } else {
-                    let id = self.function(id, &vars)?;
-                    let mut args_str = args_str;
-                    args_str.insert(0, "_x, _y, _z".into());
-                    format!("{};\n", self.gen_call(&id, &args_str, &vars)?)
-                }
+            Stmt::Var { id, ty, init } => {
+                self.gen_var(id, *ty, init, self.uniformity.stmt(stmt))?
             }
+            Stmt::Const { id, ty, init } => format!(
+                "const {}",
+                self.gen_var(id, *ty, init, self.uniformity.stmt(stmt))?
+            ),
+            Stmt::Assign(place, val) => self.gen_assign(place, val)?,
+            Stmt::Call(expr) => format!("{};\n", self.gen_expr(expr)?),
             Stmt::For {
                 var,
+                ty,
                 from,
                 to,
                 step,
                 body,
-            } => self.gen_for(var, from, to, step, body)?,
+            } => {
+                let uniform = self.uniformity.stmt(stmt);
+                self.gen_for(var, *ty, uniform, from, to, step, body)?
+            }
             Stmt::IfElse {
                 cond_list,
                 else_body,
             } => self.gen_if_else(cond_list, else_body)?,
-            Stmt::While { cond, body } => self.gen_while(cond, body)?,
-            Stmt::Return(None) => match self.inference.borrow().scope.get("return") {
-                Some(VarType::Void) | None => "return;\n".into(),
-                Some(t) => {
-                    return Err(format!(
-                        "Void return statement inconsistent with previously used return type '{}'",
-                        t
-                    ))
+            Stmt::While { cond, body } => format!(
+                "while ({}) {{\n{}}}\n",
+                self.gen_expr(cond)?,
+                self.gen_block(body)?
+            ),
+            Stmt::Return(values) => match values.split_first() {
+                None => "return;\n".into(),
+                Some((expr, outs)) => {
+                    let mut s = String::new();
+                    for (k, e) in outs.iter().enumerate() {
+                        s.push_str(&format!("___ret{} = {};\n", k + 1, self.gen_expr(e)?));
+                    }
+                    s.push_str(&format!("return {};\n", self.gen_expr(expr)?));
+                    s
                 }
             },
             Stmt::Continue => "continue;\n".into(),
             Stmt::Break => "break;\n".into(),
-            Stmt::Return(Some(expr)) => {
-                let expr_str = self.gen_expr(expr)?; // generate before assessing type!
-
-                // return value is either new, same as--, or promoted from the previous one
-                let new = self.inference.borrow().var_type(expr)?;
-                let old = self.inference.borrow().scope.get("return").unwrap_or(new);
-                let promoted = self.inference.borrow().promote(new, old)?;
-
-                self.inference.borrow().scope.overwrite("return", promoted);
-
-                format!("return {};\n", expr_str)
-            }
             Stmt::Comment(c) => format!("//{}\n", c),
-            stmt => return Err(format!("Unable to generate code for:\n{:?}", stmt)),
         };
 
         Ok(stmt)
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn gen_for(
-        &'a self,
+        &self,
         var: &str,
+        ty: VarType,
+        uniform: bool,
         from: &Expr,
         to: &Expr,
         step: &Option<Expr>,
         body: &[Stmt],
     ) -> Result<String, String> {
-        self.inference.borrow().scope.open();
-
-        let mut s;
-
-        // infer var type
-        // TODO: does code need to be generated before inference? e.g. function calls?
-        let from_type = self.inference.borrow().var_type(from)?;
-        let to_type = self.inference.borrow().var_type(to)?;
-
-        let mut var_type = self.inference.borrow().promote_num(from_type, to_type)?;
-
-        if let Some(step) = &step {
-            let step_type = self.inference.borrow().var_type(step)?;
-            var_type = self.inference.borrow().promote_num(var_type, step_type)?;
-            self.inference.borrow().scope.add(var, var_type);
+        let var_type = match ty {
+            VarType::Int | VarType::Float | VarType::Vec => {
+                format!("{}{}", variability(uniform), self.scalar(ty)?)
+            }
+            _ => return Err(format!("Incompatible loop variable type '{}'", ty)),
+        };
 
-            s = format!(
+        let mut s = if let Some(step) = step {
+            format!(
                 "for ({var_type} {var} = {from}; ({step}>0)?({var}<={to}):({var}>={to}); {var} += {step}) {{\n",
-                var_type = match var_type {
-                    VarType::Int => "int",
-                    VarType::Float => "float",
-                    VarType::Vec => "float<3>",
-                    _ => return Err(format!("Incompatible loop variable type '{}'", var_type)),
-                },
+                var_type = var_type,
                 var = var,
-                from = self.gen_expr(&from)?,
-                to = self.gen_expr(&to)?,
-                step = self.gen_expr(&step)?,
+                from = self.gen_expr(from)?,
+                to = self.gen_expr(to)?,
+                step = self.gen_expr(step)?,
             )
         } else {
-            let step = match var_type {
-                VarType::Int => Expr::Literal(Literal::Int(1)),
-                VarType::Float => Expr::Literal(Literal::Float(1.0)),
-                VarType::Vec => Expr::Call("vec".into(), vec![Expr::Literal(Literal::Float(1.0))]),
-                _ => return Err(format!("Incompatible loop variable type '{}'", var_type)),
-            };
-            self.inference.borrow().scope.add(var, var_type);
-
-            s = format!(
+            format!(
                 "for ({var_type} {var} = {from}; {var}<={to}; {var} += {step}) {{\n",
-                var_type = match var_type {
-                    VarType::Int => "int",
-                    VarType::Float => "float",
-                    VarType::Vec => "float<3>",
-                    _ => return Err(format!("Incompatible loop variable type '{}'", var_type)),
-                },
+                var_type = var_type,
                 var = var,
-                from = self.gen_expr(&from)?,
-                to = self.gen_expr(&to)?,
-                step = self.gen_expr(&step)?,
+                from = self.gen_expr(from)?,
+                to = self.gen_expr(to)?,
+                step = match ty {
+                    VarType::Int => "1".into(),
+                    VarType::Float => self.literal(1.0),
+                    _ => format!("{}({})", self.vec(), self.literal(1.0)),
+                },
             )
-        }
-
-        for v in body {
-            s.push_str(&self.gen_stmt(v)?);
-        }
+        };
 
+        s.push_str(&self.gen_block(body)?);
         s.push_str("}\n");
-        self.inference.borrow().scope.close();
 
         Ok(s)
     }
 
-    fn gen_if_else(&'a self, cond_list: &[Cond], else_body: &[Stmt]) -> Result<String, String> {
-        // cond_list should have 1 or more entries
-
-        let Cond { ref cond, ref body } = cond_list[0];
-
-        let mut s = format!("if ({}) {{\n", self.gen_expr(cond)?);
-        assert!(self.inference.borrow().var_type(cond)? == VarType::Bool); // type info available only after generation!
-
-        self.inference.borrow().scope.open();
-        for v in body {
-            s.push_str(&self.gen_stmt(v)?);
-        }
-        self.inference.borrow().scope.close();
-
-        for cond_item in cond_list.iter().skip(1) {
-            let Cond { ref cond, ref body } = cond_item;
-
-            s.push_str(&format!("}} else if ({}) {{\n", self.gen_expr(cond)?));
-            assert!(self.inference.borrow().var_type(cond)? == VarType::Bool); // type info available only after generation!
+    fn gen_if_else(
+        &self,
+        cond_list: &[(Expr, Vec<Stmt>)],
+        else_body: &[Stmt],
+    ) -> Result<String, String> {
+        let mut s = String::new();
 
-            self.inference.borrow().scope.open();
-            for v in body {
-                s.push_str(&self.gen_stmt(v)?);
+        // cond_list should have 1 or more entries
+        for (k, (cond, body)) in cond_list.iter().enumerate() {
+            if k == 0 {
+                s.push_str(&format!("if ({}) {{\n", self.gen_expr(cond)?));
+            } else {
+                s.push_str(&format!("}} else if ({}) {{\n", self.gen_expr(cond)?));
             }
-            self.inference.borrow().scope.close();
+            s.push_str(&self.gen_block(body)?);
         }
 
         if !else_body.is_empty() {
             s.push_str("} else {\n");
-            self.inference.borrow().scope.open();
-            for v in else_body {
-                s.push_str(&self.gen_stmt(v)?);
-            }
-            self.inference.borrow().scope.close();
+            s.push_str(&self.gen_block(else_body)?);
         }
         s.push_str("}\n");
 
         Ok(s)
     }
 
-    fn gen_while(&'a self, cond: &Expr, body: &[Stmt]) -> Result<String, String> {
-        assert!(self.inference.borrow().var_type(cond)? == VarType::Bool);
-
-        let mut s = format!("while ({}) {{\n", self.gen_expr(cond)?);
-
-        self.inference.borrow().scope.open();
-        for v in body {
-            s.push_str(&self.gen_stmt(v)?);
-        }
-        self.inference.borrow().scope.close();
-        s.push_str("}}\n");
-
-        Ok(s)
-    }
-
-    fn gen_var(&'a self, id: &str, expr: &Expr) -> Result<String, String> {
-        let no_init = String::new();
-        let expr_str = match expr {
-            Expr::Call(f, _) => match f.as_ref() {
-                "array" => no_init,
-                "bool_array" => no_init,
-                "int_array" => no_init,
-                "float_array" => no_init,
-                "vec_array" => no_init,
-                "local_array" => no_init,
-                "local_bool_array" => no_init,
-                "local_int_array" => no_init,
-                "local_float_array" => no_init,
-                "local_vec_array" => no_init,
-                "zero" => "0".into(),
-                "one" => "1".into(),
-                _ => self.gen_expr(&expr)?,
-            },
-            Expr::Array(_) => format!(" = {}", self.gen_expr(&expr)?),
-            _ => self.gen_expr(&expr)?,
-        };
-
-        let var_type = self.inference.borrow().var_type(expr)?;
-        self.inference.borrow().scope.add(id, var_type);
-
-        let s = match var_type {
-            VarType::Bool => format!("bool {} = {};\n", id, expr_str),
-            VarType::Int => format!("int {} = {};\n", id, expr_str),
-            VarType::Float => format!("float {} = {};\n", id, expr_str),
-            VarType::Vec => format!("float<3> {} = {};\n", id, expr_str),
-
-            VarType::BoolArray(n, l, a, b, c, d) => {
-                format!(
-                    "{}bool {} {}{};\n",
-                    if l { "uniform " } else { "" },
-                    id,
-                    idx4(n, a, b, c, d)?,
-                    expr_str
-                )
-            }
-            VarType::IntArray(n, l, a, b, c, d) => {
-                format!(
-                    "{}int {} {}{};\n",
-                    if l { "uniform " } else { "" },
-                    id,
-                    idx4(n, a, b, c, d)?,
-                    expr_str
-                )
-            }
-            VarType::FloatArray(n, l, a, b, c, d) => {
+    fn gen_var(
+        &self,
+        id: &str,
+        ty: VarType,
+        init: &Option<Expr>,
+        uniform: bool,
+    ) -> Result<String, String> {
+        let s = match ty {
+            VarType::Bool
+            | VarType::Int
+            | VarType::Float
+            | VarType::Vec
+            | VarType::Mat3
+            | VarType::Struct(_) => {
+                // variables receiving additional return values are declared without initializer
+                let init = match init {
+                    Some(init) => format!(" = {}", self.gen_expr(init)?),
+                    None => String::new(),
+                };
                 format!(
-                    "{}float {} {}{};\n",
-                    if l { "uniform " } else { "" },
+                    "{}{} {}{};\n",
+                    variability(uniform),
+                    self.scalar(ty)?,
                     id,
-                    idx4(n, a, b, c, d)?,
-                    expr_str
+                    init
                 )
             }
-            VarType::VecArray(n, l, a, b, c, d) => {
-                format!(
-                    "{}float<3> {} {}{};\n",
-                    if l { "uniform " } else { "" },
-                    id,
-                    idx4(n, a, b, c, d)?,
-                    expr_str
-                )
+            VarType::BoolArray(..)
+            | VarType::IntArray(..)
+            | VarType::FloatArray(..)
+            | VarType::VecArray(..)
+            | VarType::StructArray(..) => {
+                let (ty, local, dims) = self.array(ty)?;
+                let init = match init {
+                    Some(init) => format!(" = {}", self.gen_expr(init)?),
+                    None => String::new(),
+                };
+                let local = if local.is_empty() {
+                    variability(uniform)
+                } else {
+                    local
+                };
+                format!("{}{} {} {}{};\n", local, ty, id, dims, init)
             }
-
             t => {
                 return Err(format!(
-                "Unable to create variable '{}' of type '{}'.\nType inferred from expression:\n{}",
-                id, t, expr_str
-            ))
+                    "Unable to create variable '{}' of type '{}'",
+                    id, t
+                ))
             }
         };
 
         Ok(s)
     }
 
-    fn gen_expr(&'a self, expr: &Expr) -> Result<String, String> {
-        let s = match expr {
-            Expr::Literal(Literal::Bool(true)) => "true".into(),
-            Expr::Literal(Literal::Bool(false)) => "false".into(),
-            Expr::Literal(Literal::Int(n)) => format!("{}", n),
-            Expr::Literal(Literal::Float(n)) => format!("{:.7}f", n),
-            Expr::Unary(expr) => self.gen_unary(expr)?,
-            Expr::Binary(expr) => self.gen_binary(expr)?,
-            Expr::Identifier(id) => id.clone(),
-            Expr::Index(expr, idx) => self.gen_index(expr, idx)?,
-            Expr::Grouping(expr) => format!("({})", self.gen_expr(expr)?),
-            Expr::Call(id, args) => {
-                if args.len() == 1 {
-                    match (id.as_ref(), &args[0]) {
-                        ("get_global_id", Expr::Literal(Literal::Int(0))) => return Ok("_x".into()),
-                        ("get_global_id", Expr::Literal(Literal::Int(1))) => return Ok("_y".into()),
-                        ("get_global_id", Expr::Literal(Literal::Int(2))) => return Ok("_z".into()),
-                        _ => {}
-                    }
+    fn gen_expr(&self, expr: &Expr) -> Result<String, String> {
+        let s = match &expr.kind {
+            ExprKind::Literal(Literal::Bool(true)) => "true".into(),
+            ExprKind::Literal(Literal::Bool(false)) => "false".into(),
+            ExprKind::Literal(Literal::Int(n)) => format!("{}", n),
+            ExprKind::Literal(Literal::Float(n)) => self.literal(*n),
+            ExprKind::Var(id) => id.clone(),
+            ExprKind::Unary(op, right) => match op {
+                UnaryOp::Not => format!("!{}", self.gen_expr(right)?),
+                UnaryOp::Neg => format!("(-{})", self.gen_expr(right)?),
+            },
+            ExprKind::Binary(op, left, right) => self.gen_binary(op, left, right)?,
+            ExprKind::Grouping(expr) => format!("({})", self.gen_expr(expr)?),
+            ExprKind::Builtin(id, args) => self.gen_builtin(id, args, expr.ty)?,
+            ExprKind::External(b, args) => self.gen_single(&b.ispc, args, expr.ty)?,
+            ExprKind::Call(id, args) => {
+                let mut passed = Vec::new();
+                if self.uniformity.indexed(id) {
+                    passed.push("_x, _y, _z".to_string());
                 }
-
-                let args_str = args
+                if self.random.contains(id) {
+                    passed.push("_rng".to_string());
+                }
+                let args = self.gen_args(args)?;
+                if !args.is_empty() {
+                    passed.push(args);
+                }
+                format!("{}({})", id, passed.join(", "))
+            }
+            ExprKind::Array(elems) => format!(
+                "{{{}}}",
+                elems
                     .iter()
                     .map(|e| self.gen_expr(e))
-                    .collect::<Result<Vec<_>, _>>()?;
-                let vars = args
-                    .iter()
-                    .map(|e| self.inference.borrow().var_type(e))
-                    .collect::<Result<Vec<_>, _>>()?;
-                if self.inference.borrow().builtin(id, args).is_ok() {
-                    self.gen_call(id, &args_str, &vars)?
-                } else {
-                    let id = self.function(id, &vars)?;
-                    self.dependencies
-                        .borrow_mut()
-                        .last_mut()
-                        .ok_or_else(|| "No dependency frame found!".to_string())?
-                        .insert(id.clone());
-                    let mut args_str = args_str;
-                    args_str.insert(0, "_x, _y, _z".into());
-                    self.gen_call(&id, &args_str, &vars)?
-                }
+                    .collect::<Result<Vec<_>, _>>()?
+                    .join(", ")
+            ),
+            ExprKind::Ref(id) => id.clone(),
+            ExprKind::Field(expr, f) => format!("{}.{}", self.gen_expr(expr)?, f),
+            ExprKind::Struct(args) => {
+                format!("___new_{}({})", self.scalar(expr.ty)?, self.gen_args(args)?)
             }
-            Expr::Array(elems) => {
-                let mut s = String::new();
-                for (k, v) in elems.iter().enumerate() {
-                    s.push_str(&self.gen_expr(v)?);
-                    if k < elems.len() - 1 {
-                        s.push_str(", ");
+            ExprKind::Component(expr, n) => format!(
+                "{}.{}",
+                self.gen_expr(expr)?,
+                match n {
+                    0 => "x",
+                    1 => "y",
+                    _ => "z",
+                }
+            ),
+            ExprKind::BufSize(id, n) => format!("___str_{}[{}]", id, n),
+            ExprKind::BufLoad(id, var, idx) => match idx {
+                BufIdx::Linear(a) => self.widen(var.buf_idx_1d(id, &self.gen_expr(a)?), expr.ty),
+                BufIdx::Pixel(a, b) => {
+                    let a = self.gen_expr(a)?;
+                    let b = self.gen_expr(b)?;
+                    if let VarType::Buffer { z: 3, .. } = var {
+                        format!("{}{}", self.vec(), var.buf_idx_2d(id, &a, &b))
+                    } else {
+                        self.widen(var.buf_idx_3d(id, &a, &b, "0"), expr.ty)
+                    }
+                }
+                BufIdx::Element(a, b, c) => self.widen(
+                    var.buf_idx_3d(
+                        id,
+                        &self.gen_expr(a)?,
+                        &self.gen_expr(b)?,
+                        &self.gen_expr(c)?,
+                    ),
+                    expr.ty,
+                ),
+            },
+            ExprKind::BufProp(id, var, prop, idx) => {
+                let idx = match idx {
+                    BufIdx::Linear(a) => var.idx_1d(id, &self.gen_expr(a)?),
+                    BufIdx::Pixel(a, b) => {
+                        var.idx_3d(id, &self.gen_expr(a)?, &self.gen_expr(b)?, "0")
                     }
+                    BufIdx::Element(a, b, c) => var.idx_3d(
+                        id,
+                        &self.gen_expr(a)?,
+                        &self.gen_expr(b)?,
+                        &self.gen_expr(c)?,
+                    ),
+                };
+                match prop {
+                    Prop::Int => format!("(((uniform int*){})[{}])", id, idx), //only for buffers
+                    Prop::Idx => idx,
+                    Prop::Ptr => format!("({} + {})", id, idx),
+                    Prop::IntPtr => format!("(((uniform int*){}) + {})", id, idx), // only for buffers
                 }
-                format!("{{{}}}", s)
+            }
+            ExprKind::ArrayLoad(id, idx) => format!("{}{}", id, self.gen_idx(idx)?),
+            ExprKind::ArrayPtr(id, idx) => {
+                let (last, idx) = idx
+                    .split_last()
+                    .ok_or_else(|| format!("Missing index for pointer to array '{}'", id))?;
+                format!("({}{} + {})", id, self.gen_idx(idx)?, self.gen_expr(last)?)
             }
         };
 
         Ok(s)
     }
 
-    fn gen_unary(&'a self, expr: &UnaryExpr) -> Result<String, String> {
-        let s = match expr.op {
-            UnaryOp::Not => format!("!{}", self.gen_expr(&expr.right)?),
-            UnaryOp::Neg => format!("(-{})", self.gen_expr(&expr.right)?),
-        };
+    // base pointer and element index of the output of a reduction
+    fn gen_target(&self, out: &Expr) -> Result<(String, String), String> {
+        Ok(match &out.kind {
+            ExprKind::BufProp(id, var, Prop::Ptr, idx) => {
+                let idx = Expr {
+                    kind: ExprKind::BufProp(id.clone(), *var, Prop::Idx, idx.clone()),
+                    ty: VarType::Int,
+                };
+                (id.clone(), self.gen_expr(&idx)?)
+            }
+            ExprKind::ArrayPtr(id, idx) => {
+                let (last, idx) = idx
+                    .split_last()
+                    .ok_or_else(|| format!("Missing index for pointer to array '{}'", id))?;
+                (
+                    format!("{}{}", id, self.gen_idx(idx)?),
+                    self.gen_expr(last)?,
+                )
+            }
+            _ => (self.gen_expr(out)?, "0".into()),
+        })
+    }
 
+    fn gen_idx(&self, idx: &[Expr]) -> Result<String, String> {
+        let mut s = String::new();
+        for v in idx {
+            s.push_str(&format!("[{}]", self.gen_expr(v)?));
+        }
         Ok(s)
     }
 
-    fn gen_binary(&'a self, expr: &BinaryExpr) -> Result<String, String> {
-        let s = match expr.op {
-            BinaryOp::And => format!(
-                "{} && {}",
-                self.gen_expr(&expr.left)?,
-                self.gen_expr(&expr.right)?
-            ),
-            BinaryOp::Or => format!(
-                "{} || {}",
-                self.gen_expr(&expr.left)?,
-                self.gen_expr(&expr.right)?
-            ),
+    fn gen_binary(&self, op: &BinaryOp, left: &Expr, right: &Expr) -> Result<String, String> {
+        let l = self.gen_expr(left)?;
+        let r = self.gen_expr(right)?;
 
-            BinaryOp::Sub => format!(
-                "{} - {}",
-                self.gen_expr(&expr.left)?,
-                self.gen_expr(&expr.right)?
-            ),
-            BinaryOp::Add => format!(
-                "{} + {}",
-                self.gen_expr(&expr.left)?,
-                self.gen_expr(&expr.right)?
-            ),
+        let s = match op {
+            BinaryOp::And => format!("{} && {}", l, r),
+            BinaryOp::Or => format!("{} || {}", l, r),
+
+            BinaryOp::Sub => format!("{} - {}", l, r),
+            BinaryOp::Add => format!("{} + {}", l, r),
             BinaryOp::Div => {
-                if self.inference.borrow().var_type(&expr.left)? == VarType::Int {
-                    format!(
-                        "((float){})/{}",
-                        self.gen_expr(&expr.left)?,
-                        self.gen_expr(&expr.right)?,
-                    )
+                if left.ty == VarType::Int {
+                    format!("(({}){})/{}", self.scalar(VarType::Float)?, l, r)
                 } else {
-                    format!(
-                        "{}/{}",
-                        self.gen_expr(&expr.left)?,
-                        self.gen_expr(&expr.right)?,
-                    )
+                    format!("{}/{}", l, r)
                 }
             }
-            BinaryOp::Mul => format!(
-                "{}*{}",
-                self.gen_expr(&expr.left)?,
-                self.gen_expr(&expr.right)?
-            ),
-            BinaryOp::Mod => format!(
-                "{}%{}",
-                self.gen_expr(&expr.left)?,
-                self.gen_expr(&expr.right)?
-            ),
+            BinaryOp::Mul => match (left.ty, right.ty) {
+                (VarType::Mat3, VarType::Mat3) => {
+                    format!("{}({}, {})", self.mat3("_mat3_mul"), l, r)
+                }
+                (VarType::Mat3, _) => format!("{}({}, {})", self.mat3("_mat3_mul_vec"), l, r),
+                _ => format!("{}*{}", l, r),
+            },
+            BinaryOp::Mod => format!("{}%{}", l, r),
             BinaryOp::Pow => {
-                let call = if self.inference.borrow().var_type(&expr.right)? == VarType::Int {
+                let call = if right.ty == VarType::Int {
                     "pown"
                 } else {
                     "pow"
                 };
 
-                if self.inference.borrow().var_type(&expr.left)? == VarType::Int {
-                    format!(
-                        "{}((float)({}), {})",
-                        call,
-                        self.gen_expr(&expr.left)?,
-                        self.gen_expr(&expr.right)?
-                    )
+                if left.ty == VarType::Int {
+                    format!("{}(({})({}), {})", call, self.scalar(VarType::Float)?, l, r)
                 } else {
-                    format!(
-                        "{}({}, {})",
-                        call,
-                        self.gen_expr(&expr.left)?,
-                        self.gen_expr(&expr.right)?
-                    )
+                    format!("{}({}, {})", call, l, r)
                 }
             }
 
-            BinaryOp::Equal => format!(
-                "{}=={}",
-                self.gen_expr(&expr.left)?,
-                self.gen_expr(&expr.right)?
-            ),
-            BinaryOp::NotEqual => format!(
-                "{}!={}",
-                self.gen_expr(&expr.left)?,
-                self.gen_expr(&expr.right)?
-            ),
+            BinaryOp::Equal => format!("{}=={}", l, r),
+            BinaryOp::NotEqual => format!("{}!={}", l, r),
 
-            BinaryOp::Less => format!(
-                "{}<{}",
-                self.gen_expr(&expr.left)?,
-                self.gen_expr(&expr.right)?
-            ),
-            BinaryOp::LessEqual => format!(
-                "{}<={}",
-                self.gen_expr(&expr.left)?,
-                self.gen_expr(&expr.right)?
-            ),
-            BinaryOp::Greater => format!(
-                "{}>{}",
-                self.gen_expr(&expr.left)?,
-                self.gen_expr(&expr.right)?
-            ),
-            BinaryOp::GreaterEqual => format!(
-                "{}>={}",
-                self.gen_expr(&expr.left)?,
-                self.gen_expr(&expr.right)?
-            ),
+            BinaryOp::Less => format!("{}<{}", l, r),
+            BinaryOp::LessEqual => format!("{}<={}", l, r),
+            BinaryOp::Greater => format!("{}>{}", l, r),
+            BinaryOp::GreaterEqual => format!("{}>={}", l, r),
         };
 
         Ok(s)
     }
 
-    fn gen_call(&'a self, id: &str, args: &[String], vars: &[VarType]) -> Result<String, String> {
+    fn gen_builtin(&self, id: &str, args: &[Expr], ty: VarType) -> Result<String, String> {
+        if let (true, [value, out]) = (reduction(id), args) {
+            let (base, idx) = self.gen_target(out)?;
+            return Ok(format!(
+                "_{}({}, {}, {})",
+                id,
+                self.gen_expr(value)?,
+                base,
+                idx
+            ));
+        }
+
+        // draws are keyed on the seed and counted by the global index and the call counter
+        if random_stream(id, args) {
+            let seed = self.gen_expr(&args[0])?;
+            let rest = self.gen_args(&args[1..])?;
+            let sep = if rest.is_empty() { "" } else { ", " };
+            return Ok(self.widen(
+                format!("_{}({}, _x, _y, _z, _rng{}{})", id, seed, sep, rest),
+                ty,
+            ));
+        }
+
+        if args.len() == 1 {
+            match (id, &args[0].kind) {
+                ("get_global_id", ExprKind::Literal(Literal::Int(0))) => return Ok("_x".into()),
+                ("get_global_id", ExprKind::Literal(Literal::Int(1))) => return Ok("_y".into()),
+                ("get_global_id", ExprKind::Literal(Literal::Int(2))) => return Ok("_z".into()),
+                _ => {}
+            }
+        }
+
+        // matrices are in the precision of locals, their helpers take them unconverted
+        let mat3 = match (id, args.first().map(|a| a.ty)) {
+            ("transpose", _) => Some("_mat3_transpose"),
+            ("inverse", _) => Some("_mat3_inverse"),
+            ("mat3", Some(VarType::Vec)) if args.len() == 3 => Some("_mat3_rows"),
+            ("mat3", Some(VarType::Vec)) => Some("_mat3_diag"),
+            ("mat3", _) if args.len() == 1 => {
+                return Ok(format!(
+                    "{}({}({}))",
+                    self.mat3("_mat3_diag"),
+                    self.vec(),
+                    self.gen_expr(&args[0])?
+                ))
+            }
+            ("mat3", _) => Some("_mat3"),
+            _ => None,
+        };
+        if let Some(id) = mat3 {
+            return self.gen_call(&self.mat3(id), args);
+        }
+
+        let float = format!("({})", self.scalar(VarType::Float)?);
+        let constructor = id == "vec";
         let mut id = match id {
             "bool" => "(bool)",
             "int" => "(int)",
-            "float" => "(float)",
-            "vec" => "vec",
+            "float" => &float,
+            "vec" => self.vec(),
             "mod" => "fmod",
             _ => id,
         };
 
-        if !vars.is_empty() {
-            id = match (id, vars[0]) {
+        if !args.is_empty() {
+            id = match (id, args[0].ty) {
                 ("atomic_add", VarType::FloatArray(1, false, ..)) => "_atomic_float_add",
                 ("atomic_sub", VarType::FloatArray(1, false, ..)) => "_atomic_float_sub",
                 ("atomic_inc", VarType::FloatArray(1, false, ..)) => "_atomic_float_inc",
@@ -904,664 +945,122 @@ uniform int _nz = ceil((uniform float)_dim[5]/_dim[8]);
             }
         }
 
-        let mut s = String::new();
-
-        let mut args_iter = args.iter();
-        if args.len() > 0 {
-            // TODO: properly determine whether function requires global indices to be passed
-            if args[0] == "_x, _y, _z" {
-                if let Some(v) = args_iter.next() {
-                    s.push_str(v);
-                    s.push_str(", ");
-                }
-            }
+        if !constructor && library(Target::Ispc).defines(id) {
+            self.gen_single(id, args, ty)
+        } else {
+            self.gen_call(id, args)
         }
+    }
 
-        for (k, v) in args_iter.enumerate() {
-            s.push_str(&v);
-            if let VarType::Buffer { .. } = vars[k] {
-                s.push_str(", ___str_");
-                s.push_str(&v);
-            }
-            if k < vars.len() - 1 {
-                s.push_str(", ");
-            }
-        }
+    fn gen_call(&self, id: &str, args: &[Expr]) -> Result<String, String> {
+        Ok(format!("{}({})", id, self.gen_args(args)?))
+    }
 
-        Ok(format!("{}({})", id, s))
+    // call of a single precision helper of the library or the host, converting vectors
+    fn gen_single(&self, id: &str, args: &[Expr], ty: VarType) -> Result<String, String> {
+        let args = args
+            .iter()
+            .map(|a| Ok(self.narrow(self.gen_args(slice::from_ref(a))?, a.ty)))
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(self.widen(format!("{}({})", id, args.join(", ")), ty))
     }
 
-    fn gen_assign(&'a self, expr: &Expr, val: &Expr) -> Result<String, String> {
-        let s = if let Expr::Index(expr, idx) = expr {
-            if let Index::ColorSpace(cs_from) = &**idx {
-                // assign vec with color space conversion
-                if let Expr::Index(id, idx) = &**expr {
-                    if let Expr::Identifier(name) = &**id {
-                        if let Index::Array2D(a, b) = &**idx {
-                            let var = self.inference.borrow().var_type(id)?;
-                            if let VarType::Buffer { z, cs, x1y1 } = var {
-                                let cs = format!("{}to{}", cs_from, cs);
-                                let a = self.gen_expr(a)?;
-                                let b = self.gen_expr(b)?;
-                                let guard = if x1y1 {
-                                    format!("if ({}==0 && {}==0) ", a, b,)
-                                } else {
-                                    format!(
-                                        "if ({}>=0 && {}<___str_{}[0] && {}>=0 && {}<___str_{}[1]) ",
-                                        a, a, name, b, b, name
-                                    )
-                                };
-                                let val = self.gen_expr(val)?;
-                                if z == 3 {
-                                    let id_x = format!(
-                                        "{}[(varying int)({})]",
-                                        name,
-                                        var.idx_3d(name, &a, &b, "0")
-                                    );
-                                    let id_y = format!(
-                                        "{}[(varying int)({})]",
-                                        name,
-                                        var.idx_3d(name, &a, &b, "1")
-                                    );
-                                    let id_z = format!(
-                                        "{}[(varying int)({})]",
-                                        name,
-                                        var.idx_3d(name, &a, &b, "2")
-                                    );
-                                    format!("{} {{ float<3> __v = {}({}); {} = __v.x; {} = __v.y; {} = __v.z; }}\n",
-                                        guard, cs, val, id_x, id_y, id_z)
-                                } else if z == 1 {
-                                    // match buffer storage size to color space
-                                    let id = format!(
-                                        "{}[(varying int)({})]",
-                                        name,
-                                        var.idx_3d(name, &a, &b, "0")
-                                    );
-                                    format!("{} {} = {}({});\n", guard, id, cs, val)
-                                } else {
-                                    return Err(format!(
-                                        "Expected buffer '{}' to have z==1 or z==3, found z=={}",
-                                        name, z
-                                    ));
-                                }
-                            } else {
-                                return Err(format!("Expected variable '{}' to be a buffer for color space property access, found '{}'", name, var));
-                            }
-                        } else {
-                            return Err(format!("Expected 2D index for color space property access on buffer '{}', found '{:?}'", name,  idx));
-                        }
-                    } else {
-                        return Err(format!("Expected buffer identifier for color space property access, found '{:?}'", expr));
-                    }
-                } else {
-                    return Err(format!(
-                        "Expected element index for color space property asccess, found '{:?}'",
-                        expr
-                    ));
-                }
-            } else if let Index::Array1D(a) = &**idx {
-                let var = self.inference.borrow().var_type(expr)?;
-                if let Expr::Identifier(name) = &**expr {
-                    match var {
-                        VarType::BoolArray(1, ..)
-                        | VarType::IntArray(1, ..)
-                        | VarType::FloatArray(1, ..)
-                        | VarType::VecArray(1, ..) => {
-                            format!(
-                                "{}[{}] = {};\n",
-                                name,
-                                self.gen_expr(a)?,
-                                self.gen_expr(val)?
-                            )
-                        }
-                        VarType::Buffer { x1y1, .. } => {
-                            let a = self.gen_expr(a)?;
-                            let val = self.gen_expr(val)?;
-                            let guard = if x1y1 {
-                                format!("if ({}>=0 && {}<___str_{}[2]) ", a, a, name,)
-                            } else {
-                                format!("if ({}>=0 && {}<(___str_{}[0] * ___str_{}[1] * ___str_{}[2])) ",
-                                a, a, name, name, name)
-                            };
-                            let id = format!("{}[(varying int)({})]", name, var.idx_1d(name, &a));
-                            format!("{} {} = {};\n", guard, id, val)
-                        }
-                        t => {
-                            return Err(format!(
-                                "Unable to index variable '{}' of type '{}'",
-                                name, t
-                            ))
-                        }
-                    }
-                } else {
-                    return Err(format!(
-                        "Expected buffer or array identifier for indexed access, found '{:?}'",
-                        expr
-                    ));
-                }
-            } else if let Index::Array2D(a, b) = &**idx {
-                let var = self.inference.borrow().var_type(expr)?;
-                if let Expr::Identifier(name) = &**expr {
-                    match var {
-                        VarType::Buffer { z: 1, x1y1, .. } => {
-                            let a = self.gen_expr(a)?;
-                            let b = self.gen_expr(b)?;
-                            let guard = if x1y1 {
-                                format!("if ({}==0 && {}==0) ", a, b,)
-                            } else {
-                                format!(
-                                    "if ({}>=0 && {}<___str_{}[0] && {}>=0 && {}<___str_{}[1]) ",
-                                    a, a, name, b, b, name
-                                )
-                            };
-                            let val = self.gen_expr(val)?;
-
-                            let id = format!(
-                                "{}[(varying int)({})]",
-                                name,
-                                var.idx_3d(name, &a, &b, "0")
-                            );
-                            format!("{} {} = {};\n", guard, id, val)
-                        }
-                        VarType::Buffer { z: 3, x1y1, .. } => {
-                            let a = self.gen_expr(a)?;
-                            let b = self.gen_expr(b)?;
-                            let guard = if x1y1 {
-                                format!("if ({}==0 && {}==0) ", a, b,)
-                            } else {
-                                format!(
-                                    "if ({}>=0 && {}<___str_{}[0] && {}>=0 && {}<___str_{}[1]) ",
-                                    a, a, name, b, b, name
-                                )
-                            };
-                            let val = self.gen_expr(val)?;
-
-                            let id_x = format!(
-                                "{}[(varying int)({})]",
-                                name,
-                                var.idx_3d(name, &a, &b, "0")
-                            );
-                            let id_y = format!(
-                                "{}[(varying int)({})]",
-                                name,
-                                var.idx_3d(name, &a, &b, "1")
-                            );
-                            let id_z = format!(
-                                "{}[(varying int)({})]",
-                                name,
-                                var.idx_3d(name, &a, &b, "2")
-                            );
-                            format!(
-                                "{} {{ float<3> __v = {}; {} = __v.x; {} = __v.y; {} = __v.z; }}\n",
-                                guard, val, id_x, id_y, id_z
-                            )
-                        }
-                        VarType::BoolArray(2, ..)
-                        | VarType::IntArray(2, ..)
-                        | VarType::FloatArray(2, ..)
-                        | VarType::VecArray(2, ..) => format!(
-                            "{}[{}][{}] = {};\n",
-                            name,
-                            self.gen_expr(a)?,
-                            self.gen_expr(b)?,
-                            self.gen_expr(val)?
-                        ),
-                        t => {
-                            return Err(format!(
-                                "Unable to index variable '{}' of type '{}'",
-                                name, t
-                            ))
-                        }
-                    }
-                } else {
-                    return Err(format!(
-                        "Expected buffer or array identifier for indexed access, found '{:?}'",
-                        expr
-                    ));
-                }
-            } else if let Index::Array3D(a, b, c) = &**idx {
-                let var = self.inference.borrow().var_type(expr)?;
-                if let Expr::Identifier(name) = &**expr {
-                    match var {
-                        VarType::BoolArray(3, ..)
-                        | VarType::IntArray(3, ..)
-                        | VarType::FloatArray(3, ..)
-                        | VarType::VecArray(3, ..) => format!(
-                            "{}[{}][{}][{}] = {};\n",
-                            name,
-                            self.gen_expr(a)?,
-                            self.gen_expr(b)?,
-                            self.gen_expr(c)?,
-                            self.gen_expr(val)?
-                        ),
-                        VarType::Buffer { x1y1, .. } => {
-                            let a = self.gen_expr(a)?;
-                            let b = self.gen_expr(b)?;
-                            let c = self.gen_expr(c)?;
-                            let guard = if x1y1 {
-                                format!(
-                                    "if ({}==0 && {}==0 && {}>=0 && {}<___str_{}[2]) ",
-                                    a, b, c, c, name
-                                )
-                            } else {
-                                format!(
-                                    "if ({}>=0 && {}<___str_{}[0] && {}>=0 && {}<___str_{}[1] && {}>=0 && {}<___str_{}[2]) ",
-                                    a, a, name, b, b, name, c, c, name
-                                )
-                            };
-                            let val = self.gen_expr(val)?;
-
-                            let id = format!(
-                                "{}[(varying int)({})]",
-                                name,
-                                var.idx_3d(name, &a, &b, &c)
-                            );
-                            format!("{} {} = {};\n", guard, id, val)
-                        }
-                        t => {
-                            return Err(format!(
-                                "Unable to index variable '{}' of type '{}'",
-                                name, t
-                            ))
-                        }
-                    }
-                } else {
-                    return Err(format!(
-                        "Expected buffer or array identifier for indexed access, found '{:?}'",
-                        expr
-                    ));
-                }
-            } else if let Index::Array4D(a, b, c, d) = &**idx {
-                let var = self.inference.borrow().var_type(expr)?;
-                if let Expr::Identifier(name) = &**expr {
-                    match var {
-                        VarType::BoolArray(4, ..)
-                        | VarType::IntArray(4, ..)
-                        | VarType::FloatArray(4, ..)
-                        | VarType::VecArray(4, ..) => format!(
-                            "{}[{}][{}][{}][{}] = {};\n",
-                            name,
-                            self.gen_expr(a)?,
-                            self.gen_expr(b)?,
-                            self.gen_expr(c)?,
-                            self.gen_expr(d)?,
-                            self.gen_expr(val)?
-                        ),
-                        t => {
-                            return Err(format!(
-                                "Unable to index variable '{}' of type '{}'",
-                                name, t
-                            ))
-                        }
-                    }
-                } else {
-                    return Err(format!(
-                        "Expected buffer or array identifier for indexed access, found '{:?}'",
-                        expr
-                    ));
-                }
+    // comma-separated call arguments, buffers are passed together with their strides
+    fn gen_args(&self, args: &[Expr]) -> Result<String, String> {
+        let mut s = Vec::new();
+        for v in args {
+            let arg = self.gen_expr(v)?;
+            if let VarType::Buffer { .. } = v.ty {
+                s.push(format!("{}, ___str_{}", arg, arg));
             } else {
-                let id = self.gen_index(expr, idx)?;
-                format!("{} = {};\n", id, self.gen_expr(val)?)
+                s.push(arg);
             }
-        } else {
-            format!("{} = {};\n", self.gen_expr(expr)?, self.gen_expr(val)?)
-        };
+        }
 
-        Ok(s)
+        Ok(s.join(", "))
     }
 
-    fn gen_index(&'a self, expr: &Expr, idx: &Index) -> Result<String, String> {
-        // recursively unwrap nested indices to find name
-        let name;
-        let mut name_expr = expr;
-        loop {
-            match name_expr {
-                Expr::Index(expr, _) => {
-                    name_expr = &**expr;
-                }
-                Expr::Identifier(n) => {
-                    name = n;
-                    break;
-                }
-                expr => {
-                    return Err(format!(
-                        "Expected buffer or array identifier for indexed access, found '{:?}'",
-                        expr
-                    ))
-                }
-            }
-        }
+    fn gen_assign(&self, place: &Place, val: &Expr) -> Result<String, String> {
+        let val = self.gen_expr(val)?;
 
-        let s = match idx {
-            Index::Vec(0) => {
-                let var = self.inference.borrow().var_type(expr)?;
-                match var {
-                    VarType::Vec => format!("{}.x", self.gen_expr(expr)?),
-                    VarType::Buffer { .. } => format!("___str_{}[0]", name),
-                    t => {
-                        return Err(format!(
-                            "Variable '{}' of type '{}' does not support property access",
-                            name, t
-                        ))
-                    }
-                }
-            }
-            Index::Vec(1) => {
-                let var = self.inference.borrow().var_type(expr)?;
-                match var {
-                    VarType::Vec => format!("{}.y", self.gen_expr(expr)?),
-                    VarType::Buffer { .. } => format!("___str_{}[1]", name),
-                    t => {
-                        return Err(format!(
-                            "Variable '{}' of type '{}' does not support property access",
-                            name, t
-                        ))
-                    }
-                }
-            }
-            Index::Vec(2) => {
-                let var = self.inference.borrow().var_type(expr)?;
-                match var {
-                    VarType::Vec => format!("{}.z", self.gen_expr(expr)?),
-                    VarType::Buffer { .. } => format!("___str_{}[2]", name),
+        let s = match place {
+            Place::Expr(expr) => format!("{} = {};\n", self.gen_expr(expr)?, val),
+            Place::Array(id, idx) => format!("{}{} = {};\n", id, self.gen_idx(idx)?, val),
+            Place::Buffer(name, var, idx) => {
+                let (z, x1y1) = match var {
+                    VarType::Buffer { z, x1y1, .. } => (*z, *x1y1),
                     t => {
                         return Err(format!(
-                            "Variable '{}' of type '{}' does not support property access",
+                            "Unable to index variable '{}' of type '{}'",
                             name, t
                         ))
                     }
-                }
-            }
-            Index::Array1D(a) => {
-                if let Expr::Identifier(id) = expr {
-                    let var = self.inference.borrow().var_type(expr)?;
-                    match var {
-                        VarType::Buffer { .. } => var.buf_idx_1d(id, &self.gen_expr(a)?),
-                        VarType::BoolArray(1, ..)
-                        | VarType::IntArray(1, ..)
-                        | VarType::FloatArray(1, ..)
-                        | VarType::VecArray(1, ..) => format!("{}[{}]", id, self.gen_expr(a)?),
-                        t => {
-                            return Err(format!(
-                                "Unable to index variable '{}' of type '{}'",
-                                name, t
-                            ))
-                        }
-                    }
-                } else {
-                    return Err(format!(
-                        "Expected buffer or array identifier for indexed access, found '{:?}'",
-                        expr
-                    ));
-                }
-            }
-            Index::Array2D(a, b) => {
-                if let Expr::Identifier(id) = expr {
-                    let var = self.inference.borrow().var_type(expr)?;
-                    match var {
-                        VarType::Buffer { z: 1, .. } => {
-                            var.buf_idx_3d(id, &self.gen_expr(a)?, &self.gen_expr(b)?, "0")
-                        }
-                        VarType::Buffer { z: 3, .. } => format!(
-                            "vec{}",
-                            var.buf_idx_2d(id, &self.gen_expr(a)?, &self.gen_expr(b)?)
-                        ),
-                        VarType::BoolArray(2, ..)
-                        | VarType::IntArray(2, ..)
-                        | VarType::FloatArray(2, ..)
-                        | VarType::VecArray(2, ..) => {
-                            format!("{}[{}][{}]", id, self.gen_expr(a)?, self.gen_expr(b)?)
-                        }
-                        t => {
-                            return Err(format!(
-                                "Unable to index variable '{}' of type '{}'",
-                                name, t
-                            ))
-                        }
-                    }
-                } else {
-                    return Err(format!(
-                        "Expected buffer or array identifier for indexed access, found '{:?}'",
-                        expr
-                    ));
-                }
-            }
-            Index::Array3D(a, b, c) => {
-                if let Expr::Identifier(id) = expr {
-                    let var = self.inference.borrow().var_type(expr)?;
-                    match var {
-                        VarType::Buffer { .. } => var.buf_idx_3d(
-                            id,
-                            &self.gen_expr(a)?,
-                            &self.gen_expr(b)?,
-                            &self.gen_expr(c)?,
-                        ),
-                        VarType::BoolArray(3, ..)
-                        | VarType::IntArray(3, ..)
-                        | VarType::FloatArray(3, ..)
-                        | VarType::VecArray(3, ..) => format!(
-                            "{}[{}][{}][{}]",
-                            id,
-                            self.gen_expr(a)?,
-                            self.gen_expr(b)?,
-                            self.gen_expr(c)?,
-                        ),
-                        t => {
-                            return Err(format!(
-                                "Unable to index variable '{}' of type '{}'",
-                                name, t
-                            ))
-                        }
-                    }
-                } else {
-                    return Err(format!(
-                        "Expected buffer or array identifier for indexed access, found '{:?}'",
-                        expr
-                    ));
-                }
-            }
-            Index::Array4D(a, b, c, d) => {
-                if let Expr::Identifier(id) = expr {
-                    let var = self.inference.borrow().var_type(expr)?;
-                    match var {
-                        VarType::BoolArray(4, ..)
-                        | VarType::IntArray(4, ..)
-                        | VarType::FloatArray(4, ..)
-                        | VarType::VecArray(4, ..) => format!(
-                            "{}[{}][{}][{}][{}]",
-                            id,
-                            self.gen_expr(a)?,
-                            self.gen_expr(b)?,
-                            self.gen_expr(c)?,
-                            self.gen_expr(d)?,
-                        ),
-                        t => {
-                            return Err(format!(
-                                "Unable to index variable '{}' of type '{}'",
-                                name, t
-                            ))
-                        }
-                    }
-                } else {
-                    return Err(format!(
-                        "Expected buffer or array identifier for indexed access, found '{:?}'",
-                        expr
-                    ));
-                }
-            }
-            Index::ColorSpace(cs_to) => {
-                if let Expr::Index(expr, idx) = expr {
-                    if let Expr::Identifier(id) = &**expr {
-                        let var = self.inference.borrow().var_type(expr)?;
-                        if let VarType::Buffer { z, cs, .. } = var {
-                            let id = if let Index::Array2D(a, b) = &**idx {
-                                if z == 1 {
-                                    var.buf_idx_3d(id, &self.gen_expr(a)?, &self.gen_expr(b)?, "0")
-                                } else if z == 3 {
-                                    format!(
-                                        "vec{}",
-                                        var.buf_idx_2d(id, &self.gen_expr(a)?, &self.gen_expr(b)?)
-                                    )
-                                } else {
-                                    return Err(format!(
-                                        "Expected buffer '{}' to have z==1 or z==3, found z=={}",
-                                        name, z
-                                    ));
-                                }
-                            } else {
-                                return Err(format!("Expected 2D index for color space property access on buffer '{}', found '{:?}'", name,  idx));
-                            };
-                            format!("{}to{}({})", cs, cs_to, id)
+                };
+
+                match idx {
+                    BufIdx::Linear(a) => {
+                        let a = self.gen_expr(a)?;
+                        let guard = if x1y1 {
+                            format!("if ({}>=0 && {}<___str_{}[2]) ", a, a, name,)
                         } else {
-                            return Err(format!("Expected 2D index for color space property access on buffer '{}', found '{:?}'", name,  idx));
-                        }
-                    } else {
-                        return Err(format!("Expected buffer identifier for color space property access, found '{:?}'", expr));
+                            format!(
+                                "if ({}>=0 && {}<(___str_{}[0] * ___str_{}[1] * ___str_{}[2])) ",
+                                a, a, name, name, name
+                            )
+                        };
+                        let id = store(name, var.idx_1d(name, &a));
+                        format!("{} {} = {};\n", guard, id, val)
                     }
-                } else {
-                    return Err(format!(
-                        "Expected element index for color space property asccess, found '{:?}'",
-                        expr
-                    ));
-                }
-            }
+                    BufIdx::Pixel(a, b) => {
+                        let a = self.gen_expr(a)?;
+                        let b = self.gen_expr(b)?;
+                        let guard = if x1y1 {
+                            format!("if ({}==0 && {}==0) ", a, b,)
+                        } else {
+                            format!(
+                                "if ({}>=0 && {}<___str_{}[0] && {}>=0 && {}<___str_{}[1]) ",
+                                a, a, name, b, b, name
+                            )
+                        };
 
-            Index::Prop(prop) => {
-                if let Expr::Index(expr, idx) = expr {
-                    if let Expr::Identifier(id) = &**expr {
-                        let var = self.inference.borrow().var_type(expr)?;
-                        let idx = &**idx;
-                        match var {
-                            VarType::Buffer { .. } => {
-                                let idx = match (var, idx) {
-                                    (VarType::Buffer { .. }, Index::Array1D(a)) => {
-                                        var.idx_1d(id, &self.gen_expr(a)?)
-                                    }
-                                    (VarType::Buffer { z: 1, .. }, Index::Array2D(a, b)) => {
-                                        var.idx_3d(id, &self.gen_expr(a)?, &self.gen_expr(b)?, "0")
-                                    }
-                                    (VarType::Buffer { .. }, Index::Array3D(a, b, c)) => var
-                                        .idx_3d(
-                                            id,
-                                            &self.gen_expr(a)?,
-                                            &self.gen_expr(b)?,
-                                            &self.gen_expr(c)?,
-                                        ),
-                                    (t, _) => return Err(format!("Variable '{}' of type '{}' does not support property access", name, t)),
-                                };
-                                match prop {
-                                    Prop::Int => format!("(((uniform int*){})[{}])", id, idx), //only for buffers
-                                    Prop::Idx => idx,
-                                    Prop::Ptr => format!("({} + {})", id, idx),
-                                    Prop::IntPtr => format!("(((uniform int*){}) + {})", id, idx), // only for buffers
-                                }
-                            }
-                            VarType::FloatArray(..) => {
-                                if let Prop::Ptr = prop {
-                                    match (var, idx) {
-                                        (VarType::FloatArray(1, ..), Index::Array1D(a)) => {
-                                            format!("({} + {})", id, self.gen_expr(a)?)
-                                        }
-                                        (VarType::FloatArray(2, ..), Index::Array2D(a, b)) => {
-                                            format!(
-                                                "({}[{}] + {})",
-                                                id,
-                                                self.gen_expr(a)?,
-                                                self.gen_expr(b)?
-                                            )
-                                        }
-                                        (VarType::FloatArray(3, ..), Index::Array3D(a, b, c)) => {
-                                            format!(
-                                                "({}[{}][{}] + {})",
-                                                id,
-                                                self.gen_expr(a)?,
-                                                self.gen_expr(b)?,
-                                                self.gen_expr(c)?
-                                            )
-                                        }
-                                        (
-                                            VarType::FloatArray(4, ..),
-                                            Index::Array4D(a, b, c, d),
-                                        ) => format!(
-                                            "({}[{}][{}][{}] + {})",
-                                            id,
-                                            self.gen_expr(a)?,
-                                            self.gen_expr(b)?,
-                                            self.gen_expr(c)?,
-                                            self.gen_expr(d)?
-                                        ),
-                                        (t, _) => return Err(format!("Variable '{}' of type '{}' does not support property access", name, t)),
-                                    }
-                                } else {
-                                    return Err(format!("Array '{}' does not support property access except for '.ptr'", name));
-                                }
-                            }
-                            VarType::IntArray(..) => {
-                                if let Prop::Ptr = prop {
-                                    match (var, idx) {
-                                        (VarType::IntArray(1, ..), Index::Array1D(a)) => {
-                                            format!("({} + {})", id, self.gen_expr(a)?)
-                                        }
-                                        (VarType::IntArray(2, ..), Index::Array2D(a, b)) => {
-                                            format!(
-                                                "({}[{}] + {})",
-                                                id,
-                                                self.gen_expr(a)?,
-                                                self.gen_expr(b)?
-                                            )
-                                        }
-                                        (VarType::IntArray(3, ..), Index::Array3D(a, b, c)) => {
-                                            format!(
-                                                "({}[{}][{}] + {})",
-                                                id,
-                                                self.gen_expr(a)?,
-                                                self.gen_expr(b)?,
-                                                self.gen_expr(c)?
-                                            )
-                                        }
-                                        (VarType::IntArray(4, ..), Index::Array4D(a, b, c, d)) => {
-                                            format!(
-                                                "({}[{}][{}][{}] + {})",
-                                                id,
-                                                self.gen_expr(a)?,
-                                                self.gen_expr(b)?,
-                                                self.gen_expr(c)?,
-                                                self.gen_expr(d)?
-                                            )
-                                        }
-                                        (t, _) => return Err(format!("Variable '{}' of type '{}' does not support property access", name, t)),
-                                    }
-                                } else {
-                                    return Err(format!("Array '{}' does not support property access except for '.ptr'", name));
-                                }
-                            }
-                            t => {
-                                return Err(format!(
-                                    "Variable '{}' of type '{}' does not support property access",
-                                    name, t
-                                ))
-                            }
+                        if z == 3 {
+                            let id_x = store(name, var.idx_3d(name, &a, &b, "0"));
+                            let id_y = store(name, var.idx_3d(name, &a, &b, "1"));
+                            let id_z = store(name, var.idx_3d(name, &a, &b, "2"));
+                            format!(
+                                "{} {{ {} __v = {}; {} = __v.x; {} = __v.y; {} = __v.z; }}\n",
+                                guard,
+                                self.scalar(VarType::Vec)?,
+                                val,
+                                id_x,
+                                id_y,
+                                id_z
+                            )
+                        } else {
+                            let id = store(name, var.idx_3d(name, &a, &b, "0"));
+                            format!("{} {} = {};\n", guard, id, val)
                         }
-                    } else {
-                        return Err(format!(
-                            "Expected buffer or array identifier for property access, found '{:?}'",
-                            expr
-                        ));
                     }
-                } else {
-                    return Err(format!(
-                        "Expected element index for property asccess, found '{:?}'",
-                        expr
-                    ));
+                    BufIdx::Element(a, b, c) => {
+                        let a = self.gen_expr(a)?;
+                        let b = self.gen_expr(b)?;
+                        let c = self.gen_expr(c)?;
+                        let guard = if x1y1 {
+                            format!(
+                                "if ({}==0 && {}==0 && {}>=0 && {}<___str_{}[2]) ",
+                                a, b, c, c, name
+                            )
+                        } else {
+                            format!(
+                                "if ({}>=0 && {}<___str_{}[0] && {}>=0 && {}<___str_{}[1] && {}>=0 && {}<___str_{}[2]) ",
+                                a, a, name, b, b, name, c, c, name
+                            )
+                        };
+                        let id = store(name, var.idx_3d(name, &a, &b, &c));
+                        format!("{} {} = {};\n", guard, id, val)
+                    }
                 }
             }
-            i => {
-                return Err(format!(
-                    "Variable '{}' cannot be indexed with '{:?}'",
-                    name, i
-                ))
-            }
         };
 
         Ok(s)
@@ -15,11 +15,10 @@
     along with this program.  If not, see <http://www.gnu.org/licenses/>.
 */
 
-use std::cell::RefCell;
-use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
 
-use crate::ast::{BinaryOp, ColorSpace, Expr, Index, Literal, Prop, UnaryOp};
-use crate::function_id::function_id;
+use crate::ast::{Annotation, BinaryOp, ColorSpace, Index, Literal, Prop, UnaryOp};
+use crate::ir::{Expr, ExprKind, Struct};
 use crate::scope::ScopeTree;
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
@@ -28,11 +27,15 @@ pub enum VarType {
     Int,
     Float,
     Vec,
+    Mat3, // 3x3 matrix, multiplies vecs as columns
     BoolArray(u8, bool, u64, u64, u64, u64),
     IntArray(u8, bool, u64, u64, u64, u64),
     FloatArray(u8, bool, u64, u64, u64, u64),
     VecArray(u8, bool, u64, u64, u64, u64),
+    StructArray(u32, u8, u64, u64, u64, u64), // struct, dimensions and size
     Buffer { x1y1: bool, z: u64, cs: ColorSpace },
+    Struct(u32),   // index of a struct in the sorted structs of the source
+    Function(u32), // index of a function in the sorted names of the source, see Lower::function_index
     Void,
     Unknown,
 }
@@ -42,6 +45,14 @@ const I: VarType = VarType::Int;
 const F: VarType = VarType::Float;
 const V: VarType = VarType::Vec;
 
+fn bool_to_int(t: VarType) -> VarType {
+    if t == B {
+        I
+    } else {
+        t
+    }
+}
+
 impl std::fmt::Display for VarType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -49,6 +60,7 @@ impl std::fmt::Display for VarType {
             VarType::Int => write!(f, "Int"),
             VarType::Float => write!(f, "Float"),
             VarType::Vec => write!(f, "Vec"),
+            VarType::Mat3 => write!(f, "Mat3"),
             VarType::BoolArray(d, l, ..) => {
                 write!(f, "{}D {}BoolArray", d, if *l { "local " } else { "" })
             }
@@ -61,229 +73,205 @@ impl std::fmt::Display for VarType {
             VarType::VecArray(d, l, ..) => {
                 write!(f, "{}D {}VecArray", d, if *l { "local " } else { "" })
             }
+            VarType::StructArray(_, d, ..) => write!(f, "{}D StructArray", d),
             VarType::Buffer { z, cs, x1y1: true } => write!(f, "{}ch {} x1y1 Buffer", z, cs),
             VarType::Buffer { z, cs, x1y1: false } => write!(f, "{}ch {} Buffer", z, cs),
+            VarType::Struct(_) => write!(f, "Struct"),
+            VarType::Function(_) => write!(f, "Function"),
             VarType::Void => write!(f, "Void"),
             VarType::Unknown => write!(f, "Unknown"),
         }
     }
 }
 
-pub struct Inference<'a> {
+pub struct Inference {
     pub scope: ScopeTree,
-    pub functions: Option<&'a RefCell<HashMap<String, (String, String, VarType, HashSet<String>)>>>,
+    pub structs: Rc<Vec<Struct>>,
 }
 
-impl<'a> Inference<'a> {
-    pub fn new() -> Inference<'a> {
+impl Inference {
+    pub fn new(structs: Rc<Vec<Struct>>) -> Inference {
         Inference {
             scope: ScopeTree::new(),
-            functions: None,
+            structs,
         }
     }
 
-    pub fn var_type(&self, expr: &Expr) -> Result<VarType, String> {
-        // handle Prop::Ptr separately as it needs information about the array/buffer before indexing
-        if let Expr::Index(expr, idx) = expr {
-            if let (Expr::Index(expr, _), Index::Prop(Prop::Ptr)) = (&**expr, &**idx) {
-                if let Expr::Identifier(id) = &**expr {
-                    if let Some(t) = self.scope.get(id) {
-                        let t = match t {
-                            VarType::IntArray(_, true, ..) => {
-                                VarType::IntArray(1, true, 0, 0, 0, 0)
-                            }
-                            VarType::IntArray(_, false, ..) => {
-                                VarType::IntArray(1, false, 0, 0, 0, 0)
-                            }
-                            VarType::FloatArray(_, true, ..) => {
-                                VarType::FloatArray(1, true, 0, 0, 0, 0)
-                            }
-                            VarType::FloatArray(_, false, ..) => {
-                                VarType::FloatArray(1, false, 0, 0, 0, 0)
-                            }
-                            VarType::Buffer { .. } => VarType::FloatArray(1, false, 0, 0, 0, 0),
-                            t => {
-                                return Err(format!(
-                                "Variable '{}' of type '{}' does not support the '.ptr' property",
-                                id, t
-                            ))
-                            }
-                        };
-
-                        return Ok(t);
-                    } else {
-                        return Err(format!("Variable '{}' is not defined", id));
-                    }
-                }
+    // index and declaration of a struct
+    pub fn struct_type(&self, id: &str) -> Option<(u32, &Struct)> {
+        self.structs
+            .iter()
+            .enumerate()
+            .find(|(_, s)| s.id == id)
+            .map(|(n, s)| (n as u32, s))
+    }
+
+    pub fn unary(&self, op: &UnaryOp, t: VarType) -> Result<VarType, String> {
+        Ok(match (op, t) {
+            (UnaryOp::Not, B) => B,
+            (UnaryOp::Neg, I) => I,
+            (UnaryOp::Neg, F) => F,
+            (UnaryOp::Neg, V) => V,
+            (op, t) => {
+                return Err(format!(
+                    "Variable of type '{}' does not support unary operation '{:?}'",
+                    t, op
+                ))
             }
-        }
+        })
+    }
 
-        let t = match expr {
-            Expr::Literal(Literal::Bool(_)) => B,
-            Expr::Literal(Literal::Int(_)) => I,
-            Expr::Literal(Literal::Float(_)) => F,
-            Expr::Identifier(i) => {
-                if let Some(t) = self.scope.get(i) {
-                    t
-                } else {
-                    VarType::Unknown // identifier of unknown type
-                }
-            }
-            Expr::Unary(u) => match (&(*u).op, self.var_type(&u.right)?) {
-                (UnaryOp::Not, B) => B,
-                (UnaryOp::Neg, I) => I,
-                (UnaryOp::Neg, F) => F,
-                (UnaryOp::Neg, V) => V,
-                (op, t) => {
-                    return Err(format!(
-                        "Variable of type '{}' does not support unary operation '{:?}'",
-                        t, op
-                    ))
-                }
-            },
-            Expr::Binary(b) => {
-                match (&(*b).op, self.var_type(&b.left)?, self.var_type(&b.right)?) {
-                    (BinaryOp::And, B, B) => B,
-                    (BinaryOp::Or, B, B) => B,
-                    (BinaryOp::Equal, _, _) => B,
-                    (BinaryOp::NotEqual, _, _) => B,
-                    (BinaryOp::Greater, l, r) if (l == I || l == F) && (r == I || r == F) => B,
-                    (BinaryOp::GreaterEqual, l, r) if (l == I || l == F) && (r == I || r == F) => B,
-                    (BinaryOp::Less, l, r) if (l == I || l == F) && (r == I || r == F) => B,
-                    (BinaryOp::LessEqual, l, r) if (l == I || l == F) && (r == I || r == F) => B,
-                    (BinaryOp::Pow, l, r) => self.promote(self.promote(l, r)?, F)?,
-                    (BinaryOp::Div, l, r) => self.promote(self.promote(l, r)?, F)?,
-
-                    (BinaryOp::Add, l, r) => self.promote_num(l, r)?,
-                    (BinaryOp::Sub, l, r) => self.promote_num(l, r)?,
-                    (BinaryOp::Mul, l, r) => self.promote_num(l, r)?,
-                    (BinaryOp::Mod, l, r) => self.promote_num(l, r)?,
-                    (op, l, r) => {
-                        return Err(format!(
+    pub fn binary(&self, op: &BinaryOp, l: VarType, r: VarType) -> Result<VarType, String> {
+        Ok(match (op, l, r) {
+            (BinaryOp::And, B, B) => B,
+            (BinaryOp::Or, B, B) => B,
+            (BinaryOp::Equal, l, r) if l != VarType::Mat3 && r != VarType::Mat3 => B,
+            (BinaryOp::NotEqual, l, r) if l != VarType::Mat3 && r != VarType::Mat3 => B,
+            (BinaryOp::Greater, l, r) if (l == I || l == F) && (r == I || r == F) => B,
+            (BinaryOp::GreaterEqual, l, r) if (l == I || l == F) && (r == I || r == F) => B,
+            (BinaryOp::Less, l, r) if (l == I || l == F) && (r == I || r == F) => B,
+            (BinaryOp::LessEqual, l, r) if (l == I || l == F) && (r == I || r == F) => B,
+            (BinaryOp::Pow, l, r) => self.promote(self.promote(l, r)?, F)?,
+            (BinaryOp::Div, l, r) => self.promote(self.promote(l, r)?, F)?,
+
+            // matrix products, vecs are multiplied as columns
+            (BinaryOp::Mul, VarType::Mat3, V) => V,
+            (BinaryOp::Mul, VarType::Mat3, VarType::Mat3) => VarType::Mat3,
+
+            // bools count as int in arithmetic, e.g. when summing a bool array
+            (BinaryOp::Add, l, r) => self.promote_num(bool_to_int(l), bool_to_int(r))?,
+            (BinaryOp::Sub, l, r) => self.promote_num(bool_to_int(l), bool_to_int(r))?,
+            (BinaryOp::Mul, l, r) => self.promote_num(bool_to_int(l), bool_to_int(r))?,
+            (BinaryOp::Mod, l, r) => self.promote_num(bool_to_int(l), bool_to_int(r))?,
+            (op, l, r) => {
+                return Err(format!(
                     "Unable to infer type of operation '{:?}' with arguments of type '{}' and '{}'",
                     op, l, r
                 ))
-                    }
-                }
-            }
-            Expr::Index(expr, idx) => match (self.var_type(expr)?, &**idx) {
-                (V, Index::Vec(_)) => F,
-                (VarType::Buffer { .. }, Index::Vec(_)) => I,
-                (V, Index::ColorSpace(c)) => match c {
-                    // 3ch buffer
-                    ColorSpace::Srgb => V,
-                    ColorSpace::Lrgb => V,
-                    ColorSpace::Xyz => V,
-                    ColorSpace::Lab => V,
-                    ColorSpace::Lch => V,
-                    ColorSpace::Y => F,
-                    ColorSpace::L => F,
-                },
-                (F, Index::ColorSpace(c)) => match c {
-                    // 1ch buffer
-                    ColorSpace::Srgb => V,
-                    ColorSpace::Lrgb => V,
-                    ColorSpace::Xyz => V,
-                    ColorSpace::Lab => V,
-                    ColorSpace::Lch => V,
-                    ColorSpace::Y => F,
-                    ColorSpace::L => F,
-                },
-
-                (F, Index::Prop(Prop::Int)) => I,
-                (F, Index::Prop(Prop::Idx)) => I,
-                (F, Index::Prop(Prop::IntPtr)) => VarType::IntArray(1, false, 0, 0, 0, 0), // only available for buffers
-
-                (VarType::Buffer { .. }, Index::Array1D(..)) => F,
-                (VarType::Buffer { z: 3, .. }, Index::Array2D(..)) => V,
-                (VarType::Buffer { z: 1, .. }, Index::Array2D(..)) => F,
-                (VarType::Buffer { .. }, Index::Array3D(..)) => F,
-
-                (VarType::BoolArray(1, ..), Index::Array1D(..)) => B,
-                (VarType::BoolArray(2, ..), Index::Array2D(..)) => B,
-                (VarType::BoolArray(3, ..), Index::Array3D(..)) => B,
-                (VarType::BoolArray(4, ..), Index::Array4D(..)) => B,
-
-                (VarType::IntArray(1, ..), Index::Array1D(..)) => I,
-                (VarType::IntArray(2, ..), Index::Array2D(..)) => I,
-                (VarType::IntArray(3, ..), Index::Array3D(..)) => I,
-                (VarType::IntArray(4, ..), Index::Array4D(..)) => I,
-
-                (VarType::FloatArray(1, ..), Index::Array1D(..)) => F,
-                (VarType::FloatArray(2, ..), Index::Array2D(..)) => F,
-                (VarType::FloatArray(3, ..), Index::Array3D(..)) => F,
-                (VarType::FloatArray(4, ..), Index::Array4D(..)) => F,
-
-                (VarType::VecArray(1, ..), Index::Array1D(..)) => V,
-                (VarType::VecArray(2, ..), Index::Array2D(..)) => V,
-                (VarType::VecArray(3, ..), Index::Array3D(..)) => V,
-                (VarType::VecArray(4, ..), Index::Array4D(..)) => V,
-
-                _ => self.var_type(expr)?,
-            },
-            Expr::Grouping(e) => self.var_type(&**e)?,
-            Expr::Call(id, e) => {
-                if let Ok(t) = self.builtin(&id, &**e) {
-                    t
-                } else if let Ok(t) = self.function(&id, &**e) {
-                    t
-                } else {
-                    VarType::Unknown // function call with unknown return type
-                }
-            }
-            Expr::Array(v) => {
-                if v.is_empty() {
-                    return Err("Unable to construct empty array".into());
-                } else {
-                    // TODO: assert that all other elements have the same type
-                    match self.var_type(&v[0])? {
-                        B => VarType::BoolArray(1, false, v.len() as u64, 0, 0, 0),
-                        I => VarType::IntArray(1, false, v.len() as u64, 0, 0, 0),
-                        F => VarType::FloatArray(1, false, v.len() as u64, 0, 0, 0),
-                        V => VarType::VecArray(1, false, v.len() as u64, 0, 0, 0),
-                        VarType::BoolArray(1, _, a, ..) => {
-                            VarType::BoolArray(2, false, v.len() as u64, a, 0, 0)
-                        }
-                        VarType::BoolArray(2, _, a, b, ..) => {
-                            VarType::BoolArray(3, false, v.len() as u64, a, b, 0)
-                        }
-                        VarType::BoolArray(3, _, a, b, c, ..) => {
-                            VarType::BoolArray(4, false, v.len() as u64, a, b, c)
-                        }
-                        VarType::IntArray(1, _, a, ..) => {
-                            VarType::IntArray(2, false, v.len() as u64, a, 0, 0)
-                        }
-                        VarType::IntArray(2, _, a, b, ..) => {
-                            VarType::IntArray(3, false, v.len() as u64, a, b, 0)
-                        }
-                        VarType::IntArray(3, _, a, b, c, ..) => {
-                            VarType::IntArray(4, false, v.len() as u64, a, b, c)
-                        }
-                        VarType::FloatArray(1, _, a, ..) => {
-                            VarType::FloatArray(2, false, v.len() as u64, a, 0, 0)
-                        }
-                        VarType::FloatArray(2, _, a, b, ..) => {
-                            VarType::FloatArray(3, false, v.len() as u64, a, b, 0)
-                        }
-                        VarType::FloatArray(3, _, a, b, c, ..) => {
-                            VarType::FloatArray(4, false, v.len() as u64, a, b, c)
-                        }
-                        VarType::VecArray(1, _, a, ..) => {
-                            VarType::VecArray(2, false, v.len() as u64, a, 0, 0)
-                        }
-                        VarType::VecArray(2, _, a, b, ..) => {
-                            VarType::VecArray(3, false, v.len() as u64, a, b, 0)
-                        }
-                        VarType::VecArray(3, _, a, b, c, ..) => {
-                            VarType::VecArray(4, false, v.len() as u64, a, b, c)
-                        }
-                        t => return Err(format!("Unable to construct array of type '{}'", t)),
-                    }
-                }
             }
-        };
+        })
+    }
 
-        Ok(t)
+    // type of an indexed variable, falls back to the variable type for unsupported indices
+    pub fn index(&self, t: VarType, idx: &Index) -> VarType {
+        match (t, idx) {
+            (V, Index::Vec(_)) => F,
+            (VarType::Buffer { .. }, Index::Vec(_)) => I,
+            (V, Index::ColorSpace(c)) => self.color_space(*c), // 3ch buffer
+            (F, Index::ColorSpace(c)) => self.color_space(*c), // 1ch buffer
+
+            (F, Index::Prop(Prop::Int)) => I,
+            (F, Index::Prop(Prop::Idx)) => I,
+            (F, Index::Prop(Prop::IntPtr)) => VarType::IntArray(1, false, 0, 0, 0, 0), // only available for buffers
+
+            (VarType::Buffer { .. }, Index::Array1D(..)) => F,
+            (VarType::Buffer { z: 3, .. }, Index::Array2D(..)) => V,
+            (VarType::Buffer { z: 1, .. }, Index::Array2D(..)) => F,
+            (VarType::Buffer { .. }, Index::Array3D(..)) => F,
+
+            (VarType::BoolArray(1, ..), Index::Array1D(..)) => B,
+            (VarType::BoolArray(2, ..), Index::Array2D(..)) => B,
+            (VarType::BoolArray(3, ..), Index::Array3D(..)) => B,
+            (VarType::BoolArray(4, ..), Index::Array4D(..)) => B,
+
+            (VarType::IntArray(1, ..), Index::Array1D(..)) => I,
+            (VarType::IntArray(2, ..), Index::Array2D(..)) => I,
+            (VarType::IntArray(3, ..), Index::Array3D(..)) => I,
+            (VarType::IntArray(4, ..), Index::Array4D(..)) => I,
+
+            (VarType::FloatArray(1, ..), Index::Array1D(..)) => F,
+            (VarType::FloatArray(2, ..), Index::Array2D(..)) => F,
+            (VarType::FloatArray(3, ..), Index::Array3D(..)) => F,
+            (VarType::FloatArray(4, ..), Index::Array4D(..)) => F,
+
+            (VarType::VecArray(1, ..), Index::Array1D(..)) => V,
+            (VarType::VecArray(2, ..), Index::Array2D(..)) => V,
+            (VarType::VecArray(3, ..), Index::Array3D(..)) => V,
+            (VarType::VecArray(4, ..), Index::Array4D(..)) => V,
+
+            (VarType::StructArray(s, 1, ..), Index::Array1D(..)) => VarType::Struct(s),
+            (VarType::StructArray(s, 2, ..), Index::Array2D(..)) => VarType::Struct(s),
+            (VarType::StructArray(s, 3, ..), Index::Array3D(..)) => VarType::Struct(s),
+            (VarType::StructArray(s, 4, ..), Index::Array4D(..)) => VarType::Struct(s),
+
+            (t, _) => t,
+        }
+    }
+
+    pub fn color_space(&self, cs: ColorSpace) -> VarType {
+        match cs {
+            ColorSpace::Srgb => V,
+            ColorSpace::Lrgb => V,
+            ColorSpace::Xyz => V,
+            ColorSpace::Lab => V,
+            ColorSpace::Lch => V,
+            ColorSpace::Y => F,
+            ColorSpace::L => F,
+        }
+    }
+
+    // type of the '.ptr' property, needs the array/buffer type before indexing
+    pub fn ptr(&self, id: &str, t: VarType) -> Result<VarType, String> {
+        Ok(match t {
+            VarType::IntArray(_, true, ..) => VarType::IntArray(1, true, 0, 0, 0, 0),
+            VarType::IntArray(_, false, ..) => VarType::IntArray(1, false, 0, 0, 0, 0),
+            VarType::FloatArray(_, true, ..) => VarType::FloatArray(1, true, 0, 0, 0, 0),
+            VarType::FloatArray(_, false, ..) => VarType::FloatArray(1, false, 0, 0, 0, 0),
+            VarType::Buffer { .. } => VarType::FloatArray(1, false, 0, 0, 0, 0),
+            t => {
+                return Err(format!(
+                    "Variable '{}' of type '{}' does not support the '.ptr' property",
+                    id, t
+                ))
+            }
+        })
+    }
+
+    pub fn array(&self, v: &[Expr]) -> Result<VarType, String> {
+        if v.is_empty() {
+            return Err("Unable to construct empty array".into());
+        }
+
+        // TODO: assert that all other elements have the same type
+        Ok(match v[0].ty {
+            B => VarType::BoolArray(1, false, v.len() as u64, 0, 0, 0),
+            I => VarType::IntArray(1, false, v.len() as u64, 0, 0, 0),
+            F => VarType::FloatArray(1, false, v.len() as u64, 0, 0, 0),
+            V => VarType::VecArray(1, false, v.len() as u64, 0, 0, 0),
+            VarType::BoolArray(1, _, a, ..) => {
+                VarType::BoolArray(2, false, v.len() as u64, a, 0, 0)
+            }
+            VarType::BoolArray(2, _, a, b, ..) => {
+                VarType::BoolArray(3, false, v.len() as u64, a, b, 0)
+            }
+            VarType::BoolArray(3, _, a, b, c, ..) => {
+                VarType::BoolArray(4, false, v.len() as u64, a, b, c)
+            }
+            VarType::IntArray(1, _, a, ..) => VarType::IntArray(2, false, v.len() as u64, a, 0, 0),
+            VarType::IntArray(2, _, a, b, ..) => {
+                VarType::IntArray(3, false, v.len() as u64, a, b, 0)
+            }
+            VarType::IntArray(3, _, a, b, c, ..) => {
+                VarType::IntArray(4, false, v.len() as u64, a, b, c)
+            }
+            VarType::FloatArray(1, _, a, ..) => {
+                VarType::FloatArray(2, false, v.len() as u64, a, 0, 0)
+            }
+            VarType::FloatArray(2, _, a, b, ..) => {
+                VarType::FloatArray(3, false, v.len() as u64, a, b, 0)
+            }
+            VarType::FloatArray(3, _, a, b, c, ..) => {
+                VarType::FloatArray(4, false, v.len() as u64, a, b, c)
+            }
+            VarType::VecArray(1, _, a, ..) => VarType::VecArray(2, false, v.len() as u64, a, 0, 0),
+            VarType::VecArray(2, _, a, b, ..) => {
+                VarType::VecArray(3, false, v.len() as u64, a, b, 0)
+            }
+            VarType::VecArray(3, _, a, b, c, ..) => {
+                VarType::VecArray(4, false, v.len() as u64, a, b, c)
+            }
+            t => return Err(format!("Unable to construct array of type '{}'", t)),
+        })
     }
 
     // promote: int -> float -> vec
@@ -301,12 +289,33 @@ impl<'a> Inference<'a> {
         })
     }
 
+    // type of a value passed to an annotated variable, argument or return value, numeric values are promoted
+    pub fn annotation(&self, a: &Annotation, t: VarType) -> Result<VarType, String> {
+        Ok(match (a, t) {
+            (Annotation::Bool, B) => B,
+            (Annotation::Int, I) => I,
+            (Annotation::Float, I) | (Annotation::Float, F) => F,
+            (Annotation::Vec, I) | (Annotation::Vec, F) | (Annotation::Vec, V) => V,
+            (Annotation::Mat3, VarType::Mat3) => t,
+            (Annotation::Buffer { z, cs, .. }, VarType::Buffer { z: tz, cs: tcs, .. })
+                if z.unwrap_or(tz) == tz && cs.unwrap_or(tcs) == tcs =>
+            {
+                t
+            }
+            (Annotation::Function, VarType::Function(_)) => t,
+            (Annotation::Struct(id), VarType::Struct(n)) if self.structs[n as usize].id == *id => t,
+            (a, t) => return Err(format!("expected type '{}', found '{}'", a, t)),
+        })
+    }
+
     pub fn promote(&self, a: VarType, b: VarType) -> Result<VarType, String> {
         Ok(match (a, b) {
             (B, B) => B,
             (I, I) => I,
             (F, F) | (I, F) | (F, I) => F,
             (V, V) | (V, F) | (F, V) | (V, I) | (I, V) => V,
+            (VarType::Mat3, VarType::Mat3) => VarType::Mat3,
+            (VarType::Struct(a), VarType::Struct(b)) if a == b => VarType::Struct(a),
             (a, b) => {
                 return Err(format!(
                     "Unable to promote type '{}' and '{}' to a common type",
@@ -317,45 +326,24 @@ impl<'a> Inference<'a> {
     }
 
     // can be coerced to float or int
-    fn is_num(&self, a: &Expr) -> Result<bool, String> {
-        let a = self.var_type(a)?;
-        Ok(a == I || a == F)
+    fn is_num(&self, a: &Expr) -> bool {
+        a.ty == I || a.ty == F
     }
 
     fn is_int_lit(&self, a: &Expr) -> bool {
-        matches!(a, Expr::Literal(Literal::Int(_)))
+        matches!(a.kind, ExprKind::Literal(Literal::Int(_)))
     }
 
     fn get_int_lit(&self, a: &Expr) -> Result<i32, String> {
-        if let Expr::Literal(Literal::Int(v)) = a {
-            Ok(*v)
+        if let ExprKind::Literal(Literal::Int(v)) = a.kind {
+            Ok(v)
         } else {
             Err(format!("Expected an integer literal, found:\n{:?}", a))
         }
     }
 
-    fn is_num_vec(&self, a: &Expr) -> Result<bool, String> {
-        let a = self.var_type(a)?;
-        Ok(a == I || a == F || a == V)
-    }
-
-    fn function(&self, id: &str, vars: &[Expr]) -> Result<VarType, String> {
-        let vars = vars
-            .iter()
-            .map(|e| self.var_type(e))
-            .collect::<Result<Vec<_>, _>>()?;
-
-        let id = function_id(id, &vars);
-
-        if let Some(f) = self.functions {
-            if let Some((_, _, v, _)) = f.borrow().get(&id) {
-                Ok(*v)
-            } else {
-                Err(format!("Function '{}' is not defined", id))
-            }
-        } else {
-            Err("Function list is not initialized".into())
-        }
+    fn is_num_vec(&self, a: &Expr) -> bool {
+        a.ty == I || a.ty == F || a.ty == V
     }
 
     fn math_1(&self, vars: &[Expr]) -> Result<VarType, String> {
@@ -365,14 +353,12 @@ impl<'a> Inference<'a> {
                 vars.len()
             ));
         }
-        match self.is_num_vec(&vars[0])? {
-            true => self.promote_num(self.var_type(&vars[0])?, F),
-            false => {
-                return Err(format!(
-                    "Expected numeric argument to math function, found argument of type '{}'",
-                    self.var_type(&vars[0])?
-                ))
-            }
+        match self.is_num_vec(&vars[0]) {
+            true => self.promote_num(vars[0].ty, F),
+            false => Err(format!(
+                "Expected numeric argument to math function, found argument of type '{}'",
+                vars[0].ty
+            )),
         }
     }
 
@@ -383,23 +369,16 @@ impl<'a> Inference<'a> {
                 vars.len()
             ));
         }
-        match (self.is_num_vec(&vars[0])?, self.is_num_vec(&vars[1])?) {
-            (true, true) => self.promote_num(
-                self.promote_num(self.var_type(&vars[1])?, self.var_type(&vars[0])?)?,
-                F,
-            ),
-            (false, _) => {
-                return Err(format!(
-                    "Expected numeric 1st argument to math function, found argument of type '{}'",
-                    self.var_type(&vars[0])?
-                ))
-            }
-            (_, false) => {
-                return Err(format!(
-                    "Expected numeric 2nd argument to math function, found argument of type '{}'",
-                    self.var_type(&vars[1])?
-                ))
-            }
+        match (self.is_num_vec(&vars[0]), self.is_num_vec(&vars[1])) {
+            (true, true) => self.promote_num(self.promote_num(vars[1].ty, vars[0].ty)?, F),
+            (false, _) => Err(format!(
+                "Expected numeric 1st argument to math function, found argument of type '{}'",
+                vars[0].ty
+            )),
+            (_, false) => Err(format!(
+                "Expected numeric 2nd argument to math function, found argument of type '{}'",
+                vars[1].ty
+            )),
         }
     }
 
@@ -410,14 +389,12 @@ impl<'a> Inference<'a> {
                 vars.len()
             ));
         }
-        match self.is_num_vec(&vars[0])? {
+        match self.is_num_vec(&vars[0]) {
             true => Ok(t),
-            false => {
-                return Err(format!(
-                    "Expected numeric argument to geometry function, found argument of type '{}'",
-                    self.var_type(&vars[0])?
-                ))
-            }
+            false => Err(format!(
+                "Expected numeric argument to geometry function, found argument of type '{}'",
+                vars[0].ty
+            )),
         }
     }
 
@@ -428,20 +405,16 @@ impl<'a> Inference<'a> {
                 vars.len()
             ));
         }
-        match (self.is_num_vec(&vars[0])?, self.is_num_vec(&vars[1])?) {
+        match (self.is_num_vec(&vars[0]), self.is_num_vec(&vars[1])) {
             (true, true) => Ok(t),
-            (false, _) => {
-                return Err(format!(
+            (false, _) => Err(format!(
                 "Expected numeric 1st argument to geometry function, found argument of type '{}'",
-                self.var_type(&vars[0])?
-            ))
-            }
-            (_, false) => {
-                return Err(format!(
+                vars[0].ty
+            )),
+            (_, false) => Err(format!(
                 "Expected numeric 2nd argument to geometry function, found argument of type '{}'",
-                self.var_type(&vars[1])?
-            ))
-            }
+                vars[1].ty
+            )),
         }
     }
 
@@ -452,14 +425,12 @@ impl<'a> Inference<'a> {
                 vars.len()
             ));
         }
-        match self.is_num_vec(&vars[0])? {
+        match self.is_num_vec(&vars[0]) {
             true => Ok(t),
-            false => {
-                return Err(format!(
-                    "Expected numeric argument to geometry function, found argument of type '{}'",
-                    self.var_type(&vars[0])?
-                ))
-            }
+            false => Err(format!(
+                "Expected numeric argument to geometry function, found argument of type '{}'",
+                vars[0].ty
+            )),
         }
     }
 
@@ -470,14 +441,12 @@ impl<'a> Inference<'a> {
                 vars.len()
             ));
         }
-        match self.is_num(&vars[0])? {
+        match self.is_num(&vars[0]) {
             true => Ok(t),
-            false => {
-                return Err(format!(
+            false => Err(format!(
                 "Expected numeric argument to color space function, found argument of type '{}'",
-                self.var_type(&vars[0])?
-            ))
-            }
+                vars[0].ty
+            )),
         }
     }
 
@@ -488,7 +457,7 @@ impl<'a> Inference<'a> {
                 vars.len()
             ));
         }
-        match self.var_type(&vars[0])? {
+        match vars[0].ty {
             VarType::FloatArray(1, ..) => Ok(F),
             VarType::IntArray(1, ..) => Ok(I),
             t => Err(format!(
@@ -506,7 +475,7 @@ impl<'a> Inference<'a> {
             ));
         }
         // TODO: check 2nd variable
-        match (self.var_type(&vars[0])?, self.var_type(&vars[1])?) {
+        match (vars[0].ty, vars[1].ty) {
             (VarType::FloatArray(1, ..), F) => Ok(F),
             (VarType::IntArray(1, ..), I) => Ok(I),
             (t1 @ VarType::FloatArray(1, ..), t2) => Err(format!("Atomic operation on variable of type '{}' expected a 'Float' argument, found argument of type '{}'", t1, t2)),
@@ -515,6 +484,40 @@ impl<'a> Inference<'a> {
         }
     }
 
+    // workgroup reductions of a value and histograms of a bin into a global 1-D float array or buffer
+    fn reduction(&self, id: &str, vars: &[Expr]) -> Result<VarType, String> {
+        if vars.len() != 2 {
+            return Err(format!(
+                "Expected 2 arguments to '{}', found {}",
+                id,
+                vars.len()
+            ));
+        }
+        match (id, vars[0].ty) {
+            ("histogram", I) => {}
+            ("histogram", t) => {
+                return Err(format!(
+                    "Expected an 'Int' bin in 'histogram', found argument of type '{}'",
+                    t
+                ))
+            }
+            (_, I | F) => {}
+            (_, t) => {
+                return Err(format!(
+                    "Expected a numeric value in '{}', found argument of type '{}'",
+                    id, t
+                ))
+            }
+        }
+        match vars[1].ty {
+            VarType::FloatArray(1, false, ..) | VarType::Buffer { .. } => Ok(VarType::Void),
+            t => Err(format!(
+                "Output of '{}' must be a 1-D float array or buffer, found '{}'",
+                id, t
+            )),
+        }
+    }
+
     pub fn builtin(&self, id: &str, vars: &[Expr]) -> Result<VarType, String> {
         let t = match id {
             "get_work_dim" if vars.is_empty() => I,
@@ -527,39 +530,45 @@ impl<'a> Inference<'a> {
             "get_global_offset" if vars.len() == 1 && self.is_int_lit(&vars[0]) => I,
 
             // type-inferred 0 and 1
-            "zero" if vars.len() == 1 && self.is_num_vec(&vars[0])? => self.var_type(&vars[0])?,
-            "one" if vars.len() == 1 && self.is_num_vec(&vars[0])? => self.var_type(&vars[0])?,
+            "zero" if vars.len() == 1 && self.is_num_vec(&vars[0]) => vars[0].ty,
+            "one" if vars.len() == 1 && self.is_num_vec(&vars[0]) => vars[0].ty,
 
             // OpenCL math built-in functions: clamp, degrees, max, min, mix, radians, step, smoothstep, sign
             "clamp" if vars.len() == 3 => {
-                let v = self.var_type(&vars[0])?;
-                let l = self.var_type(&vars[1])?;
-                let h = self.var_type(&vars[2])?;
+                let v = vars[0].ty;
+                let l = vars[1].ty;
+                let h = vars[2].ty;
                 self.promote_num(v, self.promote_num(l, h)?)?
             }
             "mix" if vars.len() == 3 => {
-                let l = self.var_type(&vars[0])?;
-                let h = self.var_type(&vars[1])?;
-                let m = self.var_type(&vars[2])?;
+                let l = vars[0].ty;
+                let h = vars[1].ty;
+                let m = vars[2].ty;
                 self.promote_num(m, self.promote_num(l, h)?)?
             }
             "min" if vars.len() == 2 => {
-                let l = self.var_type(&vars[0])?;
-                let r = self.var_type(&vars[1])?;
+                let l = vars[0].ty;
+                let r = vars[1].ty;
                 self.promote_num(l, r)?
             }
             "max" if vars.len() == 2 => {
-                let l = self.var_type(&vars[0])?;
-                let r = self.var_type(&vars[1])?;
+                let l = vars[0].ty;
+                let r = vars[1].ty;
                 self.promote_num(l, r)?
             }
-            "sign" if vars.len() == 1 => self.var_type(&vars[0])?,
-            "abs" if vars.len() == 1 => self.var_type(&vars[0])?,
+            "sign" if vars.len() == 1 => vars[0].ty,
+            "abs" if vars.len() == 1 => vars[0].ty,
             // min, max generate same instructions as fmin, fmax on GCN4
             "range" if vars.len() == 3 => VarType::Float,
             "runif" if vars.len() == 3 => VarType::Float,
             "rnorm" if vars.len() == 3 => VarType::Float,
             "rpois" if vars.len() == 3 => VarType::Float,
+            // draws from the random stream of the work item
+            "runif" if vars.len() == 1 && vars[0].ty == I => VarType::Float,
+            "rnorm" if vars.len() == 1 && vars[0].ty == I => VarType::Float,
+            "rpois" if vars.len() == 2 && vars[0].ty == I && self.is_num(&vars[1]) => {
+                VarType::Float
+            }
 
             // OpenCL math built-in functions (selection)
             // returns F or V
@@ -607,8 +616,13 @@ impl<'a> Inference<'a> {
             "atomic_min" => self.atomic_2(vars)?,
             "atomic_max" => self.atomic_2(vars)?,
 
-            // CS conversion functions
-            // TODO: implement source code loading too
+            // workgroup reductions "reduce_sum(value, out)", "histogram(bin, out)"
+            "reduce_sum" => self.reduction(id, vars)?,
+            "reduce_min" => self.reduction(id, vars)?,
+            "reduce_max" => self.reduction(id, vars)?,
+            "histogram" => self.reduction(id, vars)?,
+
+            // CS conversion functions, defined in the embedded standard library
             "SRGBtoSRGB" => self.cs_v(vars, V)?,
             "SRGBtoLRGB" => self.cs_v(vars, V)?,
             "SRGBtoXYZ" => self.cs_v(vars, V)?,
@@ -670,22 +684,29 @@ impl<'a> Inference<'a> {
             "IasF" if vars.len() == 1 => F,
 
             // create vectors or enforce numeric types
-            "vec" if vars.len() == 1 && self.is_num(&vars[0])? => V,
+            "vec" if vars.len() == 1 && self.is_num(&vars[0]) => V,
             "vec"
                 if vars.len() == 3
-                    && self.is_num(&vars[0])?
-                    && self.is_num(&vars[1])?
-                    && self.is_num(&vars[2])? =>
+                    && self.is_num(&vars[0])
+                    && self.is_num(&vars[1])
+                    && self.is_num(&vars[2]) =>
             {
                 V
             }
-            "float" if vars.len() == 1 && self.is_num(&vars[0])? => F,
-            "int" if vars.len() == 1 && self.is_num(&vars[0])? => I,
+            "float" if vars.len() == 1 && self.is_num(&vars[0]) => F,
+            "int" if vars.len() == 1 && self.is_num(&vars[0]) => I,
+
+            // matrices from 9 values in row order, from 3 row vecs or with a diagonal
+            "mat3" if vars.len() == 9 && vars.iter().all(|v| self.is_num(v)) => VarType::Mat3,
+            "mat3" if vars.len() == 3 && vars.iter().all(|v| v.ty == V) => VarType::Mat3,
+            "mat3" if vars.len() == 1 && self.is_num_vec(&vars[0]) => VarType::Mat3,
+            "transpose" if vars.len() == 1 && vars[0].ty == VarType::Mat3 => VarType::Mat3,
+            "inverse" if vars.len() == 1 && vars[0].ty == VarType::Mat3 => VarType::Mat3,
 
-            "isnan" if vars.len() == 1 && self.is_num(&vars[0])? => I,
-            "isinf" if vars.len() == 1 && self.is_num(&vars[0])? => I,
-            "isfinite" if vars.len() == 1 && self.is_num(&vars[0])? => I,
-            "isnormal" if vars.len() == 1 && self.is_num(&vars[0])? => I,
+            "isnan" if vars.len() == 1 && self.is_num(&vars[0]) => I,
+            "isinf" if vars.len() == 1 && self.is_num(&vars[0]) => I,
+            "isfinite" if vars.len() == 1 && self.is_num(&vars[0]) => I,
+            "isnormal" if vars.len() == 1 && self.is_num(&vars[0]) => I,
 
             // array constructors
             "array" if vars.len() == 1 && self.is_int_lit(&vars[0]) => {
@@ -0,0 +1,180 @@
+/*
+  Copyright (C) 2011-2021 G. Bajlekov
+
+    Ivy is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Ivy is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+// typed intermediate representation, built once per kernel specialization by the lowering pass
+// every expression carries its inferred type, function calls refer to specialized instances and
+// color space access is desugared into explicit conversion calls
+
+use std::rc::Rc;
+
+use crate::ast::{BinaryOp, Literal, Prop, Traversal, UnaryOp};
+use crate::builtin::Builtin;
+use crate::inference::VarType;
+
+#[derive(Debug, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub ty: VarType,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExprKind {
+    Literal(Literal),
+    Var(String),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Grouping(Box<Expr>),
+    Builtin(String, Vec<Expr>),
+    Call(String, Vec<Expr>),          // call of specialized function instance
+    External(Rc<Builtin>, Vec<Expr>), // call of a builtin registered by the host
+    Ref(String),                      // variable receiving an additional return value of a call
+    Array(Vec<Expr>),
+
+    Component(Box<Expr>, u8),               // vec.x, vec.y, vec.z
+    BufSize(String, u8),                    // buf.x, buf.y, buf.z
+    BufLoad(String, VarType, BufIdx),       // buf[x], buf[x, y], buf[x, y, z]
+    BufProp(String, VarType, Prop, BufIdx), // buf[...].int, .idx, .ptr, .intptr
+    ArrayLoad(String, Vec<Expr>),           // arr[a, b, ...]
+    ArrayPtr(String, Vec<Expr>),            // arr[a, b, ...].ptr
+    Field(Box<Expr>, String),               // s.field
+    Struct(Vec<Expr>),                      // constructor of the struct type with all field values
+}
+
+#[derive(Debug, Clone)]
+pub enum BufIdx {
+    Linear(Box<Expr>),
+    Pixel(Box<Expr>, Box<Expr>),
+    Element(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone)]
+pub enum Place {
+    Expr(Expr),
+    Array(String, Vec<Expr>),
+    Buffer(String, VarType, BufIdx),
+}
+
+#[derive(Debug, Clone)]
+pub enum Stmt {
+    Var {
+        id: String,
+        ty: VarType,
+        init: Option<Expr>, // no initializer for array constructors
+    },
+    Const {
+        id: String,
+        ty: VarType,
+        init: Option<Expr>,
+    },
+    Assign(Place, Expr),
+
+    Call(Expr),
+    Return(Vec<Expr>), // additional values are written to the out-parameters of the function
+    Continue,
+    Break,
+
+    IfElse {
+        cond_list: Vec<(Expr, Vec<Stmt>)>,
+        else_body: Vec<Stmt>,
+    },
+    For {
+        var: String,
+        ty: VarType,
+        from: Expr,
+        to: Expr,
+        step: Option<Expr>,
+        body: Vec<Stmt>,
+    },
+    While {
+        cond: Expr,
+        body: Vec<Stmt>,
+    },
+
+    Comment(String),
+}
+
+// struct declared in the source, indexed by VarType::Struct
+#[derive(Debug, Clone)]
+pub struct Struct {
+    pub id: String,
+    pub fields: Vec<(String, VarType)>,
+}
+
+// order of struct declarations, with nested structs before the structs containing them
+pub fn struct_order(structs: &[Struct]) -> Vec<usize> {
+    fn visit(structs: &[Struct], n: usize, order: &mut Vec<usize>) {
+        if order.contains(&n) {
+            return;
+        }
+        for (_, ty) in &structs[n].fields {
+            if let VarType::Struct(m) = ty {
+                visit(structs, *m as usize, order);
+            }
+        }
+        order.push(n);
+    }
+
+    let mut order = Vec::new();
+    for n in 0..structs.len() {
+        visit(structs, n, &mut order);
+    }
+    order
+}
+
+#[derive(Debug)]
+pub struct Function {
+    pub id: String,          // mangled function id
+    pub name: String,        // name in the source
+    pub input: Vec<VarType>, // types of all arguments, including functions
+    pub args: Vec<(String, VarType)>,
+    pub ret: VarType,
+    pub outs: Vec<VarType>, // types of additional return values, passed by reference
+    pub body: Vec<Stmt>,
+    pub deps: Vec<String>, // ids of directly called function instances, in order of first call
+    pub random: bool,      // draws from the random stream of the kernel, directly or indirectly
+}
+
+#[derive(Debug)]
+pub struct Kernel {
+    pub id: String,
+    pub args: Vec<(String, VarType)>,
+    pub traversal: Option<Traversal>,
+    pub body: Vec<Stmt>,
+    pub random: bool, // declares the call counter of the random stream
+    pub reduce: bool, // declares local memory for workgroup reductions
+}
+
+// runif(seed), rnorm(seed) and rpois(seed, lambda) draw from the stream keyed on the seed
+// and counted by the global index and the number of previous draws of the work item
+pub fn random_stream(id: &str, args: &[Expr]) -> bool {
+    matches!((id, args.len()), ("runif", 1) | ("rnorm", 1) | ("rpois", 2))
+}
+
+// reduce_sum, reduce_min, reduce_max and histogram synchronize the work group
+pub fn reduction(id: &str) -> bool {
+    matches!(id, "reduce_sum" | "reduce_min" | "reduce_max" | "histogram")
+}
+
+// a single kernel specialization with everything needed to emit it
+#[derive(Debug)]
+pub struct Program {
+    pub externals: Vec<Rc<Builtin>>, // registered builtins called by the kernel
+    pub constants: Rc<Vec<Stmt>>,
+    pub structs: Rc<Vec<Struct>>, // all structs of the source, sorted by name
+    pub functions: Vec<Rc<Function>>,
+    pub kernel: Kernel,
+}
@@ -16,175 +16,562 @@
 */
 #![allow(clippy::many_single_char_names)]
 #![allow(clippy::type_complexity)]
+#![allow(clippy::not_unsafe_ptr_arg_deref)]
+#![allow(clippy::large_enum_variant)]
 
+use std::collections::HashSet;
 use std::ffi::{CStr, CString};
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::ptr;
+use std::sync::{Arc, RwLock};
+use std::thread;
 
 mod ast;
 mod buf_idx;
+mod builtin;
+mod cache;
+mod disk_cache;
+mod fold;
 mod fragment;
 mod function_id;
 mod generator_ispc;
 mod generator_ocl;
 mod inference;
+mod ir;
+mod library;
+mod lint;
+mod lower;
 mod parser;
 mod scanner;
 mod scope;
 mod tokens;
+mod uniformity;
+mod validate;
 
-use function_id::function_id;
+use cache::Cache;
+use disk_cache::{DiskCache, Key};
+use function_id::kernel_id;
 use generator_ispc::Generator as GeneratorISPC;
 use generator_ocl::Generator as GeneratorOCL;
+use lint::lint;
+use lower::Lower;
 use parser::Parser;
 use scanner::Scanner;
+use validate::validate;
 
-use ast::ColorSpace;
+pub use ast::{ColorSpace, Traversal};
+pub use builtin::{Builtin, ValueType};
+pub use lint::{Lint, Warning, LINTS};
+
+use ast::Literal;
 use inference::VarType;
 
-pub enum Generator<'a> {
-    Ocl(GeneratorOCL<'a>),
-    Ispc(GeneratorISPC<'a>),
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Target {
+    Ocl,
+    Ispc,
 }
 
-pub struct Translator<'a> {
-    generator: Generator<'a>,
-    inputs: Vec<VarType>,
+// precision of arithmetic in generated kernels, buffers and kernel arguments are stored as float
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Precision {
+    Single,
+    Double,
 }
 
-// create new generator with source file:
-#[no_mangle]
-pub extern "C" fn translator_new_ocl<'a>(source: *const i8) -> *mut Translator<'a> {
-    let source = unsafe {
-        assert!(!source.is_null());
-        CStr::from_ptr(source)
-    };
+// type of a kernel argument as bound by the host
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum InputType {
+    Bool,
+    Int,
+    Float,
+    Vec,
+    Mat3,            // rows of the matrix, each padded to 4 floats
+    IntConst(i32),   // value known when specializing, branches and loops depending on it are folded
+    FloatConst(f32), // value known when specializing
+    IntArray {
+        len: u64,
+    },
+    FloatArray {
+        len: u64,
+    },
+    Buffer {
+        x: u64,
+        y: u64,
+        z: u64,
+        cs: ColorSpace,
+    },
+    Function(u32), // function of the source called through the argument, see Program::function
+}
+
+impl InputType {
+    fn var_type(&self) -> Result<VarType, String> {
+        match *self {
+            InputType::Bool => Ok(VarType::Bool),
+            InputType::Int | InputType::IntConst(_) => Ok(VarType::Int),
+            InputType::Float | InputType::FloatConst(_) => Ok(VarType::Float),
+            InputType::Vec => Ok(VarType::Vec),
+            InputType::Mat3 => Ok(VarType::Mat3),
+            InputType::IntArray { len: 0 } | InputType::FloatArray { len: 0 } => {
+                Err("Invalid array length 0".into())
+            }
+            InputType::IntArray { len } => Ok(VarType::IntArray(1, false, len, 0, 0, 0)),
+            InputType::FloatArray { len } => Ok(VarType::FloatArray(1, false, len, 0, 0, 0)),
+            InputType::Buffer { x, y, z, cs } => {
+                if x == 0 || y == 0 || z == 0 {
+                    return Err(format!("Invalid buffer dimensions [{}, {}, {}]", x, y, z));
+                }
+                Ok(VarType::Buffer {
+                    z,
+                    cs,
+                    x1y1: x == 1 && y == 1,
+                })
+            }
+            InputType::Function(n) => Ok(VarType::Function(n)),
+        }
+    }
+
+    fn value(&self) -> Option<Literal> {
+        match *self {
+            InputType::IntConst(n) => Some(Literal::Int(n)),
+            InputType::FloatConst(n) => Some(Literal::Float(n as f64)),
+            _ => None,
+        }
+    }
+}
 
-    let source = source.to_str().unwrap_or_default().to_string();
+// kernel name, input types and values of constant inputs
+type Specialization = (String, Vec<VarType>, Vec<Option<Literal>>);
 
-    let mut scanner = Scanner::new(source.clone());
-    let tokens = scanner.scan();
-    if let Err((err, line)) = &tokens {
-        println!("[Line {}]: {}", line, err);
-        let line = line - 1;
+// several kernel specializations generated into one source
+pub struct Unit {
+    pub source: String,
+    pub kernels: Vec<(String, String)>, // kernel names and their ids in the source, in order of the requests
+}
 
-        let start = if line >= 3 { line - 3 } else { 0 };
-        source
-            .lines()
-            .enumerate()
-            .skip(start)
-            .take(7)
-            .for_each(|(l, s)| {
-                println!("{} {}: {}", if l == line { "=>" } else { "  " }, l + 1, s)
-            });
+impl Unit {
+    // one "name id" line per requested kernel
+    pub fn manifest(&self) -> String {
+        self.kernels
+            .iter()
+            .map(|(name, id)| format!("{} {}\n", name, id))
+            .collect()
     }
+}
+
+// parsed and validated source, kernels can be specialized for any inputs and target:
+//
+// let program = ivyscript::Program::parse(source)?;
+// let ocl = program.specialize("kernel", &[InputType::Float], Target::Ocl)?;
+//
+// a program can be shared between threads, specialized kernels are cached in memory
+// and optionally in a cache directory
+pub struct Program {
+    lower: Lower,
+    warnings: Vec<Warning>,
+    cache: Cache,
+    hash: u64, // hash of the source, part of the cache directory keys
+    disk_cache: RwLock<Option<DiskCache>>,
+    builtins: RwLock<Vec<Builtin>>, // registered by the host
+}
 
-    let tokens = tokens.unwrap_or_default();
-
-    let parser = Parser::new(tokens);
-    let ast = parser.parse();
-    if let Err((err, line)) = &ast {
-        println!("[Line {}]: {}", line, err);
-        let line = line - 1;
-
-        let start = if line >= 3 { line - 3 } else { 0 };
-        source
-            .lines()
-            .enumerate()
-            .skip(start)
-            .take(7)
-            .for_each(|(l, s)| {
-                println!("{} {}: {}", if l == line { "=>" } else { "  " }, l + 1, s)
-            });
+impl Program {
+    // errors are reported one per line as "[Line N]: message"
+    pub fn parse(source: &str) -> Result<Program, String> {
+        Program::build(source).map_err(|errors| {
+            errors
+                .into_iter()
+                .map(|(err, line)| format!("[Line {}]: {}", line, err))
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
     }
-    let ast = ast.unwrap_or_default();
 
-    let generator = GeneratorOCL::new(ast);
+    // collect all scanner, parser and validation errors with their lines
+    fn build(source: &str) -> Result<Program, Vec<(String, usize)>> {
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan().map_err(|err| vec![err])?;
+
+        let (ast, errors) = Parser::new(tokens).parse();
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        validate(&ast)?;
+
+        Ok(Program {
+            warnings: lint(&ast),
+            lower: Lower::new(ast.into_iter().map(|(stmt, _)| stmt).collect()),
+            cache: Cache::new(),
+            hash: disk_cache::hash(source.as_bytes(), disk_cache::HASH_INIT),
+            disk_cache: RwLock::new(None),
+            builtins: RwLock::new(Vec::new()),
+        })
+    }
 
-    let translator = Box::new(Translator {
-        generator: Generator::Ocl(generator),
-        inputs: Vec::new(),
-    });
+    pub fn specialize(
+        &self,
+        kernel: &str,
+        input: &[InputType],
+        target: Target,
+    ) -> Result<String, String> {
+        self.specialize_with(kernel, input, target, Precision::Single)
+    }
 
-    let ptr = Box::into_raw(translator);
+    // specialize with double precision arithmetic for locals, literals and math builtins
+    pub fn specialize_with(
+        &self,
+        kernel: &str,
+        input: &[InputType],
+        target: Target,
+        precision: Precision,
+    ) -> Result<String, String> {
+        let values = input.iter().map(InputType::value).collect::<Vec<_>>();
+        let input = input
+            .iter()
+            .map(InputType::var_type)
+            .collect::<Result<Vec<_>, _>>()?;
+        self.generate(kernel, &input, &values, target, precision)
+    }
 
-    let translator = unsafe {
-        assert!(!ptr.is_null());
-        &mut *ptr
-    };
+    // specialize independent kernels in parallel, results are in the order of the requests
+    pub fn specialize_all(
+        &self,
+        kernels: &[(&str, &[InputType])],
+        target: Target,
+        precision: Precision,
+    ) -> Vec<Result<String, String>> {
+        thread::scope(|s| {
+            let handles = kernels
+                .iter()
+                .map(|(kernel, input)| {
+                    s.spawn(move || self.specialize_with(kernel, input, target, precision))
+                })
+                .collect::<Vec<_>>();
+            handles
+                .into_iter()
+                .map(|h| {
+                    h.join()
+                        .unwrap_or_else(|_| Err("Kernel translation panicked".into()))
+                })
+                .collect()
+        })
+    }
 
-    match &translator.generator {
-        Generator::Ocl(g) => g.prepare(),
-        Generator::Ispc(g) => g.prepare(),
+    // generate kernels into a single source, to be compiled once:
+    // constants are shared, function instances are emitted once and kernels are named by their ids
+    pub fn specialize_unit(
+        &self,
+        kernels: &[(&str, &[InputType])],
+        target: Target,
+        precision: Precision,
+    ) -> Result<Unit, String> {
+        let kernels = kernels
+            .iter()
+            .map(|(name, input)| {
+                let values = input.iter().map(InputType::value).collect();
+                let input = input
+                    .iter()
+                    .map(InputType::var_type)
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok((name.to_string(), input, values))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        self.unit(&kernels, target, precision)
     }
 
-    ptr
-}
+    fn unit(
+        &self,
+        kernels: &[Specialization],
+        target: Target,
+        precision: Precision,
+    ) -> Result<Unit, String> {
+        if kernels.is_empty() {
+            return Err("No kernels to generate".into());
+        }
+
+        let builtins = self.builtins.read().unwrap_or_else(|e| e.into_inner());
+        let mut programs = Vec::new();
+        let mut manifest: Vec<(String, String)> = Vec::new();
+        for (name, input, values) in kernels {
+            let id = kernel_id(name, input, values, precision);
+            if !manifest.iter().any(|(_, i)| *i == id) {
+                let mut program = self
+                    .lower
+                    .kernel(name, input, values, &builtins)
+                    .map_err(|err| format!("Kernel '{}': {}", name, err))?;
+                program.kernel.id = id.clone();
+                programs.push(program);
+            }
+            manifest.push((name.clone(), id));
+        }
+
+        let source = match target {
+            Target::Ocl => GeneratorOCL::unit(&programs, precision)?,
+            Target::Ispc => GeneratorISPC::unit(&programs, precision)?,
+        };
+        Ok(Unit {
+            source,
+            kernels: manifest,
+        })
+    }
 
-#[no_mangle]
-pub extern "C" fn translator_new_ispc<'a>(source: *const i8) -> *mut Translator<'a> {
-    let source = unsafe {
-        assert!(!source.is_null());
-        CStr::from_ptr(source)
-    };
+    // store generated kernels in a directory and reuse them in later runs, None disables the cache directory
+    pub fn set_cache_dir(&self, dir: Option<&Path>) -> Result<(), String> {
+        let disk_cache = dir.map(DiskCache::new).transpose()?;
+        *self.disk_cache.write().unwrap_or_else(|e| e.into_inner()) = disk_cache;
+        Ok(())
+    }
+
+    // register a builtin implemented by the host, a builtin with the same name and arguments is replaced
+    pub fn add_builtin(&self, builtin: Builtin) -> Result<(), String> {
+        builtin.check()?;
+        if self.lower.has_function(&builtin.name) || self.kernels().contains(&&*builtin.name) {
+            return Err(format!(
+                "Builtin '{}' conflicts with a function in the source",
+                builtin.name
+            ));
+        }
+
+        let mut builtins = self.builtins.write().unwrap_or_else(|e| e.into_inner());
+        match builtins
+            .iter_mut()
+            .find(|b| b.name == builtin.name && b.args == builtin.args)
+        {
+            Some(b) => *b = builtin,
+            None => builtins.push(builtin),
+        }
+        Ok(())
+    }
+
+    fn generate(
+        &self,
+        kernel: &str,
+        input: &[VarType],
+        values: &[Option<Literal>],
+        target: Target,
+        precision: Precision,
+    ) -> Result<String, String> {
+        let id = kernel_id(kernel, input, values, precision);
+
+        // kernels are cached per set of registered builtins
+        let builtins = self.builtins.read().unwrap_or_else(|e| e.into_inner());
+        let hash = match builtins.len() {
+            0 => self.hash,
+            _ => disk_cache::hash(format!("{:?}", *builtins).as_bytes(), self.hash),
+        };
+
+        // double precision kernels are cached separately
+        let hash = match precision {
+            Precision::Single => hash,
+            Precision::Double => disk_cache::hash(b"double", hash),
+        };
+
+        let source =
+            self.cache
+                .get_or_insert_with(format!("{:?}:{:016x}:{}", target, hash, id), || {
+                    let disk_cache = self.disk_cache.read().unwrap_or_else(|e| e.into_inner());
+                    let key = Key::new(hash, target, &id);
+                    if let Some(source) = disk_cache.as_ref().and_then(|c| c.load(&key)) {
+                        return Ok(source);
+                    }
+
+                    let program = self.lower.kernel(kernel, input, values, &builtins)?;
+                    let source = match target {
+                        Target::Ocl => GeneratorOCL::program(&program, precision)?,
+                        Target::Ispc => GeneratorISPC::program(&program, precision)?,
+                    };
+                    if let Some(c) = disk_cache.as_ref() {
+                        c.store(&key, &source, &program.kernel);
+                    }
+                    Ok(source)
+                })?;
+        Ok(source.to_string())
+    }
+
+    // names of all kernels in the source, sorted
+    pub fn kernels(&self) -> Vec<&str> {
+        self.lower.kernels()
+    }
+
+    // input binding a function of the source to a kernel argument
+    pub fn function(&self, name: &str) -> Result<InputType, String> {
+        self.lower
+            .function_index(name)
+            .map(InputType::Function)
+            .ok_or_else(|| format!("Function '{}' not found in source", name))
+    }
+
+    // traversal declared with '@tile(x, y)' or '@rows' before the kernel, None leaves it to the host
+    pub fn traversal(&self, kernel: &str) -> Result<Option<Traversal>, String> {
+        self.lower.traversal(kernel)
+    }
 
-    let source = source.to_str().unwrap_or_default().to_string();
+    pub fn warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+}
+
+// stateful wrapper used by the FFI, collecting inputs for the next kernel
+// clones share the parsed program and its cache, and can be used from different threads
+#[derive(Clone)]
+pub struct Translator {
+    program: Result<Arc<Program>, String>, // errors in the source are reported when generating any kernel
+    target: Target,
+    inputs: Vec<VarType>,
+    values: Vec<Option<Literal>>, // values of constant inputs
+    lints: HashSet<Lint>,         // enabled lints
+    unit: Vec<Specialization>,    // kernels added for the next unit
+    precision: Precision,
+}
 
-    let mut scanner = Scanner::new(source.clone());
-    let tokens = scanner.scan();
-    if let Err((err, line)) = &tokens {
-        println!("[Line {}]: {}", line, err);
-        let line = line - 1;
+impl Translator {
+    fn new(source: &str, target: Target) -> Translator {
+        let program = Program::build(source).map(Arc::new).map_err(|errors| {
+            // report all errors, no kernels are generated from invalid sources
+            errors
+                .into_iter()
+                .map(|(err, line)| {
+                    print_error(source, &err, line);
+                    format!("[Line {}]: {}", line, err)
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        });
+
+        Translator {
+            program,
+            target,
+            inputs: Vec::new(),
+            values: Vec::new(),
+            lints: LINTS.iter().copied().collect(),
+            unit: Vec::new(),
+            precision: Precision::Single,
+        }
+    }
 
-        let start = if line >= 3 { line - 3 } else { 0 };
-        source
-            .lines()
-            .enumerate()
-            .skip(start)
-            .take(7)
-            .for_each(|(l, s)| {
-                println!("{} {}: {}", if l == line { "=>" } else { "  " }, l + 1, s)
-            });
+    fn kernel(&self, name: &str, input: &[VarType]) -> Result<String, String> {
+        let program = self.program.as_ref().map_err(|err| err.clone())?;
+        program.generate(name, input, &self.values, self.target, self.precision)
     }
 
-    let tokens = tokens.unwrap_or_default();
-
-    let parser = Parser::new(tokens);
-    let ast = parser.parse();
-    if let Err((err, line)) = &ast {
-        println!("[Line {}]: {}", line, err);
-        let line = line - 1;
-
-        let start = if line >= 3 { line - 3 } else { 0 };
-        source
-            .lines()
-            .enumerate()
-            .skip(start)
-            .take(7)
-            .for_each(|(l, s)| {
-                println!("{} {}: {}", if l == line { "=>" } else { "  " }, l + 1, s)
-            });
+    // warnings of enabled lints, one per line
+    fn warnings(&self) -> String {
+        match &self.program {
+            Ok(program) => program
+                .warnings()
+                .iter()
+                .filter(|w| self.lints.contains(&w.lint))
+                .map(|w| w.to_string())
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Err(_) => String::new(),
+        }
     }
-    let ast = ast.unwrap_or_default();
+}
 
-    let generator = GeneratorISPC::new(ast);
+// print error with surrounding source lines
+fn print_error(source: &str, err: &str, line: usize) {
+    println!("[Line {}]: {}", line, err);
+    let line = line.saturating_sub(1);
+
+    let start = line.saturating_sub(3);
+    source
+        .lines()
+        .enumerate()
+        .skip(start)
+        .take(7)
+        .for_each(|(l, s)| println!("{} {}: {}", if l == line { "=>" } else { "  " }, l + 1, s));
+}
 
-    let translator = Box::new(Translator {
-        generator: Generator::Ispc(generator),
-        inputs: Vec::new(),
-    });
+// version of the exported interface, incremented on incompatible changes
+pub const ABI_VERSION: u32 = 1;
+
+// status codes returned by the exported functions
+pub const IVY_OK: i32 = 0;
+pub const IVY_ERROR_NULL: i32 = -1; // null pointer argument
+pub const IVY_ERROR_UTF8: i32 = -2; // string argument is not valid UTF-8
+pub const IVY_ERROR_ARGUMENT: i32 = -3; // invalid argument value
+pub const IVY_ERROR_TRANSLATE: i32 = -4; // kernel could not be translated
+pub const IVY_ERROR_PANIC: i32 = -5; // internal error, the translator should be freed
+
+// run an exported function without unwinding across the C boundary
+fn guard(f: impl FnOnce() -> Result<(), i32>) -> i32 {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => IVY_OK,
+        Ok(Err(status)) => status,
+        Err(_) => IVY_ERROR_PANIC,
+    }
+}
 
-    let ptr = Box::into_raw(translator);
+unsafe fn translator<'a>(t: *mut Translator) -> Result<&'a mut Translator, i32> {
+    t.as_mut().ok_or(IVY_ERROR_NULL)
+}
 
-    let translator = unsafe {
-        assert!(!ptr.is_null());
-        &mut *ptr
-    };
+unsafe fn string<'a>(s: *const i8) -> Result<&'a str, i32> {
+    if s.is_null() {
+        return Err(IVY_ERROR_NULL);
+    }
+    CStr::from_ptr(s).to_str().map_err(|_| IVY_ERROR_UTF8)
+}
 
-    match &translator.generator {
-        Generator::Ocl(g) => g.prepare(),
-        Generator::Ispc(g) => g.prepare(),
+unsafe fn optional_string<'a>(s: *const i8) -> Result<Option<&'a str>, i32> {
+    if s.is_null() {
+        return Ok(None);
     }
+    string(s).map(Some)
+}
 
-    ptr
+// pass an owned string to the caller, to be released with translator_string_free
+unsafe fn output(s: String, out: *mut *mut i8) -> Result<(), i32> {
+    if out.is_null() {
+        return Err(IVY_ERROR_NULL);
+    }
+    *out = CString::new(s).map_err(|_| IVY_ERROR_ARGUMENT)?.into_raw();
+    Ok(())
+}
+
+fn translator_new(source: *const i8, target: Target) -> *mut Translator {
+    panic::catch_unwind(|| {
+        let source = unsafe { string(source) }.ok()?;
+        Some(Box::into_raw(Box::new(Translator::new(source, target))))
+    })
+    .ok()
+    .flatten()
+    .unwrap_or(ptr::null_mut())
+}
+
+#[no_mangle]
+pub extern "C" fn translator_abi_version() -> u32 {
+    ABI_VERSION
+}
+
+// create new generator with source file, returns null on invalid input:
+#[no_mangle]
+pub extern "C" fn translator_new_ocl(source: *const i8) -> *mut Translator {
+    translator_new(source, Target::Ocl)
+}
+
+#[no_mangle]
+pub extern "C" fn translator_new_ispc(source: *const i8) -> *mut Translator {
+    translator_new(source, Target::Ispc)
+}
+
+// new translator sharing the parsed source and generated kernels, with its own inputs and lints
+#[no_mangle]
+pub extern "C" fn translator_clone(t: *mut Translator) -> *mut Translator {
+    panic::catch_unwind(AssertUnwindSafe(|| {
+        let t = unsafe { translator(t) }.ok()?;
+        let mut clone = t.clone();
+        clone.inputs.clear();
+        clone.values.clear();
+        clone.unit.clear();
+        Some(Box::into_raw(Box::new(clone)))
+    }))
+    .ok()
+    .flatten()
+    .unwrap_or(ptr::null_mut())
 }
 
 #[no_mangle]
@@ -192,178 +579,353 @@ pub extern "C" fn translator_free(t: *mut Translator) {
     if t.is_null() {
         return;
     }
+    let _ = panic::catch_unwind(AssertUnwindSafe(|| unsafe { drop(Box::from_raw(t)) }));
+}
+
+#[no_mangle]
+pub extern "C" fn translator_string_free(s: *mut i8) {
+    if s.is_null() {
+        return;
+    }
     unsafe {
-        Box::from_raw(t);
+        drop(CString::from_raw(s));
     }
 }
 
+// writes the generated source to out, or the error message when the kernel can't be translated
 #[no_mangle]
-pub extern "C" fn translator_generate(t: *mut Translator, kernel: *const i8) -> *mut i8 {
-    let t = unsafe {
-        assert!(!t.is_null());
-        &mut *t
-    };
-    let kernel = unsafe {
-        assert!(!kernel.is_null());
-        CStr::from_ptr(kernel)
-    };
-    let kernel = kernel.to_str().unwrap_or_default();
+pub extern "C" fn translator_generate(
+    t: *mut Translator,
+    kernel: *const i8,
+    out: *mut *mut i8,
+) -> i32 {
+    guard(|| unsafe {
+        let t = translator(t)?;
+        let kernel = string(kernel)?;
+
+        match t.kernel(kernel, &t.inputs) {
+            Ok(source) => output(source, out),
+            Err(err) => {
+                println!("[Generator]: {}", err);
+                output(err, out)?;
+                Err(IVY_ERROR_TRANSLATE)
+            }
+        }
+    })
+}
 
-    let source = match &t.generator {
-        Generator::Ocl(g) => g.kernel(kernel, &t.inputs),
-        Generator::Ispc(g) => g.kernel(kernel, &t.inputs),
-    };
+// add a kernel with the current inputs to the next unit
+#[no_mangle]
+pub extern "C" fn translator_add_kernel(t: *mut Translator, kernel: *const i8) -> i32 {
+    guard(|| unsafe {
+        let t = translator(t)?;
+        let kernel = string(kernel)?;
+        let specialization = (kernel.to_string(), t.inputs.clone(), t.values.clone());
+        t.unit.push(specialization);
+        Ok(())
+    })
+}
 
-    if let Err(err) = &source {
-        println!("[Generator]: {}", err);
-    }
-    let source = source.unwrap_or_default();
+// writes all added kernels as a single source and the manifest of their ids, see Unit::manifest,
+// the added kernels are cleared, on errors out holds the error message
+#[no_mangle]
+pub extern "C" fn translator_generate_program(
+    t: *mut Translator,
+    out: *mut *mut i8,
+    manifest: *mut *mut i8,
+) -> i32 {
+    guard(|| unsafe {
+        let t = translator(t)?;
+        if out.is_null() || manifest.is_null() {
+            return Err(IVY_ERROR_NULL);
+        }
+        let kernels = mem::take(&mut t.unit);
+
+        let unit = t
+            .program
+            .as_ref()
+            .map_err(|err| err.clone())
+            .and_then(|program| program.unit(&kernels, t.target, t.precision));
+        match unit {
+            Ok(unit) => {
+                output(unit.manifest(), manifest)?;
+                output(unit.source, out)
+            }
+            Err(err) => {
+                println!("[Generator]: {}", err);
+                output(err, out)?;
+                Err(IVY_ERROR_TRANSLATE)
+            }
+        }
+    })
+}
 
-    CString::new(source).unwrap().into_raw()
+#[no_mangle]
+pub extern "C" fn translator_lint(t: *mut Translator, out: *mut *mut i8) -> i32 {
+    guard(|| unsafe {
+        let t = translator(t)?;
+        output(t.warnings(), out)
+    })
 }
 
+// enable or disable a lint by its code
 #[no_mangle]
-pub extern "C" fn translator_get_id(t: *mut Translator, name: *const i8) -> *const i8 {
-    let t = unsafe {
-        assert!(!t.is_null());
-        &mut *t
-    };
-    let name = unsafe {
-        assert!(!name.is_null());
-        CStr::from_ptr(name)
-    };
+pub extern "C" fn translator_set_lint(t: *mut Translator, code: *const i8, enabled: bool) -> i32 {
+    guard(|| unsafe {
+        let t = translator(t)?;
+        let lint = Lint::from_code(string(code)?).ok_or(IVY_ERROR_ARGUMENT)?;
+        if enabled {
+            t.lints.insert(lint);
+        } else {
+            t.lints.remove(&lint);
+        }
+        Ok(())
+    })
+}
 
-    CString::new(function_id(name.to_str().unwrap_or_default(), &t.inputs))
-        .unwrap()
-        .into_raw()
+// use a directory to cache generated kernels between runs, an empty path disables it
+#[no_mangle]
+pub extern "C" fn translator_set_cache_dir(t: *mut Translator, dir: *const i8) -> i32 {
+    guard(|| unsafe {
+        let t = translator(t)?;
+        let dir = string(dir)?;
+        if let Ok(program) = &t.program {
+            let dir = if dir.is_empty() {
+                None
+            } else {
+                Some(Path::new(dir))
+            };
+            program.set_cache_dir(dir).map_err(|err| {
+                println!("[Generator]: {}", err);
+                IVY_ERROR_ARGUMENT
+            })?;
+        }
+        Ok(())
+    })
 }
 
+// double precision arithmetic in the kernels of this translator, copied to its clones
 #[no_mangle]
-pub extern "C" fn translator_clear_inputs(t: *mut Translator) {
-    let t = unsafe {
-        assert!(!t.is_null());
-        &mut *t
-    };
-    t.inputs = Vec::new();
+pub extern "C" fn translator_set_double(t: *mut Translator, enabled: bool) -> i32 {
+    guard(|| unsafe {
+        let t = translator(t)?;
+        t.precision = if enabled {
+            Precision::Double
+        } else {
+            Precision::Single
+        };
+        Ok(())
+    })
 }
 
+// register a builtin implemented by the host, shared with all clones of the translator:
+// args is a comma-separated list of types (bool, int, float, vec), ret can also be void,
+// ocl and ispc are the names called for each target, optional sources define them
+#[allow(clippy::too_many_arguments)]
 #[no_mangle]
-pub extern "C" fn translator_add_int(t: *mut Translator) -> u64 {
-    let t = unsafe {
-        assert!(!t.is_null());
-        &mut *t
-    };
-    t.inputs.push(VarType::Int);
-    t.inputs.len() as u64
+pub extern "C" fn translator_add_builtin(
+    t: *mut Translator,
+    name: *const i8,
+    args: *const i8,
+    ret: *const i8,
+    ocl: *const i8,
+    ispc: *const i8,
+    ocl_source: *const i8,
+    ispc_source: *const i8,
+) -> i32 {
+    guard(|| unsafe {
+        let t = translator(t)?;
+        let args = string(args)?;
+        let args = if args.trim().is_empty() {
+            Vec::new()
+        } else {
+            args.split(',')
+                .map(ValueType::parse)
+                .collect::<Option<Vec<_>>>()
+                .ok_or(IVY_ERROR_ARGUMENT)?
+        };
+        let builtin = Builtin {
+            name: string(name)?.into(),
+            args,
+            ret: ValueType::parse(string(ret)?).ok_or(IVY_ERROR_ARGUMENT)?,
+            ocl: string(ocl)?.into(),
+            ispc: string(ispc)?.into(),
+            ocl_source: optional_string(ocl_source)?.map(String::from),
+            ispc_source: optional_string(ispc_source)?.map(String::from),
+        };
+        if let Ok(program) = &t.program {
+            program.add_builtin(builtin).map_err(|err| {
+                println!("[Generator]: {}", err);
+                IVY_ERROR_ARGUMENT
+            })?;
+        }
+        Ok(())
+    })
 }
 
 #[no_mangle]
-pub extern "C" fn translator_add_float(t: *mut Translator) -> u64 {
-    let t = unsafe {
-        assert!(!t.is_null());
-        &mut *t
-    };
-    t.inputs.push(VarType::Float);
-    t.inputs.len() as u64
+pub extern "C" fn translator_get_id(t: *mut Translator, name: *const i8, out: *mut *mut i8) -> i32 {
+    guard(|| unsafe {
+        let t = translator(t)?;
+        let name = string(name)?;
+        output(kernel_id(name, &t.inputs, &t.values, t.precision), out)
+    })
 }
 
+// writes the tile size declared by the kernel to size[0] and size[1], see Traversal::size,
+// both are 0 when the kernel leaves the traversal to the host
 #[no_mangle]
-pub extern "C" fn translator_add_buffer_srgb(t: *mut Translator, x: u64, y: u64, z: u64) -> u64 {
-    let t = unsafe {
-        assert!(!t.is_null());
-        &mut *t
-    };
-    t.inputs.push(VarType::Buffer {
-        z,
-        cs: ColorSpace::Srgb,
-        x1y1: x == 1 && y == 1,
-    });
-    t.inputs.len() as u64
+pub extern "C" fn translator_get_traversal(
+    t: *mut Translator,
+    kernel: *const i8,
+    size: *mut i32,
+) -> i32 {
+    guard(|| unsafe {
+        let t = translator(t)?;
+        let kernel = string(kernel)?;
+        if size.is_null() {
+            return Err(IVY_ERROR_NULL);
+        }
+
+        let program = t.program.as_ref().map_err(|_| IVY_ERROR_TRANSLATE)?;
+        let traversal = program.traversal(kernel).map_err(|err| {
+            println!("[Generator]: {}", err);
+            IVY_ERROR_ARGUMENT
+        })?;
+        let (x, y) = traversal.map_or((0, 0), |t| t.size());
+        *size = x;
+        *size.add(1) = y;
+        Ok(())
+    })
 }
 
 #[no_mangle]
-pub extern "C" fn translator_add_buffer_lrgb(t: *mut Translator, x: u64, y: u64, z: u64) -> u64 {
-    let t = unsafe {
-        assert!(!t.is_null());
-        &mut *t
-    };
-    t.inputs.push(VarType::Buffer {
-        z,
-        cs: ColorSpace::Lrgb,
-        x1y1: x == 1 && y == 1,
-    });
-    t.inputs.len() as u64
+pub extern "C" fn translator_clear_inputs(t: *mut Translator) -> i32 {
+    guard(|| unsafe {
+        let t = translator(t)?;
+        t.inputs = Vec::new();
+        t.values = Vec::new();
+        Ok(())
+    })
+}
+
+fn add_input(t: *mut Translator, input: InputType) -> i32 {
+    guard(|| unsafe {
+        let t = translator(t)?;
+        t.inputs
+            .push(input.var_type().map_err(|_| IVY_ERROR_ARGUMENT)?);
+        t.values.push(input.value());
+        Ok(())
+    })
 }
 
 #[no_mangle]
-pub extern "C" fn translator_add_buffer_xyz(t: *mut Translator, x: u64, y: u64, z: u64) -> u64 {
-    let t = unsafe {
-        assert!(!t.is_null());
-        &mut *t
-    };
-    t.inputs.push(VarType::Buffer {
-        z,
-        cs: ColorSpace::Xyz,
-        x1y1: x == 1 && y == 1,
-    });
-    t.inputs.len() as u64
+pub extern "C" fn translator_add_bool(t: *mut Translator) -> i32 {
+    add_input(t, InputType::Bool)
 }
 
 #[no_mangle]
-pub extern "C" fn translator_add_buffer_lab(t: *mut Translator, x: u64, y: u64, z: u64) -> u64 {
-    let t = unsafe {
-        assert!(!t.is_null());
-        &mut *t
-    };
-    t.inputs.push(VarType::Buffer {
-        z,
-        cs: ColorSpace::Lab,
-        x1y1: x == 1 && y == 1,
-    });
-    t.inputs.len() as u64
-}
-
-#[no_mangle]
-pub extern "C" fn translator_add_buffer_lch(t: *mut Translator, x: u64, y: u64, z: u64) -> u64 {
-    let t = unsafe {
-        assert!(!t.is_null());
-        &mut *t
-    };
-    t.inputs.push(VarType::Buffer {
-        z,
-        cs: ColorSpace::Lch,
-        x1y1: x == 1 && y == 1,
-    });
-    t.inputs.len() as u64
+pub extern "C" fn translator_add_int(t: *mut Translator) -> i32 {
+    add_input(t, InputType::Int)
 }
 
-#[no_mangle]
-pub extern "C" fn translator_add_buffer_y(t: *mut Translator, x: u64, y: u64, z: u64) -> u64 {
-    let t = unsafe {
-        assert!(!t.is_null());
-        &mut *t
-    };
-    t.inputs.push(VarType::Buffer {
-        z,
-        cs: ColorSpace::Y,
-        x1y1: x == 1 && y == 1,
-    });
-    t.inputs.len() as u64
-}
-
-#[no_mangle]
-pub extern "C" fn translator_add_buffer_l(t: *mut Translator, x: u64, y: u64, z: u64) -> u64 {
-    let t = unsafe {
-        assert!(!t.is_null());
-        &mut *t
-    };
-    t.inputs.push(VarType::Buffer {
-        z,
-        cs: ColorSpace::L,
-        x1y1: x == 1 && y == 1,
-    });
-    t.inputs.len() as u64
+#[no_mangle]
+pub extern "C" fn translator_add_float(t: *mut Translator) -> i32 {
+    add_input(t, InputType::Float)
 }
 
+#[no_mangle]
+pub extern "C" fn translator_add_vec(t: *mut Translator) -> i32 {
+    add_input(t, InputType::Vec)
+}
+
+#[no_mangle]
+pub extern "C" fn translator_add_mat3(t: *mut Translator) -> i32 {
+    add_input(t, InputType::Mat3)
+}
+
+// values of constant inputs are part of the specialization, the kernel argument is unused
+#[no_mangle]
+pub extern "C" fn translator_add_int_const(t: *mut Translator, value: i32) -> i32 {
+    add_input(t, InputType::IntConst(value))
+}
+
+#[no_mangle]
+pub extern "C" fn translator_add_float_const(t: *mut Translator, value: f32) -> i32 {
+    add_input(t, InputType::FloatConst(value))
+}
+
+// arrays of fixed length, e.g. lookup tables
+#[no_mangle]
+pub extern "C" fn translator_add_int_array(t: *mut Translator, len: u64) -> i32 {
+    add_input(t, InputType::IntArray { len })
+}
+
+#[no_mangle]
+pub extern "C" fn translator_add_float_array(t: *mut Translator, len: u64) -> i32 {
+    add_input(t, InputType::FloatArray { len })
+}
+
+// function of the source called through the argument, the kernel argument is removed
+#[no_mangle]
+pub extern "C" fn translator_add_function(t: *mut Translator, name: *const i8) -> i32 {
+    guard(|| unsafe {
+        let t = translator(t)?;
+        let name = string(name)?;
+        let program = t.program.as_ref().map_err(|_| IVY_ERROR_TRANSLATE)?;
+        let input = program.function(name).map_err(|err| {
+            println!("[Generator]: {}", err);
+            IVY_ERROR_ARGUMENT
+        })?;
+        t.inputs
+            .push(input.var_type().map_err(|_| IVY_ERROR_ARGUMENT)?);
+        t.values.push(None);
+        Ok(())
+    })
+}
+
+fn add_buffer(t: *mut Translator, x: u64, y: u64, z: u64, cs: ColorSpace) -> i32 {
+    add_input(t, InputType::Buffer { x, y, z, cs })
+}
+
+#[no_mangle]
+pub extern "C" fn translator_add_buffer_srgb(t: *mut Translator, x: u64, y: u64, z: u64) -> i32 {
+    add_buffer(t, x, y, z, ColorSpace::Srgb)
+}
+
+#[no_mangle]
+pub extern "C" fn translator_add_buffer_lrgb(t: *mut Translator, x: u64, y: u64, z: u64) -> i32 {
+    add_buffer(t, x, y, z, ColorSpace::Lrgb)
+}
+
+#[no_mangle]
+pub extern "C" fn translator_add_buffer_xyz(t: *mut Translator, x: u64, y: u64, z: u64) -> i32 {
+    add_buffer(t, x, y, z, ColorSpace::Xyz)
+}
+
+#[no_mangle]
+pub extern "C" fn translator_add_buffer_lab(t: *mut Translator, x: u64, y: u64, z: u64) -> i32 {
+    add_buffer(t, x, y, z, ColorSpace::Lab)
+}
+
+#[no_mangle]
+pub extern "C" fn translator_add_buffer_lch(t: *mut Translator, x: u64, y: u64, z: u64) -> i32 {
+    add_buffer(t, x, y, z, ColorSpace::Lch)
+}
+
+#[no_mangle]
+pub extern "C" fn translator_add_buffer_y(t: *mut Translator, x: u64, y: u64, z: u64) -> i32 {
+    add_buffer(t, x, y, z, ColorSpace::Y)
+}
+
+#[no_mangle]
+pub extern "C" fn translator_add_buffer_l(t: *mut Translator, x: u64, y: u64, z: u64) -> i32 {
+    add_buffer(t, x, y, z, ColorSpace::L)
+}
+
+#[cfg(test)]
+mod random;
+#[cfg(test)]
+mod snapshot;
 #[cfg(test)]
 mod test;
@@ -0,0 +1,277 @@
+/*
+  Copyright (C) 2011-2021 G. Bajlekov
+
+    Ivy is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Ivy is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+// standard library of each target, embedded from ops/include:
+// the sources are split in top-level items with their dependencies,
+// generated kernels include only the items they reference
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::disk_cache;
+use crate::Target;
+
+// the root file comes first, includes are resolved by name
+const OCL: &[(&str, &str)] = &[
+    ("std.cl", include_str!("../../../ops/include/std.cl")),
+    (
+        "colorspace.cl",
+        include_str!("../../../ops/include/colorspace.cl"),
+    ),
+    ("cielab.cl", include_str!("../../../ops/include/cielab.cl")),
+    ("random.cl", include_str!("../../../ops/include/random.cl")),
+    ("atomic.cl", include_str!("../../../ops/include/atomic.cl")),
+    ("mat3.cl", include_str!("../../../ops/include/mat3.cl")),
+];
+
+const ISPC: &[(&str, &str)] = &[
+    ("std.ispc", include_str!("../../../ops/include/std.ispc")),
+    (
+        "random.ispc",
+        include_str!("../../../ops/include/random.ispc"),
+    ),
+    (
+        "atomic.ispc",
+        include_str!("../../../ops/include/atomic.ispc"),
+    ),
+    ("mat3.ispc", include_str!("../../../ops/include/mat3.ispc")),
+];
+
+struct Item {
+    name: String,
+    source: String,
+    is_macro: bool,
+    deps: Vec<usize>, // earlier items referenced by this item
+}
+
+pub struct Library {
+    items: Vec<Item>,
+    names: HashMap<String, Vec<usize>>, // overloads share a name
+    hash: u64,
+}
+
+pub fn library(target: Target) -> &'static Library {
+    static OCL_LIBRARY: OnceLock<Library> = OnceLock::new();
+    static ISPC_LIBRARY: OnceLock<Library> = OnceLock::new();
+
+    match target {
+        Target::Ocl => OCL_LIBRARY.get_or_init(|| Library::new(OCL)),
+        Target::Ispc => ISPC_LIBRARY.get_or_init(|| Library::new(ISPC)),
+    }
+}
+
+fn identifiers(s: &str) -> impl Iterator<Item = &str> {
+    s.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .filter(|id| id.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_'))
+}
+
+// inline included files, includes of other files are dropped
+fn flatten(files: &[(&str, &str)], source: &str, out: &mut String) {
+    for line in source.lines() {
+        let include = line
+            .trim()
+            .strip_prefix("#include")
+            .map(|f| f.trim().trim_matches('"'));
+        match include {
+            Some(file) => {
+                if let Some((_, source)) = files.iter().find(|(name, _)| *name == file) {
+                    flatten(files, source, out);
+                }
+            }
+            None => {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+    }
+}
+
+// comments are removed, line breaks in block comments are kept
+fn strip_comments(source: &str) -> String {
+    let mut out = String::new();
+    let mut chars = source.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('/', Some('/')) => {
+                while chars.peek().is_some_and(|c| *c != '\n') {
+                    chars.next();
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut prev = ' ';
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        out.push(c);
+                    }
+                    if prev == '*' && c == '/' {
+                        break;
+                    }
+                    prev = c;
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+// name declared by a function, variable or typedef
+fn item_name(source: &str) -> Option<&str> {
+    let head = source.split('{').next()?;
+    if head.trim_start().starts_with("typedef") {
+        return identifiers(source.rsplit('}').next()?).last();
+    }
+    match head.find('(') {
+        Some(i) if !head[..i].contains('=') => identifiers(&head[..i]).last(),
+        _ => identifiers(head.split(['=', ';', '[']).next()?).last(),
+    }
+}
+
+impl Library {
+    fn new(files: &[(&str, &str)]) -> Library {
+        let mut source = String::new();
+        flatten(files, files[0].1, &mut source);
+        let source = strip_comments(&source);
+
+        let mut items = Vec::new();
+        let mut current = String::new();
+        let mut depth = 0;
+        for line in source.lines().map(str::trim_end) {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if current.is_empty() && line.starts_with('#') {
+                // include guards and undefined macros are not needed
+                let define = line.strip_prefix("#define");
+                if let Some(name) = define.and_then(|d| identifiers(d).next()) {
+                    if !name.starts_with("__INCLUDE") {
+                        items.push((name.to_string(), line.to_string(), true));
+                    }
+                }
+                continue;
+            }
+
+            current.push_str(line);
+            current.push('\n');
+            depth += line.matches('{').count() as i32 - line.matches('}').count() as i32;
+            if depth == 0 && (line.ends_with('}') || line.ends_with(';')) {
+                if let Some(name) = item_name(&current) {
+                    items.push((name.to_string(), current.clone(), false));
+                }
+                current.clear();
+            }
+        }
+
+        let mut names: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut library: Vec<Item> = Vec::new();
+        for (i, (name, source, is_macro)) in items.into_iter().enumerate() {
+            // macros refer to their latest definition, functions to all earlier overloads
+            let mut deps = Vec::new();
+            for id in identifiers(if is_macro { "" } else { &source }) {
+                if let Some(defs) = names.get(id) {
+                    match defs.last() {
+                        Some(&j) if library[j].is_macro => deps.push(j),
+                        _ => deps.extend(defs),
+                    }
+                }
+            }
+            deps.sort_unstable();
+            deps.dedup();
+
+            names.entry(name.clone()).or_default().push(i);
+            library.push(Item {
+                name,
+                source,
+                is_macro,
+                deps,
+            });
+        }
+
+        Library {
+            items: library,
+            names,
+            hash: files.iter().fold(disk_cache::HASH_INIT, |h, (_, source)| {
+                disk_cache::hash(source.as_bytes(), h)
+            }),
+        }
+    }
+
+    // function or type defined by the library
+    pub fn defines(&self, name: &str) -> bool {
+        self.names
+            .get(name)
+            .is_some_and(|items| items.iter().any(|i| !self.items[*i].is_macro))
+    }
+
+    // identifies the version of the library, part of the cache directory keys
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+
+    // items referenced by the generated code and their dependencies, in library order
+    // macros are only visible to the library, they are undefined at the end
+    pub fn helpers(&self, code: &str) -> String {
+        let mut used = vec![false; self.items.len()];
+        let mut stack = identifiers(code)
+            .filter_map(|id| self.names.get(id))
+            .flatten()
+            .copied()
+            .filter(|i| !self.items[*i].is_macro)
+            .collect::<Vec<_>>();
+        while let Some(i) = stack.pop() {
+            if !used[i] {
+                used[i] = true;
+                stack.extend(&self.items[i].deps);
+            }
+        }
+
+        // consecutive macros are grouped, other items are separated by blank lines
+        let mut s = String::new();
+        let mut macros = Vec::new();
+        let mut after_macro = false;
+        for item in self
+            .items
+            .iter()
+            .zip(used)
+            .filter(|(_, u)| *u)
+            .map(|(i, _)| i)
+        {
+            if !(s.is_empty() || item.is_macro && after_macro) {
+                s.push('\n');
+            }
+            s.push_str(&item.source);
+            if item.is_macro {
+                s.push('\n');
+                if !macros.contains(&&item.name) {
+                    macros.push(&item.name);
+                }
+            }
+            after_macro = item.is_macro;
+        }
+        if !macros.is_empty() {
+            s.push('\n');
+            for m in macros {
+                s.push_str(&format!("#undef {}\n", m));
+            }
+        }
+        if !s.is_empty() {
+            s.push('\n');
+        }
+        s
+    }
+}
@@ -0,0 +1,452 @@
+/*
+  Copyright (C) 2011-2021 G. Bajlekov
+
+    Ivy is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Ivy is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeSet;
+
+use crate::ast::{Annotation, Expr, Index, Stmt};
+use crate::scope::ScopeTree;
+
+// lints with stable codes, used in the FFI and in '-- ivy:allow(code)' comments
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Lint {
+    UnusedVariable,
+    UnusedParameter,
+    Shadowing,
+    UnreachableCode,
+    ConstAssign,
+    InputWrite,
+}
+
+pub const LINTS: [Lint; 6] = [
+    Lint::UnusedVariable,
+    Lint::UnusedParameter,
+    Lint::Shadowing,
+    Lint::UnreachableCode,
+    Lint::ConstAssign,
+    Lint::InputWrite,
+];
+
+impl Lint {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Lint::UnusedVariable => "unused_variable",
+            Lint::UnusedParameter => "unused_parameter",
+            Lint::Shadowing => "shadowing",
+            Lint::UnreachableCode => "unreachable_code",
+            Lint::ConstAssign => "const_assign",
+            Lint::InputWrite => "input_write",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Lint> {
+        LINTS.iter().find(|l| l.code() == code).copied()
+    }
+}
+
+#[derive(Debug)]
+pub struct Warning {
+    pub lint: Lint,
+    pub message: String,
+    pub line: usize, // line of the statement, or of the declaration of unused variables
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[Line {}] warning[{}]: {}",
+            self.line,
+            self.lint.code(),
+            self.message
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Kind {
+    Constant, // file scope constant
+    Parameter,
+    Variable,
+    LocalConstant,
+    Loop,
+}
+
+struct Binding {
+    name: String,
+    kind: Kind,
+    input: bool, // input-only buffer
+    used: bool,
+    allowed: bool, // unused lint is allowed at the declaration
+    line: usize,
+}
+
+// parse lint codes from '-- ivy:allow(code, ...)' comments
+fn allowed(comment: &str) -> Vec<Lint> {
+    let mut lints = Vec::new();
+    let mut s = comment;
+    while let Some(start) = s.find("ivy:allow(") {
+        s = &s[start + 10..];
+        if let Some(end) = s.find(')') {
+            lints.extend(
+                s[..end]
+                    .split(',')
+                    .filter_map(|c| Lint::from_code(c.trim())),
+            );
+            s = &s[end..];
+        }
+    }
+    lints
+}
+
+struct Linter {
+    scope: ScopeTree<usize>, // indices into bindings
+    bindings: RefCell<Vec<Binding>>,
+    allowed: RefCell<Vec<BTreeSet<Lint>>>, // allowed lints in a stack of nested blocks
+    warnings: RefCell<Vec<Warning>>,
+    function: RefCell<String>, // description of the currently linted function
+    line: Cell<usize>,         // line of the currently linted statement
+}
+
+impl Linter {
+    fn warn(&self, lint: Lint, message: String) {
+        if self.is_allowed(lint) {
+            return;
+        }
+        self.warnings.borrow_mut().push(Warning {
+            lint,
+            message: format!("{} in {}", message, self.function.borrow()),
+            line: self.line.get(),
+        });
+    }
+
+    fn is_allowed(&self, lint: Lint) -> bool {
+        matches!(self.allowed.borrow().last(), Some(set) if set.contains(&lint))
+    }
+
+    // push allowed lints from the comments in a block
+    fn allow(&self, body: &[(Stmt, usize)]) {
+        let mut set = self.allowed.borrow().last().cloned().unwrap_or_default();
+        for (stmt, _) in body {
+            if let Stmt::Comment(c) = stmt {
+                set.extend(allowed(c));
+            }
+        }
+        self.allowed.borrow_mut().push(set);
+    }
+
+    fn declare(&self, id: &str, kind: Kind, input: bool) {
+        if let Some(n) = self.scope.get(id) {
+            let outer = match self.bindings.borrow()[n].kind {
+                Kind::Constant => "file scope constant",
+                _ => "variable",
+            };
+            self.warn(
+                Lint::Shadowing,
+                format!("'{}' shadows {} '{}'", id, outer, id),
+            );
+        }
+
+        let allowed = self.is_allowed(match kind {
+            Kind::Parameter => Lint::UnusedParameter,
+            _ => Lint::UnusedVariable,
+        });
+        let mut bindings = self.bindings.borrow_mut();
+        bindings.push(Binding {
+            name: id.into(),
+            kind,
+            input,
+            used: false,
+            allowed,
+            line: self.line.get(),
+        });
+        self.scope.add(id, bindings.len() - 1);
+    }
+
+    fn function(&self, stmt: &Stmt, line: usize) {
+        let (id, args, types, body, kind) = match stmt {
+            Stmt::Kernel {
+                id,
+                args,
+                types,
+                body,
+                ..
+            } => (id, args, types, body, "kernel"),
+            Stmt::Function {
+                id,
+                args,
+                types,
+                body,
+                ..
+            } => (id, args, types, body, "function"),
+            _ => return,
+        };
+        *self.function.borrow_mut() = format!("{} '{}'", kind, id);
+        self.line.set(line);
+
+        let start = self.bindings.borrow().len();
+        self.allow(body);
+        self.scope.open();
+        self.scope.set_parent(0); // only file scope constants are visible
+
+        for (arg, ty) in args.iter().zip(types) {
+            let input = matches!(ty, Some(Annotation::Buffer { input: true, .. }));
+            self.declare(arg, Kind::Parameter, input);
+        }
+        self.block(body);
+
+        self.scope.close();
+        self.scope.set_current(0);
+        self.allowed.borrow_mut().pop();
+
+        for b in self.bindings.borrow()[start..].iter() {
+            if b.used || b.allowed || b.kind == Kind::Loop {
+                continue;
+            }
+            let (lint, what) = match b.kind {
+                Kind::Parameter => (Lint::UnusedParameter, "parameter"),
+                _ => (Lint::UnusedVariable, "variable"),
+            };
+            // allowed lints were already checked at the declaration
+            self.warnings.borrow_mut().push(Warning {
+                lint,
+                message: format!("Unused {} '{}' in {}", what, b.name, self.function.borrow()),
+                line: b.line,
+            });
+        }
+    }
+
+    fn scoped_block(&self, body: &[(Stmt, usize)]) {
+        self.allow(body);
+        self.scope.open();
+        self.block(body);
+        self.scope.close();
+        self.allowed.borrow_mut().pop();
+    }
+
+    fn block(&self, body: &[(Stmt, usize)]) {
+        let mut terminated = None;
+        for (stmt, line) in body {
+            if let Stmt::Comment(_) = stmt {
+                continue;
+            }
+            self.line.set(*line);
+            if let Some(t) = terminated.take() {
+                self.warn(
+                    Lint::UnreachableCode,
+                    format!("Unreachable statement after '{}'", t),
+                );
+            }
+            terminated = self.stmt(stmt).or(terminated);
+        }
+    }
+
+    // returns the terminating keyword for return, break and continue statements
+    fn stmt(&self, stmt: &Stmt) -> Option<&'static str> {
+        match stmt {
+            Stmt::Var(id, _, expr) => {
+                self.expr(expr);
+                self.declare(id, Kind::Variable, false);
+            }
+            Stmt::Const(id, _, expr) => {
+                self.expr(expr);
+                self.declare(id, Kind::LocalConstant, false);
+            }
+            Stmt::Assign(place, expr) => {
+                self.expr(expr);
+                self.place(place, false);
+            }
+            Stmt::AssignOp(place, _, expr) => {
+                self.expr(expr);
+                self.expr(place); // value is read before assignment
+                self.place(place, false);
+            }
+            Stmt::Call(id, args) => {
+                self.call(id);
+                args.iter().for_each(|e| self.expr(e));
+            }
+            Stmt::Vars(ids, expr) => {
+                self.expr(expr);
+                ids.iter()
+                    .for_each(|id| self.declare(id, Kind::Variable, false));
+            }
+            Stmt::Return(values) => {
+                values.iter().for_each(|e| self.expr(e));
+                return Some("return");
+            }
+            Stmt::Continue => return Some("continue"),
+            Stmt::Break => return Some("break"),
+            Stmt::IfElse {
+                cond_list,
+                else_body,
+            } => {
+                for c in cond_list {
+                    self.expr(&c.cond);
+                    self.scoped_block(&c.body);
+                }
+                self.scoped_block(else_body);
+            }
+            Stmt::For {
+                var,
+                from,
+                to,
+                step,
+                body,
+            } => {
+                self.expr(from);
+                self.expr(to);
+                if let Some(step) = step {
+                    self.expr(step);
+                }
+                self.allow(body);
+                self.scope.open();
+                self.declare(var, Kind::Loop, false);
+                self.block(body);
+                self.scope.close();
+                self.allowed.borrow_mut().pop();
+            }
+            Stmt::While { cond, body } => {
+                self.expr(cond);
+                self.scoped_block(body);
+            }
+            _ => {}
+        }
+        None
+    }
+
+    // check an assigned variable, indexed writes to parameters are outputs and count as use
+    fn place(&self, expr: &Expr, indexed: bool) {
+        match expr {
+            Expr::Index(expr, idx) => {
+                self.index(idx);
+                self.place(expr, true);
+            }
+            Expr::Identifier(id) => {
+                if let Some(n) = self.scope.get(id) {
+                    let (kind, input) = {
+                        let b = &mut self.bindings.borrow_mut()[n];
+                        if indexed && b.kind == Kind::Parameter {
+                            b.used = true;
+                        }
+                        (b.kind, b.input)
+                    };
+                    if kind == Kind::Constant || kind == Kind::LocalConstant {
+                        self.warn(
+                            Lint::ConstAssign,
+                            format!("Assignment to constant '{}'", id),
+                        );
+                    }
+                    if input {
+                        self.warn(
+                            Lint::InputWrite,
+                            format!("Write to input-only buffer '{}'", id),
+                        );
+                    }
+                }
+            }
+            expr => self.expr(expr),
+        }
+    }
+
+    fn index(&self, idx: &Index) {
+        match idx {
+            Index::Array1D(a) => self.expr(a),
+            Index::Array2D(a, b) => {
+                self.expr(a);
+                self.expr(b);
+            }
+            Index::Array3D(a, b, c) => {
+                self.expr(a);
+                self.expr(b);
+                self.expr(c);
+            }
+            Index::Array4D(a, b, c, d) => {
+                self.expr(a);
+                self.expr(b);
+                self.expr(c);
+                self.expr(d);
+            }
+            _ => {}
+        }
+    }
+
+    // calls through a function argument use it
+    fn call(&self, id: &str) {
+        if let Some(n) = self.scope.get(id) {
+            self.bindings.borrow_mut()[n].used = true;
+        }
+    }
+
+    fn expr(&self, expr: &Expr) {
+        match expr {
+            Expr::Identifier(id) => {
+                if let Some(n) = self.scope.get(id) {
+                    self.bindings.borrow_mut()[n].used = true;
+                }
+            }
+            Expr::Unary(e) => self.expr(&e.right),
+            Expr::Binary(e) => {
+                self.expr(&e.left);
+                self.expr(&e.right);
+            }
+            Expr::Grouping(e) => self.expr(e),
+            Expr::Call(id, args) => {
+                self.call(id);
+                args.iter().for_each(|e| self.expr(e));
+            }
+            Expr::Array(args) => args.iter().for_each(|e| self.expr(e)),
+            Expr::Index(e, idx) => {
+                self.expr(e);
+                self.index(idx);
+            }
+            Expr::Literal(_) => {}
+        }
+    }
+}
+
+// collect warnings for all functions and kernels in the file scope
+pub fn lint(ast: &[(Stmt, usize)]) -> Vec<Warning> {
+    let linter = Linter {
+        scope: ScopeTree::new(),
+        bindings: RefCell::new(Vec::new()),
+        allowed: RefCell::new(Vec::new()),
+        warnings: RefCell::new(Vec::new()),
+        function: RefCell::new("file scope".into()),
+        line: Cell::new(0),
+    };
+
+    // allow comments in the file scope apply to the whole file
+    let stmts = ast.iter().map(|(s, _)| s).collect::<Vec<_>>();
+    let mut set = BTreeSet::new();
+    for stmt in &stmts {
+        if let Stmt::Comment(c) = stmt {
+            set.extend(allowed(c));
+        }
+    }
+    linter.allowed.borrow_mut().push(set);
+
+    for stmt in stmts {
+        if let Stmt::Const(id, ..) = stmt {
+            linter.declare(id, Kind::Constant, false);
+        }
+    }
+    for (stmt, line) in ast {
+        linter.function(stmt, *line);
+    }
+
+    linter.warnings.into_inner()
+}
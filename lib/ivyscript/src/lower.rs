@@ -0,0 +1,1555 @@
+/*
+  Copyright (C) 2011-2021 G. Bajlekov
+
+    Ivy is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Ivy is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use crate::ast;
+use crate::ast::{Annotation, AssignOp, BinaryOp, Index, Literal, Prop, Traversal};
+use crate::builtin::Builtin;
+use crate::fold::{constant, Folder};
+use crate::function_id::function_id;
+use crate::inference::{Inference, VarType};
+use crate::ir::{
+    random_stream, reduction, BufIdx, Expr, ExprKind, Function, Kernel, Place, Program, Stmt,
+    Struct,
+};
+
+// recursively unwrap nested indices to find the variable name
+fn root(expr: &ast::Expr) -> Result<&str, String> {
+    match expr {
+        ast::Expr::Index(expr, _) => root(expr),
+        ast::Expr::Identifier(name) => Ok(name),
+        expr => Err(format!(
+            "Expected buffer or array identifier for indexed access, found '{:?}'",
+            expr
+        )),
+    }
+}
+
+// collect identifiers referenced in an expression
+fn identifiers(expr: &ast::Expr, ids: &mut Vec<String>) {
+    match expr {
+        ast::Expr::Identifier(id) => ids.push(id.clone()),
+        ast::Expr::Unary(e) => identifiers(&e.right, ids),
+        ast::Expr::Binary(e) => {
+            identifiers(&e.left, ids);
+            identifiers(&e.right, ids);
+        }
+        ast::Expr::Grouping(e) => identifiers(e, ids),
+        ast::Expr::Call(_, args) | ast::Expr::Array(args) => {
+            args.iter().for_each(|e| identifiers(e, ids))
+        }
+        ast::Expr::Index(e, idx) => {
+            identifiers(e, ids);
+            match idx.as_ref() {
+                Index::Array1D(a) => identifiers(a, ids),
+                Index::Array2D(a, b) => {
+                    identifiers(a, ids);
+                    identifiers(b, ids);
+                }
+                Index::Array3D(a, b, c) => {
+                    identifiers(a, ids);
+                    identifiers(b, ids);
+                    identifiers(c, ids);
+                }
+                Index::Array4D(a, b, c, d) => {
+                    identifiers(a, ids);
+                    identifiers(b, ids);
+                    identifiers(c, ids);
+                    identifiers(d, ids);
+                }
+                _ => {}
+            }
+        }
+        ast::Expr::Literal(_) => {}
+    }
+}
+
+// source definitions, shared immutably between concurrent specializations
+pub struct Lower {
+    constants: Vec<(String, Option<Annotation>, ast::Expr)>, // in source order
+    functions: HashMap<String, ast::Stmt>,
+    kernels: HashMap<String, ast::Stmt>,
+    names: Vec<String>, // sorted function names, indexed by function arguments
+    structs: Vec<Struct>,
+}
+
+impl Lower {
+    pub fn new(ast: Vec<ast::Stmt>) -> Lower {
+        let mut constants = Vec::new();
+        let mut functions = HashMap::new();
+        let mut kernels = HashMap::new();
+        let mut structs = Vec::new();
+
+        for stmt in ast {
+            match stmt {
+                ast::Stmt::Const(id, annotation, expr) => {
+                    // redefined constants replace earlier definitions
+                    constants.retain(|(c, _, _)| *c != id);
+                    constants.push((id, annotation, expr));
+                }
+                ast::Stmt::Function { ref id, .. } => {
+                    functions.insert(id.clone(), stmt);
+                }
+                ast::Stmt::Kernel { ref id, .. } => {
+                    kernels.insert(id.clone(), stmt);
+                }
+                ast::Stmt::Struct { id, fields } => structs.push((id, fields)),
+                _ => {} // other file scope statements are rejected by validation
+            }
+        }
+
+        let mut names = functions.keys().cloned().collect::<Vec<_>>();
+        names.sort_unstable();
+
+        // field types of validated structs refer to other structs by index
+        structs.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        let index = |id: &str| structs.iter().position(|(s, _)| s == id);
+        let structs = structs
+            .iter()
+            .map(|(id, fields)| Struct {
+                id: id.clone(),
+                fields: fields
+                    .iter()
+                    .map(|(f, a)| {
+                        let ty = match a {
+                            Annotation::Bool => VarType::Bool,
+                            Annotation::Int => VarType::Int,
+                            Annotation::Float => VarType::Float,
+                            Annotation::Vec => VarType::Vec,
+                            Annotation::Mat3 => VarType::Mat3,
+                            Annotation::Struct(s) => {
+                                index(s).map_or(VarType::Unknown, |n| VarType::Struct(n as u32))
+                            }
+                            _ => VarType::Unknown,
+                        };
+                        (f.clone(), ty)
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        Lower {
+            constants,
+            functions,
+            kernels,
+            names,
+            structs,
+        }
+    }
+
+    pub fn kernels(&self) -> Vec<&str> {
+        let mut kernels = self.kernels.keys().map(|k| k.as_str()).collect::<Vec<_>>();
+        kernels.sort_unstable();
+        kernels
+    }
+
+    pub fn has_function(&self, name: &str) -> bool {
+        self.functions.contains_key(name)
+    }
+
+    // index of a function passed as argument, see VarType::Function
+    pub fn function_index(&self, name: &str) -> Option<u32> {
+        self.names
+            .binary_search_by(|n| n.as_str().cmp(name))
+            .ok()
+            .map(|n| n as u32)
+    }
+
+    // traversal annotation of a kernel, independent of its inputs
+    pub fn traversal(&self, name: &str) -> Result<Option<Traversal>, String> {
+        match self.kernels.get(name) {
+            Some(ast::Stmt::Kernel { traversal, .. }) => Ok(*traversal),
+            _ => Err(format!("Kernel '{}' not found in source", name)),
+        }
+    }
+
+    // lowers the AST of a kernel to the typed IR, specializing functions for their argument types
+    // values are known constant inputs, missing values are runtime inputs
+    pub fn kernel(
+        &self,
+        name: &str,
+        input: &[VarType],
+        values: &[Option<Literal>],
+        builtins: &[Builtin],
+    ) -> Result<Program, String> {
+        let lowering = Lowering {
+            constants: &self.constants,
+            functions: &self.functions,
+            kernels: &self.kernels,
+            names: &self.names,
+            builtins,
+            externals: RefCell::new(Vec::new()),
+            inference: Inference::new(Rc::new(self.structs.clone())),
+            instances: RefCell::new(HashMap::new()),
+            dependencies: RefCell::new(Vec::new()),
+            active: RefCell::new(Vec::new()),
+            random: RefCell::new(Vec::new()),
+            reduce: Cell::new(false),
+            returns: RefCell::new(vec![None]), // kernels have no annotated return type
+            outs: RefCell::new(vec![None]),
+        };
+        lowering.kernel(name, input, values)
+    }
+}
+
+// state of a single kernel specialization
+struct Lowering<'a> {
+    constants: &'a [(String, Option<Annotation>, ast::Expr)],
+    functions: &'a HashMap<String, ast::Stmt>,
+    kernels: &'a HashMap<String, ast::Stmt>,
+    names: &'a [String],
+    builtins: &'a [Builtin],
+    externals: RefCell<Vec<Rc<Builtin>>>, // called builtins registered by the host
+    inference: Inference,
+    instances: RefCell<HashMap<String, Rc<Function>>>, // collect specialized functions
+    dependencies: RefCell<Vec<Vec<String>>>, // collects dependencies of currently lowered function in a stack
+    active: RefCell<Vec<String>>, // ids of currently lowered functions, calls through function arguments can recurse
+    random: RefCell<Vec<bool>>, // whether currently lowered functions draw from the random stream, in a stack
+    reduce: Cell<bool>,         // whether the kernel calls workgroup reductions
+    returns: RefCell<Vec<Option<Annotation>>>, // annotated return types of currently lowered functions in a stack
+    outs: RefCell<Vec<Option<Vec<VarType>>>>, // types of additional return values, None before the first return
+}
+
+impl<'a> Lowering<'a> {
+    fn kernel(
+        &self,
+        name: &str,
+        input: &[VarType],
+        values: &[Option<Literal>],
+    ) -> Result<Program, String> {
+        let constants = self.constants()?;
+
+        if let Some(ast::Stmt::Kernel {
+            id,
+            args,
+            types,
+            traversal,
+            body,
+        }) = self.kernels.get(name)
+        {
+            if args.len() != input.len() {
+                return Err(format!(
+                    "Kernel '{}' expects {} arguments, found {}",
+                    name,
+                    args.len(),
+                    input.len()
+                ));
+            }
+
+            // kernel arguments are bound by the host and must match their annotations exactly
+            for ((v, a), t) in args.iter().zip(types).zip(input) {
+                if let Some(a) = a {
+                    match self.inference.annotation(a, *t) {
+                        Ok(promoted) if promoted == *t => {}
+                        _ => {
+                            return Err(format!(
+                                "Argument '{}' of kernel '{}': expected type '{}', found '{}'",
+                                v, name, a, t
+                            ))
+                        }
+                    }
+                }
+            }
+
+            // new kernel scope with void return type
+            self.inference.scope.open();
+            self.inference.scope.add("return", VarType::Void); // explicitly expect void return type for kernels
+
+            // new frame on the dependency stack
+            self.dependencies.borrow_mut().push(Vec::new());
+            self.random.borrow_mut().push(false);
+
+            // constant inputs are declared in the kernel body, the renamed argument is unused
+            // function arguments are resolved in calls and not passed at all
+            let mut prelude = Vec::new();
+            let args = args
+                .iter()
+                .zip(input)
+                .enumerate()
+                .filter_map(|(n, (v, t))| {
+                    self.inference.scope.add(v, *t); // add argument to scope
+                    match (values.get(n).copied().flatten(), t) {
+                        (_, VarType::Function(_)) => None,
+                        (Some(value), _) => {
+                            prelude.push(Stmt::Const {
+                                id: v.clone(),
+                                ty: *t,
+                                init: Some(Expr {
+                                    kind: ExprKind::Literal(value),
+                                    ty: *t,
+                                }),
+                            });
+                            Some((format!("___const_{}", v), *t))
+                        }
+                        (None, _) => Some((v.clone(), *t)),
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            let mut body = self.block(body)?;
+            if !prelude.is_empty() {
+                prelude.append(&mut body);
+                let mut folder = Folder::with_instances(&self.instances);
+                body = folder.block(prelude);
+
+                // calls redirected to specialized instances replace the dependencies of the kernel
+                if let Some(deps) = self.dependencies.borrow_mut().last_mut() {
+                    *deps = folder.calls();
+                }
+            }
+
+            // check whether return value is of type void
+            if self.inference.scope.get("return") != Some(VarType::Void) {
+                return Err(format!(
+                    "Expected return value of type 'Void' for kernel '{}'",
+                    name
+                ));
+            }
+            self.inference.scope.close();
+
+            let functions = self.dependencies()?; // pops dependencies frame
+            let random = self.random.borrow_mut().pop().unwrap_or(false);
+
+            Ok(Program {
+                externals: self.externals.take(),
+                constants,
+                structs: self.inference.structs.clone(),
+                functions,
+                kernel: Kernel {
+                    id: id.clone(),
+                    args,
+                    traversal: *traversal,
+                    body,
+                    random,
+                    reduce: self.reduce.get(),
+                },
+            })
+        } else {
+            Err(format!("Kernel '{}' not found in source", name))
+        }
+    }
+
+    fn constants(&self) -> Result<Rc<Vec<Stmt>>, String> {
+        // constants are added to the root scope and remain visible in all kernels
+        let mut constants = Vec::new();
+        for n in self.constant_order()? {
+            let (id, annotation, expr) = &self.constants[n];
+            let (ty, init) = self.var(id, annotation, expr)?;
+            if let VarType::Struct(_) | VarType::StructArray(..) = ty {
+                return Err(format!("Constant '{}' can not be a struct", id));
+            }
+            if let VarType::Mat3 = ty {
+                return Err(format!("Constant '{}' can not be a matrix", id));
+            }
+            constants.push(Stmt::Const {
+                id: id.clone(),
+                ty,
+                init,
+            });
+        }
+
+        Ok(Rc::new(constants))
+    }
+
+    // source order of constants, with constants moved after the constants they reference
+    fn constant_order(&self) -> Result<Vec<usize>, String> {
+        fn visit(
+            constants: &[(String, Option<Annotation>, ast::Expr)],
+            n: usize,
+            visiting: &mut Vec<usize>,
+            order: &mut Vec<usize>,
+        ) -> Result<(), String> {
+            if order.contains(&n) {
+                return Ok(());
+            }
+            if visiting.contains(&n) {
+                return Err(format!(
+                    "Circular definition of constant '{}'",
+                    constants[n].0
+                ));
+            }
+            visiting.push(n);
+
+            let mut ids = Vec::new();
+            identifiers(&constants[n].2, &mut ids);
+            for id in ids {
+                if let Some(m) = constants.iter().position(|(c, _, _)| *c == id) {
+                    visit(constants, m, visiting, order)?;
+                }
+            }
+
+            visiting.pop();
+            order.push(n);
+            Ok(())
+        }
+
+        let mut order = Vec::new();
+        for n in 0..self.constants.len() {
+            visit(self.constants, n, &mut Vec::new(), &mut order)?;
+        }
+        Ok(order)
+    }
+
+    fn function(&self, name: &str, input: &[VarType]) -> Result<Rc<Function>, String> {
+        let id = function_id(name, input);
+
+        if let Some(f) = self.instances.borrow().get(&id) {
+            return Ok(f.clone());
+        }
+
+        if let Some(ast::Stmt::Function {
+            args, ret, body, ..
+        }) = self.functions.get(name)
+        {
+            if args.len() != input.len() {
+                return Err(format!(
+                    "Function '{}' expects {} arguments, found {}",
+                    name,
+                    args.len(),
+                    input.len()
+                ));
+            }
+
+            if self.active.borrow().contains(&id) {
+                return Err(format!(
+                    "Recursive function calls are not supported: {}",
+                    name
+                ));
+            }
+            self.active.borrow_mut().push(id.clone());
+
+            // new function scope, keep outer scope reference to restore at the end
+            let outer_scope = self.inference.scope.current.get();
+            self.inference.scope.open();
+            self.inference.scope.set_parent(0); // no parent scope
+            match ret {
+                Some(a) => {
+                    let t = match a {
+                        Annotation::Bool => VarType::Bool,
+                        Annotation::Int => VarType::Int,
+                        Annotation::Float => VarType::Float,
+                        Annotation::Vec => VarType::Vec,
+                        Annotation::Mat3 => VarType::Mat3,
+                        Annotation::Buffer { .. } => {
+                            return Err(format!("Function '{}' can not return a buffer", name))
+                        }
+                        Annotation::Function => {
+                            return Err(format!("Function '{}' can not return a function", name))
+                        }
+                        Annotation::Struct(s) => match self.inference.struct_type(s) {
+                            Some((n, _)) => VarType::Struct(n),
+                            None => return Err(format!("Unknown type '{}'", s)),
+                        },
+                    };
+                    self.inference.scope.add("return", t)
+                }
+                None => self.inference.scope.placeholder("return"),
+            };
+            self.returns.borrow_mut().push(ret.clone());
+            self.outs.borrow_mut().push(None);
+
+            // new frame on the dependency stack
+            self.dependencies.borrow_mut().push(Vec::new());
+            self.random.borrow_mut().push(false);
+
+            let args = args
+                .iter()
+                .zip(input)
+                .filter_map(|(v, t)| {
+                    self.inference.scope.add(v, *t); // add argument to scope
+                    match t {
+                        VarType::Function(_) => None,
+                        _ => Some((v.clone(), *t)),
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            let body = self.block(body)?;
+
+            // get function return type
+            let ret = self.inference.scope.get("return").unwrap_or(VarType::Void); // use void return type if none specified
+            match ret {
+                VarType::Bool
+                | VarType::Int
+                | VarType::Float
+                | VarType::Vec
+                | VarType::Mat3
+                | VarType::Struct(_)
+                | VarType::Void => {}
+                _ => return Err(format!("Unknown return type of function '{}'", name)),
+            }
+            let outs = self.outs.borrow_mut().pop().flatten().unwrap_or_default();
+            for t in &outs {
+                match t {
+                    VarType::Bool
+                    | VarType::Int
+                    | VarType::Float
+                    | VarType::Vec
+                    | VarType::Mat3
+                    | VarType::Struct(_) => {}
+                    _ => return Err(format!("Unknown return type of function '{}'", name)),
+                }
+            }
+            self.inference.scope.close();
+            self.inference.scope.set_current(outer_scope);
+            self.returns.borrow_mut().pop();
+            self.active.borrow_mut().pop();
+
+            // collect function dependencies from stack
+            let deps = self
+                .dependencies
+                .borrow_mut()
+                .pop()
+                .ok_or_else(|| "No dependency frame found!".to_string())?;
+            let random = self.random.borrow_mut().pop().unwrap_or(false);
+
+            let function = Rc::new(Function {
+                id: id.clone(),
+                name: name.to_string(),
+                input: input.to_vec(),
+                args,
+                ret,
+                outs,
+                body,
+                deps,
+                random,
+            });
+
+            // register specialized function
+            self.instances.borrow_mut().insert(id, function.clone());
+
+            Ok(function)
+        } else {
+            Err(format!("Function '{}' not found", id))
+        }
+    }
+
+    // collect the function instances a kernel depends on, directly or indirectly,
+    // in order of first call with called functions before their callers
+    fn dependencies(&self) -> Result<Vec<Rc<Function>>, String> {
+        fn visit(
+            instances: &HashMap<String, Rc<Function>>,
+            id: &str,
+            satisfied: &mut HashSet<String>,
+            order: &mut Vec<Rc<Function>>,
+        ) -> Result<(), String> {
+            if !satisfied.insert(id.to_string()) {
+                return Ok(());
+            }
+            let function = instances
+                .get(id)
+                .ok_or::<String>(format!("No function dependency '{}' found", id))?;
+            for dep in &function.deps {
+                visit(instances, dep, satisfied, order)?;
+            }
+            order.push(function.clone());
+            Ok(())
+        }
+
+        let deps = self
+            .dependencies
+            .borrow_mut()
+            .pop()
+            .ok_or_else(|| "No dependency frame found!".to_string())?;
+
+        let mut satisfied = HashSet::new(); // dependencies which are already satisfied
+        let mut order = Vec::new();
+        for id in &deps {
+            visit(&self.instances.borrow(), id, &mut satisfied, &mut order)?;
+        }
+        Ok(order)
+    }
+
+    fn block(&self, body: &[(ast::Stmt, usize)]) -> Result<Vec<Stmt>, String> {
+        let mut stmts = Vec::new();
+        for (s, _) in body {
+            // branches eliminated when specializing leave no statement
+            if let ast::Stmt::IfElse {
+                cond_list,
+                else_body,
+            } = s
+            {
+                stmts.extend(self.if_else(cond_list, else_body)?);
+            } else if let ast::Stmt::Vars(ids, expr) = s {
+                stmts.extend(self.vars(ids, expr)?);
+            } else {
+                stmts.push(self.stmt(s)?);
+            }
+        }
+        Ok(stmts)
+    }
+
+    // conditions known when specializing, such as 'buf.z == 3', select their branch and
+    // branches which are not taken are not lowered, the selected branch keeps its scope
+    fn if_else(
+        &self,
+        cond_list: &[ast::Cond],
+        else_body: &[(ast::Stmt, usize)],
+    ) -> Result<Option<Stmt>, String> {
+        let mut branches = Vec::new();
+        let mut else_body = else_body;
+        for ast::Cond { cond, body } in cond_list {
+            let cond = self.cond(cond)?;
+            match constant(&cond) {
+                Some(Literal::Bool(false)) => {}
+                Some(Literal::Bool(true)) => {
+                    else_body = body;
+                    break;
+                }
+                _ => branches.push((cond, self.scoped_block(body)?)),
+            }
+        }
+        let else_body = self.scoped_block(else_body)?;
+
+        if branches.is_empty() {
+            if else_body.is_empty() {
+                return Ok(None);
+            }
+            let cond = Expr {
+                kind: ExprKind::Literal(Literal::Bool(true)),
+                ty: VarType::Bool,
+            };
+            return Ok(Some(Stmt::IfElse {
+                cond_list: vec![(cond, else_body)],
+                else_body: Vec::new(),
+            }));
+        }
+        Ok(Some(Stmt::IfElse {
+            cond_list: branches,
+            else_body,
+        }))
+    }
+
+    // lower a body in its own scope
+    fn scoped_block(&self, body: &[(ast::Stmt, usize)]) -> Result<Vec<Stmt>, String> {
+        self.inference.scope.open();
+        let body = self.block(body)?;
+        self.inference.scope.close();
+        Ok(body)
+    }
+
+    fn stmt(&self, stmt: &ast::Stmt) -> Result<Stmt, String> {
+        let stmt = match stmt {
+            ast::Stmt::Var(id, annotation, expr) => {
+                let (ty, init) = self.var(id, annotation, expr)?;
+                Stmt::Var {
+                    id: id.clone(),
+                    ty,
+                    init,
+                }
+            }
+            ast::Stmt::Const(id, annotation, expr) => {
+                let (ty, init) = self.var(id, annotation, expr)?;
+                Stmt::Const {
+                    id: id.clone(),
+                    ty,
+                    init,
+                }
+            }
+            ast::Stmt::Assign(target, value) => {
+                let value = self.expr(value)?;
+                self.assign(target, value)?
+            }
+            ast::Stmt::AssignOp(target, op, value) => {
+                // desugar into assignment of a binary operation on the current value
+                let op = match op {
+                    AssignOp::Sub => BinaryOp::Sub,
+                    AssignOp::Add => BinaryOp::Add,
+                    AssignOp::Div => BinaryOp::Div,
+                    AssignOp::Mul => BinaryOp::Mul,
+                    AssignOp::Mod => BinaryOp::Mod,
+                    AssignOp::Pow => BinaryOp::Pow,
+                };
+                let left = self.expr(target)?;
+                let right = self.expr(value)?;
+                let ty = self.inference.binary(&op, left.ty, right.ty)?;
+                let value = Expr {
+                    kind: ExprKind::Binary(op, Box::new(left), Box::new(right)),
+                    ty,
+                };
+                self.assign(target, value)?
+            }
+            ast::Stmt::Call(id, args) => Stmt::Call(self.call(id, args)?),
+            ast::Stmt::For {
+                var,
+                from,
+                to,
+                step,
+                body,
+            } => self.for_loop(var, from, to, step, body)?,
+            ast::Stmt::While { cond, body } => Stmt::While {
+                cond: self.cond(cond)?,
+                body: self.scoped_block(body)?,
+            },
+            ast::Stmt::Return(values) if values.is_empty() => {
+                match self.inference.scope.get("return") {
+                    Some(VarType::Void) | None => Stmt::Return(Vec::new()),
+                    Some(t) => {
+                        return Err(format!(
+                        "Void return statement inconsistent with previously used return type '{}'",
+                        t
+                    ))
+                    }
+                }
+            }
+            ast::Stmt::Return(values) => {
+                let mut values = values
+                    .iter()
+                    .map(|e| self.expr(e))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut expr = values.remove(0);
+
+                // annotated return values are promoted to the annotated type
+                if let Some(Some(a)) = self.returns.borrow().last() {
+                    if !values.is_empty() {
+                        return Err("Functions with an annotated return type can not return multiple values".into());
+                    }
+                    let ty = self.inference.annotation(a, expr.ty).map_err(|e| {
+                        format!("Return value does not match the annotated type: {}", e)
+                    })?;
+                    expr = self.coerce(expr, ty);
+                }
+
+                // return value is either new, same as--, or promoted from the previous one
+                let old = self.inference.scope.get("return").unwrap_or(expr.ty);
+                let promoted = self.inference.promote(expr.ty, old)?;
+                self.inference.scope.overwrite("return", promoted);
+
+                // additional values match in number and are promoted the same way
+                let mut outs = self.outs.borrow_mut();
+                let outs = outs
+                    .last_mut()
+                    .ok_or_else(|| "No return frame found!".to_string())?;
+                match outs {
+                    Some(old) if old.len() != values.len() => {
+                        return Err(format!(
+                            "Return statement with {} values inconsistent with previously returned {} values",
+                            values.len() + 1,
+                            old.len() + 1
+                        ))
+                    }
+                    Some(old) => {
+                        for (t, e) in old.iter_mut().zip(&values) {
+                            *t = self.inference.promote(e.ty, *t)?;
+                        }
+                    }
+                    None => *outs = Some(values.iter().map(|e| e.ty).collect()),
+                }
+
+                values.insert(0, expr);
+                Stmt::Return(values)
+            }
+            ast::Stmt::Continue => Stmt::Continue,
+            ast::Stmt::Break => Stmt::Break,
+            ast::Stmt::Comment(c) => Stmt::Comment(c.clone()),
+            stmt => return Err(format!("Unable to generate code for:\n{:?}", stmt)),
+        };
+
+        Ok(stmt)
+    }
+
+    fn cond(&self, cond: &ast::Expr) -> Result<Expr, String> {
+        let cond = self.expr(cond)?;
+        if cond.ty != VarType::Bool {
+            return Err(format!(
+                "Expected condition of type 'Bool', found '{}'",
+                cond.ty
+            ));
+        }
+        Ok(cond)
+    }
+
+    fn for_loop(
+        &self,
+        var: &str,
+        from: &ast::Expr,
+        to: &ast::Expr,
+        step: &Option<ast::Expr>,
+        body: &[(ast::Stmt, usize)],
+    ) -> Result<Stmt, String> {
+        self.inference.scope.open();
+
+        let from = self.expr(from)?;
+        let to = self.expr(to)?;
+        let step = match step {
+            Some(step) => Some(self.expr(step)?),
+            None => None,
+        };
+
+        // infer var type
+        let mut ty = self.inference.promote_num(from.ty, to.ty)?;
+        if let Some(step) = &step {
+            ty = self.inference.promote_num(ty, step.ty)?;
+        }
+        match ty {
+            VarType::Int | VarType::Float | VarType::Vec => {}
+            _ => return Err(format!("Incompatible loop variable type '{}'", ty)),
+        }
+        self.inference.scope.add(var, ty);
+
+        let body = self.block(body)?;
+        self.inference.scope.close();
+
+        Ok(Stmt::For {
+            var: var.into(),
+            ty,
+            from,
+            to,
+            step,
+            body,
+        })
+    }
+
+    fn var(
+        &self,
+        id: &str,
+        annotation: &Option<Annotation>,
+        expr: &ast::Expr,
+    ) -> Result<(VarType, Option<Expr>), String> {
+        let mut init = self.expr(expr)?;
+        if let Some(a) = annotation {
+            let ty = self.inference.annotation(a, init.ty).map_err(|e| {
+                format!("Variable '{}' does not match the annotated type: {}", id, e)
+            })?;
+            init = self.coerce(init, ty);
+        }
+        let ty = init.ty;
+
+        let init = match &init.kind {
+            ExprKind::Builtin(f, _) => match f.as_ref() {
+                "array" | "bool_array" | "int_array" | "float_array" | "vec_array"
+                | "local_array" | "local_bool_array" | "local_int_array" | "local_float_array"
+                | "local_vec_array" | "struct_array" => None,
+                "zero" => Some(self.literal(ty, 0)),
+                "one" => Some(self.literal(ty, 1)),
+                _ => Some(init),
+            },
+            _ => Some(init),
+        };
+
+        self.inference.scope.add(id, ty);
+
+        match ty {
+            VarType::Bool
+            | VarType::Int
+            | VarType::Float
+            | VarType::Vec
+            | VarType::Mat3
+            | VarType::BoolArray(..)
+            | VarType::IntArray(..)
+            | VarType::FloatArray(..)
+            | VarType::VecArray(..)
+            | VarType::Struct(_)
+            | VarType::StructArray(..) => Ok((ty, init)),
+            t => Err(format!(
+                "Unable to create variable '{}' of type '{}'.\nType inferred from expression:\n{:?}",
+                id, t, expr
+            )),
+        }
+    }
+
+    // explicitly convert a numeric value to a promoted type
+    fn coerce(&self, expr: Expr, ty: VarType) -> Expr {
+        let f = match (expr.ty, ty) {
+            (VarType::Int, VarType::Float) => "float",
+            (VarType::Int, VarType::Vec) | (VarType::Float, VarType::Vec) => "vec",
+            _ => return expr,
+        };
+        Expr {
+            kind: ExprKind::Builtin(f.into(), vec![expr]),
+            ty,
+        }
+    }
+
+    // numeric literal of the requested type
+    fn literal(&self, ty: VarType, n: i32) -> Expr {
+        match ty {
+            VarType::Int => Expr {
+                kind: ExprKind::Literal(Literal::Int(n)),
+                ty,
+            },
+            VarType::Vec => Expr {
+                kind: ExprKind::Builtin("vec".into(), vec![self.literal(VarType::Float, n)]),
+                ty,
+            },
+            _ => Expr {
+                kind: ExprKind::Literal(Literal::Float(n as f64)),
+                ty,
+            },
+        }
+    }
+
+    fn assign(&self, target: &ast::Expr, value: Expr) -> Result<Stmt, String> {
+        if let ast::Expr::Index(expr, idx) = target {
+            if let Index::ColorSpace(cs_from) = &**idx {
+                // assign vec with color space conversion
+                let place = self.place(expr)?;
+                return match &place {
+                    Place::Buffer(_, VarType::Buffer { cs, .. }, BufIdx::Pixel(..)) => {
+                        let value = Expr {
+                            kind: ExprKind::Builtin(format!("{}to{}", cs_from, cs), vec![value]),
+                            ty: self.inference.color_space(*cs),
+                        };
+                        Ok(Stmt::Assign(place, value))
+                    }
+                    _ => Err(format!(
+                        "Expected 2D buffer index for color space property access, found '{:?}'",
+                        expr
+                    )),
+                };
+            }
+        }
+
+        let place = self.place(target)?;
+
+        // structs and matrices are only assigned from values of the same type
+        let ty = match &place {
+            Place::Expr(expr) => Some(expr.ty),
+            Place::Array(..) => Some(self.expr(target)?.ty),
+            Place::Buffer(..) => None,
+        };
+        if let Some(ty) = ty {
+            let is_struct = |t| matches!(t, VarType::Struct(_) | VarType::Mat3);
+            if (is_struct(ty) || is_struct(value.ty)) && ty != value.ty {
+                return Err(format!(
+                    "Can not assign value of type '{}' to target of type '{}'",
+                    value.ty, ty
+                ));
+            }
+        }
+
+        Ok(Stmt::Assign(place, value))
+    }
+
+    fn place(&self, target: &ast::Expr) -> Result<Place, String> {
+        if let ast::Expr::Index(expr, idx) = target {
+            let idx_list = match &**idx {
+                Index::Array1D(a) => vec![a],
+                Index::Array2D(a, b) => vec![a, b],
+                Index::Array3D(a, b, c) => vec![a, b, c],
+                Index::Array4D(a, b, c, d) => vec![a, b, c, d],
+                _ => return Ok(Place::Expr(self.expr(target)?)),
+            };
+
+            let name = if let ast::Expr::Identifier(name) = &**expr {
+                name
+            } else {
+                return Err(format!(
+                    "Expected buffer or array identifier for indexed access, found '{:?}'",
+                    expr
+                ));
+            };
+
+            let var = self.expr(expr)?.ty;
+            let idx_list = idx_list
+                .into_iter()
+                .map(|e| self.expr(e))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            match var {
+                VarType::Buffer { .. } => Ok(Place::Buffer(
+                    name.clone(),
+                    var,
+                    self.buf_idx(name, var, idx_list)?,
+                )),
+                VarType::BoolArray(n, ..)
+                | VarType::IntArray(n, ..)
+                | VarType::FloatArray(n, ..)
+                | VarType::VecArray(n, ..)
+                | VarType::StructArray(_, n, ..)
+                    if n as usize == idx_list.len() =>
+                {
+                    Ok(Place::Array(name.clone(), idx_list))
+                }
+                t => Err(format!(
+                    "Unable to index variable '{}' of type '{}'",
+                    name, t
+                )),
+            }
+        } else {
+            Ok(Place::Expr(self.expr(target)?))
+        }
+    }
+
+    // buffer index of a 1D, 2D or 3D buffer access
+    fn buf_idx(&self, name: &str, var: VarType, mut idx: Vec<Expr>) -> Result<BufIdx, String> {
+        let z = if let VarType::Buffer { z, .. } = var {
+            z
+        } else {
+            return Err(format!("Expected variable '{}' to be a buffer", name));
+        };
+
+        Ok(match idx.len() {
+            1 => BufIdx::Linear(Box::new(idx.remove(0))),
+            2 if z == 1 || z == 3 => {
+                BufIdx::Pixel(Box::new(idx.remove(0)), Box::new(idx.remove(0)))
+            }
+            2 => {
+                return Err(format!(
+                    "Expected buffer '{}' to have z==1 or z==3, found z=={}",
+                    name, z
+                ))
+            }
+            3 => BufIdx::Element(
+                Box::new(idx.remove(0)),
+                Box::new(idx.remove(0)),
+                Box::new(idx.remove(0)),
+            ),
+            _ => {
+                return Err(format!(
+                    "Unable to index variable '{}' of type '{}'",
+                    name, var
+                ))
+            }
+        })
+    }
+
+    fn expr(&self, expr: &ast::Expr) -> Result<Expr, String> {
+        let (kind, ty) = match expr {
+            ast::Expr::Literal(l) => (
+                ExprKind::Literal(*l),
+                match l {
+                    Literal::Bool(_) => VarType::Bool,
+                    Literal::Int(_) => VarType::Int,
+                    Literal::Float(_) => VarType::Float,
+                },
+            ),
+            ast::Expr::Identifier(id) => (
+                ExprKind::Var(id.clone()),
+                match self.inference.scope.get(id) {
+                    Some(ty) => ty,
+                    None => match self.names.binary_search(id) {
+                        Ok(n) => VarType::Function(n as u32), // function passed by name
+                        Err(_) => VarType::Unknown,           // identifier of unknown type
+                    },
+                },
+            ),
+            ast::Expr::Unary(u) => {
+                let right = self.expr(&u.right)?;
+                let ty = self.inference.unary(&u.op, right.ty)?;
+                (ExprKind::Unary(u.op, Box::new(right)), ty)
+            }
+            ast::Expr::Binary(b) => {
+                let left = self.expr(&b.left)?;
+                let right = self.expr(&b.right)?;
+                let ty = self.inference.binary(&b.op, left.ty, right.ty)?;
+                (ExprKind::Binary(b.op, Box::new(left), Box::new(right)), ty)
+            }
+            ast::Expr::Index(expr, idx) => return self.index(expr, idx),
+            ast::Expr::Grouping(e) => {
+                let e = self.expr(e)?;
+                let ty = e.ty;
+                (ExprKind::Grouping(Box::new(e)), ty)
+            }
+            ast::Expr::Call(id, args) => return self.call(id, args),
+            ast::Expr::Array(elems) => {
+                let elems = elems
+                    .iter()
+                    .map(|e| self.expr(e))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let ty = self.inference.array(&elems)?;
+                (ExprKind::Array(elems), ty)
+            }
+        };
+
+        Ok(Expr { kind, ty })
+    }
+
+    // calls of functions returning multiple values are only lowered in 'var a, b = f(...)'
+    fn call(&self, id: &str, args: &[ast::Expr]) -> Result<Expr, String> {
+        let call = self.call_values(id, args)?;
+        let outs = self.outs(&call);
+        if !outs.is_empty() {
+            return Err(format!(
+                "Function '{}' returns {} values, receive them with 'var a, b = {}(...)'",
+                id,
+                outs.len() + 1,
+                id
+            ));
+        }
+        Ok(call)
+    }
+
+    // types of the additional return values of a lowered call
+    fn outs(&self, call: &Expr) -> Vec<VarType> {
+        match &call.kind {
+            ExprKind::Call(f, _) => self
+                .instances
+                .borrow()
+                .get(f)
+                .map(|f| f.outs.clone())
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        }
+    }
+
+    // declare the variables receiving all values of a function, the first one is initialized
+    // with the returned value and the others are passed by reference
+    fn vars(&self, ids: &[String], expr: &ast::Expr) -> Result<Vec<Stmt>, String> {
+        let (id, args) = match expr {
+            ast::Expr::Call(id, args) => (id, args),
+            _ => {
+                return Err(format!(
+                    "Expected a function call returning {} values",
+                    ids.len()
+                ))
+            }
+        };
+        for (n, v) in ids.iter().enumerate() {
+            if ids[..n].contains(v) {
+                return Err(format!("Duplicate variable '{}'", v));
+            }
+        }
+
+        let mut call = self.call_values(id, args)?;
+        let outs = self.outs(&call);
+        if outs.len() + 1 != ids.len() {
+            return Err(format!(
+                "Function '{}' returns {} values, found {} variables",
+                id,
+                outs.len() + 1,
+                ids.len()
+            ));
+        }
+
+        let mut stmts = Vec::new();
+        for (v, ty) in ids[1..].iter().zip(&outs) {
+            self.inference.scope.add(v, *ty);
+            stmts.push(Stmt::Var {
+                id: v.clone(),
+                ty: *ty,
+                init: None,
+            });
+            if let ExprKind::Call(_, args) = &mut call.kind {
+                args.push(Expr {
+                    kind: ExprKind::Ref(v.clone()),
+                    ty: *ty,
+                });
+            }
+        }
+        self.inference.scope.add(&ids[0], call.ty);
+        stmts.push(Stmt::Var {
+            id: ids[0].clone(),
+            ty: call.ty,
+            init: Some(call),
+        });
+        Ok(stmts)
+    }
+
+    fn call_values(&self, id: &str, args: &[ast::Expr]) -> Result<Expr, String> {
+        // function arguments call the function they are bound to
+        let id = match self.inference.scope.get(id) {
+            Some(VarType::Function(n)) => self.names[n as usize].as_str(),
+            _ => id,
+        };
+
+        if id == "struct_array" && !self.functions.contains_key(id) {
+            return self.struct_array(args);
+        }
+
+        let args = args
+            .iter()
+            .map(|e| self.expr(e))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if let Some((n, s)) = self.inference.struct_type(id) {
+            return self.construct(n, s, args);
+        }
+
+        // reductions are reached by the whole work group, only in the kernel body
+        if reduction(id) && !self.functions.contains_key(id) {
+            if self.returns.borrow().len() > 1 {
+                return Err(format!("Builtin '{}' can only be called in a kernel", id));
+            }
+            self.inference.builtin(id, &args)?;
+            self.reduce.set(true);
+        }
+
+        if let Ok(ty) = self.inference.builtin(id, &args) {
+            if random_stream(id, &args) {
+                self.draws();
+            }
+            return Ok(Expr {
+                kind: ExprKind::Builtin(id.into(), args),
+                ty,
+            });
+        }
+
+        if self.builtins.iter().any(|b| b.name == id) {
+            return self.external(id, args);
+        }
+
+        // promote arguments to annotated types before specializing the function
+        let args = if let Some(ast::Stmt::Function {
+            args: names, types, ..
+        }) = self.functions.get(id)
+        {
+            if names.len() != args.len() {
+                return Err(format!(
+                    "Function '{}' expects {} arguments, found {}",
+                    id,
+                    names.len(),
+                    args.len()
+                ));
+            }
+            args.into_iter()
+                .zip(names.iter().zip(types))
+                .map(|(e, (v, a))| match a {
+                    Some(a) => {
+                        let ty = self.inference.annotation(a, e.ty).map_err(|err| {
+                            format!("Argument '{}' of function '{}': {}", v, id, err)
+                        })?;
+                        Ok(self.coerce(e, ty))
+                    }
+                    None => Ok(e),
+                })
+                .collect::<Result<Vec<_>, String>>()?
+        } else {
+            args
+        };
+
+        let vars = args.iter().map(|e| e.ty).collect::<Vec<_>>();
+        let function = self.function(id, &vars)?;
+        let mut dependencies = self.dependencies.borrow_mut();
+        let deps = dependencies
+            .last_mut()
+            .ok_or_else(|| "No dependency frame found!".to_string())?;
+        if !deps.contains(&function.id) {
+            deps.push(function.id.clone());
+        }
+        if function.random {
+            self.draws();
+        }
+
+        // functions passed as arguments are part of the instance id
+        let args = args
+            .into_iter()
+            .filter(|e| !matches!(e.ty, VarType::Function(_)))
+            .collect();
+
+        Ok(Expr {
+            kind: ExprKind::Call(function.id.clone(), args),
+            ty: function.ret,
+        })
+    }
+
+    // struct value from all its fields in order, numeric values are promoted to the field types
+    fn construct(&self, n: u32, s: &Struct, args: Vec<Expr>) -> Result<Expr, String> {
+        if args.len() != s.fields.len() {
+            return Err(format!(
+                "Struct '{}' has {} fields, found {} values",
+                s.id,
+                s.fields.len(),
+                args.len()
+            ));
+        }
+        let args = args
+            .into_iter()
+            .zip(&s.fields)
+            .map(|(e, (f, ty))| match self.inference.promote(e.ty, *ty) {
+                Ok(t) if t == *ty => Ok(self.coerce(e, t)),
+                _ => Err(format!(
+                    "Field '{}' of struct '{}' expects type '{}', found '{}'",
+                    f, s.id, ty, e.ty
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Expr {
+            kind: ExprKind::Struct(args),
+            ty: VarType::Struct(n),
+        })
+    }
+
+    // struct_array(S, a, b, ...) declares an array of structs with literal dimensions
+    fn struct_array(&self, args: &[ast::Expr]) -> Result<Expr, String> {
+        let (n, s) = match args.first() {
+            Some(ast::Expr::Identifier(id)) => self
+                .inference
+                .struct_type(id)
+                .ok_or_else(|| format!("Unknown struct '{}' in 'struct_array'", id))?,
+            _ => return Err("Expected a struct as first argument of 'struct_array'".into()),
+        };
+        let mut dims = [0; 4];
+        if args.len() < 2 || args.len() > 5 {
+            return Err(format!(
+                "Array dimensions must be between 1 and 4, found: {}",
+                args.len().saturating_sub(1)
+            ));
+        }
+        for (d, e) in dims.iter_mut().zip(&args[1..]) {
+            *d = match e {
+                ast::Expr::Literal(Literal::Int(l)) if *l > 0 => *l as u64,
+                _ => {
+                    return Err(format!(
+                        "Expected literal dimensions for array of struct '{}'",
+                        s.id
+                    ))
+                }
+            };
+        }
+
+        Ok(Expr {
+            kind: ExprKind::Builtin("struct_array".into(), Vec::new()),
+            ty: VarType::StructArray(
+                n,
+                (args.len() - 1) as u8,
+                dims[0],
+                dims[1],
+                dims[2],
+                dims[3],
+            ),
+        })
+    }
+
+    // mark the currently lowered function as drawing from the random stream
+    fn draws(&self) {
+        if let Some(random) = self.random.borrow_mut().last_mut() {
+            *random = true;
+        }
+    }
+
+    // call of a builtin registered by the host, with the first matching signature
+    fn external(&self, id: &str, args: Vec<Expr>) -> Result<Expr, String> {
+        let input = args.iter().map(|e| e.ty).collect::<Vec<_>>();
+        let builtin = self
+            .builtins
+            .iter()
+            .find(|b| b.name == id && b.accepts(&input))
+            .ok_or_else(|| {
+                format!(
+                    "No signature of builtin '{}' accepts arguments ({})",
+                    id,
+                    input
+                        .iter()
+                        .map(|t| t.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })?;
+
+        let args = args
+            .into_iter()
+            .zip(&builtin.args)
+            .map(|(e, a)| self.coerce(e, a.var_type()))
+            .collect();
+
+        let mut externals = self.externals.borrow_mut();
+        let external = match externals.iter().find(|e| ***e == *builtin) {
+            Some(e) => e.clone(),
+            None => {
+                let e = Rc::new(builtin.clone());
+                externals.push(e.clone());
+                e
+            }
+        };
+
+        Ok(Expr {
+            kind: ExprKind::External(external, args),
+            ty: builtin.ret.var_type(),
+        })
+    }
+
+    fn index(&self, expr: &ast::Expr, idx: &Index) -> Result<Expr, String> {
+        let (kind, ty) = match idx {
+            Index::Field(field) => {
+                let base = self.expr(expr)?;
+                let ty = match base.ty {
+                    VarType::Struct(n) => self.inference.structs[n as usize]
+                        .fields
+                        .iter()
+                        .find(|(f, _)| f == field)
+                        .map(|(_, ty)| *ty),
+                    _ => None,
+                }
+                .ok_or_else(|| {
+                    format!(
+                        "Variable '{}' of type '{}' has no field '{}'",
+                        root(expr).unwrap_or("?"),
+                        base.ty,
+                        field
+                    )
+                })?;
+                (ExprKind::Field(Box::new(base), field.clone()), ty)
+            }
+            Index::Vec(n) => {
+                let base = self.expr(expr)?;
+                let ty = self.inference.index(base.ty, idx);
+                match (base.ty, &base.kind) {
+                    (VarType::Vec, _) => (ExprKind::Component(Box::new(base), *n), ty),
+                    // channel count is part of the specialization
+                    (VarType::Buffer { z, .. }, ExprKind::Var(_)) if *n == 2 => {
+                        (ExprKind::Literal(Literal::Int(z as i32)), ty)
+                    }
+                    (VarType::Buffer { .. }, ExprKind::Var(name)) => {
+                        (ExprKind::BufSize(name.clone(), *n), ty)
+                    }
+                    (t, _) => {
+                        return Err(format!(
+                            "Variable '{}' of type '{}' does not support property access",
+                            root(expr)?,
+                            t
+                        ))
+                    }
+                }
+            }
+            Index::ColorSpace(cs_to) => {
+                let base = self.expr(expr)?;
+                match &base.kind {
+                    ExprKind::BufLoad(_, VarType::Buffer { cs, .. }, BufIdx::Pixel(..)) => {
+                        let ty = self.inference.index(base.ty, idx);
+                        (
+                            ExprKind::Builtin(format!("{}to{}", cs, cs_to), vec![base]),
+                            ty,
+                        )
+                    }
+                    _ => {
+                        return Err(format!(
+                        "Expected 2D buffer index for color space property access, found '{:?}'",
+                        expr
+                    ))
+                    }
+                }
+            }
+            Index::Prop(prop) => {
+                let (name, idx) = match expr {
+                    ast::Expr::Index(name, idx) => match &**name {
+                        ast::Expr::Identifier(name) => (name, &**idx),
+                        expr => {
+                            return Err(format!(
+                            "Expected buffer or array identifier for property access, found '{:?}'",
+                            expr
+                        ))
+                        }
+                    },
+                    expr => {
+                        return Err(format!(
+                            "Expected element index for property access, found '{:?}'",
+                            expr
+                        ))
+                    }
+                };
+
+                let var = self.inference.scope.get(name).unwrap_or(VarType::Unknown);
+                let idx_list = match idx {
+                    Index::Array1D(a) => vec![a],
+                    Index::Array2D(a, b) => vec![a, b],
+                    Index::Array3D(a, b, c) => vec![a, b, c],
+                    Index::Array4D(a, b, c, d) => vec![a, b, c, d],
+                    _ => {
+                        return Err(format!(
+                            "Variable '{}' of type '{}' does not support property access",
+                            name, var
+                        ))
+                    }
+                }
+                .into_iter()
+                .map(|e| self.expr(e))
+                .collect::<Result<Vec<_>, _>>()?;
+
+                match (var, prop) {
+                    (VarType::Buffer { z, .. }, prop) if idx_list.len() != 2 || z == 1 => {
+                        let ty = match prop {
+                            Prop::Ptr => self.inference.ptr(name, var)?,
+                            _ => self.inference.index(VarType::Float, &Index::Prop(*prop)),
+                        };
+                        (
+                            ExprKind::BufProp(
+                                name.clone(),
+                                var,
+                                *prop,
+                                self.buf_idx(name, var, idx_list)?,
+                            ),
+                            ty,
+                        )
+                    }
+                    (VarType::FloatArray(n, ..), Prop::Ptr)
+                    | (VarType::IntArray(n, ..), Prop::Ptr)
+                        if n as usize == idx_list.len() =>
+                    {
+                        (
+                            ExprKind::ArrayPtr(name.clone(), idx_list),
+                            self.inference.ptr(name, var)?,
+                        )
+                    }
+                    (VarType::FloatArray(..), _) | (VarType::IntArray(..), _) => {
+                        return Err(format!(
+                            "Array '{}' does not support property access except for '.ptr'",
+                            name
+                        ))
+                    }
+                    (t, _) => {
+                        return Err(format!(
+                            "Variable '{}' of type '{}' does not support property access",
+                            name, t
+                        ))
+                    }
+                }
+            }
+            Index::Array1D(..) | Index::Array2D(..) | Index::Array3D(..) | Index::Array4D(..) => {
+                let name = if let ast::Expr::Identifier(name) = expr {
+                    name
+                } else {
+                    return Err(format!(
+                        "Expected buffer or array identifier for indexed access, found '{:?}'",
+                        expr
+                    ));
+                };
+
+                let var = self.expr(expr)?.ty;
+                let ty = self.inference.index(var, idx);
+                let idx_list = match idx {
+                    Index::Array1D(a) => vec![a],
+                    Index::Array2D(a, b) => vec![a, b],
+                    Index::Array3D(a, b, c) => vec![a, b, c],
+                    Index::Array4D(a, b, c, d) => vec![a, b, c, d],
+                    _ => vec![],
+                }
+                .into_iter()
+                .map(|e| self.expr(e))
+                .collect::<Result<Vec<_>, _>>()?;
+
+                match var {
+                    VarType::Buffer { .. } => (
+                        ExprKind::BufLoad(name.clone(), var, self.buf_idx(name, var, idx_list)?),
+                        ty,
+                    ),
+                    VarType::BoolArray(n, ..)
+                    | VarType::IntArray(n, ..)
+                    | VarType::FloatArray(n, ..)
+                    | VarType::VecArray(n, ..)
+                    | VarType::StructArray(_, n, ..)
+                        if n as usize == idx_list.len() =>
+                    {
+                        (ExprKind::ArrayLoad(name.clone(), idx_list), ty)
+                    }
+                    t => {
+                        return Err(format!(
+                            "Unable to index variable '{}' of type '{}'",
+                            name, t
+                        ))
+                    }
+                }
+            }
+        };
+
+        Ok(Expr { kind, ty })
+    }
+}
@@ -15,18 +15,25 @@
     along with this program.  If not, see <http://www.gnu.org/licenses/>.
 */
 
-use std::cell::Cell;
+use std::cell::{Cell, RefCell};
 
 use crate::ast::{
-    AssignOp, BinaryExpr, BinaryOp, ColorSpace, Cond, Expr, Index, Literal, Prop, Stmt, UnaryExpr,
-    UnaryOp,
+    Annotation, AssignOp, BinaryExpr, BinaryOp, ColorSpace, Cond, Expr, Index, Literal, Prop, Stmt,
+    Traversal, UnaryExpr, UnaryOp, MAX_TILE,
 };
 
 use crate::tokens::{Token, TokenType};
 
+// color spaces, channels and buffer properties claimed by '.' access, not available as struct fields
+const PROPERTIES: &[&str] = &[
+    "SRGB", "LRGB", "XYZ", "LAB", "LCH", "Y", "L", "r", "g", "b", "x", "y", "z", "l", "a", "c",
+    "h", "int", "idx", "ptr", "intptr",
+];
+
 pub struct Parser {
     tokens: Vec<Token>,
     current: Cell<Option<usize>>,
+    errors: RefCell<Vec<(String, usize)>>, // errors recovered from while parsing
 }
 
 impl Parser {
@@ -34,15 +41,117 @@ impl Parser {
         Parser {
             tokens,
             current: Cell::new(Some(0)),
+            errors: RefCell::new(Vec::new()),
         }
     }
 
-    pub fn parse(&self) -> Result<Vec<Stmt>, (String, usize)> {
+    // parse file scope statements along with their line numbers, returns all syntax errors
+    // together with the partial AST of the statements that could be parsed
+    pub fn parse(&self) -> (Vec<(Stmt, usize)>, Vec<(String, usize)>) {
         let mut stmts = Vec::new();
         while self.current.get().is_some() {
-            stmts.push(self.statement()?.0)
+            let start = self.current.get();
+            match self.statement() {
+                Ok(stmt) => stmts.push(stmt),
+                Err(err) => self.synchronize(err, start, true),
+            }
+        }
+        (stmts, self.errors.replace(Vec::new()))
+    }
+
+    // record an error and skip the rest of the failed statement up to the next statement boundary
+    fn synchronize(&self, err: (String, usize), start: Option<usize>, file_scope: bool) {
+        self.errors.borrow_mut().push(err);
+
+        // always make progress past a token that can't start a statement
+        if self.current.get() == start {
+            self.advance();
+        }
+
+        // skip the remaining tokens on the line of the last parsed token
+        let line = self.previous_line();
+        while self.current.get().is_some() {
+            let new_line = self.line() != line;
+            match self.peek() {
+                TokenType::Eof
+                | TokenType::Function
+                | TokenType::Kernel
+                | TokenType::Struct
+                | TokenType::At => return,
+                TokenType::End | TokenType::Else | TokenType::ElseIf if !file_scope => return,
+                TokenType::Var | TokenType::Const | TokenType::Comment(_) if new_line => return,
+                TokenType::Return
+                | TokenType::Continue
+                | TokenType::Break
+                | TokenType::If
+                | TokenType::For
+                | TokenType::While
+                | TokenType::Identifier(_)
+                    if new_line && !file_scope =>
+                {
+                    return
+                }
+                _ => self.advance(),
+            }
+        }
+    }
+
+    // skip to the end of a block header, after an error in its condition or argument list
+    fn skip_header(&self, err: (String, usize)) {
+        self.errors.borrow_mut().push(err);
+
+        let line = self.line();
+        while self.current.get().is_some() && self.line() == line {
+            match self.peek() {
+                TokenType::Then | TokenType::Do => {
+                    self.advance();
+                    return;
+                }
+                TokenType::Eof | TokenType::End => return,
+                _ => self.advance(),
+            }
+        }
+    }
+
+    // parse a block body up to its closing token, recovering from errors in single statements
+    fn body(&self, context: &str) -> Result<Vec<(Stmt, usize)>, (String, usize)> {
+        let mut body = Vec::new();
+        loop {
+            match self.peek() {
+                TokenType::End | TokenType::Else | TokenType::ElseIf => return Ok(body),
+                _ => {}
+            }
+
+            let start = self.current.get();
+            match self.statement() {
+                Ok((Stmt::Eof, line)) => {
+                    return Err((format!("Unexpected end of file in {}", context), line))
+                }
+                Ok(stmt) => body.push(stmt),
+                Err(err) => self.synchronize(err, start, false),
+            }
+        }
+    }
+
+    // skip the 'end' closing a block
+    fn end(&self, context: &str) -> Result<(), (String, usize)> {
+        if self.peek() == &TokenType::End {
+            self.advance();
+            Ok(())
+        } else {
+            Err((format!("Expected 'end' to close {}", context), self.line()))
+        }
+    }
+
+    // condition of a block header, replaced by a placeholder on errors
+    fn condition(&self) -> Expr {
+        match self.expression() {
+            Ok((cond, _)) => cond,
+            Err(err) => {
+                self.skip_header(err);
+                Expr::Literal(Literal::Bool(false))
+            }
         }
-        Ok(stmts)
     }
 
     fn advance(&self) {
@@ -78,11 +187,8 @@ impl Parser {
 
     fn line(&self) -> usize {
         if let Some(current) = self.current.get() {
-            match &self.tokens.get(current) {
-                Some(token) => {
-                    return token.fragment.line;
-                }
-                None => {}
+            if let Some(token) = &self.tokens.get(current) {
+                return token.fragment.line;
             }
         }
 
@@ -93,14 +199,187 @@ impl Parser {
         }
     }
 
-    fn var_decl(&self) -> Result<(String, Expr, usize), (String, usize)> {
+    // line of the last parsed token
+    fn previous_line(&self) -> usize {
+        match self.current.get() {
+            Some(n) if n > 0 => self.tokens.get(n - 1).map_or(0, |t| t.fragment.line),
+            _ => 0,
+        }
+    }
+
+    fn color_space(id: &str) -> Option<ColorSpace> {
+        match id {
+            "SRGB" => Some(ColorSpace::Srgb),
+            "LRGB" => Some(ColorSpace::Lrgb),
+            "XYZ" => Some(ColorSpace::Xyz),
+            "LAB" => Some(ColorSpace::Lab),
+            "LCH" => Some(ColorSpace::Lch),
+            "Y" => Some(ColorSpace::Y),
+            "L" => Some(ColorSpace::L),
+            _ => None,
+        }
+    }
+
+    // optional type annotation: ': int', ': vec', ': buffer[3, LAB]'
+    fn annotation(&self) -> Result<Option<Annotation>, (String, usize)> {
+        let line = self.line();
+        if self.peek() != &TokenType::Colon {
+            return Ok(None);
+        }
+        self.advance(); // skip :
+
+        // input-only buffers are annotated as constant
+        let input = self.peek() == &TokenType::Const;
+        if input {
+            self.advance(); // skip const
+            if self.peek() != &TokenType::Identifier("buffer".into()) {
+                return Err(("Expected buffer type after 'const'".into(), line));
+            }
+        }
+
+        let annotation = match self.peek() {
+            TokenType::Identifier(s) => match s.as_ref() {
+                "bool" => Annotation::Bool,
+                "int" => Annotation::Int,
+                "float" => Annotation::Float,
+                "vec" => Annotation::Vec,
+                "mat3" => Annotation::Mat3,
+                "fn" => Annotation::Function,
+                "buffer" => {
+                    let mut z = None;
+                    let mut cs = None;
+                    if self.peek_next() == &TokenType::LeftBracket {
+                        self.advance(); // skip buffer
+                        loop {
+                            self.advance(); // skip [ or ,
+                            match self.peek() {
+                                TokenType::Int(n) if z.is_none() && cs.is_none() && *n > 0 => {
+                                    z = Some(*n as u64)
+                                }
+                                TokenType::Identifier(s) if cs.is_none() => {
+                                    cs = Some(Parser::color_space(s).ok_or((
+                                        format!("Unknown color space '{}' in buffer type", s),
+                                        line,
+                                    ))?)
+                                }
+                                _ => {
+                                    return Err((
+                                        "Expected channel count or color space in buffer type"
+                                            .into(),
+                                        line,
+                                    ))
+                                }
+                            }
+                            self.advance(); // skip channel count or color space
+                            match self.peek() {
+                                TokenType::Comma => {}
+                                TokenType::RightBracket => break,
+                                _ => {
+                                    return Err(("Expected ',' or ']' in buffer type".into(), line))
+                                }
+                            }
+                        }
+                    }
+                    Annotation::Buffer { z, cs, input }
+                }
+                s => Annotation::Struct(s.into()), // checked against the declared structs when validating
+            },
+            _ => return Err(("Expected type after ':'".into(), line)),
+        };
+        self.advance(); // skip type or ]
+
+        Ok(Some(annotation))
+    }
+
+    // traversal annotation preceding a kernel: '@tile(32, 8)', '@rows'
+    fn traversal(&self) -> Result<Traversal, (String, usize)> {
+        let line = self.line();
+        self.advance(); // skip @
+
+        let traversal = match self.peek() {
+            TokenType::Identifier(s) if s == "rows" => Traversal::Rows,
+            TokenType::Identifier(s) if s == "tile" => {
+                let mut size = Vec::new();
+                self.advance(); // skip tile
+                if self.peek() != &TokenType::LeftParen {
+                    return Err(("Expected tile size in '@tile(x, y)'".into(), line));
+                }
+                loop {
+                    self.advance(); // skip ( or ,
+                    match self.peek() {
+                        TokenType::Int(n) if *n > 0 && size.len() < 2 => size.push(*n as u32),
+                        _ => {
+                            return Err((
+                                "Expected positive integer tile size in '@tile(x, y)'".into(),
+                                line,
+                            ))
+                        }
+                    }
+                    self.advance(); // skip size
+                    match self.peek() {
+                        TokenType::Comma => {}
+                        TokenType::RightParen if size.len() == 2 => break,
+                        _ => return Err(("Expected tile size in '@tile(x, y)'".into(), line)),
+                    }
+                }
+                if size[0] as u64 * size[1] as u64 > MAX_TILE as u64 {
+                    return Err((
+                        format!(
+                            "Tile size {}x{} exceeds the maximum of {} work items",
+                            size[0], size[1], MAX_TILE
+                        ),
+                        line,
+                    ));
+                }
+                Traversal::Tile(size[0], size[1])
+            }
+            TokenType::Identifier(s) => {
+                return Err((
+                    format!(
+                        "Unknown kernel annotation '@{}', expected '@tile(x, y)' or '@rows'",
+                        s
+                    ),
+                    line,
+                ))
+            }
+            _ => return Err(("Expected kernel annotation after '@'".into(), line)),
+        };
+        self.advance(); // skip rows or )
+
+        if self.peek() != &TokenType::Kernel {
+            return Err(("Expected kernel after traversal annotation".into(), line));
+        }
+        Ok(traversal)
+    }
+
+    fn var_decl(&self) -> Result<(Vec<String>, Option<Annotation>, Expr, usize), (String, usize)> {
         let line = self.line();
         self.advance(); // skip var
         if let TokenType::Identifier(id) = self.peek() {
             self.advance(); // skip identifier
+            let annotation = self.annotation()?;
+
+            // further identifiers receive the additional return values of a function
+            let mut ids = vec![id.clone()];
+            while &TokenType::Comma == self.peek() {
+                self.advance(); // skip comma
+                if let TokenType::Identifier(id) = self.peek() {
+                    self.advance(); // skip identifier
+                    ids.push(id.clone());
+                } else {
+                    return Err(("Missing identifier".into(), line));
+                }
+            }
+            if ids.len() > 1 && annotation.is_some() {
+                return Err((
+                    "Type annotations are not supported when declaring multiple variables".into(),
+                    line,
+                ));
+            }
+
             if &TokenType::Equal == self.peek() {
                 self.advance(); // skip =
-                Ok((id.clone(), self.expression()?.0, line))
+                Ok((ids, annotation, self.expression()?.0, line))
             } else {
                 Err((format!("Missing initial value assignment to {}", id), line))
             }
@@ -109,112 +388,57 @@ impl Parser {
         }
     }
 
-    fn if_branch(&self) -> Result<(Vec<Cond>, Vec<Stmt>, usize), (String, usize)> {
+    fn if_branch(&self) -> Result<(Vec<Cond>, Vec<(Stmt, usize)>, usize), (String, usize)> {
         let mut cond_list = Vec::new();
         let mut else_body = Vec::new();
 
         let line = self.line();
         self.advance(); // skip if
-        let (cond, _) = self.expression()?;
-        let mut body = Vec::new();
+        let cond = self.condition();
         if self.peek() == &TokenType::Then {
             self.advance(); // skip optional then
         }
-        loop {
-            match self.peek() {
-                TokenType::ElseIf => break,
-                TokenType::Else => break,
-                TokenType::End => break,
-                _ => {}
-            }
-
-            match self.statement()? {
-                (Stmt::Eof, line) => {
-                    return Err(("Unexpected end of file in 'if' body".into(), line))
-                }
-                (stmt, _) => body.push(stmt),
-            }
-        }
+        let body = self.body("'if' body")?;
         cond_list.push(Cond { cond, body });
 
         while self.peek() == &TokenType::ElseIf {
             self.advance(); // skip elseIf
-            let (cond, _) = self.expression()?;
-            let mut body = Vec::new();
+            let cond = self.condition();
             if self.peek() == &TokenType::Then {
                 self.advance(); // skip optional then
             }
-            loop {
-                match self.peek() {
-                    TokenType::ElseIf => continue,
-                    TokenType::Else => break,
-                    TokenType::End => break,
-                    _ => {}
-                }
-
-                match self.statement()? {
-                    (Stmt::Eof, line) => {
-                        return Err(("Unexpected end of file in 'elseif' body".into(), line))
-                    }
-                    (stmt, _) => body.push(stmt),
-                }
-            }
+            let body = self.body("'elseif' body")?;
             cond_list.push(Cond { cond, body });
         }
 
         if self.peek() == &TokenType::Else {
             self.advance(); // skip else
-            loop {
-                if self.peek() == &TokenType::End {
-                    break;
-                }
-
-                match self.statement()? {
-                    (Stmt::Eof, line) => {
-                        return Err(("Unexpected end of file in 'else' body".into(), line))
-                    }
-                    (stmt, _) => else_body.push(stmt),
-                }
-            }
+            else_body = self.body("'else' body")?;
         }
 
-        self.advance(); // skip end
+        self.end("'if' statement")?;
         Ok((cond_list, else_body, line))
     }
 
-    fn while_loop(&self) -> Result<(Expr, Vec<Stmt>, usize), (String, usize)> {
+    fn while_loop(&self) -> Result<(Expr, Vec<(Stmt, usize)>, usize), (String, usize)> {
         let line = self.line();
         self.advance(); // skip while
 
-        let (cond, _) = self.expression()?;
+        let cond = self.condition();
 
         if self.peek() == &TokenType::Do {
             // skip optional do
             self.advance();
         }
 
-        let mut body = Vec::new();
-        loop {
-            if self.peek() == &TokenType::End {
-                self.advance();
-                break;
-            }
-            match self.statement()? {
-                (Stmt::Eof, line) => {
-                    return Err(("Unexpected end of file in 'while' loop body".into(), line))
-                }
-                (stmt, _) => body.push(stmt),
-            }
-        }
+        let body = self.body("'while' loop body")?;
+        self.end("'while' loop")?;
 
         Ok((cond, body, line))
     }
 
-    fn for_loop(
-        &self,
-    ) -> Result<(String, Expr, Expr, Option<Expr>, Vec<Stmt>, usize), (String, usize)> {
+    fn for_range(&self) -> Result<(String, Expr, Expr, Option<Expr>), (String, usize)> {
         let line = self.line();
-        self.advance(); // skip for
 
         let var;
         if let TokenType::Identifier(s) = self.peek() {
@@ -238,38 +462,103 @@ impl Parser {
 
         let (to, _) = self.expression()?;
 
-        let step;
-        if self.peek() == &TokenType::Comma {
+        let step = if self.peek() == &TokenType::Comma {
             self.advance(); // skip comma
-            step = Some(self.expression()?.0);
+            Some(self.expression()?.0)
         } else {
-            step = None
-        }
+            None
+        };
+
+        Ok((var, from, to, step))
+    }
+
+    fn for_loop(
+        &self,
+    ) -> Result<(String, Expr, Expr, Option<Expr>, Vec<(Stmt, usize)>, usize), (String, usize)>
+    {
+        let line = self.line();
+        self.advance(); // skip for
+
+        // placeholder range on errors, so the body can still be parsed
+        let (var, from, to, step) = self.for_range().unwrap_or_else(|err| {
+            self.skip_header(err);
+            let zero = || Expr::Literal(Literal::Int(0));
+            ("_".into(), zero(), zero(), None)
+        });
 
         if self.peek() == &TokenType::Do {
             // skip optional do
             self.advance();
         }
 
-        // get body
-        let mut body = Vec::new();
+        let body = self.body("'for' loop body")?;
+        self.end("'for' loop")?;
+
+        Ok((var, from, to, step, body, line))
+    }
+
+    fn fun_args(
+        &self,
+    ) -> Result<(Vec<String>, Vec<Option<Annotation>>, Option<Annotation>), (String, usize)> {
+        let line = self.line();
+        if self.peek() != &TokenType::LeftParen {
+            return Err((
+                "Expected argument list in function declaration".into(),
+                line,
+            ));
+        }
+        self.advance(); // skip left parenthesis
+
+        let mut args = Vec::new();
+        let mut types = Vec::new();
         loop {
-            if self.peek() == &TokenType::End {
-                self.advance();
-                break;
+            match self.peek() {
+                TokenType::Identifier(s) => {
+                    args.push(s.clone());
+                    self.advance();
+                    types.push(self.annotation()?);
+                }
+                TokenType::RightParen => break,
+                _ => {
+                    return Err((
+                        "Expected argument identifier or ')' in function declaration argument list"
+                            .into(),
+                        line,
+                    ))
+                }
             }
-            match self.statement()? {
-                (Stmt::Eof, line) => {
-                    return Err(("Unexpected end of file in 'for' loop body".into(), line))
+            match self.peek() {
+                TokenType::Comma => self.advance(), // skip comma
+                TokenType::RightParen => break,
+                _ => {
+                    return Err((
+                        "Expected ',' or ')' in function declaration argument list".into(),
+                        line,
+                    ))
                 }
-                (stmt, _) => body.push(stmt),
             }
         }
+        self.advance();
 
-        Ok((var, from, to, step, body, line))
+        // optional return type
+        let ret = self.annotation()?;
+
+        Ok((args, types, ret))
     }
 
-    fn fun_decl(&self) -> Result<(String, Vec<String>, Vec<Stmt>, usize), (String, usize)> {
+    fn fun_decl(
+        &self,
+    ) -> Result<
+        (
+            String,
+            Vec<String>,
+            Vec<Option<Annotation>>,
+            Option<Annotation>,
+            Vec<(Stmt, usize)>,
+            usize,
+        ),
+        (String, usize),
+    > {
         let line = self.line();
         self.advance(); // skip fun
 
@@ -281,120 +570,171 @@ impl Parser {
             return Err(("Missing function declaration idetifier".into(), line));
         }
 
-        // get arguments
-        if self.peek() == &TokenType::LeftParen {
-            self.advance(); // skip left parenthesis
+        // get arguments, the body is still parsed after errors in the argument list
+        let (args, types, ret) = self.fun_args().unwrap_or_else(|err| {
+            self.skip_header(err);
+            (Vec::new(), Vec::new(), None)
+        });
 
-            let mut args = Vec::new();
-            loop {
-                match self.peek() {
-                    TokenType::Identifier(s) => {
-                        args.push(s.clone());
-                    }
-                    TokenType::RightParen => break,
-                    _ => return Err((
-                        "Expected argument identifier or ')' in function declaration argument list"
-                            .into(),
-                        line,
-                    )),
-                }
-                self.advance();
-                match self.peek() {
-                    TokenType::Comma => self.advance(), // skip comma
-                    TokenType::RightParen => break,
-                    _ => {
-                        return Err((
-                            "Expected ',' or ')' in function declaration argument list".into(),
-                            line,
-                        ))
-                    }
-                }
-            }
-            self.advance();
+        // get body
+        let body = self.body("function declaration body")?;
+        self.end("function declaration")?;
 
-            // get body
-            let mut body = Vec::new();
-            loop {
-                if self.peek() == &TokenType::End {
-                    self.advance();
-                    break;
-                }
-                match self.statement()? {
-                    (Stmt::Eof, line) => {
+        Ok((id, args, types, ret, body, line))
+    }
+
+    // struct declaration with typed fields: 'struct Name a: float b: vec end'
+    fn struct_decl(&self) -> Result<(String, Vec<(String, Annotation)>), (String, usize)> {
+        let line = self.line();
+        self.advance(); // skip struct
+
+        let id = if let TokenType::Identifier(s) = self.peek() {
+            s.clone()
+        } else {
+            return Err(("Missing struct declaration identifier".into(), line));
+        };
+        self.advance(); // skip identifier
+
+        let mut fields = Vec::new();
+        loop {
+            let line = self.line();
+            match self.peek() {
+                TokenType::Identifier(s) => {
+                    // '.' access to properties takes precedence over fields
+                    if PROPERTIES.contains(&s.as_str()) {
                         return Err((
-                            "Unexpected end of file in function declaration body".into(),
+                            format!(
+                                "Field '{}' of struct '{}' clashes with a channel or property name",
+                                s, id
+                            ),
                             line,
-                        ))
+                        ));
+                    }
+                    let field = s.clone();
+                    self.advance(); // skip identifier
+                    match self.annotation()? {
+                        Some(a) => fields.push((field, a)),
+                        None => {
+                            return Err((
+                                format!("Expected type of field '{}' in struct '{}'", field, id),
+                                line,
+                            ))
+                        }
+                    }
+                    if self.peek() == &TokenType::Comma {
+                        self.advance(); // optional comma
                     }
-                    (stmt, _) => body.push(stmt),
                 }
+                TokenType::Comment(_) => self.advance(),
+                TokenType::End => break,
+                _ => return Err((format!("Expected field or 'end' in struct '{}'", id), line)),
             }
-
-            Ok((id, args, body, line))
-        } else {
-            Err((
-                "Expected argument list in function declaration".into(),
-                line,
-            ))
         }
+        self.end("struct declaration")?;
+
+        Ok((id, fields))
     }
 
-    fn fun_return(&self) -> Result<(Option<Expr>, usize), (String, usize)> {
+    fn fun_return(&self) -> Result<(Vec<Expr>, usize), (String, usize)> {
         let line = self.line();
         self.advance(); // skip return
 
+        // return without value at the end of a line, a body or before another statement,
+        // unreachable statements after a return are reported by the linter
+        if self.line() != line {
+            return Ok((Vec::new(), line));
+        }
         let (expr, _) = match self.peek() {
-            TokenType::End | TokenType::Else | TokenType::ElseIf => return Ok((None, line)),
+            TokenType::End
+            | TokenType::Else
+            | TokenType::ElseIf
+            | TokenType::Var
+            | TokenType::Const
+            | TokenType::Return
+            | TokenType::Continue
+            | TokenType::Break
+            | TokenType::If
+            | TokenType::For
+            | TokenType::While
+            | TokenType::Comment(_) => return Ok((Vec::new(), line)),
             _ => self.expression()?,
         };
 
-        match self.peek() {
-            TokenType::End | TokenType::Else | TokenType::ElseIf => Ok((Some(expr), line)),
-            _ => Err(("Expected end of body after return statement".into(), line)),
+        // comma-separated values are returned together
+        let mut values = vec![expr];
+        while &TokenType::Comma == self.peek() {
+            self.advance(); // skip comma
+            values.push(self.expression()?.0);
         }
+
+        Ok((values, line))
     }
 
     fn statement(&self) -> Result<(Stmt, usize), (String, usize)> {
         let line = self.line();
         let stmt = match self.peek() {
             TokenType::Var => {
-                let (id, expr, _) = self.var_decl()?;
-                Stmt::Var(id, expr)
+                let (mut ids, annotation, expr, _) = self.var_decl()?;
+                if ids.len() > 1 {
+                    Stmt::Vars(ids, expr)
+                } else {
+                    Stmt::Var(ids.remove(0), annotation, expr)
+                }
             }
 
             TokenType::Const => {
-                let (id, expr, _) = self.var_decl()?;
-                Stmt::Const(id, expr)
+                let (mut ids, annotation, expr, line) = self.var_decl()?;
+                if ids.len() > 1 {
+                    return Err(("Constants can not receive multiple values".into(), line));
+                }
+                Stmt::Const(ids.remove(0), annotation, expr)
             }
 
             TokenType::Function => {
-                let (id, args, body, _) = self.fun_decl()?;
-                Stmt::Function { id, args, body }
+                let (id, args, types, ret, body, _) = self.fun_decl()?;
+                Stmt::Function {
+                    id,
+                    args,
+                    types,
+                    ret,
+                    body,
+                }
             }
 
-            TokenType::Kernel => {
-                let (id, args, body, _) = self.fun_decl()?;
-                Stmt::Kernel { id, args, body }
+            TokenType::Struct => {
+                let (id, fields) = self.struct_decl()?;
+                Stmt::Struct { id, fields }
             }
 
-            TokenType::Return => match self.fun_return()? {
-                (Some(expr), _) => Stmt::Return(Some(expr)),
-                (None, _) => Stmt::Return(None),
-            },
-
-            TokenType::Continue => match self.fun_return()? {
-                (None, _) => Stmt::Continue,
-                (_, line) => {
-                    return Err(("Expected end of body after continue statement".into(), line))
+            TokenType::Kernel | TokenType::At => {
+                let traversal = match self.peek() {
+                    TokenType::At => Some(self.traversal()?),
+                    _ => None,
+                };
+                let (id, args, types, ret, body, line) = self.fun_decl()?;
+                if ret.is_some() {
+                    return Err(("Kernels can not have a return type".into(), line));
                 }
-            },
-
-            TokenType::Break => match self.fun_return()? {
-                (None, _) => Stmt::Break,
-                (_, line) => {
-                    return Err(("Expected end of body after break statement".into(), line))
+                Stmt::Kernel {
+                    id,
+                    args,
+                    types,
+                    traversal,
+                    body,
                 }
-            },
+            }
+
+            TokenType::Return => Stmt::Return(self.fun_return()?.0),
+
+            TokenType::Continue => {
+                self.advance(); // skip continue
+                Stmt::Continue
+            }
+
+            TokenType::Break => {
+                self.advance(); // skip break
+                Stmt::Break
+            }
 
             TokenType::If => {
                 let (cond_list, else_body, _) = self.if_branch()?;
@@ -632,7 +972,20 @@ impl Parser {
                 }
                 Expr::Array(elems)
             }
-            _ => return Err(("Invalid expression".into(), line)),
+            TokenType::Eof => return Err(("Incomplete expression at end of file".into(), line)),
+            _ if self.previous_line() < line => {
+                return Err((
+                    "Incomplete expression at end of line".into(),
+                    self.previous_line(),
+                ))
+            }
+            _ => {
+                let lexeme = match self.current.get().and_then(|n| self.tokens.get(n)) {
+                    Some(token) => token.fragment.lexeme.clone(),
+                    None => String::new(),
+                };
+                return Err((format!("Invalid expression, unexpected '{}'", lexeme), line));
+            }
         };
 
         self.advance();
@@ -750,13 +1103,9 @@ impl Parser {
                     "idx" => Expr::Index(Box::new(id), Box::new(Index::Prop(Prop::Idx))), // returns buffer's linear index
                     "ptr" => Expr::Index(Box::new(id), Box::new(Index::Prop(Prop::Ptr))), // returns ptr at origin or index
                     "intptr" => Expr::Index(Box::new(id), Box::new(Index::Prop(Prop::IntPtr))), // returns ptr at origin or index
-                    _ => {
-                        return Err((
-                            "Invalid property, channel selection or color space transformation"
-                                .into(),
-                            line,
-                        ))
-                    }
+
+                    // any other name is a struct field
+                    s => Expr::Index(Box::new(id), Box::new(Index::Field(s.into()))),
                 }
             } else {
                 return Err(("Invalid '.' syntax, expected identifier".into(), line));
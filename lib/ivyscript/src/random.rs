@@ -0,0 +1,105 @@
+/*
+  Copyright (C) 2011-2021 G. Bajlekov
+
+    Ivy is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Ivy is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+// reference implementation of the random streams in ops/include/random.cl and random.ispc:
+// philox4x32-10 keyed on (seed, 0) with the counter (x, y, z, n), one block per draw or attempt
+
+const M0: u32 = 0xD2511F53;
+const M1: u32 = 0xCD9E8D57;
+const W0: u32 = 0x9E3779B9;
+const W1: u32 = 0xBB67AE85;
+
+fn mulhilo(m: u32, a: u32) -> (u32, u32) {
+    let p = m as u64 * a as u64;
+    ((p >> 32) as u32, p as u32)
+}
+
+pub fn philox4x32(ctr: [u32; 4], key: [u32; 2]) -> [u32; 4] {
+    let mut ctr = ctr;
+    let mut key = key;
+    for i in 0..10 {
+        if i > 0 {
+            key = [key[0].wrapping_add(W0), key[1].wrapping_add(W1)];
+        }
+        let (hi0, lo0) = mulhilo(M0, ctr[0]);
+        let (hi1, lo1) = mulhilo(M1, ctr[2]);
+        ctr = [hi1 ^ ctr[1] ^ key[0], lo1, hi0 ^ ctr[3] ^ key[1], lo0];
+    }
+    ctr
+}
+
+// upper 24 bits scaled by 2^-24
+pub fn u01(a: u32) -> f32 {
+    (a >> 8) as f32 * 5.960_464_5e-8
+}
+
+pub struct Stream {
+    seed: u32,
+    x: u32,
+    y: u32,
+    z: u32,
+    pub n: u32, // number of consumed blocks
+}
+
+impl Stream {
+    pub fn new(seed: u32, x: u32, y: u32, z: u32) -> Stream {
+        Stream {
+            seed,
+            x,
+            y,
+            z,
+            n: 0,
+        }
+    }
+
+    fn block(&mut self) -> [u32; 4] {
+        let block = philox4x32([self.x, self.y, self.z, self.n], [self.seed, 0]);
+        self.n += 1;
+        block
+    }
+
+    pub fn runif(&mut self) -> f32 {
+        u01(self.block()[0])
+    }
+
+    // Marsaglia polar method
+    pub fn rnorm(&mut self) -> f32 {
+        loop {
+            let r = self.block();
+            let u = u01(r[0]) * 2.0 - 1.0;
+            let v = u01(r[1]) * 2.0 - 1.0;
+            let s = u * u + v * v;
+            if s < 1.0 && s != 0.0 {
+                return u * (-2.0 * s.ln() / s).sqrt();
+            }
+        }
+    }
+
+    // Knuth's method, used below lambda = 10
+    pub fn rpois_small(&mut self, lambda: f32) -> f32 {
+        let l = (-lambda).exp();
+        let mut p = 1.0;
+        let mut k = 0;
+        loop {
+            k += 1;
+            p *= self.runif();
+            if p <= l {
+                return (k - 1) as f32;
+            }
+        }
+    }
+}
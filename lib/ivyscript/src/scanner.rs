@@ -55,7 +55,7 @@ impl Scanner {
             token: TokenType::Eof,
             fragment: Fragment {
                 line: self.line + 1,
-                position: self.start - self.line_start + 1,
+                position: self.current - self.line_start + 1,
                 lexeme: "[End of file]".into(),
             },
         });
@@ -98,21 +98,21 @@ impl Scanner {
 
     fn match_number(&mut self) -> Result<TokenType, (String, usize)> {
         let line = self.line;
-        while self.peek().is_digit(10) {
+        while self.peek().is_ascii_digit() {
             self.advance();
         }
 
-        if self.peek() == '.' && self.peek_next().is_digit(10) {
+        if self.peek() == '.' && self.peek_next().is_ascii_digit() {
             self.advance();
 
-            while self.peek().is_digit(10) {
+            while self.peek().is_ascii_digit() {
                 self.advance();
             }
 
             let value = self.source[self.start..self.current]
                 .iter()
                 .collect::<String>();
-            if let Ok(value) = value.parse::<f32>() {
+            if let Ok(value) = value.parse::<f64>() {
                 Ok(TokenType::Float(value))
             } else {
                 Err((
@@ -164,6 +164,7 @@ impl Scanner {
 
             "function" => TokenType::Function,
             "kernel" => TokenType::Kernel,
+            "struct" => TokenType::Struct,
             "return" => TokenType::Return,
             "continue" => TokenType::Continue,
             "break" => TokenType::Break,
@@ -187,6 +188,8 @@ impl Scanner {
             ']' => TokenType::RightBracket,
             ',' => TokenType::Comma,
             '.' => TokenType::Dot,
+            ':' => TokenType::Colon,
+            '@' => TokenType::At,
             '-' => {
                 if self.match_advance('-') {
                     // handle comments
@@ -279,7 +282,7 @@ impl Scanner {
                 self.line_start = self.current;
                 return Ok(()); // skip
             }
-            c if c.is_digit(10) => self.match_number()?,
+            c if c.is_ascii_digit() => self.match_number()?,
             c if c.is_alphabetic() => self.match_identifier(),
             c => {
                 return Err((format!("Invalid character: '{}'", c), self.line));
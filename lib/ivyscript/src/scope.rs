@@ -21,19 +21,20 @@ use std::collections::HashMap;
 use crate::inference::VarType;
 
 #[derive(Debug)]
-struct Scope {
+struct Scope<T> {
     parent: usize,
-    vars: HashMap<String, Option<VarType>>,
+    vars: HashMap<String, Option<T>>,
 }
 
+// tree of nested scopes, holding variable types by default
 #[derive(Debug)]
-pub struct ScopeTree {
-    scopes: RefCell<Vec<Scope>>,
+pub struct ScopeTree<T = VarType> {
+    scopes: RefCell<Vec<Scope<T>>>,
     pub current: Cell<usize>,
 }
 
-impl ScopeTree {
-    pub fn new() -> ScopeTree {
+impl<T: Copy> ScopeTree<T> {
+    pub fn new() -> ScopeTree<T> {
         ScopeTree {
             scopes: RefCell::new(vec![Scope {
                 parent: 0,
@@ -43,12 +44,7 @@ impl ScopeTree {
         }
     }
 
-    pub fn clear(&self) {
-        self.current.set(0);
-        self.scopes.borrow_mut().drain(1..);
-    }
-
-    pub fn add(&self, id: &str, t: VarType) -> usize {
+    pub fn add(&self, id: &str, t: T) -> usize {
         let n = self.current.get();
         self.scopes.borrow_mut()[n].vars.insert(id.into(), Some(t));
         n
@@ -60,11 +56,11 @@ impl ScopeTree {
         n
     }
 
-    pub fn overwrite(&self, var: &str, t: VarType) -> usize {
+    pub fn overwrite(&self, var: &str, t: T) -> usize {
         let mut id = self.current.get();
         loop {
             let scope = &mut self.scopes.borrow_mut()[id];
-            if scope.vars.get(var).is_some() {
+            if scope.vars.contains_key(var) {
                 scope.vars.insert(var.into(), Some(t));
                 return id;
             } else {
@@ -76,7 +72,7 @@ impl ScopeTree {
         }
     }
 
-    pub fn get(&self, var: &str) -> Option<VarType> {
+    pub fn get(&self, var: &str) -> Option<T> {
         let mut id = self.current.get();
         loop {
             let scope = &self.scopes.borrow()[id];
@@ -0,0 +1,190 @@
+/*
+  Copyright (C) 2011-2021 G. Bajlekov
+
+    Ivy is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Ivy is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+// translates all kernels in ops/ocl/*.ivy and compares the output with the files in snapshots/,
+// run with IVY_UPDATE_SNAPSHOTS=1 to write the expected output after an intended change
+
+use std::fs;
+use std::path::Path;
+
+use super::*;
+use crate::ast::Stmt;
+use crate::function_id::function_id;
+
+// kernel signatures: I - int, F - float, <color space><channels>[1] - buffer, trailing 1 for 1x1 buffers
+const SIGNATURES: &[(&str, &str, &str)] = &[
+    ("localLaplacian", "zero_LL", "L1"),
+    ("localLaplacian", "post_LL", "XYZ3 XYZ3"),
+    ("localLaplacian", "transform", "XYZ3 Y1 Y1 XYZ3 F"),
+    ("localLaplacian", "apply_LL", "XYZ3 L1 L1 I I"),
+    ("math_kernels_1", "*", "LRGB3 LRGB3"),
+    ("math_kernels_2", "*", "LRGB3 LRGB3 LRGB3"),
+    ("nlmeans", "init", "LRGB3 LRGB3 LRGB3"),
+    ("nlmeans", "dist", "LRGB3 Y1 Y1 Y1 Y11 I I"),
+    ("nlmeans", "horizontal", "Y1 Y1 Y1"),
+    ("nlmeans", "vertical", "Y1 Y1 Y1"),
+    (
+        "nlmeans",
+        "accumulate",
+        "LRGB3 Y1 LRGB3 LRGB3 LRGB3 Y1 Y1 I I",
+    ),
+    ("nlmeans", "norm", "LRGB3 LRGB3 LRGB3 LRGB3 LRGB3 Y1"),
+    ("pyr", "pyrDown", "XYZ3 XYZ3"),
+    ("pyr", "pyrUp", "XYZ3 XYZ3"),
+    ("pyr", "pyrUpG", "XYZ3 XYZ3 XYZ3 XYZ3"),
+    ("pyr", "pyrUpL", "XYZ3 XYZ3 XYZ3"),
+    ("pyr_c_2d", "pyrDown", "XYZ3 Y1"),
+    ("pyr_c_2d", "pyrUp", "Y1 Y1"),
+    ("pyr_c_2d", "pyrUpG", "Y1 Y1 Y1 Y1"),
+    ("pyr_c_2d", "pyrUpL", "XYZ3 Y1 Y1"),
+    ("pyr_c_3d", "pyrDown", "XYZ3 XYZ3"),
+    ("pyr_c_3d", "pyrUp", "XYZ3 XYZ3"),
+    ("pyr_c_3d", "pyrUpG", "XYZ3 XYZ3 XYZ3 Y1"),
+    ("pyr_c_3d", "pyrUpL", "XYZ3 XYZ3 XYZ3"),
+];
+
+fn var_type(s: &str) -> VarType {
+    match s {
+        "I" => VarType::Int,
+        "F" => VarType::Float,
+        s => {
+            let n = s.find(|c: char| c.is_ascii_digit()).unwrap();
+            let cs = match &s[..n] {
+                "SRGB" => ColorSpace::Srgb,
+                "LRGB" => ColorSpace::Lrgb,
+                "XYZ" => ColorSpace::Xyz,
+                "LAB" => ColorSpace::Lab,
+                "LCH" => ColorSpace::Lch,
+                "Y" => ColorSpace::Y,
+                "L" => ColorSpace::L,
+                cs => panic!("Unknown color space '{}' in signature", cs),
+            };
+            VarType::Buffer {
+                z: s[n..n + 1].parse().unwrap(),
+                cs,
+                x1y1: s.len() > n + 1,
+            }
+        }
+    }
+}
+
+fn signature(file: &str, kernel: &str) -> Vec<VarType> {
+    let (_, _, sig) = SIGNATURES
+        .iter()
+        .find(|(f, k, _)| *f == file && (*k == kernel || *k == "*"))
+        .unwrap_or_else(|| panic!("No signature for kernel '{}' in '{}.ivy'", kernel, file));
+    sig.split(' ').map(var_type).collect()
+}
+
+fn kernels(source: &str) -> Vec<String> {
+    let tokens = Scanner::new(source.into()).scan().unwrap();
+    let (ast, _) = Parser::new(tokens).parse();
+    ast.into_iter()
+        .filter_map(|(stmt, _)| match stmt {
+            Stmt::Kernel { id, .. } => Some(id),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn snapshots() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let update = std::env::var("IVY_UPDATE_SNAPSHOTS").is_ok();
+
+    let mut files = fs::read_dir(root.join("../../ops/ocl"))
+        .unwrap()
+        .map(|e| e.unwrap().path())
+        .filter(|p| p.extension().is_some_and(|e| e == "ivy"))
+        .collect::<Vec<_>>();
+    files.sort();
+
+    let mut failed = Vec::new();
+    for path in files {
+        let file = path.file_stem().unwrap().to_str().unwrap();
+        let source = fs::read_to_string(&path).unwrap();
+
+        for kernel in kernels(&source) {
+            let input = signature(file, &kernel);
+            for (target, ext) in [(Target::Ocl, "cl"), (Target::Ispc, "ispc")] {
+                let output = Translator::new(&source, target)
+                    .kernel(&kernel, &input)
+                    .unwrap_or_else(|e| panic!("{}.ivy, kernel '{}': {}", file, kernel, e));
+
+                let expected = root
+                    .join("snapshots")
+                    .join(file)
+                    .join(format!("{}.{}", kernel, ext));
+                if update {
+                    fs::create_dir_all(expected.parent().unwrap()).unwrap();
+                    fs::write(&expected, &output).unwrap();
+                } else if fs::read_to_string(&expected).ok() != Some(output) {
+                    failed.push(expected.display().to_string());
+                }
+            }
+        }
+    }
+
+    assert!(
+        failed.is_empty(),
+        "Generated code differs from snapshots:\n{}",
+        failed.join("\n")
+    );
+}
+
+#[test]
+fn deterministic() {
+    let source = "
+const b = a*2.0
+const a = 1.0
+
+function g(x)
+  return x*b
+end
+
+function f(x)
+  return g(x) + h(x)
+end
+
+function h(x)
+  return x + a
+end
+
+kernel k(O)
+  O[0] = f(1.0) + h(2.0)
+end
+";
+    let output = Translator::new(source, Target::Ocl)
+        .kernel("k", &[var_type("Y1")])
+        .unwrap();
+
+    // constants follow their dependencies, functions follow the functions they call
+    let pos = |s: &str| output.find(s).unwrap();
+    assert!(pos("constant float a") < pos("constant float b"));
+    let g = function_id("g", &[VarType::Float]);
+    let h = function_id("h", &[VarType::Float]);
+    let f = function_id("f", &[VarType::Float]);
+    assert!(pos(&format!("float {} (", g)) < pos(&format!("float {} (", h)));
+    assert!(pos(&format!("float {} (", h)) < pos(&format!("float {} (", f)));
+
+    for _ in 0..10 {
+        let again = Translator::new(source, Target::Ocl)
+            .kernel("k", &[var_type("Y1")])
+            .unwrap();
+        assert_eq!(output, again);
+    }
+}
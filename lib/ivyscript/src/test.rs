@@ -0,0 +1,2268 @@
+/*
+  Copyright (C) 2011-2021 G. Bajlekov
+
+    Ivy is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Ivy is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use super::*;
+use crate::function_id::function_id;
+
+const LRGB: VarType = VarType::Buffer {
+    x1y1: false,
+    z: 3,
+    cs: ColorSpace::Lrgb,
+};
+
+const Y: VarType = VarType::Buffer {
+    x1y1: false,
+    z: 1,
+    cs: ColorSpace::Y,
+};
+
+const SOURCE: &str = "
+const eps = 0.0001
+
+function scale(a, b)
+  return a*b + eps
+end
+
+kernel test(I, O, f)
+  const x = get_global_id(0)
+  const y = get_global_id(1)
+
+  var i = I[x, y]
+  i.y = scale(i.y, f)
+  O[x, y].LAB = i
+end
+";
+
+#[test]
+fn kernel_ocl() {
+    let t = Translator::new(SOURCE, Target::Ocl);
+    let source = t.kernel("test", &[LRGB, LRGB, VarType::Float]).unwrap();
+
+    let id = function_id("scale", &[VarType::Float, VarType::Float]);
+    assert!(!source.contains("#include"));
+    assert!(source.contains("constant float eps = 0.0001000f;\n"));
+    assert!(source.contains(&format!("float {} (\n\tfloat a,\n\tfloat b\n);", id)));
+    assert!(source.contains(&format!("i.y = {}(i.y, f);\n", id)));
+    assert!(source.contains("kernel void test (\n\tglobal float *I, global int *___str_I,"));
+    assert!(source.contains("float3 __v = LABtoLRGB(i);"));
+}
+
+#[test]
+fn kernel_ispc() {
+    let t = Translator::new(SOURCE, Target::Ispc);
+    let source = t.kernel("test", &[LRGB, LRGB, VarType::Float]).unwrap();
+
+    let id = function_id("scale", &[VarType::Float, VarType::Float]);
+    assert!(!source.contains("#include"));
+    assert!(source.contains("const uniform float eps = 0.0001000f;\n"));
+    assert!(source.contains("const int x = _x;\n"));
+    assert!(source.contains(&format!("float {} (\n\tfloat a,\n\tfloat b\n);", id)));
+    assert!(source.contains(&format!("i.y = {}(i.y, f);\n", id)));
+    assert!(source.contains("task void ___task_test ("));
+    assert!(source.contains("export void test ("));
+}
+
+#[test]
+fn uniformity() {
+    let source = "
+function weight(a, b)
+  return a*b
+end
+
+function offset(a)
+  return a + get_global_id(0)
+end
+
+function outer(a)
+  return offset(a)*2
+end
+
+kernel test(I, O, n, f)
+  const x = get_global_id(0)
+  const y = get_global_id(1)
+
+  var c = 0
+  var w = n*2
+  var h = f + 1
+  for i = 1, n do
+    c = c + 1
+    if I[x, y] > 0.5 then
+      w = w + 1
+    end
+  end
+
+  var j = 0
+  while j < n do
+    j = j + 1
+    if I[x, j] > f then
+      break
+    end
+  end
+
+  var a = array(3)
+  a[0] = h
+  O[x, y] = c + w + weight(h, a[0]) + outer(h) + j
+end
+";
+    let t = Translator::new(source, Target::Ispc);
+    let source = t
+        .kernel("test", &[Y, Y, VarType::Int, VarType::Float])
+        .unwrap();
+
+    // values derived from uniform arguments and loop counters
+    assert!(source.contains("const int x = _x;\n"));
+    assert!(source.contains("uniform int c = 0;\n"));
+    assert!(source.contains("uniform float h = f + 1;\n"));
+    assert!(source.contains("for (uniform int i = 1;"));
+    assert!(source.contains("uniform float a [3];\n"));
+
+    // assignment under varying control flow and loops with a varying exit
+    assert!(source.contains("\nint w = n*2;\n"));
+    assert!(source.contains("\nint j = 0;\n"));
+
+    // global indices are passed only to functions using them
+    let weight = function_id("weight", &[VarType::Float, VarType::Float]);
+    let offset = function_id("offset", &[VarType::Float]);
+    let outer = function_id("outer", &[VarType::Float]);
+    assert!(source.contains(&format!("float {} (\n\tfloat a,", weight)));
+    assert!(source.contains(&format!("{}(h, a[0])", weight)));
+    assert!(source.contains(&format!("{}(_x, _y, _z, a)", offset)));
+    assert!(source.contains(&format!("{}(_x, _y, _z, h)", outer)));
+}
+
+#[test]
+fn kernel_cached() {
+    let t = Translator::new(SOURCE, Target::Ocl);
+    let a = t.kernel("test", &[LRGB, LRGB, VarType::Float]).unwrap();
+    let b = t.kernel("test", &[LRGB, LRGB, VarType::Float]).unwrap();
+    assert_eq!(a, b);
+
+    // different specialization of the same kernel
+    let c = t.kernel("test", &[LRGB, LRGB, VarType::Int]).unwrap();
+    assert!(c.contains(&function_id("scale", &[VarType::Float, VarType::Int])));
+}
+
+#[test]
+fn color_space_load() {
+    let source = "
+kernel test(I, O)
+  const x = get_global_id(0)
+  const y = get_global_id(1)
+  O[x, y] = I[x, y].L
+end
+";
+    let t = Translator::new(source, Target::Ocl);
+    let source = t.kernel("test", &[LRGB, Y]).unwrap();
+    assert!(source.contains("= LRGBtoL((float3)( I["));
+}
+
+#[test]
+fn assign_op() {
+    let source = "
+kernel test(O)
+  var a = 1
+  a += 2
+  a *= 3.0
+  O[0] = a
+end
+";
+    let t = Translator::new(source, Target::Ocl);
+    let source = t.kernel("test", &[Y]).unwrap();
+    assert!(source.contains("a = a + 2;\n"));
+    assert!(source.contains("a = a*3.0000000f;\n"));
+}
+
+#[test]
+fn while_loop() {
+    let source = "
+kernel test(O)
+  var i = 0
+  while i<10 do
+    i = i + 1
+  end
+  O[0] = i
+end
+";
+    let t = Translator::new(source, Target::Ocl);
+    let source = t.kernel("test", &[Y]).unwrap();
+    assert!(source.contains("while (i<10) {\ni = i + 1;\n}\n"));
+}
+
+#[test]
+fn errors() {
+    let source = "
+function f(a)
+  return a
+end
+
+kernel cond(O)
+  if 1 then
+    O[0] = 1.0
+  end
+end
+
+kernel args(O)
+  O[0] = f(O)
+end
+";
+    let t = Translator::new(source, Target::Ocl);
+    assert!(t.kernel("cond", &[Y]).is_err());
+    assert!(t.kernel("args", &[Y]).is_err());
+    assert!(t.kernel("missing", &[Y]).is_err());
+    assert!(t.kernel("cond", &[Y, Y]).is_err());
+}
+
+#[test]
+fn annotations() {
+    let source = "
+function blend(a: vec, b: vec, f: float): vec
+  return a*(1.0 - f) + b*f
+end
+
+function half(a): float
+  return a/2
+end
+
+kernel test(I: buffer[3, LRGB], O: buffer[3])
+  const x = get_global_id(0)
+  const y = get_global_id(1)
+
+  var k: float = 1
+  var i = blend(I[x, y], 0, k)
+  i.y = half(2)
+  O[x, y] = i
+end
+
+kernel mismatch(O)
+  var k: int = 1.0
+end
+
+kernel call(O)
+  O[0] = blend(true, 1, 1)
+end
+
+kernel ret(O)
+  O[0] = half(true)
+end
+";
+    let t = Translator::new(source, Target::Ocl);
+    let source = t.kernel("test", &[LRGB, LRGB]).unwrap();
+
+    // int and float arguments are promoted to the annotated vec type
+    let id = function_id("blend", &[VarType::Vec, VarType::Vec, VarType::Float]);
+    assert!(source.contains(&format!("float3 {} (\n\tfloat3 a,", id)));
+    assert!(source.contains("float k = (float)(1);\n"));
+    assert!(source.contains(&function_id("half", &[VarType::Int])));
+
+    // buffer annotations restrict channel count and color space
+    assert!(t.kernel("test", &[Y, LRGB]).is_err());
+    assert!(t.kernel("test", &[LRGB, Y]).is_err());
+
+    let err = t.kernel("mismatch", &[Y]).unwrap_err();
+    assert!(err.contains("expected type 'int'"));
+    let err = t.kernel("call", &[Y]).unwrap_err();
+    assert!(err.contains("Argument 'a' of function 'blend'"));
+    assert!(t.kernel("ret", &[Y]).is_err());
+}
+
+#[test]
+fn annotation_syntax() {
+    let parse = |source: &str| {
+        let tokens = Scanner::new(source.to_string()).scan().unwrap();
+        Parser::new(tokens).parse().1 // syntax errors
+    };
+    assert!(parse("kernel k(I: buffer[LAB], f: int)\nend\n").is_empty());
+    assert!(!parse("kernel k(I): int\nend\n").is_empty());
+    assert!(!parse("var a: = 1\n").is_empty());
+
+    // other type names refer to structs and are checked once the file scope is known
+    assert!(parse("function f(a: image)\nend\n").is_empty());
+    match Program::parse("function f(a: image)\nend\n") {
+        Err(err) => assert!(err.contains("Unknown type 'image'"), "{}", err),
+        Ok(_) => panic!("expected unknown type"),
+    }
+}
+
+#[test]
+fn traversal() {
+    let source = "
+@tile(32, 8)
+kernel vertical(I, O)
+  const x = get_global_id(0)
+  const y = get_global_id(1)
+  O[x, y] = (I[x, y - 1] + I[x, y] + I[x, y + 1])/3
+end
+
+@rows
+kernel horizontal(I, O)
+  const x = get_global_id(0)
+  const y = get_global_id(1)
+  O[x, y] = (I[x - 1, y] + I[x, y] + I[x + 1, y])/3
+end
+
+kernel default(I, O)
+  O[0] = I[0]
+end
+";
+    let program = Program::parse(source).unwrap();
+    assert_eq!(
+        program.traversal("vertical").unwrap(),
+        Some(Traversal::Tile(32, 8))
+    );
+    assert_eq!(
+        program.traversal("horizontal").unwrap(),
+        Some(Traversal::Rows)
+    );
+    assert_eq!(program.traversal("default").unwrap(), None);
+    assert!(program.traversal("missing").is_err());
+
+    let y = InputType::Buffer {
+        x: 16,
+        y: 16,
+        z: 1,
+        cs: ColorSpace::Y,
+    };
+
+    // declared tiles replace the host task size and the x/y swap
+    let ispc = program
+        .specialize("vertical", &[y, y], Target::Ispc)
+        .unwrap();
+    assert!(ispc.contains("uniform int _xmin = _dim[0] + taskIndex0*32;\n"));
+    assert!(ispc.contains("uniform int _ny = ceil((uniform float)_dim[4]/8);\n"));
+    assert!(ispc.contains(
+        "foreach (_z = _zmin ... _zmax, _y = _ymin ... _ymax, _x = _xmin ... _xmax) {\n"
+    ));
+    assert!(!ispc.contains("_dim[3]<16"));
+
+    let ispc = program
+        .specialize("horizontal", &[y, y], Target::Ispc)
+        .unwrap();
+    assert!(ispc.contains("uniform int _ymin = _dim[1] + taskIndex1*1;\n"));
+    assert!(ispc.contains("uniform int _nx = ceil((uniform float)_dim[3]/_dim[3]);\n"));
+
+    let ispc = program
+        .specialize("default", &[y, y], Target::Ispc)
+        .unwrap();
+    assert!(ispc.contains("taskIndex0*_dim[6]"));
+    assert!(ispc.contains("_dim[3]<16 && _dim[4]>16"));
+
+    let ocl = program
+        .specialize("vertical", &[y, y], Target::Ocl)
+        .unwrap();
+    assert!(
+        ocl.contains("kernel __attribute__((work_group_size_hint(32, 8, 1))) void vertical (\n")
+    );
+    let ocl = program
+        .specialize("horizontal", &[y, y], Target::Ocl)
+        .unwrap();
+    assert!(ocl.contains("kernel void horizontal (\n"));
+
+    let parse = |source: &str| {
+        let tokens = Scanner::new(source.to_string()).scan().unwrap();
+        Parser::new(tokens).parse().1 // syntax errors
+    };
+    assert!(parse("@tile(16, 16)\nkernel k(I)\nend\n").is_empty());
+    assert!(parse("@tile(256, 1)\nkernel k(I)\nend\n").is_empty());
+    assert_eq!(
+        parse("@tile(64, 8)\nkernel k(I)\nend\n"),
+        [(
+            "Tile size 64x8 exceeds the maximum of 256 work items".to_string(),
+            1
+        )]
+    );
+    assert!(!parse("@tile(65536, 65536)\nkernel k(I)\nend\n").is_empty());
+    assert!(!parse("@tile(16)\nkernel k(I)\nend\n").is_empty());
+    assert!(!parse("@tile(0, 8)\nkernel k(I)\nend\n").is_empty());
+    assert!(!parse("@tile(8, 8, 8)\nkernel k(I)\nend\n").is_empty());
+    assert!(!parse("@columns\nkernel k(I)\nend\n").is_empty());
+    assert!(!parse("@rows\nfunction f(a)\nend\n").is_empty());
+
+    // the annotated kernel is still parsed after an invalid annotation
+    let errors = parse("@tile(0, 8)\nkernel k(I)\nend\n@rows\nkernel l(I)\nend\n");
+    assert_eq!(errors.len(), 1);
+}
+
+#[test]
+fn validation() {
+    let validate = |source: &str| {
+        let tokens = Scanner::new(source.to_string()).scan().unwrap();
+        validate(&Parser::new(tokens).parse().0)
+    };
+
+    let ok = "
+function f(a)
+  return g(a) + abs(a)
+end
+
+function g(a)
+  return a
+end
+
+kernel k(O)
+  O[0] = f(1.0) + g(2.0)
+end
+";
+    assert!(validate(ok).is_ok());
+
+    let recursive = "
+function f(a)
+  return a*f(a - 1)
+end
+
+function g(a)
+  if a>0 then
+    h(a)
+  end
+end
+
+function h(a)
+  var b = g(a)
+end
+";
+    let errors = validate(recursive).unwrap_err();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(
+        errors[0],
+        (
+            "Recursive function calls are not supported: f -> f".into(),
+            2
+        )
+    );
+    assert!(errors[1].0.contains("g -> h -> g"));
+
+    let invalid = "
+var a = 1
+
+kernel k(O)
+  for i = 0, 10 do
+    return 1
+  end
+end
+
+function k(a)
+end
+";
+    let errors = validate(invalid).unwrap_err();
+    assert_eq!(errors.len(), 3);
+    assert_eq!(errors[0].1, 2);
+    assert!(errors[1].0.contains("can not return a value"));
+    assert!(errors[2].0.contains("Duplicate definition of 'k'"));
+
+    // invalid sources are reported for all kernels instead of panicking
+    let t = Translator::new(invalid, Target::Ocl);
+    assert!(t.kernel("k", &[Y]).is_err());
+}
+
+#[test]
+fn lints() {
+    let source = "
+const eps = 0.0001
+
+function f(a, b)
+  return a
+  var c = 1
+end
+
+kernel k(I: const buffer, O)
+  const x = get_global_id(0)
+  var eps = 1.0
+  for x = 0, 10 do
+    break
+  end
+  x = 2
+  I[x] = 1.0
+  O[x] = I[x]
+end
+
+kernel early(O)
+  O[0, 0] = 1
+  return
+  O[0, 0] = 2
+end
+
+kernel allowed(O, p)
+  -- ivy:allow(unused_parameter, shadowing)
+  var eps = 0.0
+  O[0] = eps
+end
+";
+    let mut t = Translator::new(source, Target::Ocl);
+    let codes = t
+        .program
+        .as_ref()
+        .unwrap()
+        .warnings()
+        .iter()
+        .map(|w| (w.lint.code(), w.line))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        codes,
+        [
+            ("unreachable_code", 6),
+            ("unused_parameter", 4),
+            ("unused_variable", 6),
+            ("shadowing", 11),
+            ("shadowing", 12),
+            ("const_assign", 15),
+            ("input_write", 16),
+            ("unused_variable", 11),
+            ("unreachable_code", 23),
+        ]
+    );
+    assert!(t
+        .warnings()
+        .contains("[Line 4] warning[unused_parameter]: Unused parameter 'b' in function 'f'"));
+    assert!(t
+        .warnings()
+        .contains("'eps' shadows file scope constant 'eps' in kernel 'k'"));
+
+    // lints can be disabled by code
+    t.lints.remove(&Lint::from_code("unused_variable").unwrap());
+    assert!(!t.warnings().contains("unused_variable"));
+    assert!(Lint::from_code("unknown").is_none());
+}
+
+#[test]
+fn error_recovery() {
+    let source = "
+const a = 1.0 +
+
+function f(x)
+  var y = x *
+  return x
+end
+
+kernel k(O)
+  var i = 0
+  if i == then
+    i = 1
+  elseif i == 1 then
+    i = 2
+  elseif i == 2 then
+    i = )
+  end
+  for j = 0 10 do
+    O[j] = i
+  end
+end
+
+kernel ok(O)
+  O[0] = 1.0
+end
+";
+    let tokens = Scanner::new(source.to_string()).scan().unwrap();
+    let (ast, errors) = Parser::new(tokens).parse();
+
+    // all errors are reported with the line they occur on
+    let lines = errors.iter().map(|(_, line)| *line).collect::<Vec<_>>();
+    assert_eq!(lines, [2, 5, 11, 16, 18]);
+
+    // partial AST keeps all definitions and the valid statements in their bodies
+    let ids = ast
+        .iter()
+        .filter_map(|(stmt, _)| match stmt {
+            ast::Stmt::Function { id, body, .. } | ast::Stmt::Kernel { id, body, .. } => {
+                Some((id.as_str(), body.len()))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(ids, [("f", 1), ("k", 3), ("ok", 1)]);
+
+    // kernels are not generated from sources with syntax errors
+    let t = Translator::new(source, Target::Ocl);
+    let err = t.kernel("ok", &[Y]).unwrap_err();
+    assert_eq!(err.lines().count(), 5);
+}
+
+#[test]
+fn program() {
+    let program = Program::parse(SOURCE).unwrap();
+    assert_eq!(program.kernels(), ["test"]);
+
+    let lrgb = InputType::Buffer {
+        x: 16,
+        y: 16,
+        z: 3,
+        cs: ColorSpace::Lrgb,
+    };
+    let input = [lrgb, lrgb, InputType::Float];
+    for target in [Target::Ocl, Target::Ispc] {
+        let source = program.specialize("test", &input, target).unwrap();
+        let t = Translator::new(SOURCE, target);
+        assert_eq!(
+            source,
+            t.kernel("test", &[LRGB, LRGB, VarType::Float]).unwrap()
+        );
+    }
+
+    // 1x1 buffers are specialized separately
+    let lrgb_1x1 = InputType::Buffer {
+        x: 1,
+        y: 1,
+        z: 3,
+        cs: ColorSpace::Lrgb,
+    };
+    let input = [lrgb_1x1, lrgb, InputType::Float];
+    assert_ne!(
+        program.specialize("test", &input, Target::Ocl),
+        program.specialize("test", &[lrgb, lrgb, InputType::Float], Target::Ocl)
+    );
+
+    let empty = InputType::Buffer {
+        x: 16,
+        y: 0,
+        z: 3,
+        cs: ColorSpace::Lrgb,
+    };
+    let err = program
+        .specialize("test", &[empty, lrgb, InputType::Float], Target::Ocl)
+        .unwrap_err();
+    assert!(err.contains("Invalid buffer dimensions"));
+    assert!(program.specialize("missing", &[], Target::Ocl).is_err());
+
+    // errors are reported for the whole source
+    let err = Program::parse("kernel k(O)\n  O[0] = \nend\nvar a = 1\n")
+        .err()
+        .unwrap();
+    assert!(err.starts_with("[Line 2]: "));
+}
+
+#[test]
+fn input_types() {
+    let source = "
+function pick(flag, a, b)
+  if flag then
+    return a
+  end
+  return b
+end
+
+kernel k(O, lut, idx, v, flag)
+  const x = get_global_id(0)
+  var c = v
+  c.x = lut[idx[x]]
+  O[x] = pick(flag, c, v)
+end
+";
+    let program = Program::parse(source).unwrap();
+    let input = [
+        InputType::Buffer {
+            x: 16,
+            y: 16,
+            z: 3,
+            cs: ColorSpace::Lrgb,
+        },
+        InputType::FloatArray { len: 256 },
+        InputType::IntArray { len: 16 },
+        InputType::Vec,
+        InputType::Bool,
+    ];
+
+    let ocl = program.specialize("k", &input, Target::Ocl).unwrap();
+    assert!(
+        ocl.contains("\tglobal float *lut,\n\tglobal int *idx,\n\tfloat3 v,\n\tint ___bool_flag\n")
+    );
+    assert!(ocl.contains("bool flag = ___bool_flag;"));
+    assert!(ocl.contains("bool flag,"));
+
+    let ispc = program.specialize("k", &input, Target::Ispc).unwrap();
+    assert!(ispc.contains("\tuniform float lut[],\n\tuniform int idx[],\n\tuniform float ___vec_v[3],\n\tuniform bool flag\n"));
+    assert!(ispc.contains("float<3> v = {___vec_v[0], ___vec_v[1], ___vec_v[2]};"));
+    assert!(ispc.contains(" lut, idx, ___vec_v, flag);"));
+
+    let empty = [input[0], InputType::FloatArray { len: 0 }];
+    assert!(program.specialize("k", &empty, Target::Ocl).is_err());
+}
+
+#[test]
+fn constant_inputs() {
+    let source = "
+kernel k(O, mode, radius, scale)
+  const x = get_global_id(0)
+  var s = 0.0
+  for i = -radius, radius do
+    s += O[x + i]
+  end
+  if mode == 1 then
+    O[x] = s
+  elseif mode == 2 then
+    O[x] = s*scale
+  else
+    O[x] = 0.0
+  end
+  while mode > 3 do
+    s = 1.0
+  end
+end
+";
+    let program = Program::parse(source).unwrap();
+    let y = InputType::Buffer {
+        x: 16,
+        y: 16,
+        z: 1,
+        cs: ColorSpace::Y,
+    };
+    let input = |mode| [y, mode, InputType::IntConst(3), InputType::FloatConst(0.5)];
+
+    // values are substituted, dead branches and loops are removed
+    let ocl = program
+        .specialize("k", &input(InputType::IntConst(2)), Target::Ocl)
+        .unwrap();
+    assert!(ocl.contains("\tint ___const_mode,\n"));
+    assert!(ocl.contains("const int mode = 2;"));
+    assert!(ocl.contains("for (int i = (-3); i<=3; i += 1) {"));
+    assert!(ocl.contains("= s*0.5000000f;"));
+    assert!(!ocl.contains("mode=="));
+    assert!(!ocl.contains("0.0000000f;\n}"));
+    assert!(!ocl.contains("while"));
+
+    let ispc = program
+        .specialize("k", &input(InputType::IntConst(1)), Target::Ispc)
+        .unwrap();
+    assert!(ispc.contains("if (true) {"));
+    assert!(!ispc.contains("*0.5000000f"));
+
+    // runtime inputs are not folded
+    let ocl = program
+        .specialize("k", &input(InputType::Int), Target::Ocl)
+        .unwrap();
+    assert!(ocl.contains("mode==1"));
+    assert!(ocl.contains("while (mode>3)"));
+
+    // called functions are specialized on constant arguments and folded
+    let source = "
+function g(v, n)
+  var s = 0.0
+  for i = 1, n do
+    s += v
+  end
+  return s
+end
+
+function f(v, n, m)
+  m = m + 1
+  if n == 2 then
+    return g(v, n)*m
+  end
+  return v
+end
+
+kernel k(O, mode, m)
+  const x = get_global_id(0)
+  O[x] = f(O[x], mode, 1) + f(O[x], 3, m) + g(O[x], m)
+end
+";
+    let program = Program::parse(source).unwrap();
+    let input = [y, InputType::IntConst(2), InputType::Int];
+    let ocl = program.specialize("k", &input, Target::Ocl).unwrap();
+    let f = kernel_id(
+        "f",
+        &[VarType::Float, VarType::Int, VarType::Int],
+        &[None, Some(Literal::Int(2)), None],
+        Precision::Single,
+    );
+    let g = kernel_id(
+        "g",
+        &[VarType::Float, VarType::Int],
+        &[None, Some(Literal::Int(2))],
+        Precision::Single,
+    );
+    assert!(ocl.contains(&format!("{}(O[", f)));
+    assert!(ocl.contains(&format!(
+        "float {} (\n\tfloat v\n) {{\nconst int n = 2;\n",
+        g
+    )));
+    assert!(ocl.contains("for (int i = 1; i<=2; i += 1) {"));
+    assert!(ocl.contains(&format!("return {}(v)*m;", g)));
+    assert!(!ocl.contains("n==2"));
+    assert!(ocl.contains("const int n = 3;\nm = m + 1;\nreturn v;\n}"));
+
+    // assigned arguments and runtime values are passed, the unspecialized instance remains
+    assert!(ocl.contains(&format!("float {} (\n\tfloat v,\n\tint m\n)", f)));
+    let plain = function_id("g", &[VarType::Float, VarType::Int]);
+    assert!(ocl.contains(&format!("{}(O[", plain)));
+    assert!(ocl.contains("for (int i = 1; i<=n; i += 1) {"));
+
+    let ispc = program.specialize("k", &input, Target::Ispc).unwrap();
+    assert!(ispc.contains(&format!("return {}(v)*m;", g)));
+    assert!(!ispc.contains("n==2"));
+
+    // values are part of the kernel id
+    let ty = [VarType::Int];
+    assert_ne!(
+        kernel_id("k", &ty, &[Some(Literal::Int(1))], Precision::Single),
+        kernel_id("k", &ty, &[Some(Literal::Int(2))], Precision::Single)
+    );
+    assert_ne!(
+        kernel_id("k", &ty, &[Some(Literal::Int(1))], Precision::Single),
+        function_id("k", &ty)
+    );
+    assert_eq!(
+        kernel_id("k", &ty, &[None], Precision::Single),
+        function_id("k", &ty)
+    );
+}
+
+#[test]
+fn concurrent() {
+    fn shared<T: Send + Sync>(_: &T) {}
+
+    let program = Program::parse(SOURCE).unwrap();
+    shared(&program);
+
+    let lrgb = InputType::Buffer {
+        x: 16,
+        y: 16,
+        z: 3,
+        cs: ColorSpace::Lrgb,
+    };
+    let float = [lrgb, lrgb, InputType::Float];
+    let int = [lrgb, lrgb, InputType::Int];
+    let kernels = [
+        ("test", &float[..]),
+        ("test", &int[..]),
+        ("missing", &[][..]),
+        ("test", &float[..]),
+    ];
+
+    let results = program.specialize_all(&kernels, Target::Ispc, Precision::Single);
+    assert_eq!(results.len(), 4);
+    assert!(results[2].is_err());
+    assert_eq!(results[0], results[3]);
+    assert_ne!(results[0], results[1]);
+
+    // the cache is shared between threads and targets are cached separately
+    let program = Arc::new(program);
+    let handles = (0..8)
+        .map(|i| {
+            let program = program.clone();
+            let target = if i % 2 == 0 {
+                Target::Ocl
+            } else {
+                Target::Ispc
+            };
+            thread::spawn(move || program.specialize("test", &float, target).unwrap())
+        })
+        .collect::<Vec<_>>();
+    let sources = handles
+        .into_iter()
+        .map(|h| h.join().unwrap())
+        .collect::<Vec<_>>();
+    for (i, source) in sources.iter().enumerate() {
+        assert_eq!(source, &sources[i % 2]);
+    }
+    assert_eq!(sources[1], results[0].clone().unwrap());
+    assert_ne!(sources[0], sources[1]);
+}
+
+#[test]
+fn disk_cache() {
+    let dir = std::env::temp_dir().join(format!("ivyscript-test-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let lrgb = InputType::Buffer {
+        x: 16,
+        y: 16,
+        z: 3,
+        cs: ColorSpace::Lrgb,
+    };
+    let input = [lrgb, lrgb, InputType::Float];
+
+    let program = Program::parse(SOURCE).unwrap();
+    program.set_cache_dir(Some(&dir)).unwrap();
+    let source = program.specialize("test", &input, Target::Ocl).unwrap();
+
+    let files = |ext: &str| {
+        std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .filter(|p| p.extension().is_some_and(|e| e == ext))
+            .collect::<Vec<_>>()
+    };
+    let (cl, meta) = (files("cl"), files("meta"));
+    assert_eq!((cl.len(), meta.len()), (1, 1));
+    assert_eq!(std::fs::read_to_string(&cl[0]).unwrap(), source);
+    let meta = std::fs::read_to_string(&meta[0]).unwrap();
+    assert!(meta.contains("target: cl\n"));
+    assert!(meta.contains("kernel: test\n"));
+    assert!(meta.contains("arg: f Float\n"));
+
+    // later runs load the stored kernel instead of generating it
+    std::fs::write(&cl[0], "cached").unwrap();
+    let program = Program::parse(SOURCE).unwrap();
+    program.set_cache_dir(Some(&dir)).unwrap();
+    assert_eq!(
+        program.specialize("test", &input, Target::Ocl).unwrap(),
+        "cached"
+    );
+
+    // entries are keyed on the source and target
+    assert_ne!(
+        program.specialize("test", &input, Target::Ispc).unwrap(),
+        "cached"
+    );
+    let changed = Program::parse(&SOURCE.replace("0.0001", "0.001")).unwrap();
+    changed.set_cache_dir(Some(&dir)).unwrap();
+    assert_ne!(
+        changed.specialize("test", &input, Target::Ocl).unwrap(),
+        "cached"
+    );
+    assert_eq!(files("cl").len(), 2);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unit() {
+    let program = Program::parse(SOURCE).unwrap();
+    let lrgb = InputType::Buffer {
+        x: 16,
+        y: 16,
+        z: 3,
+        cs: ColorSpace::Lrgb,
+    };
+    let float = [lrgb, lrgb, InputType::Float];
+    let half = [lrgb, lrgb, InputType::FloatConst(0.5)];
+    let kernels = [
+        ("test", &float[..]),
+        ("test", &half[..]),
+        ("test", &float[..]),
+    ];
+
+    let unit = program
+        .specialize_unit(&kernels, Target::Ocl, Precision::Single)
+        .unwrap();
+    let ids = [
+        kernel_id(
+            "test",
+            &[LRGB, LRGB, VarType::Float],
+            &[],
+            Precision::Single,
+        ),
+        kernel_id(
+            "test",
+            &[LRGB, LRGB, VarType::Float],
+            &[None, None, Some(Literal::Float(0.5))],
+            Precision::Single,
+        ),
+    ];
+    assert_eq!(
+        unit.manifest(),
+        format!("test {}\ntest {}\ntest {}\n", ids[0], ids[1], ids[0])
+    );
+
+    // shared constants and functions, one kernel per distinct specialization
+    let scale = function_id("scale", &[VarType::Float, VarType::Float]);
+    let source = &unit.source;
+    assert_eq!(source.matches("constant float eps").count(), 1);
+    assert_eq!(source.matches(&format!("float {} (", scale)).count(), 2);
+    assert_eq!(source.matches("kernel void").count(), 2);
+    assert!(source.contains(&format!("kernel void {} (", ids[0])));
+    assert!(source.contains(&format!("kernel void {} (", ids[1])));
+    assert_eq!(source.matches("inline float3 LABtoLRGB(").count(), 1);
+
+    let ispc = program
+        .specialize_unit(&kernels, Target::Ispc, Precision::Single)
+        .unwrap();
+    assert!(ispc.source.contains(&format!("export void {} (", ids[1])));
+    assert!(ispc
+        .source
+        .contains(&format!("task void ___task_{} (", ids[1])));
+
+    // errors name the failing kernel
+    let err = program
+        .specialize_unit(
+            &[("test", &float[..]), ("test", &[][..])],
+            Target::Ocl,
+            Precision::Single,
+        )
+        .err()
+        .unwrap();
+    assert!(err.starts_with("Kernel 'test': "));
+    assert!(program
+        .specialize_unit(&[], Target::Ocl, Precision::Single)
+        .is_err());
+
+    // through the FFI, added kernels are cleared after generating
+    let source = CString::new(SOURCE).unwrap();
+    let t = translator_new_ocl(source.as_ptr());
+    let kernel = CString::new("test").unwrap();
+    assert_eq!(translator_add_buffer_lrgb(t, 16, 16, 3), IVY_OK);
+    assert_eq!(translator_add_buffer_lrgb(t, 16, 16, 3), IVY_OK);
+    assert_eq!(translator_add_float(t), IVY_OK);
+    assert_eq!(translator_add_kernel(t, kernel.as_ptr()), IVY_OK);
+
+    let (mut out, mut manifest) = (ptr::null_mut(), ptr::null_mut());
+    assert_eq!(
+        translator_generate_program(t, &mut out, &mut manifest),
+        IVY_OK
+    );
+    let generated = unsafe { CStr::from_ptr(out) }.to_str().unwrap();
+    assert!(generated.contains(&format!("kernel void {} (", ids[0])));
+    let m = unsafe { CStr::from_ptr(manifest) }.to_str().unwrap();
+    assert_eq!(m, format!("test {}\n", ids[0]));
+    translator_string_free(out);
+    translator_string_free(manifest);
+
+    assert_eq!(
+        translator_generate_program(t, &mut out, &mut manifest),
+        IVY_ERROR_TRANSLATE
+    );
+    translator_string_free(out);
+    assert_eq!(
+        translator_generate_program(t, ptr::null_mut(), &mut manifest),
+        IVY_ERROR_NULL
+    );
+    translator_free(t);
+}
+
+#[test]
+fn standard_library() {
+    let source = "
+kernel k(I, O)
+  const x = get_global_id(0)
+  O[x] = LABtoLRGB(I[x]) + vec(range(0.5, 0.1, 0.3))
+end
+
+kernel plain(O)
+  O[0] = 1.0
+end
+";
+    let program = Program::parse(source).unwrap();
+    let lab = InputType::Buffer {
+        x: 16,
+        y: 1,
+        z: 3,
+        cs: ColorSpace::Lab,
+    };
+
+    // used helpers and their dependencies are defined before use, macros are undefined
+    let ocl = program.specialize("k", &[lab, lab], Target::Ocl).unwrap();
+    let position = |name: &str| ocl.find(name).unwrap();
+    assert!(position("constant float __M_1[]") < position("inline float3 _XYZ_LRGB("));
+    assert!(position("inline float _xyz(") < position("inline float3 _LAB_XYZ("));
+    assert!(position("inline float3 LABtoLRGB(") < position("kernel void k"));
+    assert!(ocl.contains("inline float range("));
+    assert!(ocl.contains("#define K (24389.0f/27.0f)\n"));
+    assert!(ocl.contains("#undef K\n"));
+    assert!(!ocl.contains("_lab("));
+    assert!(!ocl.contains("runif"));
+    assert!(!ocl.contains("#include"));
+
+    let ispc = program.specialize("k", &[lab, lab], Target::Ispc).unwrap();
+    assert!(ispc.contains("inline float<3> vec(float x)"));
+    assert!(ispc.contains("inline float<3> LABtoLRGB("));
+    assert!(!ispc.contains("SRGBtoLAB"));
+
+    // kernels without helpers only contain generated code
+    let y = InputType::Buffer {
+        x: 16,
+        y: 1,
+        z: 1,
+        cs: ColorSpace::Y,
+    };
+    let plain = program.specialize("plain", &[y], Target::Ocl).unwrap();
+    assert!(!plain.contains("inline"));
+}
+
+#[test]
+fn custom_builtins() {
+    let source = "
+function f(x)
+  return x
+end
+
+kernel k(O, a)
+  const x = get_global_id(0)
+  O[x] = gain(a, 2)
+end
+
+kernel plain(O)
+  O[0] = 1.0
+end
+";
+    let program = Program::parse(source).unwrap();
+    let y = InputType::Buffer {
+        x: 16,
+        y: 16,
+        z: 1,
+        cs: ColorSpace::Y,
+    };
+    let gain = Builtin {
+        name: "gain".into(),
+        args: vec![ValueType::Float, ValueType::Float],
+        ret: ValueType::Float,
+        ocl: "gain_cl".into(),
+        ispc: "gain_ispc".into(),
+        ocl_source: Some("float gain_cl(float a, float b) { return a*b; }".into()),
+        ispc_source: None,
+    };
+
+    // unknown before registration
+    assert!(program
+        .specialize("k", &[y, InputType::Float], Target::Ocl)
+        .is_err());
+    program.add_builtin(gain.clone()).unwrap();
+
+    // int arguments are promoted, sources are included before the kernel
+    let ocl = program
+        .specialize("k", &[y, InputType::Int], Target::Ocl)
+        .unwrap();
+    assert!(ocl.starts_with("float gain_cl(float a, float b) { return a*b; }\n"));
+    assert!(ocl.contains("gain_cl((float)(a), (float)(2))"));
+    let ispc = program
+        .specialize("k", &[y, InputType::Float], Target::Ispc)
+        .unwrap();
+    assert!(ispc.contains("gain_ispc(a, (float)(2))"));
+    assert!(!ispc.contains("return a*b;"));
+
+    // unused builtins are not included
+    let plain = program.specialize("plain", &[y], Target::Ocl).unwrap();
+    assert!(!plain.contains("gain_cl"));
+
+    // no matching signature
+    assert!(program
+        .specialize("k", &[y, InputType::Vec], Target::Ocl)
+        .is_err());
+
+    // registering again replaces the builtin and invalidates cached kernels
+    program
+        .add_builtin(Builtin {
+            ocl: "gain2".into(),
+            ocl_source: None,
+            ..gain.clone()
+        })
+        .unwrap();
+    let ocl = program
+        .specialize("k", &[y, InputType::Int], Target::Ocl)
+        .unwrap();
+    assert!(ocl.contains("gain2("));
+    assert!(!ocl.contains("gain_cl"));
+
+    // invalid registrations
+    let invalid = |name: &str, args| Builtin {
+        name: name.into(),
+        args,
+        ..gain.clone()
+    };
+    assert!(program.add_builtin(invalid("f", vec![])).is_err());
+    assert!(program.add_builtin(invalid("k", vec![])).is_err());
+    assert!(program.add_builtin(invalid("1x", vec![])).is_err());
+    assert!(program
+        .add_builtin(invalid("g", vec![ValueType::Void]))
+        .is_err());
+
+    // registration through the FFI
+    let source = CString::new(source).unwrap();
+    let t = translator_new_ispc(source.as_ptr());
+    let s = |s: &str| CString::new(s).unwrap();
+    let (name, args, ret, ocl, ispc) = (s("gain"), s("float, float"), s("float"), s("g"), s("g"));
+    let add = |args: &CString, ret: &CString| {
+        translator_add_builtin(
+            t,
+            name.as_ptr(),
+            args.as_ptr(),
+            ret.as_ptr(),
+            ocl.as_ptr(),
+            ispc.as_ptr(),
+            ptr::null(),
+            ptr::null(),
+        )
+    };
+    assert_eq!(add(&args, &s("matrix")), IVY_ERROR_ARGUMENT);
+    assert_eq!(add(&s("float, string"), &ret), IVY_ERROR_ARGUMENT);
+    assert_eq!(add(&args, &ret), IVY_OK);
+
+    assert_eq!(translator_add_buffer_y(t, 16, 16, 1), IVY_OK);
+    assert_eq!(translator_add_float(t), IVY_OK);
+    let mut out = ptr::null_mut();
+    let kernel = s("k");
+    assert_eq!(translator_generate(t, kernel.as_ptr(), &mut out), IVY_OK);
+    let generated = unsafe { CStr::from_ptr(out) }.to_str().unwrap();
+    assert!(generated.contains("g(a, (float)(2))"));
+    translator_string_free(out);
+    translator_free(t);
+}
+
+#[test]
+fn generic_functions() {
+    let source = "
+-- one source for 1 and 3 channel buffers, branches on the channel count are chosen when specializing
+function blur(I, x, y)
+  if I.z == 3 then
+    var h = vec_array(3)
+    for i = 0, 2 do
+      h[i] = I[x + i - 1, y]
+    end
+    return (h[0] + h[1] + h[2])/3
+  elseif I.z == 1 then
+    var h = array(3)
+    for i = 0, 2 do
+      h[i] = I[x + i - 1, y]
+    end
+    return (h[0] + h[1] + h[2])/3
+  else
+    return I[x, y, 0] + undefined
+  end
+end
+
+kernel k(I, O)
+  const x = get_global_id(0)
+  const y = get_global_id(1)
+  var z = 0
+  if O.z == 3 then z = 1 end
+  O[x, y] = blur(I, x, y)
+end
+";
+    let program = Program::parse(source).unwrap();
+    let buffer = |z, cs| InputType::Buffer {
+        x: 16,
+        y: 16,
+        z,
+        cs,
+    };
+    let rgb = buffer(3, ColorSpace::Lrgb);
+    let y = buffer(1, ColorSpace::Y);
+
+    // the channel count is a literal and only the selected branch is type checked and generated
+    let id = function_id("blur", &[LRGB, VarType::Int, VarType::Int]);
+    let ocl = program.specialize("k", &[rgb, rgb], Target::Ocl).unwrap();
+    assert!(ocl.contains(&format!("float3 {} (", id)));
+    assert!(ocl.contains("if (true) {\nfloat3 h [3];"));
+    assert!(!ocl.contains("float h [3]"));
+    assert!(ocl.contains("if (true) {\nz = 1;\n}"));
+
+    let id = function_id("blur", &[Y, VarType::Int, VarType::Int]);
+    let ocl = program.specialize("k", &[y, y], Target::Ocl).unwrap();
+    assert!(ocl.contains(&format!("float {} (", id)));
+    assert!(ocl.contains("if (true) {\nfloat h [3];"));
+    assert!(!ocl.contains("float3"));
+    assert!(!ocl.contains("z = 1;"));
+
+    let ispc = program.specialize("k", &[y, rgb], Target::Ispc).unwrap();
+    assert!(ispc.contains("float h [3];"));
+    assert!(ispc.contains("if (true) {\nz = 1;\n}"));
+
+    // the remaining branch is checked for other channel counts
+    let err = program
+        .specialize("k", &[buffer(4, ColorSpace::Y), y], Target::Ocl)
+        .unwrap_err();
+    assert!(err.contains("Unknown"), "{}", err);
+}
+
+#[test]
+fn function_arguments() {
+    let source = "
+function screen(a, b)
+  return 1 - (1 - a)*(1 - b)
+end
+
+function multiply(a, b)
+  return a*b
+end
+
+function mix(a, b, op: fn, f)
+  return a + (op(a, b) - a)*f
+end
+
+kernel blend(A, B, O, op: fn)
+  const x = get_global_id(0)
+  const y = get_global_id(1)
+  O[x, y] = mix(A[x, y], B[x, y], op, 0.5)
+end
+
+kernel darken(A, B, O)
+  const x = get_global_id(0)
+  const y = get_global_id(1)
+  O[x, y] = mix(A[x, y], B[x, y], multiply, 0.5)
+end
+
+function twice(op, x)
+  return op(op, x)
+end
+
+kernel recursive(O, op)
+  O[0, 0] = twice(op, 1.0)
+end
+";
+    let program = Program::parse(source).unwrap();
+    assert!(program.warnings().is_empty(), "{:?}", program.warnings());
+    let lrgb = InputType::Buffer {
+        x: 16,
+        y: 16,
+        z: 3,
+        cs: ColorSpace::Lrgb,
+    };
+    let screen = program.function("screen").unwrap();
+    let multiply = program.function("multiply").unwrap();
+    assert!(program.function("missing").is_err());
+
+    // the function is part of the instance and kernel ids, the argument is not passed
+    let op = |name| VarType::Function(program.lower.function_index(name).unwrap());
+    let mix = function_id(
+        "mix",
+        &[VarType::Vec, VarType::Vec, op("screen"), VarType::Float],
+    );
+    let screen_id = function_id("screen", &[VarType::Vec, VarType::Vec]);
+    for target in [Target::Ocl, Target::Ispc] {
+        let code = program
+            .specialize("blend", &[lrgb, lrgb, lrgb, screen], target)
+            .unwrap();
+        assert!(code.contains(&format!("{} (\n\tfloat", mix)));
+        assert!(code.contains(&format!("return a + ({}(a, b) - a)*f;", screen_id)));
+        assert!(!code.contains("op"));
+    }
+    let kernel = |input: &[InputType]| {
+        let values = input.iter().map(InputType::value).collect::<Vec<_>>();
+        let input = input
+            .iter()
+            .map(|t| t.var_type().unwrap())
+            .collect::<Vec<_>>();
+        kernel_id("blend", &input, &values, Precision::Single)
+    };
+    assert_ne!(
+        kernel(&[lrgb, lrgb, lrgb, screen]),
+        kernel(&[lrgb, lrgb, lrgb, multiply])
+    );
+
+    // functions can be passed by name in the source
+    let ocl = program
+        .specialize("darken", &[lrgb, lrgb, lrgb], Target::Ocl)
+        .unwrap();
+    let mix = function_id(
+        "mix",
+        &[VarType::Vec, VarType::Vec, op("multiply"), VarType::Float],
+    );
+    assert!(ocl.contains(&format!("{}((float3)", mix)));
+
+    let err = program
+        .specialize("blend", &[lrgb, lrgb, lrgb, InputType::Float], Target::Ocl)
+        .unwrap_err();
+    assert!(err.contains("expected type 'fn', found 'Float'"), "{}", err);
+    let twice = program.function("twice").unwrap();
+    let err = program
+        .specialize("recursive", &[lrgb, twice], Target::Ocl)
+        .unwrap_err();
+    assert!(err.contains("Recursive function calls are not supported"));
+
+    let source = CString::new(source).unwrap();
+    let t = translator_new_ocl(source.as_ptr());
+    let name = CString::new("screen").unwrap();
+    let missing = CString::new("missing").unwrap();
+    assert_eq!(translator_add_function(t, name.as_ptr()), IVY_OK);
+    assert_eq!(
+        translator_add_function(t, missing.as_ptr()),
+        IVY_ERROR_ARGUMENT
+    );
+    translator_free(t);
+}
+
+#[test]
+fn structs() {
+    let source = "
+struct Pair
+  weight: float,
+  value: vec
+end
+
+struct Stats
+  n: int
+  mean: float
+  best: Pair -- nested structs are declared first
+end
+
+function update(s: Stats, x)
+  var r = s
+  r.n = r.n + 1
+  r.mean = r.mean + (x - r.mean)/r.n
+  return r
+end
+
+function total(p)
+  var w = 0.0
+  for i = 0, 8 do
+    w = w + p[i].weight
+  end
+  return w
+end
+
+kernel k(I, O)
+  const x = get_global_id(0)
+  const y = get_global_id(1)
+  var s = Stats(0, 0.0, Pair(0.0, vec(0)))
+  var p = struct_array(Pair, 9)
+  for i = 0, 8 do
+    p[i] = Pair(1, I[x + i - 4, y])
+    s = update(s, p[i].weight)
+  end
+  p[0].weight = 2.0
+  s.best.weight = total(p)
+  O[x, y] = s.best.value*s.mean
+end
+
+kernel fields(O)
+  var s = Stats(0, 0.0)
+  O[0, 0] = s.sum
+end
+
+kernel assign(O)
+  var s = Pair(0.0, vec(0))
+  s = 1.0
+  O[0, 0] = s.value
+end
+";
+    let program = Program::parse(source).unwrap();
+    assert!(program.warnings().is_empty(), "{:?}", program.warnings());
+    let lrgb = InputType::Buffer {
+        x: 16,
+        y: 16,
+        z: 3,
+        cs: ColorSpace::Lrgb,
+    };
+
+    let ocl = program.specialize("k", &[lrgb, lrgb], Target::Ocl).unwrap();
+    let pair = ocl.find("} Pair;").unwrap();
+    let stats = ocl.find("} Stats;").unwrap();
+    assert!(pair < stats);
+    assert!(ocl.contains("typedef struct {\n\tint n;\n\tfloat mean;\n\tPair best;\n} Stats;"));
+    assert!(ocl.contains("Pair ___new_Pair (float weight, float3 value) {"));
+    assert!(ocl
+        .contains("Stats s = ___new_Stats(0, 0.0000000f, ___new_Pair(0.0000000f, (float3)(0)));"));
+    assert!(ocl.contains("Pair p [9];"));
+    assert!(ocl.contains("p[i] = ___new_Pair((float)(1), (float3)("));
+    assert!(ocl.contains("p[0].weight = 2.0000000f;"));
+    assert!(ocl.contains("w = w + p[i].weight;"));
+    assert!(ocl.contains("float3 __v = s.best.value*s.mean;"));
+
+    let update = function_id("update", &[VarType::Struct(1), VarType::Float]);
+    assert!(ocl.contains(&format!("Stats {} (\n\tStats s,\n\tfloat x\n)", update)));
+
+    let ispc = program
+        .specialize("k", &[lrgb, lrgb], Target::Ispc)
+        .unwrap();
+    assert!(ispc.contains("struct Stats {\n\tint n;\n\tfloat mean;\n\tPair best;\n};"));
+    assert!(ispc.contains("inline Pair ___new_Pair (float weight, float<3> value) {"));
+    assert!(ispc.contains("Pair p [9];"));
+    assert!(ispc.contains("\tPair p[9]\n"));
+
+    let err = program
+        .specialize("fields", &[lrgb], Target::Ocl)
+        .unwrap_err();
+    assert!(
+        err.contains("Struct 'Stats' has 3 fields, found 2 values"),
+        "{}",
+        err
+    );
+    let err = program
+        .specialize("assign", &[lrgb], Target::Ocl)
+        .unwrap_err();
+    assert!(
+        err.contains("Can not assign value of type 'Float'"),
+        "{}",
+        err
+    );
+
+    let errors = |source: &str| match Program::parse(source) {
+        Err(e) => e,
+        Ok(_) => panic!("expected errors"),
+    };
+    let err = errors("struct P\n  x: float\nend");
+    assert!(
+        err.contains("clashes with a channel or property name"),
+        "{}",
+        err
+    );
+    let err = errors("struct P\n  u: Q\nend");
+    assert!(err.contains("Unknown type 'Q'"), "{}", err);
+    let err = errors("struct P\n  u: Q\nend\nstruct Q\n  v: P\nend");
+    assert!(
+        err.contains("Recursive struct definition: P -> Q -> P"),
+        "{}",
+        err
+    );
+    let err = errors("struct P\n  u: float\n  u: int\nend");
+    assert!(err.contains("Duplicate field 'u' in struct 'P'"), "{}", err);
+    let err = errors("struct P\n  v: buffer\nend");
+    assert!(err.contains("can not be of type"), "{}", err);
+}
+
+#[test]
+fn multiple_returns() {
+    let source = "
+struct Pair
+  weight: float
+  value: vec
+end
+
+function grad(I, x, y)
+  const dx = I[x + 1, y] - I[x - 1, y]
+  const dy = I[x, y + 1] - I[x, y - 1]
+  if x == 0 then
+    return 0, 0, Pair(0, vec(0))
+  end
+  return dx, sqrt(dx^2 + dy^2), Pair(1, vec(dx, dy, 0))
+end
+
+kernel k(I, O)
+  const x = get_global_id(0)
+  const y = get_global_id(1)
+  var g, m, p = grad(I, x, y)
+  O[x, y] = g*m*p.weight
+end
+
+kernel single(I, O)
+  O[0, 0] = grad(I, 0, 0)
+end
+
+kernel count(I, O)
+  var g, m = grad(I, 0, 0)
+  O[0, 0] = g*m
+end
+
+function mismatch(x)
+  if x > 0 then
+    return x
+  end
+  return x, x
+end
+
+kernel inconsistent(O)
+  var a, b = mismatch(1.0)
+  O[0, 0] = a*b
+end
+";
+    let program = Program::parse(source).unwrap();
+    assert!(program.warnings().is_empty(), "{:?}", program.warnings());
+    let y = InputType::Buffer {
+        x: 16,
+        y: 16,
+        z: 1,
+        cs: ColorSpace::Y,
+    };
+
+    // additional values are written through out-parameters, their types are promoted across returns
+    let grad = function_id("grad", &[Y, VarType::Int, VarType::Int]);
+    let ocl = program.specialize("k", &[y, y], Target::Ocl).unwrap();
+    assert!(ocl.contains(&format!("float {} (", grad)));
+    assert!(ocl.contains("\tfloat *___ret1,\n\tPair *___ret2\n)"));
+    assert!(
+        ocl.contains("*___ret1 = 0;\n*___ret2 = ___new_Pair((float)(0), (float3)(0));\nreturn 0;")
+    );
+    assert!(ocl.contains("*___ret1 = sqrt("));
+    assert!(ocl.contains(&format!(
+        "float m;\nPair p;\nfloat g = {}(I, ___str_I, x, y, &m, &p);",
+        grad
+    )));
+
+    let ispc = program.specialize("k", &[y, y], Target::Ispc).unwrap();
+    assert!(ispc.contains("\tfloat &___ret1,\n\tPair &___ret2\n)"));
+    assert!(ispc.contains("___ret1 = sqrt("));
+    assert!(ispc.contains(&format!(
+        "float m;\nPair p;\nfloat g = {}(I, ___str_I, x, y, m, p);",
+        grad
+    )));
+
+    let err = program
+        .specialize("single", &[y, y], Target::Ocl)
+        .unwrap_err();
+    assert!(err.contains("Function 'grad' returns 3 values"), "{}", err);
+    let err = program
+        .specialize("count", &[y, y], Target::Ocl)
+        .unwrap_err();
+    assert!(err.contains("found 2 variables"), "{}", err);
+    let err = program
+        .specialize("inconsistent", &[y], Target::Ocl)
+        .unwrap_err();
+    assert!(
+        err.contains("inconsistent with previously returned 1 values"),
+        "{}",
+        err
+    );
+
+    let tokens = Scanner::new("const a, b = f()\n".into()).scan().unwrap();
+    assert!(!Parser::new(tokens).parse().1.is_empty());
+}
+
+#[test]
+fn matrices() {
+    let source = "
+function adapt(m: mat3, c)
+  return inverse(m)*c
+end
+
+kernel convert(I, O, M)
+  const x = get_global_id(0)
+  const y = get_global_id(1)
+  var b = mat3(
+    0.8951, 0.2664, -0.1614,
+    -0.7502, 1.7135, 0.0367,
+    0.0389, -0.0685, 1.0296)
+  var s = mat3(vec(2.0, 1.0, 1.0))
+  O[x, y] = adapt(b, s*b*I[x, y])
+  O[x, y] = transpose(mat3(I[0, 0], I[1, 0], I[2, 0]))*M*O[x, y]
+end
+
+kernel scaled(O)
+  var m = mat3(1)
+  m = m*2
+end
+
+kernel assigned(O)
+  var m = mat3(1)
+  m = vec(1)
+end
+";
+    let program = Program::parse(source).unwrap();
+    let lrgb = InputType::Buffer {
+        x: 16,
+        y: 16,
+        z: 3,
+        cs: ColorSpace::Lrgb,
+    };
+
+    // products of matrices and vectors call the matrix helpers of the library
+    let ocl = program
+        .specialize("convert", &[lrgb, lrgb, InputType::Mat3], Target::Ocl)
+        .unwrap();
+    assert!(ocl.contains("typedef struct {\n  float3 x, y, z;\n} mat3;"));
+    assert!(ocl.contains("\tmat3 M\n)"));
+    assert!(ocl.contains("mat3 b = _mat3(0.8951000f, 0.2664000f, (-0.1614000f),"));
+    assert!(ocl.contains("mat3 s = _mat3_diag((float3)(2.0000000f, 1.0000000f, 1.0000000f));"));
+    assert!(ocl.contains("_mat3_mul_vec(_mat3_mul(s, b), "));
+    assert!(ocl.contains("_mat3_mul_vec(_mat3_inverse(m), c)"));
+    assert!(ocl.contains("_mat3_mul_vec(_mat3_mul(_mat3_transpose(_mat3_rows("));
+    assert!(ocl.contains("inline mat3 _mat3_inverse(mat3 m) {"));
+
+    // uniform kernel arguments are passed as rows padded to 4 floats
+    let ispc = program
+        .specialize("convert", &[lrgb, lrgb, InputType::Mat3], Target::Ispc)
+        .unwrap();
+    assert!(ispc.contains("typedef float<9> mat3;"));
+    assert!(ispc.contains("\tuniform float ___mat3_M[12]\n"));
+    assert!(ispc.contains(
+        "mat3 M = {___mat3_M[0], ___mat3_M[1], ___mat3_M[2], ___mat3_M[4], ___mat3_M[5], \
+         ___mat3_M[6], ___mat3_M[8], ___mat3_M[9], ___mat3_M[10]};"
+    ));
+    assert!(ispc.contains("mat3 s = _mat3_diag(vec(2.0000000f, 1.0000000f, 1.0000000f));"));
+    assert!(ispc.contains("_mat3_mul_vec(_mat3_inverse(m), c)"));
+
+    // matrices only multiply vectors and matrices and are only assigned from matrices
+    let err = program
+        .specialize("scaled", &[lrgb], Target::Ocl)
+        .unwrap_err();
+    assert!(err.contains("'Mat3' and 'Int'"), "{}", err);
+    let err = program
+        .specialize("assigned", &[lrgb], Target::Ocl)
+        .unwrap_err();
+    assert!(
+        err.contains("Can not assign value of type 'Vec' to target of type 'Mat3'"),
+        "{}",
+        err
+    );
+    let err = Program::parse("const m = mat3(1)\nkernel k(O)\nO[0, 0] = 1\nend\n")
+        .unwrap()
+        .specialize("k", &[lrgb], Target::Ocl)
+        .unwrap_err();
+    assert!(err.contains("Constant 'm' can not be a matrix"), "{}", err);
+
+    let source = CString::new(source).unwrap();
+    let t = translator_new_ispc(source.as_ptr());
+    assert_eq!(translator_add_buffer_lrgb(t, 16, 16, 3), IVY_OK);
+    assert_eq!(translator_add_buffer_lrgb(t, 16, 16, 3), IVY_OK);
+    assert_eq!(translator_add_mat3(t), IVY_OK);
+    let mut out = ptr::null_mut();
+    let kernel = CString::new("convert").unwrap();
+    assert_eq!(translator_generate(t, kernel.as_ptr(), &mut out), IVY_OK);
+    let source = unsafe { CStr::from_ptr(out) }.to_str().unwrap();
+    assert!(source.contains("uniform float ___mat3_M[12]"));
+    translator_string_free(out);
+    translator_free(t);
+}
+
+#[test]
+fn double_precision() {
+    let source = "
+kernel k(I, O, f, v)
+  const x = get_global_id(0)
+  const y = get_global_id(1)
+  var s = 0.5*f
+  var l = I[x, y].LAB
+  O[x, y] = l*v + s
+  O[x, y, 0] = 1/x + sqrt(s)
+end
+
+kernel m(I, O, M)
+  const x = get_global_id(0)
+  const y = get_global_id(1)
+  var a = mat3(2.0)
+  O[x, y] = inverse(M*a)*transpose(a)*I[x, y]
+end
+";
+    let program = Program::parse(source).unwrap();
+    let srgb = InputType::Buffer {
+        x: 16,
+        y: 16,
+        z: 3,
+        cs: ColorSpace::Srgb,
+    };
+    let inputs = [srgb, srgb, InputType::Float, InputType::Vec];
+    let single = program.specialize("k", &inputs, Target::Ocl).unwrap();
+
+    // locals, literals and kernel arguments are double, buffers remain single precision
+    let ocl = program
+        .specialize_with("k", &inputs, Target::Ocl, Precision::Double)
+        .unwrap();
+    assert!(ocl.starts_with("#pragma OPENCL EXTENSION cl_khr_fp64 : enable\n"));
+    assert!(ocl.contains("\tglobal float *I, global int *___str_I,\n"));
+    assert!(ocl.contains("\tfloat ___float_f,\n\tfloat3 ___vec_v\n"));
+    assert!(ocl.contains("double f = (double)(___float_f);\n"));
+    assert!(ocl.contains("double3 v = convert_double3(___vec_v);\n"));
+    assert!(ocl.contains("double s = 5e-1*f;\n"));
+    assert!(ocl.contains("double3 l = convert_double3(SRGBtoLAB(convert_float3((double3)("));
+    assert!(ocl.contains("{ double3 __v = l*v + s;"));
+    assert!(ocl.contains("((double)1)/x + sqrt(s)"));
+
+    let ispc = program
+        .specialize_with("k", &inputs, Target::Ispc, Precision::Double)
+        .unwrap();
+    assert!(ispc.contains("inline float<3> _vec_float(double<3> v)"));
+    assert!(ispc.contains("\tuniform float ___float_f,\n"));
+    assert!(ispc.contains("uniform double f = ___float_f;\n"));
+    assert!(ispc.contains("double<3> v = {___vec_v[0], ___vec_v[1], ___vec_v[2]};\n"));
+    assert!(ispc.contains("s = 5d-1*f;\n"));
+    assert!(ispc.contains("l = _vec_double(SRGBtoLAB(_vec_float(_dvec("));
+    assert!(ispc.contains("I, ___str_I, O, ___str_O, ___float_f, ___vec_v);"));
+
+    // single precision kernels are cached separately
+    assert_eq!(
+        program.specialize("k", &inputs, Target::Ocl).unwrap(),
+        single
+    );
+
+    // precision is part of the kernel id
+    let buffer = VarType::Buffer {
+        z: 3,
+        cs: ColorSpace::Srgb,
+        x1y1: false,
+    };
+    let ty = [buffer, buffer, VarType::Float, VarType::Vec];
+    let ids = [
+        kernel_id("k", &ty, &[], Precision::Single),
+        kernel_id("k", &ty, &[], Precision::Double),
+    ];
+    assert_ne!(ids[0], ids[1]);
+    assert_eq!(ids[0], function_id("k", &ty));
+    let kernels = [("k", &inputs[..])];
+    let unit = program
+        .specialize_unit(&kernels, Target::Ocl, Precision::Double)
+        .unwrap();
+    assert_eq!(unit.manifest(), format!("k {}\n", ids[1]));
+    assert!(unit.source.contains(&format!("kernel void {} (", ids[1])));
+    let results = program.specialize_all(&kernels, Target::Ocl, Precision::Double);
+    assert_eq!(results[0].as_ref().unwrap(), &ocl);
+
+    // matrices are double precision, their arguments are converted once
+    let inputs = [srgb, srgb, InputType::Mat3];
+    let matrix = program
+        .specialize_with("m", &inputs, Target::Ocl, Precision::Double)
+        .unwrap();
+    assert!(matrix.contains("typedef struct {\n  double3 x, y, z;\n} dmat3;"));
+    assert!(matrix.contains("\tmat3 ___mat3_M\n"));
+    assert!(matrix.contains("dmat3 M = _mat3_double(___mat3_M);\n"));
+    assert!(matrix.contains("dmat3 a = _dmat3_diag((double3)(2e0));\n"));
+    assert!(matrix.contains(
+        "double3 __v = _dmat3_mul_vec(_dmat3_mul(_dmat3_inverse(_dmat3_mul(M, a)), _dmat3_transpose(a)), (double3)("
+    ));
+    assert!(!matrix.contains("convert_float3"));
+    let matrix = program
+        .specialize_with("m", &inputs, Target::Ispc, Precision::Double)
+        .unwrap();
+    assert!(matrix.contains("typedef double<9> dmat3;"));
+    assert!(matrix.contains("\tuniform float ___mat3_M[12]\n"));
+    assert!(matrix.contains("dmat3 M = {___mat3_M[0], ___mat3_M[1], ___mat3_M[2], ___mat3_M[4],"));
+    assert!(matrix.contains("dmat3 a = _dmat3_diag(_dvec(2d0));\n"));
+    assert!(matrix.contains("_dmat3_mul_vec(_dmat3_mul(_dmat3_inverse(_dmat3_mul(M, a)),"));
+    assert!(!matrix.contains("_vec_float"));
+
+    // precision is set per translator and copied to its clones
+    let source = CString::new(source).unwrap();
+    let t = translator_new_ocl(source.as_ptr());
+    assert_eq!(translator_set_double(t, true), IVY_OK);
+    let c = translator_clone(t);
+    assert_eq!(translator_set_double(t, false), IVY_OK);
+    let kernel = CString::new("k").unwrap();
+    for (t, expected, id) in [(t, &single, &ids[0]), (c, &ocl, &ids[1])] {
+        assert_eq!(translator_add_buffer_srgb(t, 16, 16, 3), IVY_OK);
+        assert_eq!(translator_add_buffer_srgb(t, 16, 16, 3), IVY_OK);
+        assert_eq!(translator_add_float(t), IVY_OK);
+        assert_eq!(translator_add_vec(t), IVY_OK);
+        let mut out = ptr::null_mut();
+        assert_eq!(translator_get_id(t, kernel.as_ptr(), &mut out), IVY_OK);
+        assert_eq!(unsafe { CStr::from_ptr(out) }.to_str().unwrap(), id);
+        translator_string_free(out);
+        let mut out = ptr::null_mut();
+        assert_eq!(translator_generate(t, kernel.as_ptr(), &mut out), IVY_OK);
+        let generated = unsafe { CStr::from_ptr(out) }.to_str().unwrap();
+        assert_eq!(generated, expected);
+        translator_string_free(out);
+        translator_free(t);
+    }
+}
+
+#[test]
+fn random() {
+    use crate::random::{philox4x32, u01, Stream};
+
+    // known answers of philox4x32-10 from Random123
+    assert_eq!(
+        philox4x32([0; 4], [0; 2]),
+        [0x6627e8d5, 0xe169c58d, 0xbc57ac4c, 0x9b00dbd8]
+    );
+    assert_eq!(
+        philox4x32([u32::MAX; 4], [u32::MAX; 2]),
+        [0x408f276d, 0x41c83b0e, 0xa20bc7c6, 0x6d5451fd]
+    );
+    assert_eq!(
+        philox4x32(
+            [0x243f6a88, 0x85a308d3, 0x13198a2e, 0x03707344],
+            [0xa4093822, 0x299f31d0]
+        ),
+        [0xd16cfe09, 0x94fdcceb, 0x5001e420, 0x24126ea1]
+    );
+
+    // uniforms are exact and below 1
+    assert_eq!(u01(0), 0.0);
+    assert_eq!(u01(0x100), 1.0 / 16777216.0);
+    assert!(u01(u32::MAX) < 1.0);
+
+    // runif, rnorm, rpois(4.5), runif drawn from one stream with z = 2 and y = 7, as produced by
+    // the reference in random.rs, see random_cl for the streams of random.cl
+    let expected = [
+        (0, 0, [0x3f769784, 0x3f0afb15, 0x40e00000, 0x3f1bb9d1], 11),
+        (0, 1, [0x3f7211d4, 0xbeccc36c, 0x40000000, 0x3f205609], 6),
+        (
+            0x12345,
+            0,
+            [0x3e98b0c0, 0x3e6466d0, 0x40000000, 0x3e81b94a],
+            6,
+        ),
+        (
+            0x2468a,
+            2,
+            [0x3f79e0ab, 0xbe9f6835, 0x40a00000, 0x3d492c50],
+            9,
+        ),
+    ];
+    for (seed, x, bits, n) in expected {
+        let mut stream = Stream::new(seed, x, 7, 2);
+        let a = stream.runif();
+        let b = stream.rnorm();
+        let c = stream.rpois_small(4.5);
+        let d = stream.runif();
+        assert_eq!(a.to_bits(), bits[0]);
+        assert!((b - f32::from_bits(bits[1])).abs() < 1e-6);
+        assert_eq!(c.to_bits(), bits[2]);
+        assert_eq!(d.to_bits(), bits[3]);
+        assert_eq!(stream.n, n);
+    }
+
+    // draws without explicit indices are keyed on the global index and counted per work item
+    let source = "
+function grain(seed, v)
+  return v + rnorm(seed)*0.1
+end
+
+function plain(v)
+  return v*2
+end
+
+kernel noise(O, seed)
+  const x = get_global_id(0)
+  const y = get_global_id(1)
+  var a = runif(seed) + rpois(seed, 4.5)
+  O[x, y] = grain(seed, a) + plain(a)
+end
+
+kernel fixed(O, seed)
+  const x = get_global_id(0)
+  O[x] = runif(seed, x, 0)
+end
+";
+    let program = Program::parse(source).unwrap();
+    let input = [
+        InputType::Buffer {
+            x: 16,
+            y: 16,
+            z: 1,
+            cs: ColorSpace::Y,
+        },
+        InputType::Int,
+    ];
+
+    let ocl = program.specialize("noise", &input, Target::Ocl).unwrap();
+    assert!(ocl.contains("uint ___rng_n = 0;\nuint *_rng = &___rng_n;\n"));
+    assert!(
+        ocl.contains("_runif(seed, get_global_id(0), get_global_id(1), get_global_id(2), _rng)")
+    );
+    assert!(ocl.contains(
+        "_rpois(seed, get_global_id(0), get_global_id(1), get_global_id(2), _rng, 4.5000000f)"
+    ));
+    assert!(ocl.contains("float ___2_I_F___grain (\n\tuint *_rng,\n\tint seed,"));
+    assert!(ocl.contains("___2_I_F___grain(_rng, seed, a) + ___1_F___plain(a)"));
+    assert!(ocl.contains("_philox_ctr _rng_block("));
+    assert!(!ocl.contains("float runif("));
+
+    let ispc = program.specialize("noise", &input, Target::Ispc).unwrap();
+    assert!(ispc.contains("unsigned int32 * uniform _rng = &___rng_n;\n"));
+    assert!(ispc.contains("_runif(seed, _x, _y, _z, _rng)"));
+    assert!(ispc.contains(
+        "float ___2_I_F___grain (varying int _x, varying int _y, varying int _z, unsigned int32 * uniform _rng, "
+    ));
+    assert!(ispc.contains("___2_I_F___grain(_x, _y, _z, _rng, seed, a) + ___1_F___plain(a)"));
+    assert!(!ispc.contains("seed_rng"));
+    assert!(!ispc.contains("frandom"));
+
+    // explicit indices select a single draw, the kernel keeps no counter
+    for target in [Target::Ocl, Target::Ispc] {
+        let fixed = program.specialize("fixed", &input, target).unwrap();
+        assert!(fixed.contains("runif(seed, x, 0)"));
+        assert!(!fixed.contains("___rng_n"));
+    }
+
+    // seeds are integers
+    let err = Program::parse("kernel k(O)\n  O[0] = runif(0.5)\nend\n")
+        .unwrap()
+        .specialize("k", &input[..1], Target::Ocl)
+        .unwrap_err();
+    assert!(err.contains("runif"), "{}", err);
+}
+
+// random.cl is plain C apart from its integer types, it is compiled with the host C compiler
+// and its streams must match the reference in random.rs bit for bit
+#[test]
+fn random_cl() {
+    use crate::random::{philox4x32, Stream};
+    use std::process::Command;
+
+    let main = r#"
+int main(int argc, char **argv) {
+  for (int i = 1; i + 3 < argc; i += 4) {
+    uint seed = strtoul(argv[i], 0, 0), x = strtoul(argv[i + 1], 0, 0),
+         y = strtoul(argv[i + 2], 0, 0), z = strtoul(argv[i + 3], 0, 0);
+    _philox_ctr ctr = {x, y, z, 0};
+    _philox_ctr b = _philox(ctr, seed, 0);
+    uint n = 0;
+    float f[4] = {_runif(seed, x, y, z, &n), _rnorm(seed, x, y, z, &n),
+                  _poisson_small(seed, x, y, z, &n, 4.5f), _runif(seed, x, y, z, &n)};
+    uint u[4];
+    memcpy(u, f, sizeof(u));
+    printf("%08x %08x %08x %08x %08x %08x %08x %08x %u\n", b.a, b.b, b.c, b.d, u[0], u[1], u[2],
+           u[3], n);
+  }
+  return 0;
+}
+"#;
+    // float arguments select the single precision math functions, as used by the reference
+    let source = format!(
+        "#include <stdio.h>\n#include <stdlib.h>\n#include <string.h>\n#include <tgmath.h>\n\
+         #define uint unsigned int\n#define ulong unsigned long long\n#define inline static inline\n{}{}",
+        include_str!("../../../ops/include/random.cl"),
+        main
+    );
+
+    let dir = std::env::temp_dir().join(format!("ivyscript-random-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let c = dir.join("random.c");
+    let exe = dir.join("random");
+    std::fs::write(&c, source).unwrap();
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".into());
+    let build = Command::new(&cc)
+        .args(["-std=c11", "-O2", "-o"])
+        .arg(&exe)
+        .arg(&c)
+        .arg("-lm")
+        .output();
+    let build = match build {
+        Ok(build) => build,
+        Err(err) => {
+            // no host C compiler, nothing to compare
+            eprintln!("random_cl skipped, unable to run '{}': {}", cc, err);
+            std::fs::remove_dir_all(&dir).unwrap();
+            return;
+        }
+    };
+    assert!(
+        build.status.success(),
+        "{}",
+        String::from_utf8_lossy(&build.stderr)
+    );
+
+    let tuples = [
+        [0, 0, 0, 0],
+        [0, 0, 7, 2],
+        [0x12345, 1, 7, 2],
+        [0x2468a, 2, 7, 2],
+        [42, 1919, 1079, 0],
+        [u32::MAX, u32::MAX, u32::MAX, u32::MAX],
+        [0xdeadbeef, 3, 5, 1],
+        [7, 640, 480, 2],
+    ];
+    let run = Command::new(&exe)
+        .args(tuples.iter().flatten().map(|v| v.to_string()))
+        .output()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(run.status.success());
+
+    let output = String::from_utf8(run.stdout).unwrap();
+    let lines = output.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), tuples.len());
+    for (&[seed, x, y, z], line) in tuples.iter().zip(lines) {
+        let b = philox4x32([x, y, z, 0], [seed, 0]);
+        let mut stream = Stream::new(seed, x, y, z);
+        let f = [
+            stream.runif(),
+            stream.rnorm(),
+            stream.rpois_small(4.5),
+            stream.runif(),
+        ];
+        let expected = format!(
+            "{:08x} {:08x} {:08x} {:08x} {:08x} {:08x} {:08x} {:08x} {}",
+            b[0],
+            b[1],
+            b[2],
+            b[3],
+            f[0].to_bits(),
+            f[1].to_bits(),
+            f[2].to_bits(),
+            f[3].to_bits(),
+            stream.n
+        );
+        assert_eq!(line, expected, "seed {}, ({}, {}, {})", seed, x, y, z);
+    }
+}
+
+#[test]
+fn reductions() {
+    let source = "
+kernel stats(I, O, H)
+  const x = get_global_id(0)
+  const y = get_global_id(1)
+  const z = get_global_id(2)
+  var v = I[x, y, z]
+  reduce_sum(v, O[z].ptr)
+  reduce_min(v, O[3 + z].ptr)
+  histogram(clamp(int(v*255), 0, 255), H)
+end
+
+kernel plain(I, O, H)
+  O[0] = I[0]
+end
+
+function f(v, O)
+  reduce_max(v, O)
+end
+
+kernel nested(I, O, H)
+  f(I[0], O)
+end
+
+kernel output(I, O, H)
+  reduce_sum(I[0], 1.0)
+end
+
+kernel local(I, O, H)
+  var l = local_array(16)
+  reduce_sum(I[0], l[0].ptr)
+end
+
+kernel bin(I, O, H)
+  histogram(I[0], H)
+end
+";
+    let program = Program::parse(source).unwrap();
+    let y = InputType::Buffer {
+        x: 16,
+        y: 16,
+        z: 1,
+        cs: ColorSpace::Y,
+    };
+    let input = [y, y, y];
+
+    // local reduction over the work group, the output is passed as base pointer and element index
+    let ocl = program.specialize("stats", &input, Target::Ocl).unwrap();
+    assert!(ocl.contains("local float ___scratch[257];\n"));
+    assert!(ocl.contains("_reduce_sum(v, O, clamp((int)(z), 0, (___str_O[0] * ___str_O[1] * ___str_O[2] - 1)), ___scratch);"));
+    assert!(ocl.contains("_reduce_min(v, O, clamp((int)(3 + z), 0,"));
+    assert!(ocl.contains("_histogram(clamp((int)(v*255), 0, 255), H, 0, ___scratch);"));
+    assert!(ocl.contains("void _reduce(int op, float v, global float *out, int i, local float *s)"));
+    assert!(ocl.contains("inline void _atomic_float_min("));
+    assert!(!ocl.contains("_reduce_max("));
+
+    // reduction over the gang with one atomic per target
+    let ispc = program.specialize("stats", &input, Target::Ispc).unwrap();
+    assert!(ispc.contains(
+        "_reduce_sum(v, O, clamp((int)(z), 0, (___str_O[0] * ___str_O[1] * ___str_O[2] - 1)));"
+    ));
+    assert!(ispc.contains("_histogram(clamp((int)(v*255), 0, 255), H, 0);"));
+    assert!(ispc.contains("_atomic_float_add(out + j, reduce_add(v));"));
+    assert!(ispc.contains("_atomic_float_min(out + j, reduce_min(v));"));
+
+    let plain = program.specialize("plain", &input, Target::Ocl).unwrap();
+    assert!(!plain.contains("___scratch"));
+
+    let err = program
+        .specialize("nested", &input, Target::Ocl)
+        .unwrap_err();
+    assert!(
+        err.contains("Builtin 'reduce_max' can only be called in a kernel"),
+        "{}",
+        err
+    );
+    let err = program
+        .specialize("output", &input, Target::Ocl)
+        .unwrap_err();
+    assert!(
+        err.contains("Output of 'reduce_sum' must be a 1-D float array or buffer, found 'Float'"),
+        "{}",
+        err
+    );
+    let err = program
+        .specialize("local", &input, Target::Ocl)
+        .unwrap_err();
+    assert!(
+        err.contains("Output of 'reduce_sum' must be a 1-D float array or buffer"),
+        "{}",
+        err
+    );
+    let err = program.specialize("bin", &input, Target::Ocl).unwrap_err();
+    assert!(
+        err.contains("Expected an 'Int' bin in 'histogram'"),
+        "{}",
+        err
+    );
+
+    // barriers of reductions must be reached by the whole work group
+    let validate = |source: &str| {
+        let tokens = Scanner::new(source.to_string()).scan().unwrap();
+        validate(&Parser::new(tokens).parse().0).unwrap_err()
+    };
+    assert_eq!(
+        validate("kernel k(I, O)\n  if I[0] > 0.5 then\n    reduce_sum(I[0], O)\n  end\nend\n"),
+        [(
+            "Builtin 'reduce_sum' can not be called in a conditional or loop in kernel 'k'".into(),
+            3
+        )]
+    );
+    assert_eq!(
+        validate("kernel k(I, O)\n  for i = 0, 3 do\n    histogram(i, O)\n  end\nend\n"),
+        [(
+            "Builtin 'histogram' can not be called in a conditional or loop in kernel 'k'".into(),
+            3
+        )]
+    );
+    assert_eq!(
+        validate(
+            "kernel k(I, O)\n  const x = get_global_id(0)\n  if x > 3 then\n    return\n  end\n  reduce_max(I[x], O)\nend\n"
+        ),
+        [(
+            "Builtin 'reduce_max' can not be called after a return in kernel 'k'".into(),
+            6
+        )]
+    );
+}
+
+#[test]
+fn ffi() {
+    assert_eq!(translator_abi_version(), ABI_VERSION);
+
+    // invalid input never unwinds across the boundary
+    assert!(translator_new_ocl(ptr::null()).is_null());
+    let invalid = [0xffu8 as i8, 0];
+    assert!(translator_new_ocl(invalid.as_ptr()).is_null());
+    assert_eq!(translator_add_int(ptr::null_mut()), IVY_ERROR_NULL);
+    translator_free(ptr::null_mut());
+    translator_string_free(ptr::null_mut());
+
+    let source = CString::new(SOURCE).unwrap();
+    let t = translator_new_ocl(source.as_ptr());
+    assert!(!t.is_null());
+
+    assert_eq!(translator_add_buffer_lrgb(t, 16, 16, 3), IVY_OK);
+    assert_eq!(translator_add_buffer_lrgb(t, 16, 16, 0), IVY_ERROR_ARGUMENT);
+    assert_eq!(translator_add_buffer_lrgb(t, 16, 16, 3), IVY_OK);
+    assert_eq!(translator_add_float(t), IVY_OK);
+
+    let mut out = ptr::null_mut();
+    let kernel = CString::new("test").unwrap();
+    assert_eq!(translator_generate(t, kernel.as_ptr(), &mut out), IVY_OK);
+    let source = unsafe { CStr::from_ptr(out) }.to_str().unwrap();
+    assert!(source.contains("kernel void test ("));
+    translator_string_free(out);
+
+    assert_eq!(
+        translator_generate(t, invalid.as_ptr(), &mut out),
+        IVY_ERROR_UTF8
+    );
+    assert_eq!(
+        translator_generate(t, kernel.as_ptr(), ptr::null_mut()),
+        IVY_ERROR_NULL
+    );
+
+    // translation errors return the error message
+    assert_eq!(translator_clear_inputs(t), IVY_OK);
+    assert_eq!(
+        translator_generate(t, kernel.as_ptr(), &mut out),
+        IVY_ERROR_TRANSLATE
+    );
+    let err = unsafe { CStr::from_ptr(out) }.to_str().unwrap();
+    assert!(err.contains("expects 3 arguments"));
+    translator_string_free(out);
+
+    // kernels without a traversal annotation leave it to the host
+    let mut size = [-1; 2];
+    assert_eq!(
+        translator_get_traversal(t, kernel.as_ptr(), size.as_mut_ptr()),
+        IVY_OK
+    );
+    assert_eq!(size, [0, 0]);
+    let missing = CString::new("missing").unwrap();
+    assert_eq!(
+        translator_get_traversal(t, missing.as_ptr(), size.as_mut_ptr()),
+        IVY_ERROR_ARGUMENT
+    );
+
+    let code = CString::new("unknown").unwrap();
+    assert_eq!(
+        translator_set_lint(t, code.as_ptr(), false),
+        IVY_ERROR_ARGUMENT
+    );
+
+    // clones share the program but not the inputs
+    let clone = translator_clone(t);
+    assert!(!clone.is_null());
+    assert_eq!(
+        translator_generate(clone, kernel.as_ptr(), &mut out),
+        IVY_ERROR_TRANSLATE
+    );
+    translator_string_free(out);
+    assert!(translator_clone(ptr::null_mut()).is_null());
+
+    translator_free(t);
+    translator_free(clone);
+}
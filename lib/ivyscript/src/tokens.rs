@@ -27,6 +27,8 @@ pub enum TokenType {
     RightBracket,
     Comma,
     Dot,
+    Colon,
+    At,
 
     Minus,
     Plus,
@@ -51,7 +53,7 @@ pub enum TokenType {
     LessEqual,
 
     Identifier(String),
-    Float(f32),
+    Float(f64),
     Int(i32),
     Bool(bool),
 
@@ -70,6 +72,7 @@ pub enum TokenType {
 
     Function,
     Kernel,
+    Struct,
     Return,
     Continue,
     Break,
@@ -0,0 +1,422 @@
+/*
+  Copyright (C) 2011-2021 G. Bajlekov
+
+    Ivy is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Ivy is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+// uniformity analysis for the ISPC target:
+// values derived only from uniform kernel arguments, constants and uniform loop counters are the same
+// for all program instances, variables holding them can be uniform when they are only assigned
+// uniform values in the control flow of their declaration
+//
+// all variables start out uniform and are marked varying until nothing changes,
+// control flow diverges under varying conditions and in loops with a varying break or continue
+
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{BinaryOp, Prop};
+use crate::inference::VarType;
+use crate::ir::{random_stream, BufIdx, Expr, ExprKind, Function, Kernel, Place, Program, Stmt};
+
+// builtins with uniform variants in the ISPC standard library
+const UNIFORM_BUILTINS: &[&str] = &[
+    "bool", "int", "float", "min", "max", "clamp", "abs", "sqrt", "floor", "ceil", "round", "exp",
+    "log", "pow", "sin", "cos", "tan", "asin", "acos", "atan", "atan2",
+];
+
+// results are identified by the address of the analyzed statements
+pub struct Uniformity {
+    indexed: HashSet<String>, // functions using the global indices, directly or through calls
+    stmts: HashSet<*const Stmt>, // declarations and loops with a uniform variable
+}
+
+impl Uniformity {
+    pub fn new(programs: &[Program]) -> Uniformity {
+        let mut analysis = Analysis::default();
+        loop {
+            analysis.changed = false;
+            analysis.stmts.clear();
+            for program in programs {
+                analysis.program(program);
+            }
+            if !analysis.changed {
+                break;
+            }
+        }
+
+        // propagate the use of indices to callers
+        let functions = programs
+            .iter()
+            .flat_map(|p| &p.functions)
+            .collect::<Vec<_>>();
+        let mut indexed = analysis.indexed;
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for f in &functions {
+                if !indexed.contains(&f.id) && f.deps.iter().any(|d| indexed.contains(d)) {
+                    indexed.insert(f.id.clone());
+                    changed = true;
+                }
+            }
+        }
+
+        Uniformity {
+            indexed,
+            stmts: analysis.stmts,
+        }
+    }
+
+    pub fn indexed(&self, function: &str) -> bool {
+        self.indexed.contains(function)
+    }
+
+    pub fn stmt(&self, stmt: &Stmt) -> bool {
+        self.stmts.contains(&(stmt as *const Stmt))
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Symbol {
+    decl: Option<*const Stmt>, // None for arguments and variables with a fixed uniformity
+    depth: usize,              // divergence of the control flow at the declaration
+    uniform: bool,
+    array: bool,
+}
+
+#[derive(Default)]
+struct Analysis {
+    varying: HashSet<*const Stmt>, // declarations found to hold varying values
+    diverging: HashSet<*const Stmt>, // loops with a break or continue in varying control flow
+    indexed: HashSet<String>,
+    stmts: HashSet<*const Stmt>,
+    changed: bool,
+
+    scopes: Vec<HashMap<String, Symbol>>,
+    depth: usize,
+    loops: Vec<(*const Stmt, usize)>, // enclosing loops and the depth of their bodies
+    function: Option<String>,
+}
+
+fn scalar(ty: VarType) -> bool {
+    matches!(ty, VarType::Bool | VarType::Int | VarType::Float)
+}
+
+// local arrays are always uniform, vec and struct arrays are always varying
+fn array(ty: VarType) -> Option<bool> {
+    match ty {
+        VarType::BoolArray(_, local, ..)
+        | VarType::IntArray(_, local, ..)
+        | VarType::FloatArray(_, local, ..) => Some(local),
+        VarType::VecArray(..) | VarType::StructArray(..) => Some(false),
+        _ => None,
+    }
+}
+
+impl Analysis {
+    fn program(&mut self, program: &Program) {
+        self.scopes = vec![HashMap::new()];
+        self.depth = 0;
+        self.block(&program.constants);
+
+        self.kernel(&program.kernel);
+        for f in &program.functions {
+            self.function(f);
+        }
+    }
+
+    fn kernel(&mut self, kernel: &Kernel) {
+        // scalar and array arguments are passed as uniform values
+        let mut scope = HashMap::new();
+        for (id, ty) in &kernel.args {
+            let uniform =
+                scalar(*ty) || matches!(ty, VarType::IntArray(..) | VarType::FloatArray(..));
+            scope.insert(id.clone(), self.fixed(*ty, uniform));
+        }
+
+        self.function = None;
+        self.scopes.push(scope);
+        self.block(&kernel.body);
+        self.scopes.pop();
+    }
+
+    fn function(&mut self, function: &Function) {
+        let mut scope = HashMap::new();
+        for (id, ty) in &function.args {
+            scope.insert(id.clone(), self.fixed(*ty, array(*ty).unwrap_or(false)));
+        }
+
+        self.function = Some(function.id.clone());
+        self.scopes.push(scope);
+        self.block(&function.body);
+        self.scopes.pop();
+    }
+
+    fn fixed(&self, ty: VarType, uniform: bool) -> Symbol {
+        Symbol {
+            decl: None,
+            depth: self.depth,
+            uniform,
+            array: array(ty).is_some(),
+        }
+    }
+
+    fn lookup(&self, id: &str) -> Option<Symbol> {
+        self.scopes.iter().rev().find_map(|s| s.get(id)).copied()
+    }
+
+    fn mark_varying(&mut self, decl: *const Stmt) {
+        if self.varying.insert(decl) {
+            self.changed = true;
+        }
+    }
+
+    fn declare(&mut self, stmt: &Stmt, id: &str, ty: VarType, uniform: bool) {
+        let decl = stmt as *const Stmt;
+        let symbol = match array(ty) {
+            Some(true) => self.fixed(ty, true),
+            Some(false) if matches!(ty, VarType::VecArray(..) | VarType::StructArray(..)) => {
+                self.fixed(ty, false)
+            }
+            _ => {
+                let uniform =
+                    uniform && (scalar(ty) || array(ty).is_some()) && !self.varying.contains(&decl);
+                if uniform {
+                    self.stmts.insert(decl);
+                }
+                Symbol {
+                    decl: Some(decl),
+                    depth: self.depth,
+                    uniform,
+                    array: array(ty).is_some(),
+                }
+            }
+        };
+        self.scopes.last_mut().unwrap().insert(id.into(), symbol);
+    }
+
+    // uniform variables only hold uniform values assigned in the control flow of their declaration
+    fn assign(&mut self, id: &str, uniform: bool) {
+        if let Some(Symbol {
+            decl: Some(decl),
+            depth,
+            uniform: true,
+            ..
+        }) = self.lookup(id)
+        {
+            if !uniform || depth != self.depth {
+                self.mark_varying(decl);
+            }
+        }
+    }
+
+    fn scoped_block(&mut self, body: &[Stmt], depth: usize) {
+        let outer = self.depth;
+        self.depth = depth;
+        self.scopes.push(HashMap::new());
+        self.block(body);
+        self.scopes.pop();
+        self.depth = outer;
+    }
+
+    fn block(&mut self, body: &[Stmt]) {
+        for stmt in body {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Var { id, ty, init } | Stmt::Const { id, ty, init } => {
+                let uniform = init.as_ref().is_none_or(|e| self.expr(e));
+                self.declare(stmt, id, *ty, uniform);
+            }
+            Stmt::Assign(place, val) => {
+                let uniform = self.expr(val);
+                match place {
+                    Place::Expr(Expr {
+                        kind: ExprKind::Var(id),
+                        ..
+                    }) => self.assign(id, uniform),
+                    Place::Expr(e) => {
+                        self.expr(e);
+                    }
+                    Place::Array(id, idx) => {
+                        let idx = self.exprs(idx);
+                        self.assign(id, uniform && idx);
+                    }
+                    Place::Buffer(_, _, idx) => {
+                        self.buf_idx(idx);
+                    }
+                }
+            }
+            Stmt::Call(e) => {
+                self.expr(e);
+            }
+            Stmt::Return(values) => {
+                self.exprs(values);
+            }
+            Stmt::IfElse {
+                cond_list,
+                else_body,
+            } => {
+                // all later branches diverge after a varying condition
+                let mut depth = self.depth;
+                for (cond, body) in cond_list {
+                    if !self.expr(cond) {
+                        depth = self.depth + 1;
+                    }
+                    self.scoped_block(body, depth);
+                }
+                self.scoped_block(else_body, depth);
+            }
+            Stmt::For {
+                var,
+                ty,
+                from,
+                to,
+                step,
+                body,
+            } => {
+                let mut uniform = self.expr(from);
+                uniform &= self.expr(to);
+                if let Some(step) = step {
+                    uniform &= self.expr(step);
+                }
+                let uniform = uniform && matches!(ty, VarType::Int | VarType::Float);
+
+                self.scopes.push(HashMap::new());
+                self.declare(stmt, var, *ty, uniform);
+                let uniform = self.lookup(var).is_some_and(|s| s.uniform);
+                self.body(stmt, body, uniform);
+                self.scopes.pop();
+            }
+            Stmt::While { cond, body } => {
+                let uniform = self.expr(cond);
+                self.body(stmt, body, uniform);
+            }
+            Stmt::Break | Stmt::Continue => {
+                if let Some(&(l, depth)) = self.loops.last() {
+                    if self.depth > depth && self.diverging.insert(l) {
+                        self.changed = true;
+                    }
+                }
+            }
+            Stmt::Comment(_) => {}
+        }
+    }
+
+    fn body(&mut self, stmt: &Stmt, body: &[Stmt], uniform: bool) {
+        let l = stmt as *const Stmt;
+        let depth = if uniform && !self.diverging.contains(&l) {
+            self.depth
+        } else {
+            self.depth + 1
+        };
+        self.loops.push((l, depth));
+        self.scoped_block(body, depth);
+        self.loops.pop();
+    }
+
+    // all expressions are visited, returns whether all are uniform
+    fn exprs(&mut self, exprs: &[Expr]) -> bool {
+        let mut uniform = true;
+        for e in exprs {
+            uniform &= self.expr(e);
+        }
+        uniform
+    }
+
+    fn buf_idx(&mut self, idx: &BufIdx) -> bool {
+        match idx {
+            BufIdx::Linear(a) => self.expr(a),
+            BufIdx::Pixel(a, b) => self.expr(a) & self.expr(b),
+            BufIdx::Element(a, b, c) => self.expr(a) & self.expr(b) & self.expr(c),
+        }
+    }
+
+    // whole arrays are passed to functions as varying arrays
+    fn array_use(&mut self, id: &str) {
+        if let Some(Symbol {
+            decl: Some(decl),
+            array: true,
+            uniform: true,
+            ..
+        }) = self.lookup(id)
+        {
+            self.mark_varying(decl);
+        }
+    }
+
+    fn expr(&mut self, e: &Expr) -> bool {
+        match &e.kind {
+            ExprKind::Literal(_) | ExprKind::BufSize(..) => true,
+            ExprKind::Var(id) => match self.lookup(id) {
+                Some(s) if s.array => {
+                    self.array_use(id);
+                    false
+                }
+                Some(s) => s.uniform,
+                None => false,
+            },
+            ExprKind::Unary(_, right) => self.expr(right) && scalar(e.ty),
+            ExprKind::Binary(op, left, right) => {
+                let uniform = self.expr(left) & self.expr(right);
+                // integer powers use a varying library function
+                let pown = matches!(op, BinaryOp::Pow) && right.ty == VarType::Int;
+                uniform && scalar(e.ty) && !pown
+            }
+            ExprKind::Grouping(inner) => self.expr(inner),
+            ExprKind::Builtin(id, args) => {
+                if id == "get_global_id" || random_stream(id, args) {
+                    if let Some(f) = &self.function {
+                        self.indexed.insert(f.clone());
+                    }
+                }
+                self.exprs(args) && scalar(e.ty) && UNIFORM_BUILTINS.contains(&id.as_str())
+            }
+            ExprKind::Call(_, args) | ExprKind::External(_, args) => {
+                self.exprs(args);
+                false
+            }
+            ExprKind::Array(elems) => self.exprs(elems),
+            // out-parameters are passed by varying reference
+            ExprKind::Ref(id) => {
+                self.assign(id, false);
+                false
+            }
+            ExprKind::Component(inner, _) | ExprKind::Field(inner, _) => {
+                self.expr(inner);
+                false
+            }
+            ExprKind::Struct(args) => {
+                self.exprs(args);
+                false
+            }
+            ExprKind::BufLoad(_, _, idx) => self.buf_idx(idx) && scalar(e.ty),
+            ExprKind::BufProp(_, _, prop, idx) => {
+                self.buf_idx(idx) && matches!(prop, Prop::Int | Prop::Idx)
+            }
+            ExprKind::ArrayLoad(id, idx) => {
+                let uniform = self.exprs(idx);
+                uniform && scalar(e.ty) && self.lookup(id).is_some_and(|s| s.uniform)
+            }
+            ExprKind::ArrayPtr(id, idx) => {
+                self.exprs(idx);
+                self.array_use(id);
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,401 @@
+/*
+  Copyright (C) 2011-2021 G. Bajlekov
+
+    Ivy is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Ivy is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::ast::{Annotation, Expr, Index, Stmt};
+use crate::ir::reduction;
+
+// calls from each function and kernel to user defined functions
+struct CallGraph {
+    calls: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl CallGraph {
+    fn new(functions: &BTreeMap<&str, &Stmt>, kernels: &BTreeMap<&str, &Stmt>) -> CallGraph {
+        let mut calls = BTreeMap::new();
+        for (id, stmt) in functions.iter().chain(kernels.iter()) {
+            let mut set = BTreeSet::new();
+            if let Stmt::Function { body, .. } | Stmt::Kernel { body, .. } = stmt {
+                body.iter().for_each(|(s, _)| stmt_calls(s, &mut set));
+            }
+            // builtins are not part of the call graph
+            set.retain(|f| functions.contains_key(f.as_str()));
+            calls.insert(id.to_string(), set);
+        }
+        CallGraph { calls }
+    }
+
+    // find a chain of calls leading from a function back to itself
+    fn cycle(&self, id: &str) -> Option<Vec<String>> {
+        let mut path = vec![id.to_string()];
+        let mut visited = BTreeSet::new();
+        if self.path(id, id, &mut path, &mut visited) {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    fn path(
+        &self,
+        from: &str,
+        to: &str,
+        path: &mut Vec<String>,
+        visited: &mut BTreeSet<String>,
+    ) -> bool {
+        for f in self.calls.get(from).into_iter().flatten() {
+            path.push(f.clone());
+            if f == to {
+                return true;
+            }
+            if visited.insert(f.clone()) && self.path(f, to, path, visited) {
+                return true;
+            }
+            path.pop();
+        }
+        false
+    }
+}
+
+fn stmt_calls(stmt: &Stmt, calls: &mut BTreeSet<String>) {
+    match stmt {
+        Stmt::Var(_, _, expr) | Stmt::Const(_, _, expr) | Stmt::Vars(_, expr) => {
+            expr_calls(expr, calls)
+        }
+        Stmt::Return(values) => values.iter().for_each(|e| expr_calls(e, calls)),
+        Stmt::Assign(a, b) | Stmt::AssignOp(a, _, b) => {
+            expr_calls(a, calls);
+            expr_calls(b, calls);
+        }
+        Stmt::Call(id, args) => {
+            calls.insert(id.clone());
+            args.iter().for_each(|e| expr_calls(e, calls));
+        }
+        Stmt::IfElse {
+            cond_list,
+            else_body,
+        } => {
+            for c in cond_list {
+                expr_calls(&c.cond, calls);
+                c.body.iter().for_each(|(s, _)| stmt_calls(s, calls));
+            }
+            else_body.iter().for_each(|(s, _)| stmt_calls(s, calls));
+        }
+        Stmt::For {
+            from,
+            to,
+            step,
+            body,
+            ..
+        } => {
+            expr_calls(from, calls);
+            expr_calls(to, calls);
+            if let Some(step) = step {
+                expr_calls(step, calls);
+            }
+            body.iter().for_each(|(s, _)| stmt_calls(s, calls));
+        }
+        Stmt::While { cond, body } => {
+            expr_calls(cond, calls);
+            body.iter().for_each(|(s, _)| stmt_calls(s, calls));
+        }
+        _ => {}
+    }
+}
+
+fn expr_calls(expr: &Expr, calls: &mut BTreeSet<String>) {
+    match expr {
+        Expr::Call(id, args) => {
+            calls.insert(id.clone());
+            args.iter().for_each(|e| expr_calls(e, calls));
+        }
+        Expr::Unary(e) => expr_calls(&e.right, calls),
+        Expr::Binary(e) => {
+            expr_calls(&e.left, calls);
+            expr_calls(&e.right, calls);
+        }
+        Expr::Grouping(e) => expr_calls(e, calls),
+        Expr::Array(elements) => elements.iter().for_each(|e| expr_calls(e, calls)),
+        Expr::Index(e, idx) => {
+            expr_calls(e, calls);
+            match idx.as_ref() {
+                Index::Array1D(a) => expr_calls(a, calls),
+                Index::Array2D(a, b) => {
+                    expr_calls(a, calls);
+                    expr_calls(b, calls);
+                }
+                Index::Array3D(a, b, c) => {
+                    expr_calls(a, calls);
+                    expr_calls(b, calls);
+                    expr_calls(c, calls);
+                }
+                Index::Array4D(a, b, c, d) => {
+                    expr_calls(a, calls);
+                    expr_calls(b, calls);
+                    expr_calls(c, calls);
+                    expr_calls(d, calls);
+                }
+                _ => {}
+            }
+        }
+        Expr::Literal(_) | Expr::Identifier(_) => {}
+    }
+}
+
+// find a return statement with a value in a block, including nested blocks
+fn returns_value(body: &[(Stmt, usize)]) -> bool {
+    body.iter().any(|(stmt, _)| match stmt {
+        Stmt::Return(values) => !values.is_empty(),
+        Stmt::IfElse {
+            cond_list,
+            else_body,
+        } => cond_list.iter().any(|c| returns_value(&c.body)) || returns_value(else_body),
+        Stmt::For { body, .. } | Stmt::While { body, .. } => returns_value(body),
+        _ => false,
+    })
+}
+
+// reductions synchronize the work group and are reached by all of its work items,
+// only in the top level of a kernel body and before any return
+fn reductions(
+    id: &str,
+    body: &[(Stmt, usize)],
+    top: bool,
+    returned: &mut bool,
+    functions: &BTreeMap<&str, &Stmt>,
+    errors: &mut Vec<(String, usize)>,
+) {
+    for (stmt, line) in body {
+        let mut calls = BTreeSet::new();
+        match stmt {
+            Stmt::IfElse {
+                cond_list,
+                else_body,
+            } => {
+                for c in cond_list {
+                    expr_calls(&c.cond, &mut calls);
+                    reductions(id, &c.body, false, returned, functions, errors);
+                }
+                reductions(id, else_body, false, returned, functions, errors);
+            }
+            Stmt::For {
+                from,
+                to,
+                step,
+                body,
+                ..
+            } => {
+                expr_calls(from, &mut calls);
+                expr_calls(to, &mut calls);
+                if let Some(step) = step {
+                    expr_calls(step, &mut calls);
+                }
+                reductions(id, body, false, returned, functions, errors);
+            }
+            Stmt::While { cond, body } => {
+                expr_calls(cond, &mut calls);
+                reductions(id, body, false, returned, functions, errors);
+            }
+            stmt => stmt_calls(stmt, &mut calls),
+        }
+
+        for f in calls
+            .iter()
+            .filter(|f| reduction(f) && !functions.contains_key(f.as_str()))
+        {
+            if !top {
+                errors.push((
+                    format!(
+                        "Builtin '{}' can not be called in a conditional or loop in kernel '{}'",
+                        f, id
+                    ),
+                    *line,
+                ));
+            } else if *returned {
+                errors.push((
+                    format!(
+                        "Builtin '{}' can not be called after a return in kernel '{}'",
+                        f, id
+                    ),
+                    *line,
+                ));
+            }
+        }
+        if let Stmt::Return(_) = stmt {
+            *returned = true;
+        }
+    }
+}
+
+// find a chain of struct fields leading from a struct back to itself
+fn struct_cycle<'a>(
+    structs: &BTreeMap<&'a str, &'a Stmt>,
+    id: &'a str,
+    path: &mut Vec<&'a str>,
+) -> bool {
+    if let Some(Stmt::Struct { fields, .. }) = structs.get(id) {
+        for (_, a) in fields.iter() {
+            if let Annotation::Struct(s) = a {
+                if path.contains(&s.as_str()) {
+                    path.push(s);
+                    return true;
+                }
+                path.push(s);
+                if struct_cycle(structs, s, path) {
+                    return true;
+                }
+                path.pop();
+            }
+        }
+    }
+    false
+}
+
+// check the file scope for constructs that can not be translated, before any code is generated
+pub fn validate(ast: &[(Stmt, usize)]) -> Result<(), Vec<(String, usize)>> {
+    let mut errors = Vec::new();
+    let mut functions = BTreeMap::new();
+    let mut kernels = BTreeMap::new();
+    let mut structs = BTreeMap::new();
+    let mut lines = BTreeMap::new();
+
+    for (stmt, line) in ast {
+        match stmt {
+            Stmt::Function { id, .. } | Stmt::Kernel { id, .. } | Stmt::Struct { id, .. } => {
+                if let Some(first) = lines.get(id.as_str()) {
+                    errors.push((
+                        format!(
+                            "Duplicate definition of '{}', first defined on line {}",
+                            id, first
+                        ),
+                        *line,
+                    ));
+                    continue;
+                }
+                lines.insert(id.as_str(), *line);
+
+                if let Stmt::Kernel { body, .. } = stmt {
+                    if returns_value(body) {
+                        errors.push((format!("Kernel '{}' can not return a value", id), *line));
+                    }
+                    kernels.insert(id.as_str(), stmt);
+                } else if let Stmt::Struct { fields, .. } = stmt {
+                    if fields.is_empty() {
+                        errors.push((format!("Struct '{}' has no fields", id), *line));
+                    }
+                    for (n, (field, a)) in fields.iter().enumerate() {
+                        if let Annotation::Buffer { .. } | Annotation::Function = a {
+                            errors.push((
+                                format!(
+                                    "Field '{}' of struct '{}' can not be of type '{}'",
+                                    field, id, a
+                                ),
+                                *line,
+                            ));
+                        }
+                        if fields[..n].iter().any(|(f, _)| f == field) {
+                            errors.push((
+                                format!("Duplicate field '{}' in struct '{}'", field, id),
+                                *line,
+                            ));
+                        }
+                    }
+                    structs.insert(id.as_str(), stmt);
+                } else {
+                    functions.insert(id.as_str(), stmt);
+                }
+            }
+            Stmt::Var(id, ..) => errors.push((
+                format!(
+                    "Variable '{}' can not be declared in file scope, use 'const' instead",
+                    id
+                ),
+                *line,
+            )),
+            Stmt::Const(..) | Stmt::Comment(..) | Stmt::Eof => {}
+            _ => errors.push(("Unexpected statement in file scope".into(), *line)),
+        }
+    }
+
+    // annotations name a builtin type or a declared struct
+    for (stmt, line) in ast {
+        let annotations = match stmt {
+            Stmt::Function { types, ret, .. } => types.iter().flatten().chain(ret).collect(),
+            Stmt::Kernel { types, .. } => types.iter().flatten().collect(),
+            Stmt::Struct { fields, .. } => fields.iter().map(|(_, a)| a).collect(),
+            Stmt::Const(_, a, _) => a.iter().collect(),
+            _ => Vec::new(),
+        };
+        for a in annotations {
+            if let Annotation::Struct(s) = a {
+                if !structs.contains_key(s.as_str()) {
+                    errors.push((format!("Unknown type '{}'", s), *line));
+                }
+            }
+        }
+    }
+
+    // report each cycle of nested structs once
+    let mut reported = BTreeSet::new();
+    for id in structs.keys() {
+        let mut path = vec![*id];
+        if !reported.contains(*id)
+            && struct_cycle(&structs, id, &mut path)
+            && path.last() == Some(id)
+        {
+            errors.push((
+                format!("Recursive struct definition: {}", path.join(" -> ")),
+                lines[*id],
+            ));
+            reported.extend(path);
+        }
+    }
+
+    for (id, stmt) in &kernels {
+        if let Stmt::Kernel { body, .. } = stmt {
+            reductions(id, body, true, &mut false, &functions, &mut errors);
+        }
+    }
+
+    let graph = CallGraph::new(&functions, &kernels);
+
+    // report each cycle of recursive functions once
+    let mut reported = BTreeSet::new();
+    for id in functions.keys() {
+        if reported.contains(*id) {
+            continue;
+        }
+        if let Some(cycle) = graph.cycle(id) {
+            errors.push((
+                format!(
+                    "Recursive function calls are not supported: {}",
+                    cycle.join(" -> ")
+                ),
+                lines[*id],
+            ));
+            reported.extend(cycle);
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        errors.sort_by_key(|(_, line)| *line);
+        Err(errors)
+    }
+}
@@ -124,10 +124,11 @@ end
 
 local i32 = ffi.typeof("cl_int[1]")
 local f32 = ffi.typeof("cl_float[1]")
+local m33 = ffi.typeof("cl_float[12]") -- mat3 rows, padded as cl_float3
 
 function process:getKernel(name, buffers)
 	if not self.ivy then
-		self.ivy = ivy.new(self.source)
+		self.ivy = ivy.shared(self.source)
 	end
 	self.ivy:clear()
 	for k, v in ipairs(buffers) do
@@ -136,7 +137,11 @@ function process:getKernel(name, buffers)
 				self.ivy:addInt()
 			elseif ffi.istype(v, f32) then
 				self.ivy:addFloat()
+			elseif ffi.istype(v, m33) then
+				self.ivy:addMat3()
 			end
+		elseif type(v)=="string" then
+			self.ivy:addFunction(v) -- resolved in the kernel, not passed
 		else
 			self.ivy:addBuffer(v)
 		end
@@ -147,7 +152,7 @@ function process:getKernel(name, buffers)
 	if self.kernels[id] then
 		return self.kernels[id]
 	else
-		local source = self.ivy:generate(name)
+		local source, err = self.ivy:generate(name)
 		if #source>0 then
 			local program = self.context:create_program_with_source(source)
 			if not pcall(program.build, program, tools.buildParams) then
@@ -160,8 +165,8 @@ function process:getKernel(name, buffers)
 				return kernel
 			end
 		else
-			print("ERROR ["..name.."]: \nIvyScript unable to parse source!")
-			messageCh:push{"error", "ERROR ["..name.."]: \nIvyScript unable to parse source!"}
+			print("ERROR ["..name.."]: \nIvyScript unable to parse source!\n"..err)
+			messageCh:push{"error", "ERROR ["..name.."]: \nIvyScript unable to parse source!\n"..err}
 			return nil
 		end
 	end
@@ -178,12 +183,13 @@ local function setArgs(kernel, buffers)
 			kernel:set_arg(n, v.buffer[0].dataDev)
 			n = n + 1
 			kernel:set_arg(n, v.buffer[0].strDev)
-		else
+			n = n + 1
+		elseif type(v)~="string" then
 			assert(type(v)=="cdata")
-			assert(ffi.istype(v, i32) or ffi.istype(v, f32))
+			assert(ffi.istype(v, i32) or ffi.istype(v, f32) or ffi.istype(v, m33))
 			kernel:set_arg(n, v)
+			n = n + 1
 		end
-		n = n + 1
 	end
 end
 
@@ -201,6 +207,25 @@ function process:enqueueKernel(name, size, offset, buffers)
 	offset[3] = offset[3] or 0
 
 	local workgroupSize = self.workgroupSize or workgroupSize
+
+	-- work group shape declared by the kernel, rows keep the work group size
+	local tx, ty = self.ivy:traversal(name)
+	if tx then
+		if tx<0 then
+			tx = workgroupSize[1]*workgroupSize[2]
+		end
+		-- shrink tiles beyond the work group size supported by the kernel on this device
+		local max = kernel:get_work_group_info(self.device, "work_group_size")
+		while max and tx*ty>max do
+			if ty>1 then
+				ty = math.ceil(ty/2)
+			else
+				tx = math.ceil(tx/2)
+			end
+		end
+		workgroupSize = {tx, ty, workgroupSize[3]}
+	end
+
 	local workgroup = {math.min(size[1], workgroupSize[1]), math.min(size[2], workgroupSize[2]), math.min(size[3], workgroupSize[3])}
 
 	local sx = workgroup[1]
@@ -112,6 +112,7 @@ end
 
 local i32 = ffi.typeof("int[1]")
 local f32 = ffi.typeof("float[1]")
+local m33 = ffi.typeof("float[12]") -- mat3 rows, padded to 4 floats
 
 local function file_exists(name)
    local f=io.open(name,"r")
@@ -139,7 +140,7 @@ ivyjit.ivyjit_init()
 
 function process:getKernel(name, buffers)
 	if not self.generator then
-		self.generator = generator.new(self.source, "ISPC")
+		self.generator = generator.shared(self.source, "ISPC")
 	end
 	self.generator:clear()
 	local decl = {}
@@ -151,7 +152,12 @@ function process:getKernel(name, buffers)
 			elseif ffi.istype(v, f32) then
 				self.generator:addFloat()
 				table.insert(decl, "float")
+			elseif ffi.istype(v, m33) then
+				self.generator:addMat3()
+				table.insert(decl, "float *")
 			end
+		elseif type(v)=="string" then
+			self.generator:addFunction(v) -- resolved in the kernel, not passed
 		else
 			self.generator:addBuffer(v)
 			table.insert(decl, "float *")
@@ -163,7 +169,7 @@ function process:getKernel(name, buffers)
 	if self.kernels[id] then
 		return self.kernels[id].k
 	else
-		local source = self.generator:generate(name)
+		local source, err = self.generator:generate(name)
 		if #source>0 then
 			local f = io.open("___temp.ispc", "wb")
 			f:write(source)
@@ -187,7 +193,7 @@ function process:getKernel(name, buffers)
 			self.kernels[id] = {k = kernel, j = J}
 			return kernel
 		else
-			messageCh:push{"error", "ERROR ["..name.."]: \nIvyScript unable to parse source!"}
+			messageCh:push{"error", "ERROR ["..name.."]: \nIvyScript unable to parse source!\n"..err}
 			return nil
 		end
 	end
@@ -202,10 +208,10 @@ local function args(buffers)
 			v:allocHost()
 			table.insert(args, v.buffer[0].dataHost)
 			table.insert(args, v.buffer[0].strHost)
-		else
+		elseif type(v)~="string" then
 			assert(type(v)=="cdata")
-			assert(ffi.istype(v, i32) or ffi.istype(v, f32))
-			table.insert(args, v[0])
+			assert(ffi.istype(v, i32) or ffi.istype(v, f32) or ffi.istype(v, m33))
+			table.insert(args, ffi.istype(v, m33) and v or v[0])
 		end
 	end
 	return unpack(args)
@@ -209,4 +209,103 @@ inline void _atomic_local_float_max(volatile local float *addr, float val) {
 
 inline void local_barrier() { barrier(CLK_LOCAL_MEM_FENCE); }
 
-inline void global_barrier() { barrier(CLK_GLOBAL_MEM_FENCE); }
\ No newline at end of file
+inline void global_barrier() { barrier(CLK_GLOBAL_MEM_FENCE); }
+// workgroup reductions into out[i], called by all work items of the work group
+// like barrier, with one global atomic per work group; work items targeting
+// another element than the first work item of the group use global atomics,
+// work groups of declared tiles are at most MAX_TILE in ast.rs
+#define _REDUCE_SIZE 256
+
+inline uint _local_count() {
+  return get_local_size(0) * get_local_size(1) * get_local_size(2);
+}
+
+inline uint _local_index() {
+  return get_local_id(0) +
+         get_local_size(0) * (get_local_id(1) + get_local_size(1) * get_local_id(2));
+}
+
+inline float _reduce_op(int op, float a, float b) {
+  return op == 0 ? a + b : op == 1 ? fmin(a, b) : fmax(a, b);
+}
+
+inline void _reduce_atomic(int op, global float *addr, float val) {
+  if (op == 0) {
+    _atomic_float_add(addr, val);
+  } else if (op == 1) {
+    _atomic_float_min(addr, val);
+  } else {
+    _atomic_float_max(addr, val);
+  }
+}
+
+// scratch holds one value per work item and the target of the group
+void _reduce(int op, float v, global float *out, int i, local float *s) {
+  uint n = _local_count();
+  uint l = _local_index();
+  local int *t = (local int *)(s + _REDUCE_SIZE);
+
+  if (n > _REDUCE_SIZE) {
+    _reduce_atomic(op, out + i, v);
+    return;
+  }
+
+  if (l == 0)
+    *t = i;
+  barrier(CLK_LOCAL_MEM_FENCE);
+  if (*t != i) {
+    _reduce_atomic(op, out + i, v);
+    v = op == 0 ? 0.0f : op == 1 ? INFINITY : -INFINITY;
+  }
+
+  s[l] = v;
+  for (uint k = 1; k < n; k *= 2) {
+    barrier(CLK_LOCAL_MEM_FENCE);
+    if ((l & (2 * k - 1)) == 0 && l + k < n)
+      s[l] = _reduce_op(op, s[l], s[l + k]);
+  }
+  barrier(CLK_LOCAL_MEM_FENCE);
+  if (l == 0)
+    _reduce_atomic(op, out + i, s[0]);
+  barrier(CLK_LOCAL_MEM_FENCE);
+}
+
+void _reduce_sum(float v, global float *out, int i, local float *s) {
+  _reduce(0, v, out, i, s);
+}
+
+void _reduce_min(float v, global float *out, int i, local float *s) {
+  _reduce(1, v, out, i, s);
+}
+
+void _reduce_max(float v, global float *out, int i, local float *s) {
+  _reduce(2, v, out, i, s);
+}
+
+// counts in out[i + bin] accumulated in local bins below _REDUCE_SIZE, negative
+// bins are ignored
+void _histogram(int bin, global float *out, int i, local float *s) {
+  uint n = _local_count();
+  uint l = _local_index();
+  local int *t = (local int *)(s + _REDUCE_SIZE);
+
+  if (l == 0)
+    *t = i;
+  for (uint k = l; k < _REDUCE_SIZE; k += n)
+    s[k] = 0.0f;
+  barrier(CLK_LOCAL_MEM_FENCE);
+
+  if (bin >= 0) {
+    if (*t == i && bin < _REDUCE_SIZE) {
+      _atomic_local_float_inc(s + bin);
+    } else {
+      _atomic_float_inc(out + i + bin);
+    }
+  }
+  barrier(CLK_LOCAL_MEM_FENCE);
+
+  for (uint k = l; k < _REDUCE_SIZE; k += n)
+    if (s[k] != 0.0f)
+      _atomic_float_add(out + *t + k, s[k]);
+  barrier(CLK_LOCAL_MEM_FENCE);
+}
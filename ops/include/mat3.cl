@@ -0,0 +1,116 @@
+/*
+  Copyright (C) 2011-2021 G. Bajlekov
+
+    Ivy is free software: you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation, either version 3 of the License, or
+    (at your option) any later version.
+
+    Ivy is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program.  If not, see <http://www.gnu.org/licenses/>.
+*/
+
+#ifndef __INCLUDE_MAT3
+#define __INCLUDE_MAT3
+
+// 3x3 matrix stored as rows, vectors are multiplied as columns
+typedef struct {
+  float3 x, y, z;
+} mat3;
+
+inline mat3 _mat3_rows(float3 x, float3 y, float3 z) {
+  mat3 m;
+  m.x = x;
+  m.y = y;
+  m.z = z;
+  return m;
+}
+
+inline mat3 _mat3(float a, float b, float c, float d, float e, float f, float g,
+                  float h, float i) {
+  return _mat3_rows((float3)(a, b, c), (float3)(d, e, f), (float3)(g, h, i));
+}
+
+inline mat3 _mat3_diag(float3 d) {
+  return _mat3(d.x, 0.0f, 0.0f, 0.0f, d.y, 0.0f, 0.0f, 0.0f, d.z);
+}
+
+inline float3 _mat3_mul_vec(mat3 m, float3 v) {
+  return (float3)(dot(m.x, v), dot(m.y, v), dot(m.z, v));
+}
+
+inline mat3 _mat3_transpose(mat3 m) {
+  return _mat3(m.x.x, m.y.x, m.z.x, m.x.y, m.y.y, m.z.y, m.x.z, m.y.z, m.z.z);
+}
+
+inline mat3 _mat3_mul(mat3 a, mat3 b) {
+  mat3 t = _mat3_transpose(b);
+  return _mat3_rows(_mat3_mul_vec(t, a.x), _mat3_mul_vec(t, a.y),
+                    _mat3_mul_vec(t, a.z));
+}
+
+// columns of the inverse are the cross products of the rows over the determinant
+inline mat3 _mat3_inverse(mat3 m) {
+  float3 a = cross(m.y, m.z);
+  float3 b = cross(m.z, m.x);
+  float3 c = cross(m.x, m.y);
+  float d = 1.0f / dot(m.x, a);
+  return _mat3_transpose(_mat3_rows(a * d, b * d, c * d));
+}
+
+// double precision matrices, kernel arguments are passed as mat3
+typedef struct {
+  double3 x, y, z;
+} dmat3;
+
+inline dmat3 _dmat3_rows(double3 x, double3 y, double3 z) {
+  dmat3 m;
+  m.x = x;
+  m.y = y;
+  m.z = z;
+  return m;
+}
+
+inline dmat3 _mat3_double(mat3 m) {
+  return _dmat3_rows(convert_double3(m.x), convert_double3(m.y),
+                     convert_double3(m.z));
+}
+
+inline dmat3 _dmat3(double a, double b, double c, double d, double e, double f,
+                    double g, double h, double i) {
+  return _dmat3_rows((double3)(a, b, c), (double3)(d, e, f),
+                     (double3)(g, h, i));
+}
+
+inline dmat3 _dmat3_diag(double3 d) {
+  return _dmat3(d.x, 0.0, 0.0, 0.0, d.y, 0.0, 0.0, 0.0, d.z);
+}
+
+inline double3 _dmat3_mul_vec(dmat3 m, double3 v) {
+  return (double3)(dot(m.x, v), dot(m.y, v), dot(m.z, v));
+}
+
+inline dmat3 _dmat3_transpose(dmat3 m) {
+  return _dmat3(m.x.x, m.y.x, m.z.x, m.x.y, m.y.y, m.z.y, m.x.z, m.y.z, m.z.z);
+}
+
+inline dmat3 _dmat3_mul(dmat3 a, dmat3 b) {
+  dmat3 t = _dmat3_transpose(b);
+  return _dmat3_rows(_dmat3_mul_vec(t, a.x), _dmat3_mul_vec(t, a.y),
+                     _dmat3_mul_vec(t, a.z));
+}
+
+inline dmat3 _dmat3_inverse(dmat3 m) {
+  double3 a = cross(m.y, m.z);
+  double3 b = cross(m.z, m.x);
+  double3 c = cross(m.x, m.y);
+  double d = 1.0 / dot(m.x, a);
+  return _dmat3_transpose(_dmat3_rows(a * d, b * d, c * d));
+}
+
+#endif
@@ -17,13 +17,26 @@
 
 // counter-based random number generator philox for efficient parallel
 // generation of random numbers
+//
+// stream model: each work item draws from the stream keyed on the seed and
+// counted by (x, y, z, n), where n is the index of the block within the
+// stream; a block of four words is consumed per draw or rejection attempt
+// - runif uses the first word
+// - rnorm uses the first two words per attempt
+// - rpois uses the first word per factor below lambda = 10, else the first
+//   two words per attempt
+// uniforms are the upper 24 bits scaled by 2^-24, exact in single precision
+// derived samples agree to the precision of the math functions of each target
+// this file is compiled as C in the tests and checked against random.rs
 
 typedef struct {
   uint a;
   uint b;
+  uint c;
+  uint d;
 } _philox_ctr;
 
-// start adapted philox2x32_R10
+// start adapted philox4x32_R10
 // adapted from https://www.thesalmons.org/john/random123/
 /*
 Copyright 2010-2012, D. E. Shaw Research. All rights reserved.
@@ -48,98 +61,62 @@ IN ANY WAY OUT OF THE USE OF THIS SOFTWARE, EVEN IF ADVISED OF THE POSSIBILITY
 OF SUCH DAMAGE.
 */
 
-uint _philox_mulhilo(uint a, uint b, uint *hip) {
-  ulong product = ((ulong)a) * ((ulong)b);
-  *hip = product >> 32;
-  return (uint)product;
-}
-
-#define _M2 ((uint)0xd256d193)
-#define _W32 ((uint)0x9E3779B9)
+#define _M0 ((uint)0xD2511F53)
+#define _M1 ((uint)0xCD9E8D57)
+#define _W0 ((uint)0x9E3779B9)
+#define _W1 ((uint)0xBB67AE85)
 
-inline _philox_ctr _philox_round(_philox_ctr ctr, uint key) {
-  uint hi;
-  uint lo = _philox_mulhilo(_M2, ctr.a, &hi);
-  _philox_ctr out = {hi ^ key ^ ctr.b, lo};
+inline _philox_ctr _philox_round(_philox_ctr ctr, uint k0, uint k1) {
+  ulong p0 = (ulong)_M0 * ctr.a;
+  ulong p1 = (ulong)_M1 * ctr.c;
+  _philox_ctr out = {(uint)(p1 >> 32) ^ ctr.b ^ k0, (uint)p1,
+                     (uint)(p0 >> 32) ^ ctr.d ^ k1, (uint)p0};
   return out;
 }
 
-inline uint _philox_bumpkey(uint key) {
-  key += _W32;
-  return key;
-}
-
-_philox_ctr _philox(_philox_ctr ctr, uint key) {
-  ctr = _philox_round(ctr, key);
-  key = _philox_bumpkey(key);
-  ctr = _philox_round(ctr, key);
-  key = _philox_bumpkey(key);
-  ctr = _philox_round(ctr, key);
-  key = _philox_bumpkey(key);
-  ctr = _philox_round(ctr, key);
-  key = _philox_bumpkey(key);
-  ctr = _philox_round(ctr, key);
-  key = _philox_bumpkey(key);
-  ctr = _philox_round(ctr, key);
-  key = _philox_bumpkey(key);
-  ctr = _philox_round(ctr, key);
-  key = _philox_bumpkey(key);
-  ctr = _philox_round(ctr, key);
-  key = _philox_bumpkey(key);
-  ctr = _philox_round(ctr, key);
-  key = _philox_bumpkey(key);
-  ctr = _philox_round(ctr, key);
+_philox_ctr _philox(_philox_ctr ctr, uint k0, uint k1) {
+  ctr = _philox_round(ctr, k0, k1);
+  for (int i = 1; i < 10; i++) {
+    k0 += _W0;
+    k1 += _W1;
+    ctr = _philox_round(ctr, k0, k1);
+  }
   return ctr;
 }
-// end adapted philox2x32_R10
-
+// end adapted philox4x32_R10
 
-// uniformly distributed random numbers in the range 0-1
-float runif(uint key, uint x, uint y) {
-  _philox_ctr ctr = {x, y};
-  _philox_ctr res = _philox(ctr, key);
-  return (float)res.a / 4294967296;
+// next block of the stream
+_philox_ctr _rng_block(uint seed, uint x, uint y, uint z, uint *n) {
+  _philox_ctr ctr = {x, y, z, *n};
+  *n += 1;
+  return _philox(ctr, seed, 0);
 }
 
-// using the Box-Muller transform to obtain normally distributed samples
-// https://en.wikipedia.org/wiki/Box%E2%80%93Muller_transform
-float _rnorm_alt(uint key, uint x, uint y) {
-  _philox_ctr ctr = {x, y};
-  _philox_ctr res = _philox(ctr, key);
-
-  float u1 = (float)res.a / 4294967296;
-  float u2 = (float)res.b / 4294967296;
-
-  float r = sqrt(-2.0f * log(u1));
-  float t = M_2PI * u2;
+// uniformly distributed in the range [0, 1)
+inline float _rng_u01(uint a) {
+  return (float)(a >> 8) * 5.9604645e-8f;
+}
 
-  return r * sin(t);
+float _runif(uint seed, uint x, uint y, uint z, uint *n) {
+  return _rng_u01(_rng_block(seed, x, y, z, n).a);
 }
 
 // using the Marsaglia polar method to obtain normally distributed samples
 // https://en.wikipedia.org/wiki/Marsaglia_polar_method
-float rnorm(uint key, uint x, uint y) {
-  uint k = 0;
-
+float _rnorm(uint seed, uint x, uint y, uint z, uint *n) {
   float s, u, v;
   do {
-    _philox_ctr ctr = {x, y};
-    _philox_ctr res = _philox(ctr, key + k);
-
-    float u1 = (float)res.a / 4294967296;
-    float u2 = (float)res.b / 4294967296;
-
-    u = u1 * 2 - 1;
-    v = u2 * 2 - 1;
+    _philox_ctr res = _rng_block(seed, x, y, z, n);
+    u = _rng_u01(res.a) * 2.0f - 1.0f;
+    v = _rng_u01(res.b) * 2.0f - 1.0f;
     s = u * u + v * v;
-    k += 1;
-  } while (s >= 1 || s == 0);
-  s = sqrt(-2.0 * log(s) / s);
+  } while (s >= 1.0f || s == 0.0f);
+  s = sqrt(-2.0f * log(s) / s);
 
-  return u * s; // use alternating solutions u*s, v*s
+  return u * s;
 }
 
-float _poisson_small(uint key, uint x, float lambda) {
+float _poisson_small(uint seed, uint x, uint y, uint z, uint *n, float lambda) {
   // Algorithm due to Donald Knuth, 1969.
   float p = 1.0f;
   float L = exp(-lambda);
@@ -147,10 +124,7 @@ float _poisson_small(uint key, uint x, float lambda) {
   uint k = 0;
   do {
     k++;
-    _philox_ctr ctr = {x, k};
-    _philox_ctr res =
-        _philox(ctr, key); // use alternative solutions res.a, res.b
-    p *= (float)res.a / 4294967296;
+    p *= _runif(seed, x, y, z, n);
   } while (p > L);
   return (float)(k - 1);
 }
@@ -161,40 +135,55 @@ The transformed rejection method for generating Poisson random variables
 W. Hormann, Mathematics and Economics 12, 39-45 (1993)
 Described PTRS algorithm
 */
-float _poisson_large(uint key, uint x, float lam) {
+float _poisson_large(uint seed, uint x, uint y, uint z, uint *n, float lam) {
   float k;
   float U, V, slam, loglam, a, b, invalpha, vr, us;
 
   slam = sqrt(lam);
   loglam = log(lam);
-  b = 0.931 + 2.53 * slam;
-  a = -0.059 + 0.02483 * b;
-  invalpha = 1.1239 + 1.1328 / (b - 3.4);
-  vr = 0.9277 - 3.6224 / (b - 2);
-
-  for (int y = 0; y < 1024; y++) {
-    _philox_ctr ctr = {x, y};
-    _philox_ctr res = _philox(ctr, key);
-
-    U = (float)res.a / 4294967296 - 0.5;
-    V = (float)res.b / 4294967296;
-    us = 0.5 - fabs(U);
-    k = floor((2 * a / us + b) * U + lam + 0.43);
-    if ((us >= 0.07) && (V <= vr)) {
+  b = 0.931f + 2.53f * slam;
+  a = -0.059f + 0.02483f * b;
+  invalpha = 1.1239f + 1.1328f / (b - 3.4f);
+  vr = 0.9277f - 3.6224f / (b - 2.0f);
+
+  for (int i = 0; i < 1024; i++) {
+    _philox_ctr res = _rng_block(seed, x, y, z, n);
+
+    U = _rng_u01(res.a) - 0.5f;
+    V = _rng_u01(res.b);
+    us = 0.5f - fabs(U);
+    k = floor((2.0f * a / us + b) * U + lam + 0.43f);
+    if ((us >= 0.07f) && (V <= vr)) {
       return k;
     }
-    if ((k < 0) || ((us < 0.013) && (V > us))) {
+    if ((k < 0.0f) || ((us < 0.013f) && (V > us))) {
       continue;
     }
     if ((log(V) + log(invalpha) - log(a / (us * us) + b)) <=
-        (-lam + k * loglam - lgamma(k + 1))) {
+        (-lam + k * loglam - lgamma(k + 1.0f))) {
       return k;
     }
   }
   return k;
 }
 
+float _rpois(uint seed, uint x, uint y, uint z, uint *n, float lambda) {
+  return (lambda < 10.0f) ? _poisson_small(seed, x, y, z, n, lambda)
+                          : _poisson_large(seed, x, y, z, n, lambda);
+}
+
+// single draws keyed on (key, x, y), independent of the draws of the kernel
+float runif(uint key, uint x, uint y) {
+  uint n = 0;
+  return _runif(key, x, y, 0, &n);
+}
+
+float rnorm(uint key, uint x, uint y) {
+  uint n = 0;
+  return _rnorm(key, x, y, 0, &n);
+}
+
 float rpois(uint key, uint x, float lambda) {
-  return (lambda < 10.0f) ? _poisson_small(key, x, lambda)
-                          : _poisson_large(key, x, lambda);
-}
\ No newline at end of file
+  uint n = 0;
+  return _rpois(key, x, 0, 0, &n, lambda);
+}
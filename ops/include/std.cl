@@ -21,6 +21,7 @@
 #include "colorspace.cl"
 #include "random.cl"
 #include "atomic.cl"
+#include "mat3.cl"
 
 inline float range(float p, float w, float x) {
   x = (x - (p - w)) / (2 * w + 0.000001f);
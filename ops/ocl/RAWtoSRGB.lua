@@ -134,10 +134,11 @@ kernel convert(I, M, W, P, flags, C)
   end
 
 	if flags[3]>0.5 then
-		var o = vec(0.0)
-		o.x = i.x*M[0, 0, 0] + i.y*M[0, 1, 0] + i.z*M[0, 2, 0]
-		o.y = i.x*M[1, 0, 0] + i.y*M[1, 1, 0] + i.z*M[1, 2, 0]
-		o.z = i.x*M[2, 0, 0] + i.y*M[2, 1, 0] + i.z*M[2, 2, 0]
+		var m = mat3(
+			M[0, 0, 0], M[0, 1, 0], M[0, 2, 0],
+			M[1, 0, 0], M[1, 1, 0], M[1, 2, 0],
+			M[2, 0, 0], M[2, 1, 0], M[2, 2, 0])
+		var o = m*i
 
     if clip then
       -- desaturate clipped values
@@ -146,10 +147,7 @@ kernel convert(I, M, W, P, flags, C)
 
     if flags[5]>0.5 then
       -- replace luminance with reconstructed value
-      var o_r = vec(0.0)
-      o_r.x = i_r.x*M[0, 0, 0] + i_r.y*M[0, 1, 0] + i_r.z*M[0, 2, 0]
-      o_r.y = i_r.x*M[1, 0, 0] + i_r.y*M[1, 1, 0] + i_r.z*M[1, 2, 0]
-      o_r.z = i_r.x*M[2, 0, 0] + i_r.y*M[2, 1, 0] + i_r.z*M[2, 2, 0]
+      var o_r = m*i_r
 
       var y = LRGBtoXYZ(o)
       var yr = LRGBtoY(o_r)
@@ -17,11 +17,10 @@
 
 local process = require "lib.opencl.process.ivy"
 
-local source = {}
-for k, v in ipairs{"negate", "exclude", "screen", "overlay", "hardlight", "softlight", "dodge", "burn", "softdodge", "softburn", "linearlight", "vividlight", "pinlight"} do
-  source[v] = [[
+-- blend mode functions are passed by name and resolved when specializing the kernel
+local source = [[
 
-	kernel kernel_]]..v..[[(A, B, F, O)
+	kernel blend(A, B, F, O, op: fn)
 	  const x = get_global_id(0)
 	  const y = get_global_id(1)
 	  const z = get_global_id(2)
@@ -30,25 +29,22 @@ for k, v in ipairs{"negate", "exclude", "screen", "overlay", "hardlight", "softl
 		var b = B[x, y, z]
 		var f = F[x, y, z]
 
-		var o = ]]..v..[[(a, b)
+		var o = op(a, b)
 
 	  O[x, y, z] = a*(1.0 - f) + o*f
 	end
 	]]
-end
-
-
 
 local function init(d, c, q, name)
   local proc = process.new()
 
   proc:init(d, c, q)
   proc:loadSourceFile("blendops_LRGB.ivy")
-  proc:loadSourceString(source[name])
+  proc:loadSourceString(source)
 
   local function execute()
     local A, B, F, O = proc:getAllBuffers(4)
-    proc:executeKernel("kernel_"..name, proc:size3D(O), {A, B, F, O})
+    proc:executeKernel("blend", proc:size3D(O), {A, B, F, O, name})
   end
 
   return execute
@@ -28,7 +28,7 @@ kernel random(I, P, O, W, B, seed)
   var w = W[x, y, z] > 0.5
   var b = B[x, y, z] > 0.5
 
-  var r = runif(seed+z, x, y)
+  var r = runif(seed)
   if r > (0.5 + p) and w then
     O[x, y, z] = 1.0
   else
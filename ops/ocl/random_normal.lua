@@ -30,7 +30,7 @@ kernel random(I, W, P, O, seed)
   if P[0]>0.5 then
     i = YtoL(i)
   end
-  var o = i + rnorm(seed+z, x, y)*w
+  var o = i + rnorm(seed)*w
   if P[0]>0.5 then
     o = LtoY(o)
   end
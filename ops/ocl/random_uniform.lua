@@ -32,7 +32,7 @@ kernel random(I, W, P, O, seed)
   end
 
 
-  var o = i + runif(seed+z, x, y)*w - 0.5*w
+  var o = i + runif(seed)*w - 0.5*w
   if P[0]>0.5 then
     o = LtoY(o)
   end